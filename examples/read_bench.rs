@@ -0,0 +1,80 @@
+// socketcan/examples/read_bench.rs
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+//
+
+//! Compares the time to read frames with `read_raw_frame()`, which zeroes
+//! each frame before the read, against `read_raw_frame_uninit()`, which
+//! doesn't.
+//!
+//! Run it on a virtual interface:
+//!
+//! ```text
+//! $ cargo run --release --example read_bench -- vcan0
+//! ```
+
+use anyhow::Context;
+use embedded_can::{Frame as EmbeddedFrame, StandardId};
+use socketcan::{CanFrame, CanSocket, Socket, SocketOptions};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+/// The number of frames in each burst
+const BURST: usize = 64;
+
+/// The number of bursts to time
+const ROUNDS: usize = 10000;
+
+/// Sends a burst of frames to be looped back to the socket.
+fn send_burst(sock: &CanSocket) -> anyhow::Result<()> {
+    let frame = CanFrame::new(StandardId::new(0x100).unwrap(), &[0; 8]).unwrap();
+    for _ in 0..BURST {
+        sock.write_frame_insist(&frame)?;
+    }
+    Ok(())
+}
+
+/// Times reading each burst with the read function given.
+fn bench<F>(sock: &CanSocket, mut read: F) -> anyhow::Result<Duration>
+where
+    F: FnMut(&CanSocket) -> std::io::Result<libc::can_frame>,
+{
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        send_burst(sock)?;
+        let start = Instant::now();
+        for _ in 0..BURST {
+            read(sock)?;
+        }
+        elapsed += start.elapsed();
+    }
+    Ok(elapsed)
+}
+
+// --------------------------------------------------------------------------
+
+fn main() -> anyhow::Result<()> {
+    let iface = env::args().nth(1).unwrap_or_else(|| "vcan0".into());
+
+    let sock = CanSocket::open(&iface)
+        .with_context(|| format!("Failed to open socket on interface {}", iface))?;
+    sock.set_recv_own_msgs(true)?;
+    sock.set_read_timeout(Duration::from_secs(1))?;
+
+    let nframes = (BURST * ROUNDS) as u32;
+
+    let t = bench(&sock, CanSocket::read_raw_frame).context("Reading zeroed frames")?;
+    println!("read_raw_frame:        {:?} ({:?}/frame)", t, t / nframes);
+
+    let t = bench(&sock, CanSocket::read_raw_frame_uninit).context("Reading uninit frames")?;
+    println!("read_raw_frame_uninit: {:?} ({:?}/frame)", t, t / nframes);
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+// socketcan/examples/ring_bench.rs
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+//
+
+//! Compares the time to receive bursts of frames with a `CanRingReader`
+//! against reading them one at a time into a `Vec`.
+//!
+//! Run it on a virtual interface:
+//!
+//! ```text
+//! $ cargo run --release --example ring_bench -- vcan0
+//! ```
+
+use anyhow::Context;
+use embedded_can::{Frame as EmbeddedFrame, StandardId};
+use socketcan::{CanFrame, CanRingReader, CanSocket, Socket, SocketOptions};
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+/// The number of frames in each burst
+const BURST: usize = 64;
+
+/// The number of bursts to time
+const ROUNDS: usize = 1000;
+
+/// Sends a burst of frames to be looped back to the socket.
+fn send_burst(sock: &CanSocket) -> anyhow::Result<()> {
+    let frame = CanFrame::new(StandardId::new(0x100).unwrap(), &[0; 8]).unwrap();
+    for _ in 0..BURST {
+        sock.write_frame_insist(&frame)?;
+    }
+    Ok(())
+}
+
+/// Reads each burst one frame at a time into a Vec.
+fn bench_vec(sock: &CanSocket) -> anyhow::Result<Duration> {
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        send_burst(sock)?;
+        let start = Instant::now();
+        let mut frames = Vec::new();
+        while frames.len() < BURST {
+            frames.push(sock.read_frame()?);
+        }
+        elapsed += start.elapsed();
+    }
+    Ok(elapsed)
+}
+
+/// Reads each burst with a ring reader.
+fn bench_ring(sock: &CanSocket) -> anyhow::Result<Duration> {
+    let mut reader = Box::new(CanRingReader::<BURST>::new());
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        send_burst(sock)?;
+        let start = Instant::now();
+        let mut n = 0;
+        while n < BURST {
+            n += reader.read(sock)?.len();
+        }
+        elapsed += start.elapsed();
+    }
+    Ok(elapsed)
+}
+
+// --------------------------------------------------------------------------
+
+fn main() -> anyhow::Result<()> {
+    let iface = env::args().nth(1).unwrap_or_else(|| "vcan0".into());
+
+    let sock = CanSocket::open(&iface)
+        .with_context(|| format!("Failed to open socket on interface {}", iface))?;
+    sock.set_recv_own_msgs(true)?;
+    sock.set_read_timeout(Duration::from_secs(1))?;
+
+    let nframes = (BURST * ROUNDS) as u32;
+
+    let t = bench_vec(&sock).context("Reading into a Vec")?;
+    println!("Vec:           {:?} ({:?}/frame)", t, t / nframes);
+
+    let t = bench_ring(&sock).context("Reading with a CanRingReader")?;
+    println!("CanRingReader: {:?} ({:?}/frame)", t, t / nframes);
+
+    Ok(())
+}
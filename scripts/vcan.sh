@@ -28,9 +28,10 @@ if [ -z "${VCAN_LOADED}" ]; then
 fi
 
 # Add and set up the CAN interface
-# Request an MTU size of 72 to allow for FD frames
+# Request an MTU size of 2060 to allow for XL frames, or 72 to allow for
+# FD frames on kernels without CAN XL support (before v6.2)
 
 ip link add type vcan && \
-    ip link set "${IFACE}" mtu 72
+    { ip link set "${IFACE}" mtu 2060 2>/dev/null || ip link set "${IFACE}" mtu 72; }
     ip link set up "${IFACE}"
 
@@ -12,14 +12,30 @@
 //! SocketCAN address type.
 
 use crate::id::id_to_canid_t;
-use embedded_can::Id;
+use embedded_can::{ExtendedId, Id, StandardId};
 use libc::{sa_family_t, sockaddr, sockaddr_can, sockaddr_storage, socklen_t};
 use nix::net::if_::if_nametoindex;
 use socket2::SockAddr;
-use std::{fmt, io, mem, mem::size_of, os::raw::c_int};
+use std::{
+    ffi::CStr,
+    fmt, io, mem,
+    mem::size_of,
+    os::raw::{c_char, c_int, c_uint},
+};
 
 pub use libc::{AF_CAN, CAN_RAW, PF_CAN};
 
+/// Gets the ID from a raw `canid_t`, as an extended ID if the EFF flag is
+/// set, ignoring any other flags.
+fn canid_t_to_id(id: libc::canid_t) -> Id {
+    match id & libc::CAN_EFF_FLAG {
+        0 => StandardId::new((id & libc::CAN_SFF_MASK) as u16)
+            .unwrap()
+            .into(),
+        _ => ExtendedId::new(id & libc::CAN_EFF_MASK).unwrap().into(),
+    }
+}
+
 /// CAN socket address.
 ///
 /// This is the address for use with CAN sockets. It is simply an address to
@@ -66,12 +82,101 @@ impl CanAddr {
         addr
     }
 
+    /// Creates an address from an interface index that was already resolved,
+    /// such as one cached from an earlier lookup.
+    ///
+    /// This avoids repeating the name lookup, and keeps working if the
+    /// interface is renamed. An index of zero is the "any" address which
+    /// can be used to read frames from all interfaces. A negative index is
+    /// an `InvalidInput` error.
+    pub fn from_ifindex(ifindex: c_int) -> io::Result<Self> {
+        if ifindex < 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "negative interface index",
+            ));
+        }
+        Ok(Self::new(ifindex as u32))
+    }
+
+    /// Creates the "any" address, with an interface index of zero, for
+    /// reading frames from all interfaces.
+    pub fn any() -> Self {
+        Self::new(0)
+    }
+
+    /// Gets the interface index of the address.
+    pub fn ifindex(&self) -> c_int {
+        self.0.can_ifindex
+    }
+
+    /// Determines if this is the "any" address, which is bound to all
+    /// interfaces.
+    pub fn is_any(&self) -> bool {
+        self.0.can_ifindex == 0
+    }
+
+    /// Gets the J1939 NAME of the address.
+    ///
+    /// This is only meaningful for a J1939 address.
+    pub fn j1939_name(&self) -> u64 {
+        unsafe { self.0.can_addr.j1939.name }
+    }
+
+    /// Gets the J1939 parameter group number (PGN) of the address.
+    ///
+    /// This is only meaningful for a J1939 address.
+    pub fn j1939_pgn(&self) -> u32 {
+        unsafe { self.0.can_addr.j1939.pgn }
+    }
+
+    /// Gets the J1939 source or destination address byte of the address.
+    ///
+    /// This is only meaningful for a J1939 address.
+    pub fn j1939_addr(&self) -> u8 {
+        unsafe { self.0.can_addr.j1939.addr }
+    }
+
+    /// Gets the CAN ID that an ISO-TP socket receives on.
+    ///
+    /// This is only meaningful for an ISO-TP address.
+    pub fn isotp_rx_id(&self) -> Id {
+        canid_t_to_id(unsafe { self.0.can_addr.tp.rx_id })
+    }
+
+    /// Gets the CAN ID that an ISO-TP socket transmits with.
+    ///
+    /// This is only meaningful for an ISO-TP address.
+    pub fn isotp_tx_id(&self) -> Id {
+        canid_t_to_id(unsafe { self.0.can_addr.tp.tx_id })
+    }
+
     /// Try to create an address from an interface name.
     pub fn from_iface(ifname: &str) -> io::Result<Self> {
         let ifindex = if_nametoindex(ifname)?;
         Ok(Self::new(ifindex))
     }
 
+    /// Gets the name of the interface for the address.
+    ///
+    /// This is an error for the "any" address, or if there's no longer an
+    /// interface with the index.
+    pub fn iface_name(&self) -> io::Result<String> {
+        // Note that nix's if_indextoname() doesn't detect a failed lookup,
+        // so this goes straight to libc.
+        let mut buf = [0 as c_char; libc::IF_NAMESIZE];
+        let ret = unsafe { libc::if_indextoname(self.0.can_ifindex as c_uint, buf.as_mut_ptr()) };
+
+        if ret.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        let name = unsafe { CStr::from_ptr(buf.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        Ok(name)
+    }
+
     /// Try to create a J1939 address from an interface name.
     pub fn from_iface_j1939(ifname: &str, name: u64, pgn: u32, jaddr: u8) -> io::Result<Self> {
         let mut addr = Self::from_iface(ifname)?;
@@ -157,6 +262,36 @@ impl From<sockaddr_can> for CanAddr {
     }
 }
 
+impl TryFrom<&SockAddr> for CanAddr {
+    type Error = io::Error;
+
+    /// Converts a generic socket address, such as one from `getsockname()`,
+    /// into a CAN address.
+    ///
+    /// The kernel may return an address that is shorter than the full
+    /// `sockaddr_can`, leaving off the protocol-specific parts that don't
+    /// apply to the socket. Those are left as zero. An address that isn't
+    /// from the CAN family is an `InvalidInput` error.
+    fn try_from(addr: &SockAddr) -> io::Result<Self> {
+        // The family and interface index, with padding
+        const MIN_LEN: usize = 8;
+
+        let len = addr.len() as usize;
+        if addr.family() != AF_CAN as sa_family_t || len < MIN_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a CAN socket address",
+            ));
+        }
+
+        let mut can_addr = Self::default();
+        let n = len.min(size_of::<sockaddr_can>());
+        let storage = addr.clone().as_storage();
+        crate::as_bytes_mut(&mut can_addr.0)[..n].copy_from_slice(&crate::as_bytes(&storage)[..n]);
+        Ok(can_addr)
+    }
+}
+
 impl From<CanAddr> for SockAddr {
     fn from(addr: CanAddr) -> Self {
         let (storage, len) = addr.into_storage();
@@ -173,6 +308,7 @@ impl AsRef<sockaddr_can> for CanAddr {
 /////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
+#[allow(clippy::clone_on_copy)]
 mod tests {
     use super::*;
     use crate::as_bytes;
@@ -186,6 +322,29 @@ mod tests {
         assert_eq!(size_of::<sockaddr_can>(), CanAddr::len());
     }
 
+    #[test]
+    fn test_addr_from_ifindex() {
+        let addr = CanAddr::from_ifindex(IDX as c_int).unwrap();
+        assert_eq!(addr.ifindex(), IDX as c_int);
+        assert!(!addr.is_any());
+
+        let addr = CanAddr::from_ifindex(0).unwrap();
+        assert!(addr.is_any());
+        assert!(CanAddr::any().is_any());
+
+        let err = CanAddr::from_ifindex(-1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_addr_j1939() {
+        let addr = CanAddr::new_j1939(IDX, 0x1234, 0xFEF1, 0x80);
+        assert_eq!(addr.ifindex(), IDX as c_int);
+        assert_eq!(addr.j1939_name(), 0x1234);
+        assert_eq!(addr.j1939_pgn(), 0xFEF1);
+        assert_eq!(addr.j1939_addr(), 0x80);
+    }
+
     #[test]
     fn test_addr_to_sock_addr() {
         let addr = CanAddr::new(IDX);
@@ -195,4 +354,23 @@ mod tests {
         assert_eq!(CanAddr::len() as socklen_t, len);
         assert_eq!(as_bytes(&addr), &as_bytes(&sock_addr)[0..len as usize]);
     }
+
+    #[test]
+    fn test_addr_from_sock_addr() {
+        let addr = CanAddr::new_isotp(IDX, StandardId::new(0x7E8).unwrap(), StandardId::ZERO);
+        let sock_addr = SockAddr::from(addr);
+
+        let can_addr = CanAddr::try_from(&sock_addr).unwrap();
+        assert_eq!(can_addr.ifindex(), IDX as c_int);
+        assert_eq!(as_bytes(&can_addr), as_bytes(&addr));
+        assert_eq!(
+            can_addr.isotp_rx_id(),
+            Id::from(StandardId::new(0x7E8).unwrap())
+        );
+        assert_eq!(can_addr.isotp_tx_id(), Id::from(StandardId::ZERO));
+
+        let sock_addr = SockAddr::from("127.0.0.1:0".parse::<std::net::SocketAddr>().unwrap());
+        let err = CanAddr::try_from(&sock_addr).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
 }
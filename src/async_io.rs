@@ -10,11 +10,21 @@
 // to those terms.
 
 //! Bindings to async-io for CANbus 2.0 and FD sockets using SocketCAN on Linux.
+//!
+//! Besides the `async` reads and writes, the sockets are a
+//! [`Stream`] of the frames received, and a [`Sink`] for the frames to
+//! send, to work with the `futures` combinators.
 
-use crate::{frame::AsPtr, CanAnyFrame, CanFrame, Socket, SocketOptions};
+use crate::{
+    errors::is_interface_gone, frame::AsPtr, CanAnyFrame, CanFrame, Error, Socket, SocketOptions,
+};
+use futures::{ready, Sink, Stream};
 use std::{
     io,
+    net::Shutdown,
     os::unix::io::{AsRawFd, RawFd},
+    pin::Pin,
+    task::{Context, Poll},
 };
 
 #[cfg(any(feature = "async-io", feature = "async-std"))]
@@ -53,10 +63,59 @@ impl CanSocket {
     pub async fn read_frame(&self) -> io::Result<CanFrame> {
         self.0.read_with(|fd| fd.read_frame()).await
     }
+
+    /// Shuts down the read, write, or both halves of the socket.
+    ///
+    /// See [`crate::CanSocket::shutdown()`] for the semantics on a CAN
+    /// socket.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.0.get_ref().shutdown(how)
+    }
 }
 
 impl SocketOptions for CanSocket {}
 
+/// The stream of frames ends, returning `None`, if the CAN interface goes
+/// away (see [`is_interface_gone`](crate::is_interface_gone)).
+impl Stream for CanSocket {
+    type Item = crate::Result<CanFrame>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.0.get_ref().read_frame() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.0.poll_readable(cx))?
+                }
+                // The interface is gone, so the stream is over
+                Err(err) if is_interface_gone(&err) => return Poll::Ready(None),
+                result => return Poll::Ready(Some(result.map_err(|e| e.into()))),
+            }
+        }
+    }
+}
+
+impl Sink<CanFrame> for CanSocket {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        ready!(self.0.poll_writable(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: CanFrame) -> crate::Result<()> {
+        self.0.get_ref().write_frame_insist(&item)?;
+        Ok(())
+    }
+}
+
 impl TryFrom<crate::CanSocket> for CanSocket {
     type Error = io::Error;
 
@@ -98,10 +157,59 @@ impl CanFdSocket {
     pub async fn read_frame(&self) -> io::Result<CanAnyFrame> {
         self.0.read_with(|fd| fd.read_frame()).await
     }
+
+    /// Shuts down the read, write, or both halves of the socket.
+    ///
+    /// See [`crate::CanFdSocket::shutdown()`] for the semantics on a CAN
+    /// socket.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.0.get_ref().shutdown(how)
+    }
 }
 
 impl SocketOptions for CanFdSocket {}
 
+/// The stream of frames ends, returning `None`, if the CAN interface goes
+/// away (see [`is_interface_gone`](crate::is_interface_gone)).
+impl Stream for CanFdSocket {
+    type Item = crate::Result<CanAnyFrame>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.0.get_ref().read_frame() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    ready!(self.0.poll_readable(cx))?
+                }
+                // The interface is gone, so the stream is over
+                Err(err) if is_interface_gone(&err) => return Poll::Ready(None),
+                result => return Poll::Ready(Some(result.map_err(|e| e.into()))),
+            }
+        }
+    }
+}
+
+impl Sink<CanAnyFrame> for CanFdSocket {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        ready!(self.0.poll_writable(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: CanAnyFrame) -> crate::Result<()> {
+        self.0.get_ref().write_frame_insist(&item)?;
+        Ok(())
+    }
+}
+
 impl TryFrom<crate::CanFdSocket> for CanFdSocket {
     type Error = io::Error;
 
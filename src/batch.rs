@@ -0,0 +1,378 @@
+// socketcan/src/batch.rs
+//
+// Implements a compact binary format for batches of CAN frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Binary batches of timestamped CAN frames.
+//!
+//! This is a compact, crate-native, alternative to the text candump format
+//! for logging high-rate captures. A [`FrameBatch`] accumulates frames and
+//! their timestamps, and serializes them into a single binary blob, which
+//! can be decoded back into `(Duration, CanAnyFrame)` pairs with a
+//! [`FrameBatchReader`].
+//!
+//! The blob starts with a 4-byte magic (`b"CANB"`) and a version byte,
+//! followed by the records. Each record is prefixed by its length, so
+//! records of types added in later versions can be skipped by readers
+//! that don't understand them. All the integers are little-endian.
+//!
+//! ```text
+//! u16   record length (not including itself)
+//! u8    record type (0 = classic, 1 = FD)
+//! u64   timestamp, in nanoseconds
+//! u32   ID word, including the EFF/RTR/ERR flags
+//! u8    FD flags (zero for classic frames)
+//! u8    DLC for classic frames, data length for FD frames
+//! [u8]  the data
+//! ```
+
+use crate::{
+    frame::{can_frame_default, canfd_frame_default, CAN_RTR_FLAG},
+    id::{CANFD_MAX_DLEN, CAN_MAX_DLEN},
+    CanAnyFrame, CanFdFrame, CanFrame, EmbeddedFrame, Frame,
+};
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+use thiserror::Error;
+
+/// The magic bytes at the start of a batch.
+pub const BATCH_MAGIC: [u8; 4] = *b"CANB";
+
+/// The current version of the batch format.
+pub const BATCH_VERSION: u8 = 1;
+
+// The size of the batch header (magic + version)
+const HEADER_SIZE: usize = BATCH_MAGIC.len() + 1;
+
+// The size of a record, not including the length prefix or data.
+const RECORD_SIZE: usize = 1 + 8 + 4 + 1 + 1;
+
+// The record types
+const RECORD_CLASSIC: u8 = 0;
+const RECORD_FD: u8 = 1;
+
+/// Errors decoding a frame batch.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchError {
+    /// The data doesn't start with the batch magic bytes
+    #[error("Not a frame batch")]
+    BadMagic,
+    /// The batch was written with a newer version of the format
+    #[error("Unsupported batch version: {0}")]
+    UnsupportedVersion(u8),
+    /// The batch ended in the middle of a record
+    #[error("Truncated batch")]
+    Truncated,
+    /// A record could not be decoded into a frame
+    #[error("Invalid frame record")]
+    InvalidFrame,
+}
+
+/// A batch of frames and their timestamps.
+#[derive(Debug, Default, Clone)]
+pub struct FrameBatch {
+    frames: Vec<(Duration, CanAnyFrame)>,
+}
+
+impl FrameBatch {
+    /// Creates a new, empty, batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new, empty, batch with space for `n` frames.
+    pub fn with_capacity(n: usize) -> Self {
+        Self {
+            frames: Vec::with_capacity(n),
+        }
+    }
+
+    /// Adds a frame to the batch.
+    ///
+    /// The timestamp is typically the time since the start of the capture
+    /// or since the UNIX epoch.
+    pub fn push<F>(&mut self, timestamp: Duration, frame: F)
+    where
+        F: Into<CanAnyFrame>,
+    {
+        self.frames.push((timestamp, frame.into()));
+    }
+
+    /// Gets the number of frames in the batch.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Determines if the batch is empty.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Removes all the frames from the batch.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Gets an iterator over the frames in the batch.
+    pub fn iter(&self) -> impl Iterator<Item = &(Duration, CanAnyFrame)> {
+        self.frames.iter()
+    }
+
+    /// Serializes the batch into a binary blob.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + self.len() * (2 + RECORD_SIZE + 8));
+        // Writing to a Vec can't fail
+        let _ = self.write_to(&mut buf);
+        buf
+    }
+
+    /// Serializes the batch to a writer.
+    pub fn write_to<W: Write>(&self, mut wr: W) -> io::Result<()> {
+        wr.write_all(&BATCH_MAGIC)?;
+        wr.write_all(&[BATCH_VERSION])?;
+
+        for (ts, frame) in &self.frames {
+            use CanAnyFrame::*;
+            let (kind, flags, dlc) = match frame {
+                Normal(f) => (RECORD_CLASSIC, 0, f.as_ref().can_dlc),
+                Remote(f) => (RECORD_CLASSIC, 0, f.as_ref().can_dlc),
+                Error(f) => (RECORD_CLASSIC, 0, f.as_ref().can_dlc),
+                Fd(f) => (RECORD_FD, f.flags().bits(), f.len() as u8),
+            };
+            let data = frame.data();
+            let ts_ns = u64::try_from(ts.as_nanos()).unwrap_or(u64::MAX);
+
+            wr.write_all(&((RECORD_SIZE + data.len()) as u16).to_le_bytes())?;
+            wr.write_all(&[kind])?;
+            wr.write_all(&ts_ns.to_le_bytes())?;
+            wr.write_all(&frame.id_word().to_le_bytes())?;
+            wr.write_all(&[flags, dlc])?;
+            wr.write_all(data)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a batch from a binary blob.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, BatchError> {
+        let frames = FrameBatchReader::new(buf)?.collect::<Result<_, _>>()?;
+        Ok(Self { frames })
+    }
+}
+
+impl IntoIterator for FrameBatch {
+    type Item = (Duration, CanAnyFrame);
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.frames.into_iter()
+    }
+}
+
+impl<F: Into<CanAnyFrame>> Extend<(Duration, F)> for FrameBatch {
+    fn extend<T: IntoIterator<Item = (Duration, F)>>(&mut self, iter: T) {
+        self.frames
+            .extend(iter.into_iter().map(|(ts, frame)| (ts, frame.into())));
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+/// A reader to decode the frames from a binary batch.
+///
+/// This is an iterator over the records in the batch, returning the
+/// timestamp and frame for each. Records of unknown types, from a later
+/// version of the format, are skipped.
+#[derive(Debug)]
+pub struct FrameBatchReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> FrameBatchReader<'a> {
+    /// Creates a reader for the batch, checking the header.
+    pub fn new(buf: &'a [u8]) -> Result<Self, BatchError> {
+        if buf.len() < HEADER_SIZE || buf[..BATCH_MAGIC.len()] != BATCH_MAGIC {
+            return Err(BatchError::BadMagic);
+        }
+        match buf[BATCH_MAGIC.len()] {
+            BATCH_VERSION => Ok(Self {
+                buf: &buf[HEADER_SIZE..],
+            }),
+            ver => Err(BatchError::UnsupportedVersion(ver)),
+        }
+    }
+
+    /// Decodes the next record, returning `None` if it's of an unknown type.
+    fn next_record(&mut self) -> Result<Option<(Duration, CanAnyFrame)>, BatchError> {
+        if self.buf.len() < 2 {
+            return Err(BatchError::Truncated);
+        }
+        let len = u16::from_le_bytes([self.buf[0], self.buf[1]]) as usize;
+
+        let rec = self.buf.get(2..2 + len).ok_or(BatchError::Truncated)?;
+        self.buf = &self.buf[2 + len..];
+
+        if len < RECORD_SIZE {
+            return Err(BatchError::InvalidFrame);
+        }
+
+        let kind = rec[0];
+        let ts_ns = u64::from_le_bytes(rec[1..9].try_into().unwrap());
+        let id_word = u32::from_le_bytes(rec[9..13].try_into().unwrap());
+        let flags = rec[13];
+        let dlc = rec[14];
+        let data = &rec[RECORD_SIZE..];
+
+        let frame = match kind {
+            RECORD_CLASSIC => {
+                // Remote frames have a DLC but no data
+                let is_remote = id_word & CAN_RTR_FLAG != 0;
+                if data.len() > CAN_MAX_DLEN
+                    || (!is_remote && data.len() != (dlc as usize).min(CAN_MAX_DLEN))
+                {
+                    return Err(BatchError::InvalidFrame);
+                }
+                let mut frame = can_frame_default();
+                frame.can_id = id_word;
+                frame.can_dlc = dlc;
+                frame.data[..data.len()].copy_from_slice(data);
+                CanFrame::from(frame).into()
+            }
+            RECORD_FD => {
+                if data.len() > CANFD_MAX_DLEN
+                    || data.len() != dlc as usize
+                    || !CanFdFrame::is_valid_data_len(data.len())
+                {
+                    return Err(BatchError::InvalidFrame);
+                }
+                let mut frame = canfd_frame_default();
+                frame.can_id = id_word;
+                frame.flags = flags;
+                frame.len = dlc;
+                frame.data[..data.len()].copy_from_slice(data);
+                CanFdFrame::from(frame).into()
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some((Duration::from_nanos(ts_ns), frame)))
+    }
+}
+
+impl Iterator for FrameBatchReader<'_> {
+    type Item = Result<(Duration, CanAnyFrame), BatchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.buf.is_empty() {
+            match self.next_record() {
+                Ok(Some(rec)) => return Some(Ok(rec)),
+                Ok(None) => continue,
+                Err(err) => {
+                    // Don't keep going after an error
+                    self.buf = &[];
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanDataFrame, CanErrorFrame, CanRemoteFrame};
+    use embedded_can::{ExtendedId, Id, StandardId};
+
+    const STD_ID: Id = Id::Standard(StandardId::MAX);
+    const EXT_ID: Id = Id::Extended(ExtendedId::MAX);
+
+    fn sample_batch() -> FrameBatch {
+        let mut batch = FrameBatch::new();
+        batch.push(
+            Duration::from_micros(1),
+            CanDataFrame::new(STD_ID, &[1, 2, 3]).unwrap(),
+        );
+        batch.push(
+            Duration::from_micros(2),
+            CanRemoteFrame::new_remote(EXT_ID, 4).unwrap(),
+        );
+        batch.push(
+            Duration::from_micros(3),
+            CanErrorFrame::new_error(0x04, &[0, 0x04]).unwrap(),
+        );
+        let mut fdframe = CanFdFrame::new(EXT_ID, &[0xAA; 24]).unwrap();
+        fdframe.set_brs(true);
+        batch.push(Duration::from_secs(1_735_270_496), fdframe);
+        batch
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let batch = sample_batch();
+        let buf = batch.to_bytes();
+        assert_eq!(&buf[..4], &BATCH_MAGIC);
+        assert_eq!(buf[4], BATCH_VERSION);
+
+        let decoded = FrameBatch::from_bytes(&buf).unwrap();
+        assert_eq!(decoded.len(), batch.len());
+
+        for ((ts, frame), (dts, dframe)) in batch.iter().zip(decoded.iter()) {
+            assert_eq!(ts, dts);
+            assert_eq!(frame.id_word(), dframe.id_word());
+            assert_eq!(frame.dlc(), dframe.dlc());
+            assert_eq!(frame.data(), dframe.data());
+            assert_eq!(
+                std::mem::discriminant(frame),
+                std::mem::discriminant(dframe)
+            );
+        }
+
+        let fdframe = decoded.iter().last().unwrap().1.as_fd().copied().unwrap();
+        assert!(fdframe.is_brs());
+    }
+
+    #[test]
+    fn test_bad_batches() {
+        assert!(matches!(
+            FrameBatch::from_bytes(b"CANX\x01"),
+            Err(BatchError::BadMagic)
+        ));
+        assert!(matches!(
+            FrameBatch::from_bytes(b"CANB\x09"),
+            Err(BatchError::UnsupportedVersion(9))
+        ));
+
+        let buf = sample_batch().to_bytes();
+        assert!(matches!(
+            FrameBatch::from_bytes(&buf[..buf.len() - 1]),
+            Err(BatchError::Truncated)
+        ));
+
+        // An empty batch is fine
+        assert!(FrameBatch::from_bytes(&FrameBatch::new().to_bytes())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unknown_record_skipped() {
+        let mut buf = FrameBatch::new().to_bytes();
+        // A record of some future type
+        buf.extend_from_slice(&(RECORD_SIZE as u16).to_le_bytes());
+        buf.push(0x7F);
+        buf.extend_from_slice(&[0; RECORD_SIZE - 1]);
+        buf.extend_from_slice(&sample_batch().to_bytes()[HEADER_SIZE..]);
+
+        let decoded = FrameBatch::from_bytes(&buf).unwrap();
+        assert_eq!(decoded.len(), sample_batch().len());
+    }
+}
@@ -0,0 +1,708 @@
+// socketcan/src/bcm.rs
+//
+// Sockets for the kernel CAN Broadcast Manager (BCM).
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! CAN Broadcast Manager (BCM) sockets.
+//!
+//! The broadcast manager is a kernel protocol (`CAN_BCM`) that takes care
+//! of periodic traffic on behalf of an application. A [`CanBcmSocket`] can
+//! register cyclic transmission jobs, which the kernel then sends on its
+//! own timers, with much less jitter than a timer loop in userspace.
+//!
+//! It can also subscribe to frames received from the bus, with an
+//! [`RxJob`]. The kernel filters them by content, so that the application
+//! only hears about a frame when its data changes, and it can watch for a
+//! periodic frame that stops arriving. These notifications are read from
+//! the socket as a [`BcmMsg`].
+//!
+//! A job can also carry a multiplexed message, which is a set of frames
+//! with the same CAN ID, told apart by some bits of their data. The kernel
+//! sends them in rotation, one each interval, and can watch the frames
+//! received for changes in each of them separately.
+//!
+//! Each job is identified by its CAN ID, and a socket can have any number
+//! of them. Setting up a job again, with the same ID, updates it in place,
+//! and all of a socket's jobs are removed when it's closed.
+//!
+//! ```no_run
+//! use socketcan::{bcm::{CanBcmSocket, TxJob}, CanFrame, EmbeddedFrame, StandardId};
+//! use std::time::Duration;
+//!
+//! let sock = CanBcmSocket::open("vcan0").unwrap();
+//!
+//! let id = StandardId::new(0x100).unwrap();
+//! let frame = CanFrame::new(id, &[1, 2, 3]).unwrap();
+//! sock.tx_setup(&TxJob::new(frame, Duration::from_millis(100))).unwrap();
+//!
+//! // ...
+//!
+//! sock.tx_delete(id).unwrap();
+//! ```
+//!
+//! The definitions are in the kernel header:
+//!
+//! <https://github.com/torvalds/linux/blob/master/include/uapi/linux/can/bcm.h?ts=4>
+
+use crate::{
+    as_bytes,
+    id::id_to_canid_t,
+    socket::{open_socket, recv_checked},
+    CanAddr, CanFrame, SocketError,
+};
+use embedded_can::{ExtendedId, Id, StandardId};
+use libc::{can_frame, canid_t, CAN_BCM, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_FD_FRAME, CAN_SFF_MASK};
+use std::{
+    io::{self, Write},
+    mem::{self, size_of},
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd},
+    ptr,
+    time::Duration,
+};
+
+pub use libc::{
+    RX_ANNOUNCE_RESUME, RX_CHANGED, RX_CHECK_DLC, RX_DELETE, RX_FILTER_ID, RX_READ, RX_SETUP,
+    RX_STATUS, RX_TIMEOUT, SETTIMER, STARTTIMER, TX_ANNOUNCE, TX_COUNTEVT, TX_CP_CAN_ID, TX_DELETE,
+    TX_EXPIRED, TX_READ, TX_SEND, TX_SETUP, TX_STATUS,
+};
+
+/// The most frames the kernel puts in a single BCM message
+const MAX_NFRAMES: usize = 256;
+
+/// Converts a duration into the timeval used by the BCM.
+fn bcm_timeval(dur: Duration) -> libc::bcm_timeval {
+    libc::bcm_timeval {
+        tv_sec: dur.as_secs() as libc::c_long,
+        tv_usec: dur.subsec_micros() as libc::c_long,
+    }
+}
+
+/// Creates a BCM message header for the operation on the CAN ID.
+fn bcm_msg_head(opcode: u32, can_id: canid_t) -> libc::bcm_msg_head {
+    let mut head: libc::bcm_msg_head = unsafe { mem::zeroed() };
+    head.opcode = opcode;
+    head.can_id = can_id;
+    head
+}
+
+/// Gets the ID from a `canid_t` value, as the kernel fills it in.
+fn canid_t_to_id(can_id: canid_t) -> Id {
+    match can_id & CAN_EFF_FLAG {
+        0 => StandardId::new((can_id & CAN_SFF_MASK) as u16)
+            .unwrap()
+            .into(),
+        _ => ExtendedId::new(can_id & CAN_EFF_MASK).unwrap().into(),
+    }
+}
+
+/// Checks that the number of frames for a job is one the kernel accepts.
+fn check_nframes(n: usize) -> Result<(), SocketError> {
+    match n {
+        1..=MAX_NFRAMES => Ok(()),
+        got => Err(SocketError::BcmFrameCount {
+            got,
+            max: MAX_NFRAMES,
+        }),
+    }
+}
+
+/// Sets a flag bit on or off.
+fn set_flag(flags: &mut u32, flag: u32, on: bool) {
+    match on {
+        true => *flags |= flag,
+        false => *flags &= !flag,
+    }
+}
+
+// ===== TxJob =====
+
+/// A cyclic transmission job for the broadcast manager.
+///
+/// The kernel sends the frame every `interval`, indefinitely. Optionally,
+/// it can first be sent a number of times at a different interval, after
+/// which it carries on at the main one.
+///
+/// For a multiplexed message, the job has a schedule of frames, and the
+/// kernel sends the next one in turn each interval, going back to the
+/// first after the last.
+#[derive(Debug, Clone)]
+pub struct TxJob {
+    frames: Vec<CanFrame>,
+    count: u32,
+    ival1: Duration,
+    ival2: Duration,
+    flags: u32,
+}
+
+impl TxJob {
+    /// Creates a job that sends the frame every `interval`.
+    pub fn new(frame: CanFrame, interval: Duration) -> Self {
+        Self {
+            frames: vec![frame],
+            count: 0,
+            ival1: Duration::ZERO,
+            ival2: interval,
+            flags: 0,
+        }
+    }
+
+    /// Creates a job for a multiplexed message, that sends the frames in
+    /// rotation, one every `interval`.
+    ///
+    /// The frames must all have the same CAN ID, and there can be up to
+    /// 256 of them. Otherwise, this fails with an `InvalidInput` error
+    /// wrapping the [`SocketError`].
+    pub fn multiplex(frames: Vec<CanFrame>, interval: Duration) -> io::Result<Self> {
+        check_nframes(frames.len())?;
+        let id = frames[0].id();
+        if frames.iter().any(|frame| frame.id() != id) {
+            return Err(SocketError::BcmMixedIds.into());
+        }
+
+        let frame = frames[0];
+        Ok(Self {
+            frames,
+            ..Self::new(frame, interval)
+        })
+    }
+
+    /// Sends the frame `count` times at `interval` first, before carrying
+    /// on at the job's main interval.
+    ///
+    /// If the main interval is zero, the job stops after `count` frames.
+    pub fn initial(mut self, count: u32, interval: Duration) -> Self {
+        self.count = count;
+        self.ival1 = interval;
+        self
+    }
+
+    /// Sends the frame immediately when the job is set up, or updated,
+    /// rather than waiting for the first interval to elapse.
+    pub fn announce(mut self, on: bool) -> Self {
+        set_flag(&mut self.flags, TX_ANNOUNCE, on);
+        self
+    }
+
+    /// Has the kernel send a [`BcmMsg::TxExpired`] notification when the
+    /// initial count of frames has been sent.
+    pub fn notify_expired(mut self, on: bool) -> Self {
+        set_flag(&mut self.flags, TX_COUNTEVT, on);
+        self
+    }
+
+    /// Gets the frames of the job, in the order they are sent.
+    pub fn frames(&self) -> &[CanFrame] {
+        &self.frames
+    }
+
+    /// Gets the CAN ID of the job, as the kernel identifies it.
+    pub fn can_id(&self) -> canid_t {
+        id_to_canid_t(self.frames[0].id())
+    }
+}
+
+// ===== RxJob =====
+
+/// A subscription to the frames received with a CAN ID.
+///
+/// By default, every frame with the ID is passed up to the socket. With a
+/// data mask, a frame is only passed up when the bits under the mask have
+/// changed from the previous frame. A timeout reports a periodic frame
+/// that stops arriving.
+///
+/// For a multiplexed message, the frames are sorted by the multiplex bits
+/// of their data, and the changes are tracked for each of them.
+#[derive(Debug, Clone)]
+pub struct RxJob {
+    can_id: canid_t,
+    // The data mask, or the multiplex mask and data masks, for the kernel
+    masks: Vec<[u8; 8]>,
+    timeout: Duration,
+    throttle: Duration,
+    flags: u32,
+}
+
+impl RxJob {
+    /// Creates a subscription to all the frames with the ID.
+    pub fn new(id: impl Into<Id>) -> Self {
+        Self {
+            can_id: id_to_canid_t(id),
+            masks: Vec::new(),
+            timeout: Duration::ZERO,
+            throttle: Duration::ZERO,
+            flags: 0,
+        }
+    }
+
+    /// Only reports a frame when the data bits under the mask differ from
+    /// those of the last frame received.
+    ///
+    /// The first frame received is always reported.
+    pub fn data_mask(mut self, mask: [u8; 8]) -> Self {
+        self.masks = vec![mask];
+        self
+    }
+
+    /// Watches a multiplexed message for changes.
+    ///
+    /// The `mux_mask` picks out the bits of the data that tell the frames
+    /// of the message apart. Each of the `masks` has the value of those
+    /// bits for one of the frames, and the rest of the bits set where
+    /// changes are to be reported to the socket. Frames that match none
+    /// of them are dropped.
+    ///
+    /// There can be up to 255 masks. Otherwise, this fails with an
+    /// `InvalidInput` error wrapping the [`SocketError`].
+    pub fn multiplex(mut self, mux_mask: [u8; 8], masks: &[[u8; 8]]) -> io::Result<Self> {
+        if masks.is_empty() {
+            return Err(SocketError::BcmFrameCount {
+                got: 0,
+                max: MAX_NFRAMES - 1,
+            }
+            .into());
+        }
+        check_nframes(masks.len() + 1)?;
+
+        self.masks = Vec::with_capacity(masks.len() + 1);
+        self.masks.push(mux_mask);
+        self.masks.extend_from_slice(masks);
+        Ok(self)
+    }
+
+    /// Also reports a frame when its data length changes.
+    ///
+    /// This only applies with a data mask.
+    pub fn check_dlc(mut self, on: bool) -> Self {
+        set_flag(&mut self.flags, RX_CHECK_DLC, on);
+        self
+    }
+
+    /// Sends a [`BcmMsg::RxTimeout`] notification if no frame has been
+    /// received within the timeout.
+    ///
+    /// The timer is restarted by each frame, so a periodic frame that
+    /// stops arriving is reported once.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limits the rate of notifications for changed frames to, at most,
+    /// one for each `interval`.
+    ///
+    /// When the interval elapses, the latest change is reported.
+    pub fn throttle(mut self, interval: Duration) -> Self {
+        self.throttle = interval;
+        self
+    }
+
+    /// Reports the first frame after a timeout, even if its data is the
+    /// same as the frame before it.
+    pub fn announce_resume(mut self, on: bool) -> Self {
+        set_flag(&mut self.flags, RX_ANNOUNCE_RESUME, on);
+        self
+    }
+
+    /// Gets the CAN ID of the subscription, as the kernel identifies it.
+    pub fn can_id(&self) -> canid_t {
+        self.can_id
+    }
+
+    // The frames carrying the data masks, given to the kernel
+    fn mask_frames(&self) -> Vec<CanFrame> {
+        self.masks
+            .iter()
+            .map(|&mask| {
+                let mut frame: can_frame = unsafe { mem::zeroed() };
+                frame.can_id = self.can_id;
+                frame.can_dlc = 8;
+                frame.data = mask;
+                CanFrame::from(frame)
+            })
+            .collect()
+    }
+}
+
+// ===== BcmMsg =====
+
+/// A message received from the broadcast manager.
+#[derive(Debug, Clone)]
+pub enum BcmMsg {
+    /// A frame for a subscription, that's new or has changed
+    RxChanged(CanFrame),
+    /// The frames for a subscription stopped arriving within its timeout
+    RxTimeout(Id),
+    /// A transmission job finished sending its initial count of frames
+    TxExpired(Id),
+    /// The setup of a transmission job, in reply to
+    /// [`CanBcmSocket::tx_read()`]
+    TxStatus {
+        /// The CAN ID of the job
+        id: Id,
+        /// The frames of the job
+        frames: Vec<CanFrame>,
+    },
+    /// The setup of a subscription, in reply to [`CanBcmSocket::rx_read()`]
+    RxStatus {
+        /// The CAN ID of the subscription
+        id: Id,
+        /// The frames with the data masks, if any. For a multiplexed
+        /// message, the first is the multiplex mask.
+        frames: Vec<CanFrame>,
+    },
+    /// Any other message, with its opcode, ID, and frames
+    Other {
+        /// The BCM operation
+        opcode: u32,
+        /// The CAN ID of the job
+        id: Id,
+        /// The frames in the message
+        frames: Vec<CanFrame>,
+    },
+}
+
+impl BcmMsg {
+    /// Parses a message, as read from a BCM socket.
+    fn from_bytes(buf: &[u8]) -> io::Result<Self> {
+        let head_len = size_of::<libc::bcm_msg_head>();
+        if buf.len() < head_len {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let head: libc::bcm_msg_head = unsafe { ptr::read_unaligned(buf.as_ptr().cast()) };
+
+        // CAN FD frames are only sent for jobs set up with them
+        if head.flags & CAN_FD_FRAME != 0 {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+
+        let frames = buf[head_len..]
+            .chunks_exact(size_of::<can_frame>())
+            .take(head.nframes as usize)
+            .map(|b| CanFrame::from(unsafe { ptr::read_unaligned(b.as_ptr().cast::<can_frame>()) }))
+            .collect::<Vec<_>>();
+        if frames.len() != head.nframes as usize {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+
+        let id = canid_t_to_id(head.can_id);
+        let msg = match head.opcode {
+            RX_CHANGED if frames.len() == 1 => Self::RxChanged(frames[0]),
+            RX_TIMEOUT => Self::RxTimeout(id),
+            TX_EXPIRED => Self::TxExpired(id),
+            TX_STATUS => Self::TxStatus { id, frames },
+            RX_STATUS => Self::RxStatus { id, frames },
+            opcode => Self::Other { opcode, id, frames },
+        };
+        Ok(msg)
+    }
+}
+
+// ===== CanBcmSocket =====
+
+/// A socket for the kernel CAN Broadcast Manager (BCM).
+///
+/// This requires the `can-bcm` kernel module.
+#[allow(missing_copy_implementations)]
+#[derive(Debug)]
+pub struct CanBcmSocket(socket2::Socket);
+
+impl CanBcmSocket {
+    /// Opens a BCM socket on the named interface.
+    pub fn open(ifname: &str) -> io::Result<Self> {
+        let addr = CanAddr::from_iface(ifname)?;
+        Self::open_addr(&addr)
+    }
+
+    /// Opens a BCM socket on the interface of the address.
+    pub fn open_addr(addr: &CanAddr) -> io::Result<Self> {
+        open_socket(addr, CAN_BCM).map(Self)
+    }
+
+    /// Gets a shared reference to the underlying socket object
+    pub fn as_raw_socket(&self) -> &socket2::Socket {
+        &self.0
+    }
+
+    /// Sets up a cyclic transmission job, or updates the one with the same
+    /// CAN ID.
+    ///
+    /// The job's timers are (re)started, so an update restarts the cycle.
+    pub fn tx_setup(&self, job: &TxJob) -> io::Result<()> {
+        let mut head = bcm_msg_head(TX_SETUP, job.can_id());
+        head.flags = job.flags | SETTIMER | STARTTIMER;
+        head.count = job.count;
+        head.ival1 = bcm_timeval(job.ival1);
+        head.ival2 = bcm_timeval(job.ival2);
+        self.send_msg(head, &job.frames)
+    }
+
+    /// Asks for the setup of the transmission job with the CAN ID.
+    ///
+    /// The kernel replies with a [`BcmMsg::TxStatus`], which is read with
+    /// [`CanBcmSocket::read_msg()`].
+    pub fn tx_read(&self, id: impl Into<Id>) -> io::Result<()> {
+        self.send_msg(bcm_msg_head(TX_READ, id_to_canid_t(id)), &[])
+    }
+
+    /// Removes the cyclic transmission job with the CAN ID, which stops
+    /// it being sent.
+    ///
+    /// This fails with an `EINVAL` error if there's no such job.
+    pub fn tx_delete(&self, id: impl Into<Id>) -> io::Result<()> {
+        self.send_msg(bcm_msg_head(TX_DELETE, id_to_canid_t(id)), &[])
+    }
+
+    /// Subscribes to the frames received with an ID, or updates the
+    /// subscription with the same CAN ID.
+    pub fn rx_setup(&self, job: &RxJob) -> io::Result<()> {
+        let mut head = bcm_msg_head(RX_SETUP, job.can_id);
+        head.flags = job.flags | SETTIMER | STARTTIMER;
+        head.ival1 = bcm_timeval(job.timeout);
+        head.ival2 = bcm_timeval(job.throttle);
+
+        let frames = job.mask_frames();
+        if frames.is_empty() {
+            head.flags |= RX_FILTER_ID;
+        }
+        self.send_msg(head, &frames)
+    }
+
+    /// Asks for the setup of the subscription to the CAN ID.
+    ///
+    /// The kernel replies with a [`BcmMsg::RxStatus`], which is read with
+    /// [`CanBcmSocket::read_msg()`].
+    pub fn rx_read(&self, id: impl Into<Id>) -> io::Result<()> {
+        self.send_msg(bcm_msg_head(RX_READ, id_to_canid_t(id)), &[])
+    }
+
+    /// Removes the subscription to the frames with the CAN ID.
+    ///
+    /// This fails with an `EINVAL` error if there's no such subscription.
+    pub fn rx_delete(&self, id: impl Into<Id>) -> io::Result<()> {
+        self.send_msg(bcm_msg_head(RX_DELETE, id_to_canid_t(id)), &[])
+    }
+
+    /// Reads the next message from the broadcast manager.
+    ///
+    /// This blocks until a message arrives, unless the socket is in
+    /// non-blocking mode or has a read timeout.
+    pub fn read_msg(&self) -> io::Result<BcmMsg> {
+        let mut buf =
+            vec![0u8; size_of::<libc::bcm_msg_head>() + MAX_NFRAMES * size_of::<can_frame>()];
+        let n = recv_checked(self.as_raw_fd(), &mut buf)?;
+        BcmMsg::from_bytes(&buf[..n])
+    }
+
+    /// Sends a single frame, once, through the broadcast manager.
+    pub fn tx_send(&self, frame: &CanFrame) -> io::Result<()> {
+        let head = bcm_msg_head(TX_SEND, id_to_canid_t(frame.id()));
+        self.send_msg(head, &[*frame])
+    }
+
+    // Sends a BCM message, which is the header, followed by the frames.
+    fn send_msg(&self, mut head: libc::bcm_msg_head, frames: &[CanFrame]) -> io::Result<()> {
+        head.nframes = frames.len() as u32;
+
+        let mut msg = as_bytes(&head).to_vec();
+        for frame in frames {
+            msg.extend_from_slice(as_bytes::<can_frame>(frame.as_ref()));
+        }
+
+        match (&self.0).write(&msg)? {
+            n if n == msg.len() => Ok(()),
+            _ => Err(io::ErrorKind::WriteZero.into()),
+        }
+    }
+
+    /// Change socket to non-blocking mode or back to blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.0.set_nonblocking(nonblocking)
+    }
+
+    /// Sets the read timeout on the socket
+    pub fn set_read_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.0.set_read_timeout(duration.into())
+    }
+
+    /// Sets the write timeout on the socket
+    pub fn set_write_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.0.set_write_timeout(duration.into())
+    }
+}
+
+impl AsRawFd for CanBcmSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsFd for CanBcmSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl IntoRawFd for CanBcmSocket {
+    fn into_raw_fd(self) -> RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for CanBcmSocket {
+    fn from(fd: OwnedFd) -> Self {
+        Self(socket2::Socket::from(fd))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmbeddedFrame, ExtendedId, StandardId};
+
+    #[test]
+    fn test_tx_job() {
+        let id = StandardId::new(0x100).unwrap();
+        let frame = CanFrame::new(id, &[1, 2, 3]).unwrap();
+
+        let job = TxJob::new(frame, Duration::from_millis(100))
+            .initial(5, Duration::from_millis(10))
+            .announce(true);
+        assert_eq!(job.can_id(), 0x100);
+        assert_eq!(job.count, 5);
+        assert_eq!(job.flags, TX_ANNOUNCE);
+        assert!(job.announce(false).flags == 0);
+
+        let id = ExtendedId::new(0x12345).unwrap();
+        let frame = CanFrame::new(id, &[]).unwrap();
+        let job = TxJob::new(frame, Duration::from_secs(1));
+        assert_eq!(job.can_id(), 0x12345 | CAN_EFF_FLAG);
+    }
+
+    #[test]
+    fn test_rx_job() {
+        let id = StandardId::new(0x100).unwrap();
+
+        let job = RxJob::new(id);
+        assert!(job.mask_frames().is_empty());
+
+        let job = RxJob::new(id).data_mask([0xFF, 0, 0, 0, 0, 0, 0, 0x0F]);
+        let frames = job.mask_frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].id(), Id::from(id));
+        assert_eq!(frames[0].data(), &[0xFF, 0, 0, 0, 0, 0, 0, 0x0F]);
+
+        let job = job.check_dlc(true).announce_resume(true);
+        assert_eq!(job.flags, RX_CHECK_DLC | RX_ANNOUNCE_RESUME);
+    }
+
+    #[test]
+    fn test_multiplex() {
+        let id = StandardId::new(0x100).unwrap();
+        let frames: Vec<_> = (0..3)
+            .map(|i| CanFrame::new(id, &[i, 0x10 * i]).unwrap())
+            .collect();
+
+        let job = TxJob::multiplex(frames.clone(), Duration::from_millis(10)).unwrap();
+        assert_eq!(job.frames().len(), 3);
+        assert_eq!(job.frames()[2].data(), &[2, 0x20]);
+        assert_eq!(job.can_id(), 0x100);
+
+        assert!(TxJob::multiplex(Vec::new(), Duration::ZERO).is_err());
+
+        let other = CanFrame::new(StandardId::new(0x101).unwrap(), &[]).unwrap();
+        let err = TxJob::multiplex(vec![frames[0], other], Duration::ZERO).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let mux_mask = [0xFF, 0, 0, 0, 0, 0, 0, 0];
+        let job = RxJob::new(id)
+            .multiplex(
+                mux_mask,
+                &[
+                    [0x01, 0xFF, 0, 0, 0, 0, 0, 0],
+                    [0x02, 0, 0xFF, 0, 0, 0, 0, 0],
+                ],
+            )
+            .unwrap();
+        let masks = job.mask_frames();
+        assert_eq!(masks.len(), 3);
+        assert_eq!(masks[0].data(), &mux_mask);
+        assert_eq!(masks[2].data()[..3], [0x02, 0, 0xFF]);
+
+        assert!(RxJob::new(id).multiplex(mux_mask, &[]).is_err());
+        assert!(RxJob::new(id).multiplex(mux_mask, &[[0; 8]; 256]).is_err());
+    }
+
+    #[test]
+    fn test_msg_from_bytes() {
+        let head_len = size_of::<libc::bcm_msg_head>();
+
+        let id = ExtendedId::new(0x12345).unwrap();
+        let frame = CanFrame::new(id, &[1, 2, 3]).unwrap();
+
+        let mut head = bcm_msg_head(RX_CHANGED, id_to_canid_t(id));
+        head.nframes = 1;
+        let mut buf = as_bytes(&head).to_vec();
+        buf.extend_from_slice(as_bytes::<can_frame>(frame.as_ref()));
+        match BcmMsg::from_bytes(&buf).unwrap() {
+            BcmMsg::RxChanged(rx_frame) => {
+                assert_eq!(rx_frame.id(), frame.id());
+                assert_eq!(rx_frame.data(), frame.data());
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+
+        // The frame is missing
+        assert!(BcmMsg::from_bytes(&buf[..head_len]).is_err());
+
+        let buf = as_bytes(&bcm_msg_head(RX_TIMEOUT, id_to_canid_t(id))).to_vec();
+        assert!(matches!(
+            BcmMsg::from_bytes(&buf).unwrap(),
+            BcmMsg::RxTimeout(Id::Extended(rx_id)) if rx_id == id
+        ));
+
+        let buf = as_bytes(&bcm_msg_head(TX_EXPIRED, 0x100)).to_vec();
+        assert!(matches!(
+            BcmMsg::from_bytes(&buf).unwrap(),
+            BcmMsg::TxExpired(Id::Standard(rx_id)) if rx_id.as_raw() == 0x100
+        ));
+
+        assert!(BcmMsg::from_bytes(&buf[..8]).is_err());
+
+        // A status reply with the frames of a multiplexed job
+        let mut head = bcm_msg_head(TX_STATUS, id_to_canid_t(id));
+        head.nframes = 3;
+        let mut buf = as_bytes(&head).to_vec();
+        for i in 0..3 {
+            let frame = CanFrame::new(id, &[i]).unwrap();
+            buf.extend_from_slice(as_bytes::<can_frame>(frame.as_ref()));
+        }
+        match BcmMsg::from_bytes(&buf).unwrap() {
+            BcmMsg::TxStatus { id: rx_id, frames } => {
+                assert_eq!(rx_id, Id::from(id));
+                assert_eq!(frames.len(), 3);
+                assert_eq!(frames[2].data(), &[2]);
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn test_bcm_timeval() {
+        let tv = bcm_timeval(Duration::from_micros(1_250_500));
+        assert_eq!((tv.tv_sec, tv.tv_usec), (1, 250_500));
+    }
+}
@@ -0,0 +1,24 @@
+// socketcan/src/bridge/mod.rs
+//
+// Bridges between local CAN interfaces and other transports.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Bridges between local CAN interfaces and other transports.
+//!
+//! A bridge reads the frames from a local socket and sends them on, over
+//! another transport, to be re-injected into an interface on the other
+//! side. This is useful for spreading the traffic of a bus across several
+//! machines, as in hardware-in-the-loop test rigs.
+//!
+//! - [`udp`] sends each frame in a UDP datagram, unicast or multicast,
+//!   with a sequence number and an optional timestamp.
+//!
+//! For a bridge to a `cannelloni` peer, see [`crate::cannelloni`].
+
+pub mod udp;
@@ -0,0 +1,433 @@
+// socketcan/src/bridge/udp.rs
+//
+// A bridge of CAN frames over UDP.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A bridge of CAN frames over UDP.
+//!
+//! A [`UdpBridgeSender`] reads the frames from a local interface and sends
+//! each one in a datagram, to a unicast or multicast address. Any number
+//! of [`UdpBridgeReceiver`]s write them to interfaces of their own. Each
+//! datagram carries a sequence number, so that a receiver can count the
+//! frames lost on the way, and drop those that arrive late, and can carry
+//! the time at which the frame was received. The sequence is tracked for
+//! each sender, by its address, and a sender that restarts from zero is
+//! picked up again, rather than having all of its messages dropped as
+//! late.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     bridge::udp::{bind_multicast, UdpBridgeReceiver, UdpBridgeSender},
+//!     CanFdSocket, Socket,
+//! };
+//! use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+//!
+//! let group = SocketAddrV4::new(Ipv4Addr::new(239, 0, 0, 42), 30000);
+//!
+//! // On the machine with the bus
+//! let udp = UdpSocket::bind("0.0.0.0:0").unwrap();
+//! let mut tx = UdpBridgeSender::new(CanFdSocket::open("can0").unwrap(), udp, group.into());
+//! tx.set_timestamps(true).unwrap();
+//! std::thread::spawn(move || tx.run());
+//!
+//! // On each of the others
+//! let udp = bind_multicast(group, Ipv4Addr::UNSPECIFIED).unwrap();
+//! let mut rx = UdpBridgeReceiver::new(CanFdSocket::open("vcan0").unwrap(), udp);
+//! rx.run().unwrap();
+//! ```
+//!
+//! Each datagram has an 8-byte header: the magic bytes `SC`, a version,
+//! flags, and a 32-bit sequence number, all big-endian. If the timestamp
+//! flag is set, the header is followed by the time as a 64-bit count of
+//! nanoseconds since the Unix epoch. Then comes the frame, in the same
+//! form as in a `cannelloni` datagram.
+
+use crate::{
+    cannelloni::{put_frame, take_frame},
+    CanAnyFrame, CanFdSocket, Socket, SocketOptions, TimestampMode,
+};
+use socket2::{Domain, Protocol, Type};
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The magic bytes at the start of each datagram
+pub const MAGIC: [u8; 2] = *b"SC";
+
+/// How far back a sequence number can be and still be taken as a late or
+/// duplicate message. Anything further back means the sender restarted.
+const REORDER_WINDOW: u32 = 64;
+
+/// The version of the datagram format
+pub const VERSION: u8 = 1;
+
+/// The flag for a datagram that carries a timestamp
+const FLAG_TIMESTAMP: u8 = 0x01;
+
+/// The size of the fixed header
+const HEADER_SIZE: usize = 8;
+
+// ===== BridgeMessage =====
+
+/// A frame, as sent over the bridge.
+#[derive(Debug, Clone, Copy)]
+pub struct BridgeMessage {
+    /// The sequence number given by the sender
+    pub seq: u32,
+    /// The time at which the sender received the frame, if it sends them
+    pub timestamp: Option<SystemTime>,
+    /// The frame
+    pub frame: CanAnyFrame,
+}
+
+impl BridgeMessage {
+    /// Encodes the message as a datagram.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE + 8 + 70);
+        let flags = match self.timestamp {
+            Some(_) => FLAG_TIMESTAMP,
+            None => 0,
+        };
+        buf.extend_from_slice(&MAGIC);
+        buf.extend_from_slice(&[VERSION, flags]);
+        buf.extend_from_slice(&self.seq.to_be_bytes());
+
+        if let Some(ts) = self.timestamp {
+            let ns = ts
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX));
+            buf.extend_from_slice(&ns.to_be_bytes());
+        }
+        put_frame(&mut buf, &self.frame);
+        buf
+    }
+
+    /// Decodes a message from a datagram.
+    ///
+    /// A datagram that isn't a bridge message, or is malformed, fails with
+    /// an `InvalidData` error.
+    pub fn decode(buf: &[u8]) -> io::Result<Self> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let (flags, seq, rest) = match buf {
+            [m0, m1, VERSION, flags, s0, s1, s2, s3, rest @ ..] if [*m0, *m1] == MAGIC => {
+                (*flags, u32::from_be_bytes([*s0, *s1, *s2, *s3]), rest)
+            }
+            _ => return Err(invalid("not a CAN bridge datagram")),
+        };
+
+        let (timestamp, rest) = match flags & FLAG_TIMESTAMP {
+            0 => (None, rest),
+            _ if rest.len() < 8 => return Err(invalid("CAN bridge datagram truncated")),
+            _ => {
+                let (ts, rest) = rest.split_at(8);
+                let ns = u64::from_be_bytes(ts.try_into().unwrap());
+                (Some(UNIX_EPOCH + Duration::from_nanos(ns)), rest)
+            }
+        };
+
+        let (frame, _) = take_frame(rest)?;
+        Ok(Self {
+            seq,
+            timestamp,
+            frame,
+        })
+    }
+}
+
+/// Creates a UDP socket bound to the port of a multicast group, and joins
+/// the group on the interface with the address given.
+///
+/// The address can be `Ipv4Addr::UNSPECIFIED` to let the kernel pick the
+/// interface. The socket is bound with `SO_REUSEADDR`, so that several
+/// receivers on the same machine can share the group.
+pub fn bind_multicast(group: SocketAddrV4, iface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let sock = socket2::Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    sock.set_reuse_address(true)?;
+    let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, group.port());
+    sock.bind(&SocketAddr::from(addr).into())?;
+    sock.join_multicast_v4(group.ip(), &iface)?;
+    Ok(sock.into())
+}
+
+// ===== UdpBridgeSender =====
+
+/// Sends the frames from a local CAN socket over UDP.
+#[derive(Debug)]
+pub struct UdpBridgeSender {
+    can: CanFdSocket,
+    udp: UdpSocket,
+    dest: SocketAddr,
+    seq: u32,
+    timestamps: bool,
+}
+
+impl UdpBridgeSender {
+    /// Creates a sender of the frames from the CAN socket, through the UDP
+    /// socket, to the destination, which can be a multicast group.
+    pub fn new(can: CanFdSocket, udp: UdpSocket, dest: SocketAddr) -> Self {
+        Self {
+            can,
+            udp,
+            dest,
+            seq: 0,
+            timestamps: false,
+        }
+    }
+
+    /// Enables or disables sending the time at which each frame was
+    /// received.
+    ///
+    /// This enables kernel timestamps on the CAN socket. The system time
+    /// is used for any frame that the kernel doesn't stamp.
+    pub fn set_timestamps(&mut self, on: bool) -> io::Result<()> {
+        match on {
+            true => self.can.enable_timestamps().map(|_| ())?,
+            false => self.can.set_timestamp_mode(TimestampMode::Disabled)?,
+        }
+        self.timestamps = on;
+        Ok(())
+    }
+
+    /// Gets a shared reference to the CAN socket
+    pub fn as_can_socket(&self) -> &CanFdSocket {
+        &self.can
+    }
+
+    /// Gets a shared reference to the UDP socket
+    pub fn as_udp_socket(&self) -> &UdpSocket {
+        &self.udp
+    }
+
+    /// Reads one frame from the CAN socket, and sends it on.
+    pub fn forward_one(&mut self) -> io::Result<()> {
+        let (frame, ts) = self.can.read_frame_with_timestamp()?;
+        let timestamp = match self.timestamps {
+            true => Some(ts.unwrap_or_else(SystemTime::now)),
+            false => None,
+        };
+
+        let msg = BridgeMessage {
+            seq: self.seq,
+            timestamp,
+            frame,
+        };
+        self.seq = self.seq.wrapping_add(1);
+        self.udp.send_to(&msg.encode(), self.dest)?;
+        Ok(())
+    }
+
+    /// Forwards frames until an error occurs.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.forward_one()?;
+        }
+    }
+}
+
+// ===== UdpBridgeReceiver =====
+
+/// Receives frames over UDP, and writes them to a local CAN socket.
+#[derive(Debug)]
+pub struct UdpBridgeReceiver {
+    can: CanFdSocket,
+    udp: UdpSocket,
+    seqs: HashMap<SocketAddr, SeqTracker>,
+}
+
+/// Tracks the sequence numbers of the messages received from one sender
+#[derive(Debug, Default, Clone, Copy)]
+struct SeqTracker {
+    next: Option<u32>,
+    lost: u64,
+}
+
+impl SeqTracker {
+    // Checks the sequence number of a message, counting any lost before
+    // it. A message that's late, or a duplicate, is rejected. One that's
+    // further back than the reorder window is from a sender that
+    // restarted, so the sequence starts over from it.
+    fn accept(&mut self, seq: u32) -> bool {
+        if let Some(next) = self.next {
+            match seq.wrapping_sub(next) {
+                gap if gap <= u32::MAX / 2 => self.lost += u64::from(gap),
+                _ if next.wrapping_sub(seq) <= REORDER_WINDOW => return false,
+                _ => (),
+            }
+        }
+        self.next = Some(seq.wrapping_add(1));
+        true
+    }
+}
+
+impl UdpBridgeReceiver {
+    /// Creates a receiver of the frames on the UDP socket, which are
+    /// written to the CAN socket.
+    pub fn new(can: CanFdSocket, udp: UdpSocket) -> Self {
+        Self {
+            can,
+            udp,
+            seqs: HashMap::new(),
+        }
+    }
+
+    /// Gets a shared reference to the CAN socket
+    pub fn as_can_socket(&self) -> &CanFdSocket {
+        &self.can
+    }
+
+    /// Gets a shared reference to the UDP socket
+    pub fn as_udp_socket(&self) -> &UdpSocket {
+        &self.udp
+    }
+
+    /// Gets the number of frames that the senders sent, that never
+    /// arrived, as told by the gaps in the sequence numbers.
+    pub fn lost(&self) -> u64 {
+        self.seqs.values().map(|seq| seq.lost).sum()
+    }
+
+    /// Receives one message, and writes its frame to the CAN socket.
+    ///
+    /// Datagrams that aren't bridge messages are skipped. A message that
+    /// arrives after a later one was already received is dropped, rather
+    /// than written out of order, and `None` is returned for it.
+    pub fn forward_one(&mut self) -> io::Result<Option<BridgeMessage>> {
+        let mut buf = [0u8; 128];
+        let (msg, src) = loop {
+            let (n, src) = self.udp.recv_from(&mut buf)?;
+            if let Ok(msg) = BridgeMessage::decode(&buf[..n]) {
+                break (msg, src);
+            }
+        };
+
+        if !self.seqs.entry(src).or_default().accept(msg.seq) {
+            return Ok(None);
+        }
+        self.can.write_frame_insist(&msg.frame)?;
+        Ok(Some(msg))
+    }
+
+    /// Forwards frames until an error occurs.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.forward_one()?;
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFdFrame, CanFrame, EmbeddedFrame, Frame, StandardId};
+    use std::os::unix::{io::OwnedFd, net::UnixDatagram};
+
+    #[test]
+    fn test_message() {
+        let id = StandardId::new(0x123).unwrap();
+        let msg = BridgeMessage {
+            seq: 0x01020304,
+            timestamp: None,
+            frame: CanFrame::new(id, &[0xAA, 0xBB]).unwrap().into(),
+        };
+        let buf = msg.encode();
+        assert_eq!(&buf[..HEADER_SIZE], b"SC\x01\x00\x01\x02\x03\x04");
+        assert_eq!(&buf[HEADER_SIZE..], &[0, 0, 1, 0x23, 2, 0xAA, 0xBB]);
+
+        let msg = BridgeMessage::decode(&buf).unwrap();
+        assert_eq!(msg.seq, 0x01020304);
+        assert!(msg.timestamp.is_none());
+        assert_eq!(msg.frame.data(), &[0xAA, 0xBB]);
+
+        let ts = UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789);
+        let msg = BridgeMessage {
+            seq: 7,
+            timestamp: Some(ts),
+            frame: CanFdFrame::new(id, &[0x55; 20]).unwrap().into(),
+        };
+        let buf = msg.encode();
+        assert_eq!(buf[3], FLAG_TIMESTAMP);
+
+        let msg = BridgeMessage::decode(&buf).unwrap();
+        assert_eq!(msg.timestamp, Some(ts));
+        assert!(msg.frame.is_fd());
+        assert_eq!(msg.frame.id_word(), 0x123);
+
+        assert!(BridgeMessage::decode(&buf[..12]).is_err());
+        assert!(BridgeMessage::decode(b"XX\x01\x00\x00\x00\x00\x00").is_err());
+    }
+
+    #[test]
+    fn test_sequence() {
+        let mut seq = SeqTracker::default();
+        assert!(seq.accept(5));
+        assert!(seq.accept(6));
+        assert!(seq.accept(9));
+        assert_eq!(seq.lost, 2);
+
+        // Late and duplicate messages are dropped
+        assert!(!seq.accept(8));
+        assert!(!seq.accept(9));
+
+        // The sequence wraps around
+        let mut seq = SeqTracker::default();
+        assert!(seq.accept(u32::MAX - 1));
+        assert!(seq.accept(1));
+        assert_eq!(seq.lost, 2);
+        assert!(!seq.accept(u32::MAX));
+
+        // A sender that restarts is picked up again, without counting
+        // the jump back as lost frames
+        let mut seq = SeqTracker::default();
+        assert!(seq.accept(1000));
+        assert!(seq.accept(0));
+        assert!(seq.accept(1));
+        assert_eq!(seq.lost, 0);
+        assert!(!seq.accept(0));
+    }
+
+    #[test]
+    fn test_sequence_per_sender() {
+        // A datagram pair can stand in for the CAN socket
+        let (can, _bus) = UnixDatagram::pair().unwrap();
+        let udp = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dest = udp.local_addr().unwrap();
+        let mut rx = UdpBridgeReceiver::new(CanFdSocket::from(OwnedFd::from(can)), udp);
+
+        let (tx1, tx2) = (
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+        );
+        let frame: CanAnyFrame = CanFrame::new(StandardId::new(0x10).unwrap(), &[1])
+            .unwrap()
+            .into();
+        let mut send = |tx: &UdpSocket, seq| {
+            let msg = BridgeMessage {
+                seq,
+                timestamp: None,
+                frame,
+            };
+            tx.send_to(&msg.encode(), dest).unwrap();
+            rx.forward_one().unwrap().is_some()
+        };
+
+        // Two senders with their own sequences don't get in each other's way
+        assert!(send(&tx1, 10));
+        assert!(send(&tx2, 3));
+        assert!(send(&tx1, 11));
+        assert!(send(&tx2, 4));
+        assert!(!send(&tx1, 11));
+        assert_eq!(rx.lost(), 0);
+    }
+}
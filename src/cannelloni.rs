@@ -0,0 +1,356 @@
+// socketcan/src/cannelloni.rs
+//
+// Tunneling of CAN frames over UDP, compatible with cannelloni.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Tunneling of CAN frames over UDP, compatible with `cannelloni`.
+//!
+//! The `cannelloni` tool bridges the CAN buses of two hosts across an IP
+//! network, by sending the frames from each side to the other in UDP
+//! datagrams. Each datagram has a short header, with a sequence number
+//! and a count, followed by a batch of frames, so that a burst of traffic
+//! doesn't take a datagram per frame.
+//!
+//! The datagrams are built and read with [`encode()`] and [`decode()`].
+//! A [`CannelloniTunnel`] bridges a local [`CanFdSocket`] to a remote
+//! `cannelloni` (or another tunnel), in both directions.
+//!
+//! ```no_run
+//! use socketcan::{cannelloni::CannelloniTunnel, CanFdSocket, Socket};
+//! use std::net::UdpSocket;
+//!
+//! let can = CanFdSocket::open("can0").unwrap();
+//! let udp = UdpSocket::bind("0.0.0.0:20000").unwrap();
+//! let mut tunnel = CannelloniTunnel::new(can, udp, "192.168.1.10:20000".parse().unwrap()).unwrap();
+//! tunnel.run().unwrap();
+//! ```
+//!
+//! The format is the version 2 data framing of:
+//!
+//! <https://github.com/mguentner/cannelloni>
+
+use crate::{
+    frame::{can_frame_default, canfd_frame_default},
+    CanAnyFrame, CanFdSocket, EmbeddedFrame, Frame, ShouldRetry, Socket,
+};
+use libc::{CANFD_MAX_DLEN, CAN_MAX_DLEN, CAN_RTR_FLAG};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    os::unix::io::AsFd,
+    time::Duration,
+};
+
+/// The version of the framing
+pub const CANNELLONI_VERSION: u8 = 2;
+
+/// The op code of a datagram carrying frames
+pub const OP_DATA: u8 = 0;
+
+/// The size of the datagram header
+pub const HEADER_SIZE: usize = 5;
+
+/// The default limit on the size of a datagram, to fit in an Ethernet
+/// frame without fragmenting
+pub const DEFAULT_MAX_DATAGRAM: usize = 1472;
+
+/// The bit in the length byte that marks an FD frame
+const FD_FRAME: u8 = 0x80;
+
+/// The largest that a frame can be, once encoded
+const MAX_FRAME_SIZE: usize = 4 + 1 + 1 + CANFD_MAX_DLEN;
+
+/// The size of a frame, once encoded
+fn encoded_size(frame: &CanAnyFrame) -> usize {
+    match frame {
+        CanAnyFrame::Remote(_) => 5,
+        CanAnyFrame::Fd(frame) => 6 + frame.data().len(),
+        _ => 5 + frame.data().len(),
+    }
+}
+
+/// Encodes a batch of frames as a datagram, with the sequence number
+/// given.
+///
+/// The frames should fit in a datagram, and there can be no more than
+/// 65535 of them.
+pub fn encode(seq: u8, frames: &[CanAnyFrame]) -> Vec<u8> {
+    let size = frames.iter().map(encoded_size).sum::<usize>();
+    let mut buf = Vec::with_capacity(HEADER_SIZE + size);
+    buf.extend_from_slice(&[CANNELLONI_VERSION, OP_DATA, seq]);
+    buf.extend_from_slice(&(frames.len() as u16).to_be_bytes());
+
+    for frame in frames {
+        put_frame(&mut buf, frame);
+    }
+    buf
+}
+
+/// Appends a frame to the buffer, in the cannelloni format.
+pub(crate) fn put_frame(buf: &mut Vec<u8>, frame: &CanAnyFrame) {
+    buf.extend_from_slice(&frame.id_word().to_be_bytes());
+    match frame {
+        CanAnyFrame::Remote(frame) => buf.push(frame.dlc() as u8),
+        CanAnyFrame::Fd(frame) => {
+            buf.push(frame.data().len() as u8 | FD_FRAME);
+            buf.push(frame.flags().bits());
+            buf.extend_from_slice(frame.data());
+        }
+        _ => {
+            buf.push(frame.data().len() as u8);
+            buf.extend_from_slice(frame.data());
+        }
+    }
+}
+
+/// Takes a frame in the cannelloni format from the front of the buffer,
+/// returning it with the rest of the buffer.
+pub(crate) fn take_frame(buf: &[u8]) -> io::Result<(CanAnyFrame, &[u8])> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let (id, len, tail) = match buf {
+        [i0, i1, i2, i3, len, tail @ ..] => (u32::from_be_bytes([*i0, *i1, *i2, *i3]), *len, tail),
+        _ => return Err(invalid("frame truncated")),
+    };
+
+    if len & FD_FRAME != 0 {
+        let n = usize::from(len & !FD_FRAME);
+        let (flags, data) = match tail.split_first() {
+            Some((&flags, data)) if n <= CANFD_MAX_DLEN && data.len() >= n => (flags, data),
+            _ => return Err(invalid("bad FD frame")),
+        };
+        let mut frame = canfd_frame_default();
+        frame.can_id = id;
+        frame.len = n as u8;
+        frame.flags = flags;
+        frame.data[..n].copy_from_slice(&data[..n]);
+        Ok((frame.into(), &data[n..]))
+    } else {
+        let n = usize::from(len);
+        let data_len = if id & CAN_RTR_FLAG != 0 { 0 } else { n };
+        if n > CAN_MAX_DLEN || tail.len() < data_len {
+            return Err(invalid("bad frame"));
+        }
+        let mut frame = can_frame_default();
+        frame.can_id = id;
+        frame.can_dlc = n as u8;
+        frame.data[..data_len].copy_from_slice(&tail[..data_len]);
+        Ok((frame.into(), &tail[data_len..]))
+    }
+}
+
+/// Decodes a datagram, returning its sequence number and frames.
+///
+/// A datagram that's malformed, or isn't a version 2 data datagram, fails
+/// with an `InvalidData` error.
+pub fn decode(buf: &[u8]) -> io::Result<(u8, Vec<CanAnyFrame>)> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let (seq, count, mut rest) = match buf {
+        [CANNELLONI_VERSION, OP_DATA, seq, c0, c1, rest @ ..] => {
+            (*seq, u16::from_be_bytes([*c0, *c1]), rest)
+        }
+        [CANNELLONI_VERSION, ..] => return Err(invalid("not a cannelloni data datagram")),
+        _ => return Err(invalid("not a cannelloni v2 datagram")),
+    };
+
+    let mut frames = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let (frame, tail) = take_frame(rest)?;
+        frames.push(frame);
+        rest = tail;
+    }
+    Ok((seq, frames))
+}
+
+// ===== CannelloniTunnel =====
+
+/// A bridge between a local CAN socket and a remote `cannelloni` peer.
+///
+/// Frames read from the CAN socket are batched into datagrams for the
+/// peer, taking as many as are waiting, up to the size limit. Frames in
+/// the datagrams from the peer are written to the CAN socket. Datagrams
+/// from any other address are ignored.
+#[derive(Debug)]
+pub struct CannelloniTunnel {
+    can: CanFdSocket,
+    udp: UdpSocket,
+    peer: SocketAddr,
+    seq: u8,
+    max_datagram: usize,
+}
+
+impl CannelloniTunnel {
+    /// Creates a tunnel between the CAN socket, and the peer, through the
+    /// UDP socket.
+    ///
+    /// The CAN socket is put into non-blocking mode, to batch the frames.
+    pub fn new(can: CanFdSocket, udp: UdpSocket, peer: SocketAddr) -> io::Result<Self> {
+        can.set_nonblocking(true)?;
+        Ok(Self {
+            can,
+            udp,
+            peer,
+            seq: 0,
+            max_datagram: DEFAULT_MAX_DATAGRAM,
+        })
+    }
+
+    /// Sets the limit on the size of the datagrams sent.
+    ///
+    /// This is raised, if needed, to fit at least one frame.
+    pub fn set_max_datagram(&mut self, size: usize) {
+        self.max_datagram = size.max(HEADER_SIZE + MAX_FRAME_SIZE);
+    }
+
+    /// Gets a shared reference to the CAN socket
+    pub fn as_can_socket(&self) -> &CanFdSocket {
+        &self.can
+    }
+
+    /// Gets a shared reference to the UDP socket
+    pub fn as_udp_socket(&self) -> &UdpSocket {
+        &self.udp
+    }
+
+    /// Runs the tunnel until an error occurs.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.poll_once(None)?;
+        }
+    }
+
+    /// Waits for traffic on either side, for up to the timeout given, and
+    /// forwards it.
+    ///
+    /// A timeout of `None` waits indefinitely. This returns once whatever
+    /// was waiting has been forwarded, or the timeout elapsed.
+    pub fn poll_once(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let mut fds = [
+            PollFd::new(self.can.as_fd(), PollFlags::POLLIN),
+            PollFd::new(self.udp.as_fd(), PollFlags::POLLIN),
+        ];
+        let timeout = match timeout {
+            Some(timeout) => timeout.try_into().unwrap_or(PollTimeout::MAX),
+            None => PollTimeout::NONE,
+        };
+        if poll(&mut fds, timeout)? == 0 {
+            return Ok(());
+        }
+
+        let ready = |fd: &PollFd| fd.revents().is_some_and(|ev| !ev.is_empty());
+        let (can_ready, udp_ready) = (ready(&fds[0]), ready(&fds[1]));
+        if can_ready {
+            self.forward_can()?;
+        }
+        if udp_ready {
+            self.forward_udp()?;
+        }
+        Ok(())
+    }
+
+    // Sends the frames waiting on the CAN socket to the peer
+    fn forward_can(&mut self) -> io::Result<()> {
+        let mut frames = Vec::new();
+        let mut size = HEADER_SIZE;
+        loop {
+            match self.can.read_frame() {
+                Ok(frame) => {
+                    size += encoded_size(&frame);
+                    frames.push(frame);
+                    if size + MAX_FRAME_SIZE > self.max_datagram {
+                        self.send_batch(&frames)?;
+                        frames.clear();
+                        size = HEADER_SIZE;
+                    }
+                }
+                Err(err) if err.should_retry() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        if !frames.is_empty() {
+            self.send_batch(&frames)?;
+        }
+        Ok(())
+    }
+
+    fn send_batch(&mut self, frames: &[CanAnyFrame]) -> io::Result<()> {
+        let buf = encode(self.seq, frames);
+        self.seq = self.seq.wrapping_add(1);
+        self.udp.send_to(&buf, self.peer)?;
+        Ok(())
+    }
+
+    // Writes the frames in a datagram from the peer to the CAN socket
+    fn forward_udp(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 65536];
+        let (n, from) = self.udp.recv_from(&mut buf)?;
+        if from != self.peer {
+            return Ok(());
+        }
+        // A bad datagram is dropped, rather than taking down the tunnel
+        if let Ok((_, frames)) = decode(&buf[..n]) {
+            for frame in &frames {
+                self.can.write_frame_insist(frame)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame::FdFlags, CanFdFrame, CanFrame, ExtendedId, StandardId};
+
+    fn frames() -> Vec<CanAnyFrame> {
+        let sid = StandardId::new(0x123).unwrap();
+        let eid = ExtendedId::new(0x1ABCDEF0).unwrap();
+        vec![
+            CanFrame::new(sid, &[1, 2, 3]).unwrap().into(),
+            CanFrame::new_remote(eid, 4).unwrap().into(),
+            CanFdFrame::with_flags(sid, &[0xAA; 12], FdFlags::BRS)
+                .unwrap()
+                .into(),
+        ]
+    }
+
+    #[test]
+    fn test_encode() {
+        let buf = encode(7, &frames());
+        assert_eq!(&buf[..HEADER_SIZE], &[2, 0, 7, 0, 3]);
+        assert_eq!(&buf[5..13], &[0, 0, 1, 0x23, 3, 1, 2, 3]);
+        assert_eq!(&buf[13..18], &[0xDA, 0xBC, 0xDE, 0xF0, 4]);
+        assert_eq!(&buf[18..24], &[0, 0, 1, 0x23, 0x80 | 12, 0x05]);
+        assert_eq!(buf.len(), 24 + 12);
+    }
+
+    #[test]
+    fn test_decode() {
+        let (seq, decoded) = decode(&encode(9, &frames())).unwrap();
+        assert_eq!(seq, 9);
+        assert_eq!(decoded.len(), 3);
+        for (a, b) in decoded.iter().zip(frames().iter()) {
+            assert_eq!(a.id_word(), b.id_word());
+            assert_eq!(a.data(), b.data());
+            assert_eq!(a.dlc(), b.dlc());
+        }
+        assert!(decoded[1].is_remote());
+        assert!(decoded[2].as_fd().unwrap().flags().contains(FdFlags::BRS));
+
+        let buf = encode(0, &frames());
+        assert!(decode(&buf[..buf.len() - 1]).is_err());
+        assert!(decode(&[1, 0, 0, 0, 0]).is_err());
+        assert!(decode(&[2, 1, 0, 0, 0]).is_err());
+        assert!(decode(&[2, 0, 0, 0, 0]).unwrap().1.is_empty());
+    }
+}
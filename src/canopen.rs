@@ -0,0 +1,675 @@
+// socketcan/src/canopen.rs
+//
+// A basic CANopen (CiA 301) client: SDO, NMT, and heartbeats.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A basic CANopen (CiA 301) client.
+//!
+//! This covers the parts of CANopen that a master, or a configuration
+//! tool, needs to talk to the nodes on a bus:
+//!
+//! - SDO (service data object) transfers, which read and write the
+//!   entries of a node's object dictionary. Values of up to 4 bytes are
+//!   sent in a single, expedited, exchange. Longer ones are split into
+//!   segments, 7 bytes at a time.
+//! - NMT (network management) commands, which start, stop, and reset the
+//!   nodes.
+//! - Heartbeats, which each node sends periodically with its NMT state, and
+//!   which a [`HeartbeatConsumer`] keeps track of.
+//!
+//! ```no_run
+//! use socketcan::canopen::{CanOpenClient, NmtCommand};
+//!
+//! let client = CanOpenClient::open("vcan0").unwrap();
+//!
+//! // Read the device type of node 5
+//! let device_type = client.sdo_upload(5, 0x1000, 0).unwrap();
+//!
+//! // Set its heartbeat producer time to 100ms, and start it
+//! client.sdo_download(5, 0x1017, 0, &100u16.to_le_bytes()).unwrap();
+//! client.send_nmt(NmtCommand::Start, 5).unwrap();
+//! ```
+//!
+//! Nodes are numbered from 1 to 127. The CAN IDs of their services are
+//! the predefined connection set of CiA 301.
+
+use crate::{CanFrame, CanSocket, EmbeddedFrame, Socket, StandardId};
+use std::{
+    collections::HashMap,
+    io,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The CAN ID of NMT commands
+pub const NMT_ID: u16 = 0x000;
+/// The base CAN ID of SDO responses, from a node
+pub const SDO_TX_BASE_ID: u16 = 0x580;
+/// The base CAN ID of SDO requests, to a node
+pub const SDO_RX_BASE_ID: u16 = 0x600;
+/// The base CAN ID of heartbeats
+pub const HEARTBEAT_BASE_ID: u16 = 0x700;
+
+/// The highest node ID
+pub const MAX_NODE_ID: u8 = 127;
+
+/// The default time a node has to respond to an SDO request
+pub const DEFAULT_SDO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The abort code for a command specifier that isn't valid
+pub const SDO_ABORT_INVALID_COMMAND: u32 = 0x0504_0001;
+
+// SDO command bytes, and their parts
+const SDO_INITIATE_DOWNLOAD: u8 = 0x20;
+const SDO_DOWNLOAD_SEGMENT: u8 = 0x00;
+const SDO_INITIATE_UPLOAD: u8 = 0x40;
+const SDO_UPLOAD_SEGMENT: u8 = 0x60;
+const SDO_ABORT: u8 = 0x80;
+const SDO_CS_MASK: u8 = 0xE0;
+const SDO_TOGGLE: u8 = 0x10;
+const SDO_EXPEDITED: u8 = 0x02;
+const SDO_SIZE_INDICATED: u8 = 0x01;
+const SDO_LAST_SEGMENT: u8 = 0x01;
+
+// The command specifiers of the responses
+const SDO_SCS_UPLOAD_SEGMENT: u8 = 0x00;
+const SDO_SCS_DOWNLOAD_SEGMENT: u8 = 0x20;
+const SDO_SCS_INITIATE_UPLOAD: u8 = 0x40;
+const SDO_SCS_INITIATE_DOWNLOAD: u8 = 0x60;
+
+// ===== Errors =====
+
+/// An error from an SDO transfer
+#[derive(Error, Debug)]
+pub enum SdoError {
+    /// I/O Error
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The transfer was aborted by the node
+    #[error("SDO transfer aborted with code 0x{0:08X}")]
+    Abort(u32),
+    /// The node didn't respond within the timeout
+    #[error("No SDO response from the node")]
+    Timeout,
+    /// The response didn't follow the protocol
+    #[error("Unexpected SDO response")]
+    UnexpectedResponse,
+}
+
+/// The result of an SDO transfer
+pub type SdoResult<T> = Result<T, SdoError>;
+
+// ===== NMT =====
+
+/// The NMT commands, to change the state of the nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NmtCommand {
+    /// Start the node, into the operational state
+    Start,
+    /// Stop the node
+    Stop,
+    /// Put the node into the pre-operational state
+    EnterPreOperational,
+    /// Reset the node's application
+    ResetNode,
+    /// Reset the node's communication
+    ResetCommunication,
+}
+
+impl From<NmtCommand> for u8 {
+    fn from(cmd: NmtCommand) -> Self {
+        use NmtCommand::*;
+        match cmd {
+            Start => 0x01,
+            Stop => 0x02,
+            EnterPreOperational => 0x80,
+            ResetNode => 0x81,
+            ResetCommunication => 0x82,
+        }
+    }
+}
+
+/// The NMT state of a node, as reported in its heartbeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NmtState {
+    /// The node has just booted
+    BootUp,
+    /// The node is stopped
+    Stopped,
+    /// The node is operational
+    Operational,
+    /// The node is pre-operational
+    PreOperational,
+    /// A state that isn't defined
+    Unknown(u8),
+}
+
+impl From<u8> for NmtState {
+    fn from(state: u8) -> Self {
+        use NmtState::*;
+        // The top bit is the toggle bit of node guarding
+        match state & 0x7F {
+            0x00 => BootUp,
+            0x04 => Stopped,
+            0x05 => Operational,
+            0x7F => PreOperational,
+            state => Unknown(state),
+        }
+    }
+}
+
+// ===== Heartbeats =====
+
+/// A heartbeat from a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    /// The ID of the node
+    pub node: u8,
+    /// The NMT state of the node
+    pub state: NmtState,
+}
+
+impl Heartbeat {
+    /// Gets a heartbeat from a frame, if it is one.
+    pub fn from_frame(frame: &CanFrame) -> Option<Self> {
+        let id = match frame.id() {
+            embedded_can::Id::Standard(id) => id.as_raw(),
+            _ => return None,
+        };
+        let node = id.checked_sub(HEARTBEAT_BASE_ID)? as u8;
+        match (node, frame.data()) {
+            (1..=MAX_NODE_ID, &[state]) if !frame.is_remote_frame() => Some(Self {
+                node,
+                state: NmtState::from(state),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps track of the heartbeats of a set of nodes, to tell when they
+/// stop.
+///
+/// Each node is watched with its own timeout, usually a bit longer than
+/// its heartbeat producer time. Every frame received on the bus can be
+/// passed to [`HeartbeatConsumer::update()`], which picks out the
+/// heartbeats.
+#[derive(Debug, Default, Clone)]
+pub struct HeartbeatConsumer {
+    nodes: HashMap<u8, Watched>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Watched {
+    timeout: Duration,
+    last_seen: Instant,
+    state: Option<NmtState>,
+}
+
+impl HeartbeatConsumer {
+    /// Creates a consumer that isn't watching any nodes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts watching the heartbeats of a node.
+    ///
+    /// The first heartbeat is expected within the timeout, from now.
+    pub fn watch(&mut self, node: u8, timeout: Duration) {
+        self.nodes.insert(
+            node,
+            Watched {
+                timeout,
+                last_seen: Instant::now(),
+                state: None,
+            },
+        );
+    }
+
+    /// Stops watching the heartbeats of a node.
+    pub fn unwatch(&mut self, node: u8) {
+        self.nodes.remove(&node);
+    }
+
+    /// Checks a frame received from the bus, returning the heartbeat if
+    /// it is one.
+    ///
+    /// Heartbeats from any node are returned, but only the watched ones
+    /// are recorded.
+    pub fn update(&mut self, frame: &CanFrame) -> Option<Heartbeat> {
+        let hb = Heartbeat::from_frame(frame)?;
+        if let Some(watched) = self.nodes.get_mut(&hb.node) {
+            watched.last_seen = Instant::now();
+            watched.state = Some(hb.state);
+        }
+        Some(hb)
+    }
+
+    /// Gets the last state reported by a watched node.
+    pub fn state(&self, node: u8) -> Option<NmtState> {
+        self.nodes.get(&node)?.state
+    }
+
+    /// Gets the watched nodes that haven't sent a heartbeat within their
+    /// timeout, as of the time given.
+    pub fn expired(&self, now: Instant) -> Vec<u8> {
+        let mut nodes: Vec<u8> = self
+            .nodes
+            .iter()
+            .filter(|(_, w)| now.saturating_duration_since(w.last_seen) > w.timeout)
+            .map(|(&node, _)| node)
+            .collect();
+        nodes.sort_unstable();
+        nodes
+    }
+}
+
+// ===== SDO transfers =====
+
+/// Creates an SDO message, with the command byte and the multiplexer of
+/// the object dictionary entry.
+fn sdo_msg(cmd: u8, index: u16, subindex: u8, data: &[u8]) -> [u8; 8] {
+    let mut msg = [0u8; 8];
+    msg[0] = cmd;
+    msg[1..3].copy_from_slice(&index.to_le_bytes());
+    msg[3] = subindex;
+    msg[4..4 + data.len()].copy_from_slice(data);
+    msg
+}
+
+/// Creates a segment message, for a download or an upload.
+fn sdo_segment(cmd: u8, toggle: bool, data: &[u8], last: bool) -> [u8; 8] {
+    let mut msg = [0u8; 8];
+    msg[0] = cmd | ((7 - data.len() as u8) << 1);
+    if toggle {
+        msg[0] |= SDO_TOGGLE;
+    }
+    if last {
+        msg[0] |= SDO_LAST_SEGMENT;
+    }
+    msg[1..1 + data.len()].copy_from_slice(data);
+    msg
+}
+
+/// Creates the request for the next segment of an upload.
+///
+/// Apart from the toggle bit, the rest of the message is reserved, and
+/// has to be zero.
+fn sdo_upload_segment_request(toggle: bool) -> [u8; 8] {
+    let mut msg = [0u8; 8];
+    msg[0] = SDO_UPLOAD_SEGMENT;
+    if toggle {
+        msg[0] |= SDO_TOGGLE;
+    }
+    msg
+}
+
+/// Checks an SDO response for an abort, and for the command specifier
+/// and toggle bit expected.
+fn check_response(resp: &[u8; 8], scs: u8, toggle: Option<bool>) -> SdoResult<()> {
+    if resp[0] == SDO_ABORT {
+        let code = u32::from_le_bytes([resp[4], resp[5], resp[6], resp[7]]);
+        return Err(SdoError::Abort(code));
+    }
+    if resp[0] & SDO_CS_MASK != scs {
+        return Err(SdoError::UnexpectedResponse);
+    }
+    match toggle {
+        Some(toggle) if (resp[0] & SDO_TOGGLE != 0) != toggle => Err(SdoError::UnexpectedResponse),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that an initiate response is for the entry that was requested.
+fn check_mux(resp: &[u8; 8], index: u16, subindex: u8) -> SdoResult<()> {
+    match resp[1..4] == sdo_msg(0, index, subindex, &[])[1..4] {
+        true => Ok(()),
+        false => Err(SdoError::UnexpectedResponse),
+    }
+}
+
+/// Reads an entry from a node's object dictionary, over an exchange of
+/// request and response messages.
+fn upload<E>(index: u16, subindex: u8, mut exchange: E) -> SdoResult<Vec<u8>>
+where
+    E: FnMut(&[u8; 8]) -> SdoResult<[u8; 8]>,
+{
+    let resp = exchange(&sdo_msg(SDO_INITIATE_UPLOAD, index, subindex, &[]))?;
+    check_response(&resp, SDO_SCS_INITIATE_UPLOAD, None)?;
+    check_mux(&resp, index, subindex)?;
+
+    let cmd = resp[0];
+    if cmd & SDO_EXPEDITED != 0 {
+        let len = match cmd & SDO_SIZE_INDICATED {
+            0 => 4,
+            _ => 4 - usize::from((cmd >> 2) & 0x03),
+        };
+        return Ok(resp[4..4 + len].to_vec());
+    }
+
+    let size = match cmd & SDO_SIZE_INDICATED {
+        0 => None,
+        _ => Some(u32::from_le_bytes([resp[4], resp[5], resp[6], resp[7]]) as usize),
+    };
+
+    let mut data = Vec::with_capacity(size.unwrap_or(0));
+    let mut toggle = false;
+    loop {
+        let resp = exchange(&sdo_upload_segment_request(toggle))?;
+        check_response(&resp, SDO_SCS_UPLOAD_SEGMENT, Some(toggle))?;
+
+        let n = 7 - usize::from((resp[0] >> 1) & 0x07);
+        data.extend_from_slice(&resp[1..1 + n]);
+        if resp[0] & SDO_LAST_SEGMENT != 0 {
+            break;
+        }
+        toggle = !toggle;
+    }
+
+    match size {
+        Some(size) if size != data.len() => Err(SdoError::UnexpectedResponse),
+        _ => Ok(data),
+    }
+}
+
+/// Writes an entry in a node's object dictionary, over an exchange of
+/// request and response messages.
+fn download<E>(index: u16, subindex: u8, data: &[u8], mut exchange: E) -> SdoResult<()>
+where
+    E: FnMut(&[u8; 8]) -> SdoResult<[u8; 8]>,
+{
+    if (1..=4).contains(&data.len()) {
+        let n = 4 - data.len() as u8;
+        let cmd = SDO_INITIATE_DOWNLOAD | (n << 2) | SDO_EXPEDITED | SDO_SIZE_INDICATED;
+        let resp = exchange(&sdo_msg(cmd, index, subindex, data))?;
+        check_response(&resp, SDO_SCS_INITIATE_DOWNLOAD, None)?;
+        return check_mux(&resp, index, subindex);
+    }
+
+    let size = (data.len() as u32).to_le_bytes();
+    let cmd = SDO_INITIATE_DOWNLOAD | SDO_SIZE_INDICATED;
+    let resp = exchange(&sdo_msg(cmd, index, subindex, &size))?;
+    check_response(&resp, SDO_SCS_INITIATE_DOWNLOAD, None)?;
+    check_mux(&resp, index, subindex)?;
+
+    // An empty value still needs a final segment
+    let segs: Vec<&[u8]> = match data {
+        [] => vec![&[]],
+        _ => data.chunks(7).collect(),
+    };
+    let mut toggle = false;
+    for (i, seg) in segs.iter().enumerate() {
+        let last = i == segs.len() - 1;
+        let resp = exchange(&sdo_segment(SDO_DOWNLOAD_SEGMENT, toggle, seg, last))?;
+        check_response(&resp, SDO_SCS_DOWNLOAD_SEGMENT, Some(toggle))?;
+        toggle = !toggle;
+    }
+    Ok(())
+}
+
+// ===== CanOpenClient =====
+
+/// A CANopen client, for the SDO and NMT services of the nodes on a bus.
+///
+/// The client sends its requests one at a time, and waits for each
+/// response. Any other frames received in the meantime are ignored, so it
+/// should have a socket of its own.
+#[derive(Debug)]
+pub struct CanOpenClient {
+    sock: CanSocket,
+    timeout: Duration,
+}
+
+impl CanOpenClient {
+    /// Creates a client that uses the CAN socket given.
+    pub fn new(sock: CanSocket) -> Self {
+        Self {
+            sock,
+            timeout: DEFAULT_SDO_TIMEOUT,
+        }
+    }
+
+    /// Opens a client on the named interface.
+    pub fn open(ifname: &str) -> io::Result<Self> {
+        CanSocket::open(ifname).map(Self::new)
+    }
+
+    /// Gets a shared reference to the underlying CAN socket
+    pub fn as_socket(&self) -> &CanSocket {
+        &self.sock
+    }
+
+    /// Sets the time a node has to respond to each SDO request.
+    pub fn set_sdo_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sends an NMT command to a node, or to all the nodes if `node` is 0.
+    pub fn send_nmt(&self, cmd: NmtCommand, node: u8) -> io::Result<()> {
+        check_node(node, true)?;
+        let frame = CanFrame::new(id(NMT_ID, 0)?, &[u8::from(cmd), node])
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.sock.write_frame(&frame)
+    }
+
+    /// Reads an entry from the object dictionary of a node.
+    pub fn sdo_upload(&self, node: u8, index: u16, subindex: u8) -> SdoResult<Vec<u8>> {
+        check_node(node, false)?;
+        self.transfer(node, index, subindex, |ex| upload(index, subindex, ex))
+    }
+
+    /// Writes an entry in the object dictionary of a node.
+    pub fn sdo_download(&self, node: u8, index: u16, subindex: u8, data: &[u8]) -> SdoResult<()> {
+        check_node(node, false)?;
+        self.transfer(node, index, subindex, |ex| {
+            download(index, subindex, data, ex)
+        })
+    }
+
+    // Runs a transfer, aborting it if the node doesn't follow the protocol
+    fn transfer<T, F>(&self, node: u8, index: u16, subindex: u8, f: F) -> SdoResult<T>
+    where
+        F: FnOnce(&mut dyn FnMut(&[u8; 8]) -> SdoResult<[u8; 8]>) -> SdoResult<T>,
+    {
+        let res = f(&mut |req| self.exchange(node, req));
+        if let Err(SdoError::UnexpectedResponse) = res {
+            let code = SDO_ABORT_INVALID_COMMAND.to_le_bytes();
+            let _ = self.send_sdo(node, &sdo_msg(SDO_ABORT, index, subindex, &code));
+        }
+        res
+    }
+
+    // Sends an SDO request to a node
+    fn send_sdo(&self, node: u8, msg: &[u8; 8]) -> io::Result<()> {
+        let frame = CanFrame::new(id(SDO_RX_BASE_ID, node)?, msg)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.sock.write_frame(&frame)
+    }
+
+    // Sends an SDO request to a node, and waits for its response
+    fn exchange(&self, node: u8, req: &[u8; 8]) -> SdoResult<[u8; 8]> {
+        self.send_sdo(node, req)?;
+
+        let resp_id = id(SDO_TX_BASE_ID, node)?;
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(SdoError::Timeout);
+            }
+            let frame = match self.sock.read_frame_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => return Err(SdoError::Timeout),
+                Err(err) => return Err(err.into()),
+            };
+            if frame.id() == resp_id.into() && !frame.is_remote_frame() {
+                return frame
+                    .data()
+                    .try_into()
+                    .map_err(|_| SdoError::UnexpectedResponse);
+            }
+        }
+    }
+}
+
+impl From<CanSocket> for CanOpenClient {
+    fn from(sock: CanSocket) -> Self {
+        Self::new(sock)
+    }
+}
+
+/// Checks that a node ID is in range, or is 0 for all nodes, if allowed.
+fn check_node(node: u8, allow_all: bool) -> io::Result<()> {
+    match node {
+        0 if allow_all => Ok(()),
+        1..=MAX_NODE_ID => Ok(()),
+        _ => Err(io::ErrorKind::InvalidInput.into()),
+    }
+}
+
+/// Gets the CAN ID of a service for a node.
+fn id(base: u16, node: u8) -> io::Result<StandardId> {
+    StandardId::new(base + u16::from(node)).ok_or_else(|| io::ErrorKind::InvalidInput.into())
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fake node, serving a single object dictionary entry
+    struct Server {
+        value: Vec<u8>,
+        pos: usize,
+        toggle: bool,
+    }
+
+    impl Server {
+        fn new(value: &[u8]) -> Self {
+            Self {
+                value: value.to_vec(),
+                pos: 0,
+                toggle: false,
+            }
+        }
+
+        fn exchange(&mut self, req: &[u8; 8]) -> SdoResult<[u8; 8]> {
+            let mut resp = [0u8; 8];
+            match req[0] & SDO_CS_MASK {
+                SDO_INITIATE_UPLOAD => {
+                    resp[..4].copy_from_slice(&req[..4]);
+                    resp[0] = SDO_SCS_INITIATE_UPLOAD | SDO_SIZE_INDICATED;
+                    if self.value.len() <= 4 {
+                        resp[0] |= SDO_EXPEDITED | ((4 - self.value.len() as u8) << 2);
+                        resp[4..4 + self.value.len()].copy_from_slice(&self.value);
+                    } else {
+                        resp[4..].copy_from_slice(&(self.value.len() as u32).to_le_bytes());
+                    }
+                }
+                SDO_UPLOAD_SEGMENT => {
+                    // Only the toggle bit may be set in the request
+                    assert_eq!(req[0] & !SDO_TOGGLE, SDO_UPLOAD_SEGMENT);
+                    assert_eq!(req[1..], [0; 7]);
+                    let seg = &self.value[self.pos..(self.pos + 7).min(self.value.len())];
+                    self.pos += seg.len();
+                    resp = sdo_segment(
+                        SDO_SCS_UPLOAD_SEGMENT,
+                        req[0] & SDO_TOGGLE != 0,
+                        seg,
+                        self.pos == self.value.len(),
+                    );
+                }
+                SDO_INITIATE_DOWNLOAD => {
+                    resp[..4].copy_from_slice(&req[..4]);
+                    resp[0] = SDO_SCS_INITIATE_DOWNLOAD;
+                    self.value.clear();
+                    if req[0] & SDO_EXPEDITED != 0 {
+                        let n = 4 - usize::from((req[0] >> 2) & 0x03);
+                        self.value.extend_from_slice(&req[4..4 + n]);
+                    }
+                }
+                SDO_DOWNLOAD_SEGMENT => {
+                    assert_eq!(req[0] & SDO_TOGGLE != 0, self.toggle);
+                    self.toggle = !self.toggle;
+                    let n = 7 - usize::from((req[0] >> 1) & 0x07);
+                    self.value.extend_from_slice(&req[1..1 + n]);
+                    resp[0] = SDO_SCS_DOWNLOAD_SEGMENT | (req[0] & SDO_TOGGLE);
+                }
+                _ => unreachable!(),
+            }
+            Ok(resp)
+        }
+    }
+
+    #[test]
+    fn test_expedited() {
+        let mut server = Server::new(&[0x92, 0x01, 0x02, 0x00]);
+        let data = upload(0x1000, 0, |req| server.exchange(req)).unwrap();
+        assert_eq!(data, &[0x92, 0x01, 0x02, 0x00]);
+
+        download(0x1017, 0, &[0x64, 0x00], |req| server.exchange(req)).unwrap();
+        assert_eq!(server.value, &[0x64, 0x00]);
+    }
+
+    #[test]
+    fn test_segmented() {
+        // Exactly two segments, and a bit over
+        for len in [14, 20] {
+            let value: Vec<u8> = (0..len).collect();
+            let mut server = Server::new(&value);
+            let data = upload(0x1008, 0, |req| server.exchange(req)).unwrap();
+            assert_eq!(data, value);
+
+            let mut server = Server::new(&[]);
+            download(0x1008, 0, &value, |req| server.exchange(req)).unwrap();
+            assert_eq!(server.value, value);
+        }
+    }
+
+    #[test]
+    fn test_abort() {
+        let code = 0x0602_0000u32.to_le_bytes();
+        let res = upload(0x2000, 1, |_| Ok(sdo_msg(SDO_ABORT, 0x2000, 1, &code)));
+        assert!(matches!(res, Err(SdoError::Abort(0x0602_0000))));
+
+        // A response for a different entry
+        let res = upload(0x2000, 1, |_| {
+            Ok(sdo_msg(SDO_SCS_INITIATE_UPLOAD | 0x03, 0x2001, 1, &[0; 4]))
+        });
+        assert!(matches!(res, Err(SdoError::UnexpectedResponse)));
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let id = StandardId::new(HEARTBEAT_BASE_ID + 5).unwrap();
+        let frame = CanFrame::new(id, &[0x05]).unwrap();
+        assert_eq!(
+            Heartbeat::from_frame(&frame),
+            Some(Heartbeat {
+                node: 5,
+                state: NmtState::Operational
+            })
+        );
+
+        let sdo = CanFrame::new(StandardId::new(0x585).unwrap(), &[0x05]).unwrap();
+        assert_eq!(Heartbeat::from_frame(&sdo), None);
+
+        let mut consumer = HeartbeatConsumer::new();
+        consumer.watch(5, Duration::from_millis(100));
+        consumer.watch(6, Duration::from_millis(100));
+        assert!(consumer.update(&frame).is_some());
+        assert_eq!(consumer.state(5), Some(NmtState::Operational));
+        assert_eq!(consumer.state(6), None);
+
+        assert!(consumer.expired(Instant::now()).is_empty());
+        let later = Instant::now() + Duration::from_millis(200);
+        assert_eq!(consumer.expired(later), &[5, 6]);
+    }
+}
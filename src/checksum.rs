@@ -0,0 +1,133 @@
+// socketcan/src/checksum.rs
+//
+// Implements common checksums for CAN payloads.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Checksums commonly used in CAN payloads.
+//!
+//! These are the XOR and CRC8 checksums that the kernel CAN gateway (CGW)
+//! can compute with its `CGW_CS_XOR` and `CGW_CS_CRC8` modifiers, and that
+//! are used by many ECUs (such as the AUTOSAR E2E profiles) to protect
+//! their payloads.
+//!
+//! The CRC8 functions are the plain, non-reflected, MSB-first form, with
+//! the initial value given, and no final XOR. The presets apply the final
+//! XOR where their specifications require it.
+
+/// Computes the XOR of all the bytes in the data, starting with `init`.
+///
+/// This matches the CGW XOR checksum, where `init` is the initial XOR
+/// value from the `cgw_csum_xor` struct.
+pub fn xor(data: &[u8], init: u8) -> u8 {
+    data.iter().fold(init, |acc, b| acc ^ b)
+}
+
+/// Computes a generic CRC8 over the data, with the polynomial and initial
+/// value given.
+///
+/// The polynomial is given in the normal (MSB-first) form, without the
+/// implied high bit, i.e. `0x1D` for x^8 + x^4 + x^3 + x^2 + 1.
+pub fn crc8(data: &[u8], poly: u8, init: u8) -> u8 {
+    data.iter().fold(init, |crc, b| crc8_byte(crc ^ b, poly))
+}
+
+// Runs a single byte through the CRC8 shift register.
+#[inline]
+fn crc8_byte(mut crc: u8, poly: u8) -> u8 {
+    for _ in 0..8 {
+        crc = if crc & 0x80 != 0 {
+            (crc << 1) ^ poly
+        } else {
+            crc << 1
+        };
+    }
+    crc
+}
+
+/// Creates the 256-byte lookup table for a CRC8 polynomial.
+///
+/// This is the table that the CGW CRC8 modifier expects in the `crctab`
+/// field of the `cgw_csum_crc8` struct.
+pub fn crc8_table(poly: u8) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = crc8_byte(i as u8, poly);
+    }
+    table
+}
+
+/// Computes a CRC8 over the data using a lookup table from
+/// [`crc8_table()`].
+///
+/// This gives the same result as [`crc8()`] with the table's polynomial,
+/// but is faster for large amounts of data.
+pub fn crc8_with_table(table: &[u8; 256], data: &[u8], init: u8) -> u8 {
+    data.iter().fold(init, |crc, b| table[usize::from(crc ^ b)])
+}
+
+/// The CRC-8/SAE-J1850 checksum.
+///
+/// Polynomial `0x1D`, initial value `0xFF`, and final XOR `0xFF`. This is
+/// also the CRC of the AUTOSAR E2E Profile 1 (with an initial value and
+/// final XOR of zero, see [`crc8()`]).
+pub fn crc8_sae_j1850(data: &[u8]) -> u8 {
+    crc8(data, 0x1D, 0xFF) ^ 0xFF
+}
+
+/// The CRC-8/AUTOSAR (8H2F) checksum.
+///
+/// Polynomial `0x2F`, initial value `0xFF`, and final XOR `0xFF`.
+pub fn crc8_autosar(data: &[u8]) -> u8 {
+    crc8(data, 0x2F, 0xFF) ^ 0xFF
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The standard check input from the CRC catalogue
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn test_xor() {
+        assert_eq!(xor(&[], 0), 0);
+        assert_eq!(xor(&[0x12, 0x34, 0x56], 0), 0x12 ^ 0x34 ^ 0x56);
+        assert_eq!(xor(&[0xFF, 0x0F], 0xA5), 0x55);
+        assert_eq!(xor(CHECK, 0), 0x31);
+    }
+
+    #[test]
+    fn test_crc8() {
+        // CRC-8/SMBUS
+        assert_eq!(crc8(CHECK, 0x07, 0x00), 0xF4);
+        // CRC-8/SAE-J1850
+        assert_eq!(crc8_sae_j1850(CHECK), 0x4B);
+        // CRC-8/AUTOSAR
+        assert_eq!(crc8_autosar(CHECK), 0xDF);
+        // CRC-8/SAE-J1850 with zero init and no final XOR (E2E Profile 1)
+        assert_eq!(crc8(CHECK, 0x1D, 0x00), 0x37);
+    }
+
+    #[test]
+    fn test_crc8_table() {
+        for poly in [0x07, 0x1D, 0x2F] {
+            let table = crc8_table(poly);
+            assert_eq!(table[0], 0);
+            assert_eq!(table[1], poly);
+            for init in [0x00, 0xFF] {
+                assert_eq!(
+                    crc8_with_table(&table, CHECK, init),
+                    crc8(CHECK, poly, init)
+                );
+            }
+        }
+    }
+}
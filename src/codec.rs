@@ -0,0 +1,207 @@
+// socketcan/src/codec.rs
+//
+// Codecs to carry CAN frames over byte streams as fixed-size records.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Codecs to carry CAN frames over byte streams.
+//!
+//! To tunnel CAN over a TCP connection, a TLS session, a Unix socket, or
+//! any other byte stream, each frame is sent as a fixed-size record in
+//! the same layout that the kernel uses for a raw CAN socket:
+//!
+//! - [`CanFrameCodec`] sends each classic frame as a 16-byte
+//!   `can_frame`.
+//! - [`CanFdFrameCodec`] sends every frame as a 72-byte `canfd_frame`.
+//!   An FD frame has the `CANFD_FDF` flag set, and a classic frame is
+//!   zero-padded to the full size, without it.
+//!
+//! The CAN ID is in the host's byte order, as it is for the socket, so
+//! both ends should have the same endianness.
+//!
+//! The codecs only turn frames into records and back. With the **tokio**
+//! feature, `tokio::CanFramed` uses them to make a `Stream` and `Sink` of
+//! frames from any `AsyncRead` + `AsyncWrite` stream.
+//!
+//! ```
+//! use socketcan::{codec::{CanFrameCodec, FrameCodec}, CanFrame, EmbeddedFrame, StandardId};
+//!
+//! let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2, 3]).unwrap();
+//! let mut buf = Vec::new();
+//! CanFrameCodec.encode(&frame, &mut buf);
+//! assert_eq!(buf.len(), CanFrameCodec.record_len());
+//!
+//! let decoded = CanFrameCodec.decode(&buf).unwrap();
+//! assert_eq!(decoded.data(), &[1, 2, 3]);
+//! ```
+
+use crate::{
+    as_bytes_mut,
+    frame::{can_frame_default, canfd_frame_default, AsPtr},
+    id::CANFD_FDF,
+    CanAnyFrame, CanFdFrame, CanFrame,
+};
+use libc::{CANFD_MTU, CAN_MAX_DLEN, CAN_MTU};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult};
+
+/// The offset of the FD flags in a `canfd_frame`, which is padding in a
+/// classic `can_frame`.
+const FLAGS_OFFSET: usize = 5;
+
+/// A way of turning frames into fixed-size records on a byte stream, and
+/// back.
+pub trait FrameCodec {
+    /// The type of frame carried in the records
+    type Item;
+
+    /// The size of each record, in bytes.
+    fn record_len(&self) -> usize;
+
+    /// Appends the record for the frame to the buffer.
+    fn encode(&self, frame: &Self::Item, dst: &mut Vec<u8>);
+
+    /// Decodes a frame from a record.
+    ///
+    /// The record has to be [`record_len()`](Self::record_len) bytes.
+    /// If it doesn't hold a valid frame, this fails with an `InvalidData`
+    /// error.
+    fn decode(&self, record: &[u8]) -> IoResult<Self::Item>;
+}
+
+/// A codec for classic CAN frames, as 16-byte `can_frame` records.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CanFrameCodec;
+
+impl FrameCodec for CanFrameCodec {
+    type Item = CanFrame;
+
+    fn record_len(&self) -> usize {
+        CAN_MTU
+    }
+
+    fn encode(&self, frame: &CanFrame, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(frame.as_bytes());
+    }
+
+    fn decode(&self, record: &[u8]) -> IoResult<CanFrame> {
+        if record.len() != CAN_MTU {
+            return Err(invalid_record());
+        }
+        decode_classic(record)
+    }
+}
+
+/// A codec for CAN FD and classic frames, as 72-byte `canfd_frame`
+/// records.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CanFdFrameCodec;
+
+impl FrameCodec for CanFdFrameCodec {
+    type Item = CanAnyFrame;
+
+    fn record_len(&self) -> usize {
+        CANFD_MTU
+    }
+
+    fn encode(&self, frame: &CanAnyFrame, dst: &mut Vec<u8>) {
+        let start = dst.len();
+        dst.extend_from_slice(frame.as_bytes());
+        if !matches!(frame, CanAnyFrame::Fd(_)) {
+            // The padding byte of a classic frame is where the FD flags go
+            dst[start + FLAGS_OFFSET] = 0;
+        }
+        dst.resize(start + CANFD_MTU, 0);
+    }
+
+    fn decode(&self, record: &[u8]) -> IoResult<CanAnyFrame> {
+        if record.len() != CANFD_MTU {
+            return Err(invalid_record());
+        }
+        if record[FLAGS_OFFSET] & CANFD_FDF as u8 == 0 {
+            return decode_classic(&record[..CAN_MTU]).map(CanAnyFrame::from);
+        }
+        if !CanFdFrame::is_valid_data_len(record[4] as usize) {
+            return Err(invalid_record());
+        }
+        let mut frame = canfd_frame_default();
+        as_bytes_mut(&mut frame).copy_from_slice(record);
+        Ok(CanFdFrame::from(frame).into())
+    }
+}
+
+/// Decodes a classic frame from its 16-byte record.
+fn decode_classic(record: &[u8]) -> IoResult<CanFrame> {
+    if record[4] as usize > CAN_MAX_DLEN {
+        return Err(invalid_record());
+    }
+    let mut frame = can_frame_default();
+    as_bytes_mut(&mut frame).copy_from_slice(record);
+    Ok(CanFrame::from(frame))
+}
+
+/// The error for a record that doesn't hold a valid frame.
+fn invalid_record() -> IoError {
+    IoError::new(IoErrorKind::InvalidData, "invalid CAN frame record")
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmbeddedFrame, ExtendedId, Frame, StandardId};
+
+    #[test]
+    fn test_can_codec() {
+        let id = ExtendedId::new(0x1234_5678).unwrap();
+        let frame = CanFrame::new(id, &[1, 2, 3, 4]).unwrap();
+
+        let mut buf = vec![0xAA];
+        CanFrameCodec.encode(&frame, &mut buf);
+        assert_eq!(buf.len(), 1 + CAN_MTU);
+
+        let decoded = CanFrameCodec.decode(&buf[1..]).unwrap();
+        assert_eq!(decoded.raw_id(), 0x1234_5678);
+        assert!(decoded.is_extended());
+        assert_eq!(decoded.data(), &[1, 2, 3, 4]);
+
+        assert!(CanFrameCodec.decode(&buf).is_err());
+        buf[1 + 4] = 9;
+        assert_eq!(
+            CanFrameCodec.decode(&buf[1..]).unwrap_err().kind(),
+            IoErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_canfd_codec() {
+        let id = StandardId::new(0x100).unwrap();
+        let classic = CanFrame::new(id, &[1, 2]).unwrap();
+        let fd = CanFdFrame::new(id, &[0x55; 12]).unwrap();
+
+        let mut buf = Vec::new();
+        CanFdFrameCodec.encode(&classic.into(), &mut buf);
+        CanFdFrameCodec.encode(&fd.into(), &mut buf);
+        assert_eq!(buf.len(), 2 * CANFD_MTU);
+
+        let (first, second) = buf.split_at(CANFD_MTU);
+        match CanFdFrameCodec.decode(first).unwrap() {
+            CanAnyFrame::Normal(frame) => assert_eq!(frame.data(), &[1, 2]),
+            frame => panic!("unexpected {:?}", frame),
+        }
+        match CanFdFrameCodec.decode(second).unwrap() {
+            CanAnyFrame::Fd(frame) => assert_eq!(frame.data(), &[0x55; 12]),
+            frame => panic!("unexpected {:?}", frame),
+        }
+
+        // 13 isn't one of the FD lengths
+        let mut bad = second.to_vec();
+        bad[4] = 13;
+        assert!(CanFdFrameCodec.decode(&bad).is_err());
+    }
+}
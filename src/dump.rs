@@ -28,21 +28,37 @@
 //!
 //! Can be parsed by a `Reader` object. The API is inspired by the
 //! [csv](https://crates.io/crates/csv) crate.
+//!
+//! Single lines of the default console output of `candump` (without the
+//! `-L` option) can be parsed with [`parse_candump_ascii()`]:
+//!
+//! ```text
+//!   can0  123   [4]  DE AD BE EF
+//!   can0  12345678   [2]  01 02
+//!   can0  123   [2]  remote request
+//! ```
+//!
+//! The records from a log can be sent back out to the bus, with their
+//! original timing, by a [`Replayer`].
 
 use crate::{
     frame::Frame,
-    id::{id_from_raw, FdFlags},
-    CanAnyFrame, CanDataFrame, CanFdFrame, CanFrame, CanRemoteFrame, ConstructionError,
+    id::{id_from_raw, FdFlags, CAN_ERR_FLAG, CAN_MAX_DLEN},
+    CanAnyFrame, CanDataFrame, CanErrorFrame, CanFdFrame, CanFdSocket, CanFrame, CanRemoteFrame,
+    ConstructionError, Socket,
 };
-use embedded_can::Frame as EmbeddedFrame;
+use embedded_can::{ExtendedId, Frame as EmbeddedFrame, Id, StandardId};
 use hex::FromHex;
 use itertools::Itertools;
 use libc::canid_t;
 use std::{
+    collections::HashMap,
     fmt,
     fs::File,
     io::{self, BufRead, BufReader},
     path::Path,
+    thread,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
@@ -108,6 +124,93 @@ impl fmt::Display for CanDumpRecord {
     }
 }
 
+/////////////////////////////////////////////////////////////////////////////
+// ASCII console format
+
+/// Parses a line of the default `candump` console output.
+///
+/// This is the space-delimited format that `candump` prints when it's not
+/// logging to a file (no `-L` option), like:
+///
+/// ```text
+///   can0  123   [4]  DE AD BE EF
+/// ```
+///
+/// IDs printed with more than three hex digits are taken to be extended
+/// IDs, as that's how `candump` distinguishes them. Remote frames show the
+/// text `remote request` in place of the data, and error frames have the
+/// error flag set in the ID. Any text following the data bytes, such as
+/// the ASCII dump from the `-a` option, is ignored.
+///
+/// Returns the name of the interface and the frame.
+pub fn parse_candump_ascii(line: &str) -> Result<(String, CanFrame), ParseError> {
+    let mut field_iter = line.split_whitespace();
+
+    // device name
+    let device = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
+
+    if device.starts_with('(') {
+        return Err(ParseError::InvalidDeviceName);
+    }
+
+    // Parse the CAN ID
+    let can_id_str = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
+    let raw_id =
+        canid_t::from_str_radix(can_id_str, 16).map_err(|_| ParseError::InvalidCanFrame)?;
+
+    // Parse the "[len]" field
+    let len = field_iter
+        .next()
+        .ok_or(ParseError::UnexpectedEndOfLine)?
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|&n| n <= CAN_MAX_DLEN)
+        .ok_or(ParseError::InvalidCanFrame)?;
+
+    let mut field_iter = field_iter.peekable();
+
+    if field_iter.peek() == Some(&"remote") {
+        if field_iter.nth(1) != Some("request") {
+            return Err(ParseError::InvalidCanFrame);
+        }
+        let can_id = ascii_id(can_id_str, raw_id)?;
+        let frame = CanRemoteFrame::new_remote(can_id, len).ok_or(ParseError::InvalidCanFrame)?;
+        return Ok((device.to_string(), frame.into()));
+    }
+
+    let mut data = Vec::with_capacity(len);
+    for _ in 0..len {
+        let byte = field_iter.next().ok_or(ParseError::UnexpectedEndOfLine)?;
+        if byte.len() != 2 {
+            return Err(ParseError::InvalidCanFrame);
+        }
+        data.push(u8::from_str_radix(byte, 16).map_err(|_| ParseError::InvalidCanFrame)?);
+    }
+
+    let frame: CanFrame = if can_id_str.len() > 3 && raw_id & CAN_ERR_FLAG != 0 {
+        CanErrorFrame::new_error(raw_id, &data)?.into()
+    } else {
+        let can_id = ascii_id(can_id_str, raw_id)?;
+        CanDataFrame::new(can_id, &data)
+            .ok_or(ParseError::InvalidCanFrame)?
+            .into()
+    };
+
+    Ok((device.to_string(), frame))
+}
+
+// Gets the ID from the candump ASCII field, using the width of the field
+// to determine if it's standard or extended.
+fn ascii_id(can_id_str: &str, raw_id: canid_t) -> Result<Id, ParseError> {
+    let id: Option<Id> = if can_id_str.len() > 3 {
+        ExtendedId::new(raw_id).map(Id::from)
+    } else {
+        StandardId::new(raw_id as u16).map(Id::from)
+    };
+    id.ok_or(ParseError::InvalidCanFrame)
+}
+
 /////////////////////////////////////////////////////////////////////////////
 // Reader
 
@@ -140,7 +243,7 @@ impl Reader<File> {
 impl<R: BufRead> Reader<R> {
     /// Returns an iterator over all records
     #[deprecated(since = "3.5.0", note = "Use `iter()`")]
-    pub fn records(&mut self) -> CanDumpRecords<R> {
+    pub fn records(&mut self) -> CanDumpRecords<'_, R> {
         CanDumpRecords { src: self }
     }
 
@@ -270,9 +373,162 @@ impl<R: io::Read> Iterator for CanDumpRecords<'_, BufReader<R>> {
     }
 }
 
+/////////////////////////////////////////////////////////////////////////////
+// Replayer
+
+/// Sends the frames from a candump log out to the bus, with their
+/// original timing.
+///
+/// The frames are sent on the sockets given for each interface named in
+/// the log. Records for any other interface are skipped, so a subset of
+/// the buses in a log can be replayed. The time between frames can be
+/// scaled by a speed factor, and long idle gaps in the log can be capped.
+///
+/// The frames are scheduled against a monotonic clock from the start of
+/// the replay, so the timing doesn't drift, even over a long log.
+///
+/// ```no_run
+/// use socketcan::{dump::{Reader, Replayer}, CanFdSocket, Socket};
+/// use std::time::Duration;
+///
+/// let replayer = Replayer::new()
+///     .socket("can0", CanFdSocket::open("vcan0").unwrap())
+///     .speed(2.0)
+///     .max_gap(Duration::from_secs(1));
+///
+/// let reader = Reader::from_file("candump.log").unwrap();
+/// replayer.play(reader).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Replayer {
+    /// The sockets on which to send the frames, by interface name
+    sockets: HashMap<String, CanFdSocket>,
+    /// The speed factor
+    speed: f64,
+    /// The maximum delay between frames
+    max_gap: Option<Duration>,
+}
+
+impl Default for Replayer {
+    fn default() -> Self {
+        Self {
+            sockets: HashMap::new(),
+            speed: 1.0,
+            max_gap: None,
+        }
+    }
+}
+
+impl Replayer {
+    /// Creates a replayer with no sockets, playing at the original speed.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the socket used to send the frames logged for the named
+    /// interface.
+    pub fn socket(mut self, iface: impl Into<String>, sock: CanFdSocket) -> Self {
+        self.sockets.insert(iface.into(), sock);
+        self
+    }
+
+    /// Sets the speed factor for the replay.
+    ///
+    /// A factor of 2.0 plays the log twice as fast as it was recorded, and
+    /// 0.5 at half speed. An infinite factor sends the frames as fast as
+    /// possible.
+    ///
+    /// # Panics
+    ///
+    /// If the factor is not greater than zero.
+    pub fn speed(mut self, factor: f64) -> Self {
+        assert!(factor > 0.0, "the replay speed must be positive");
+        self.speed = factor;
+        self
+    }
+
+    /// Sets the longest delay between two frames.
+    ///
+    /// Any longer gap in the log is shortened to this. It's applied to the
+    /// gap in the log, before the speed factor.
+    pub fn max_gap(mut self, gap: Duration) -> Self {
+        self.max_gap = Some(gap);
+        self
+    }
+
+    /// Gets the delay between two records, `gap_us` microseconds apart in
+    /// the log.
+    fn delay(&self, gap_us: u64) -> Duration {
+        let mut gap = Duration::from_micros(gap_us);
+        if let Some(max_gap) = self.max_gap {
+            gap = gap.min(max_gap);
+        }
+        gap.div_f64(self.speed)
+    }
+
+    /// Plays the records, sending each frame on the socket for its
+    /// interface.
+    ///
+    /// Error frames can't be sent, and are skipped, as are the records for
+    /// interfaces without a socket. This returns the number of frames that
+    /// were sent, and stops at the first error reading a record or sending
+    /// a frame.
+    pub fn play<I>(&self, records: I) -> Result<usize, ParseError>
+    where
+        I: IntoIterator<Item = Result<CanDumpRecord, ParseError>>,
+    {
+        self.play_with(records, |iface, frame| match self.sockets.get(iface) {
+            Some(sock) => sock.write_frame(frame).map(|_| true),
+            None => Ok(false),
+        })
+    }
+
+    /// Plays the records with the original timing, passing each frame to
+    /// the `send` function rather than a socket.
+    ///
+    /// The function receives the name of the interface and the frame, and
+    /// returns whether it sent the frame. Error frames are skipped. This
+    /// returns the number of frames sent.
+    pub fn play_with<I, F>(&self, records: I, mut send: F) -> Result<usize, ParseError>
+    where
+        I: IntoIterator<Item = Result<CanDumpRecord, ParseError>>,
+        F: FnMut(&str, &CanAnyFrame) -> io::Result<bool>,
+    {
+        let start = Instant::now();
+        let mut offset = Duration::ZERO;
+        let mut last_us = None;
+        let mut nsent = 0;
+
+        for rec in records {
+            let rec = rec?;
+
+            if let Some(last_us) = last_us {
+                offset += self.delay(rec.t_us.saturating_sub(last_us));
+            }
+            last_us = Some(rec.t_us);
+
+            if matches!(rec.frame, CanAnyFrame::Error(_)) {
+                continue;
+            }
+
+            let deadline = start + offset;
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+
+            if send(&rec.device, &rec.frame)? {
+                nsent += 1;
+            }
+        }
+        Ok(nsent)
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     use super::*;
     use crate::{CanAnyFrame, Frame};
@@ -467,4 +723,86 @@ mod test {
 
         assert!(reader.next_record().unwrap().is_none());
     }
+
+    #[test]
+    fn test_ascii() {
+        let (device, frame) = parse_candump_ascii("  can0  123   [4]  DE AD BE EF").unwrap();
+        assert_eq!(device, "can0");
+        assert!(matches!(frame, CanFrame::Data(_)));
+        assert_eq!(frame.raw_id(), 0x123);
+        assert!(!frame.is_extended());
+        assert_eq!(frame.data(), &[0xDE, 0xAD, 0xBE, 0xEF]);
+
+        // Extended IDs are always printed with 8 digits
+        let (_, frame) = parse_candump_ascii("  vcan0  0000007F   [2]  01 02").unwrap();
+        assert!(frame.is_extended());
+        assert_eq!(frame.raw_id(), 0x7F);
+        assert_eq!(frame.data(), &[0x01, 0x02]);
+
+        let (_, frame) = parse_candump_ascii("  can1  080   [0]").unwrap();
+        assert!(matches!(frame, CanFrame::Data(_)));
+        assert_eq!(frame.data(), &[]);
+
+        let (_, frame) = parse_candump_ascii("  can1  104   [3]  remote request").unwrap();
+        assert!(frame.is_remote_frame());
+        assert_eq!(frame.raw_id(), 0x104);
+        assert_eq!(frame.dlc(), 3);
+
+        // Trailing ASCII from the '-a' option is ignored
+        let (_, frame) = parse_candump_ascii("  can0  123   [2]  41 42   'AB'").unwrap();
+        assert_eq!(frame.data(), b"AB");
+
+        let (_, frame) =
+            parse_candump_ascii("  can0  20000004   [8]  00 04 00 00 00 00 00 00   ERRORFRAME")
+                .unwrap();
+        assert!(frame.is_error_frame());
+
+        assert!(matches!(
+            parse_candump_ascii("  can0  123   [4]  DE AD"),
+            Err(ParseError::UnexpectedEndOfLine)
+        ));
+        assert!(matches!(
+            parse_candump_ascii("  can0  123   4  DE AD BE EF"),
+            Err(ParseError::InvalidCanFrame)
+        ));
+        assert!(matches!(
+            parse_candump_ascii("(1469439874.299591) can1 080#"),
+            Err(ParseError::InvalidDeviceName)
+        ));
+    }
+
+    #[test]
+    fn test_replay() {
+        let input: &[u8] = b"(1469439874.000000) can0 080#\n\
+                             (1469439874.020000) can1 081#01\n\
+                             (1469439884.020000) can0 082#02\n";
+
+        let replayer = Replayer::new()
+            .speed(2.0)
+            .max_gap(Duration::from_millis(30));
+
+        assert_eq!(replayer.delay(20_000), Duration::from_millis(10));
+        assert_eq!(replayer.delay(10_000_000), Duration::from_millis(15));
+
+        let mut sent = Vec::new();
+        let start = Instant::now();
+
+        let n = replayer
+            .play_with(Reader::from_reader(input), |iface, frame| {
+                if iface != "can0" {
+                    return Ok(false);
+                }
+                sent.push((start.elapsed(), frame.raw_id()));
+                Ok(true)
+            })
+            .unwrap();
+
+        assert_eq!(n, 2);
+        assert_eq!(sent[0].1, 0x080);
+        assert_eq!(sent[1].1, 0x082);
+
+        // The 10s gap is capped, then halved, to 15ms, after the first 10ms
+        assert!(sent[1].0 >= Duration::from_millis(25));
+        assert!(sent[1].0 < Duration::from_secs(1));
+    }
 }
@@ -59,6 +59,18 @@ pub enum Error {
     Io(#[from] io::Error),
 }
 
+impl Error {
+    /// Determines if the error indicates that the CAN interface went away.
+    ///
+    /// See [`is_interface_gone`] for details.
+    pub fn is_interface_gone(&self) -> bool {
+        match self {
+            Error::Io(err) => is_interface_gone(err),
+            _ => false,
+        }
+    }
+}
+
 impl embedded_can::Error for Error {
     fn kind(&self) -> embedded_can::ErrorKind {
         match *self {
@@ -105,6 +117,20 @@ pub type IoErrorKind = io::ErrorKind;
 /// An I/O specific result
 pub type IoResult<T> = io::Result<T>;
 
+/// Determines if an I/O error indicates that the CAN interface went away.
+///
+/// When a USB adapter is unplugged, or the interface is deleted or brought
+/// down, reads and writes on a bound socket start failing with `ENODEV`
+/// or `ENETDOWN` (and `ENXIO` from some drivers). None of these will clear
+/// up by retrying, so an application would typically close the socket and
+/// start its reconnect logic when this returns `true`.
+pub fn is_interface_gone(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::ENODEV) | Some(libc::ENETDOWN) | Some(libc::ENXIO)
+    )
+}
+
 // ===== CanError ====
 
 /// A CAN bus error derived from an error frame.
@@ -590,6 +616,8 @@ pub enum ConstructionError {
     IDTooLarge,
     /// Larger payload reported than can be held in the frame.
     TooMuchData,
+    /// Smaller payload than the minimum required by the frame type.
+    NotEnoughData,
 }
 
 impl error::Error for ConstructionError {}
@@ -601,15 +629,120 @@ impl fmt::Display for ConstructionError {
             WrongFrameType => "Incompatible frame type",
             IDTooLarge => "CAN ID too large",
             TooMuchData => "Payload is too large",
+            NotEnoughData => "Payload is too small",
         };
         write!(f, "{}", msg)
     }
 }
 
+// ===== SocketError =====
+
+/// Errors in the arguments to a socket call, detected before the call is
+/// made to the kernel.
+///
+/// These are returned wrapped in an `io::Error` with a kind of
+/// `InvalidInput`, to give a clearer message than the bare `EINVAL` that
+/// the kernel would report. The exceptions are [`SocketError::FdNotSupported`],
+/// which has a kind of `Unsupported`, and [`SocketError::Truncated`], which
+/// has a kind of `InvalidData`.
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SocketError {
+    /// More filters were given than the kernel accepts on a socket.
+    #[error("Too many CAN filters: {got} given, but the maximum is {max}")]
+    TooManyFilters {
+        /// The number of filters that were given
+        got: usize,
+        /// The maximum number of filters allowed
+        max: usize,
+    },
+    /// An FD frame was sent on a socket that doesn't have FD mode enabled.
+    #[error("FD frames are not enabled on the socket")]
+    FdFramesDisabled,
+    /// XL frames were enabled on a socket that doesn't have FD mode
+    /// enabled. This crate only allows XL frames alongside FD frames.
+    #[error("XL frames require FD frames to be enabled on the socket")]
+    XlRequiresFd,
+    /// An XL frame was sent on a socket that doesn't have XL frames
+    /// enabled.
+    #[error("XL frames are not enabled on the socket")]
+    XlFramesDisabled,
+    /// A frame was scheduled for transmission on a socket that doesn't
+    /// have scheduled transmission (`SO_TXTIME`) enabled.
+    #[error("Scheduled transmission (SO_TXTIME) is not enabled on the socket")]
+    TxTimeDisabled,
+    /// Scheduled transmission was enabled on a socket whose interface has
+    /// no ETF qdisc to hold the frames until their time, or that isn't
+    /// bound to a single interface.
+    #[error("The interface has no ETF qdisc for scheduled transmission")]
+    NoEtfQdisc,
+    /// The kernel doesn't support CAN FD frames on raw sockets.
+    ///
+    /// This is reported in place of the `ENOPROTOOPT` error that old
+    /// kernels return when FD mode is enabled on a socket.
+    #[error("FD frames are not supported by the kernel")]
+    FdNotSupported,
+    /// The ISO-TP single-frame and consecutive-frame broadcast modes were
+    /// both enabled, which the kernel rejects.
+    #[error("ISO-TP functional (SF) and CF broadcast modes can't be combined")]
+    IsoTpBroadcastConflict,
+    /// A separate ISO-TP receive extended address was given without
+    /// extended addressing, so the kernel would ignore it.
+    #[error("An ISO-TP rx extended address requires extended addressing")]
+    IsoTpRxExtAddrWithoutExtAddr,
+    /// An ISO-TP transmit data length was given that isn't one of the CAN
+    /// FD frame sizes.
+    #[error("Invalid ISO-TP tx data length: {0}")]
+    IsoTpInvalidTxDataLength(u8),
+    /// The ISO-TP bit rate switch was enabled without CAN FD frames, which
+    /// the kernel rejects.
+    #[error("The ISO-TP bit rate switch requires CAN FD frames")]
+    IsoTpBrsWithoutFd,
+    /// A BCM job was given no frames, or more than the kernel accepts.
+    #[error("Invalid number of BCM frames: {got} given, but 1 to {max} are allowed")]
+    BcmFrameCount {
+        /// The number of frames that were given
+        got: usize,
+        /// The maximum number of frames allowed
+        max: usize,
+    },
+    /// The frames of a multiplexed BCM transmission job don't all have
+    /// the same CAN ID, which identifies the job.
+    #[error("The frames of a BCM job must all have the same CAN ID")]
+    BcmMixedIds,
+    /// A frame was larger than the buffer it was read into, so the kernel
+    /// truncated it.
+    #[error(
+        "Frame truncated to fit a {received}-byte buffer{}",
+        .actual.map(|n| format!(", from {} bytes", n)).unwrap_or_default()
+    )]
+    Truncated {
+        /// The size of the buffer, which is the number of bytes kept
+        received: usize,
+        /// The real size of the frame, if the protocol reports it.
+        ///
+        /// ISO-TP reports the full size of a message that didn't fit, but
+        /// raw CAN, J1939, and BCM sockets don't, so this is `None` for
+        /// them.
+        actual: Option<usize>,
+    },
+}
+
+impl From<SocketError> for io::Error {
+    fn from(err: SocketError) -> Self {
+        let kind = match err {
+            SocketError::FdNotSupported => io::ErrorKind::Unsupported,
+            SocketError::Truncated { .. } => io::ErrorKind::InvalidData,
+            _ => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
 mod tests {
+    use super::{is_interface_gone, SocketError};
     use crate::Error;
     use std::io;
 
@@ -633,4 +766,64 @@ mod tests {
             panic!("Wrong error conversion");
         }
     }
+
+    #[test]
+    fn test_interface_gone() {
+        for errno in [libc::ENODEV, libc::ENETDOWN, libc::ENXIO] {
+            let err = io::Error::from_raw_os_error(errno);
+            assert!(is_interface_gone(&err));
+            assert!(Error::from(err).is_interface_gone());
+        }
+
+        let err = io::Error::from_raw_os_error(libc::EAGAIN);
+        assert!(!is_interface_gone(&err));
+        assert!(!Error::from(io::ErrorKind::TimedOut).is_interface_gone());
+    }
+
+    #[test]
+    fn test_socket_error() {
+        let err = io::Error::from(SocketError::TooManyFilters { got: 600, max: 512 });
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "Too many CAN filters: 600 given, but the maximum is 512"
+        );
+
+        let inner = err.get_ref().unwrap().downcast_ref::<SocketError>();
+        assert_eq!(
+            inner,
+            Some(&SocketError::TooManyFilters { got: 600, max: 512 })
+        );
+
+        let err = io::Error::from(SocketError::XlRequiresFd);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let err = io::Error::from(SocketError::XlFramesDisabled);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let err = io::Error::from(SocketError::TxTimeDisabled);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let err = io::Error::from(SocketError::NoEtfQdisc);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let err = io::Error::from(SocketError::FdNotSupported);
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+        let err = io::Error::from(SocketError::Truncated {
+            received: 72,
+            actual: Some(2060),
+        });
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "Frame truncated to fit a 72-byte buffer, from 2060 bytes"
+        );
+
+        let err = SocketError::Truncated {
+            received: 8,
+            actual: None,
+        };
+        assert_eq!(err.to_string(), "Frame truncated to fit a 8-byte buffer");
+    }
 }
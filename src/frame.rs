@@ -33,7 +33,7 @@
 use crate::{id::CanId, CanError, ConstructionError};
 use embedded_can::{ExtendedId, Frame as EmbeddedFrame, Id, StandardId};
 use itertools::Itertools;
-use libc::{can_frame, canfd_frame, canid_t};
+use libc::{can_frame, canfd_frame, canid_t, canxl_frame, CANXL_HDR_SIZE};
 use std::{
     ffi::c_void,
     mem::size_of,
@@ -46,6 +46,10 @@ pub use crate::id::{
     CANFD_MAX_DLEN, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_ERR_MASK, CAN_MAX_DLEN,
     CAN_RTR_FLAG, CAN_SFF_MASK, ERR_MASK_ALL, ERR_MASK_NONE,
 };
+pub use crate::id::{
+    XlFlags, CANXL_MAX_DLEN, CANXL_MIN_DLEN, CANXL_PRIO_MASK, CANXL_SEC, CANXL_VCID_MASK,
+    CANXL_VCID_OFFSET, CANXL_VCID_VAL_MASK, CANXL_XLF,
+};
 
 // ===== can_frame =====
 
@@ -63,6 +67,13 @@ pub fn canfd_frame_default() -> canfd_frame {
     unsafe { mem::zeroed() }
 }
 
+/// Creates a default C `canxl_frame`.
+/// This initializes the entire structure to zeros.
+#[inline(always)]
+pub fn canxl_frame_default() -> canxl_frame {
+    unsafe { mem::zeroed() }
+}
+
 // ===== AsPtr trait =====
 
 /// Trait to get a pointer to an inner type
@@ -216,6 +227,68 @@ pub enum CanAnyFrame {
     Fd(CanFdFrame),
 }
 
+impl CanAnyFrame {
+    /// Gets the frame as a classic data frame, if it is one.
+    pub fn as_data(&self) -> Option<&CanDataFrame> {
+        match self {
+            CanAnyFrame::Normal(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// Gets the frame as a remote frame, if it is one.
+    pub fn as_remote(&self) -> Option<&CanRemoteFrame> {
+        match self {
+            CanAnyFrame::Remote(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// Gets the frame as an error frame, if it is one.
+    pub fn as_error(&self) -> Option<&CanErrorFrame> {
+        match self {
+            CanAnyFrame::Error(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// Gets the frame as an FD frame, if it is one.
+    pub fn as_fd(&self) -> Option<&CanFdFrame> {
+        match self {
+            CanAnyFrame::Fd(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// Gets the frame identifier, without the EFF/RTR/ERR flags.
+    ///
+    /// This is the same as `EmbeddedFrame::id()`, but doesn't require the
+    /// trait to be in scope.
+    pub fn id(&self) -> Id {
+        EmbeddedFrame::id(self)
+    }
+
+    /// Determines if the frame uses a 29-bit extended ID.
+    pub fn is_extended(&self) -> bool {
+        EmbeddedFrame::is_extended(self)
+    }
+
+    /// Determines if this is a remote frame.
+    pub fn is_remote(&self) -> bool {
+        matches!(self, CanAnyFrame::Remote(_))
+    }
+
+    /// Determines if this is an error frame.
+    pub fn is_error(&self) -> bool {
+        matches!(self, CanAnyFrame::Error(_))
+    }
+
+    /// Determines if this is an FD frame.
+    pub fn is_fd(&self) -> bool {
+        matches!(self, CanAnyFrame::Fd(_))
+    }
+}
+
 impl Frame for CanAnyFrame {
     /// Get the composite SocketCAN ID word, with EFF/RTR/ERR flags
     fn id_word(&self) -> canid_t {
@@ -479,6 +552,89 @@ pub enum CanFrame {
     Error(CanErrorFrame),
 }
 
+impl CanFrame {
+    /// Gets the frame as a data frame, if it is one.
+    pub fn as_data(&self) -> Option<&CanDataFrame> {
+        match self {
+            CanFrame::Data(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// Gets the frame as a remote frame, if it is one.
+    pub fn as_remote(&self) -> Option<&CanRemoteFrame> {
+        match self {
+            CanFrame::Remote(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// Gets the frame as an error frame, if it is one.
+    pub fn as_error(&self) -> Option<&CanErrorFrame> {
+        match self {
+            CanFrame::Error(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    /// Gets the frame identifier, without the EFF/RTR/ERR flags.
+    ///
+    /// This is the same as `EmbeddedFrame::id()`, but doesn't require the
+    /// trait to be in scope.
+    pub fn id(&self) -> Id {
+        EmbeddedFrame::id(self)
+    }
+
+    /// Determines if the frame uses a 29-bit extended ID.
+    pub fn is_extended(&self) -> bool {
+        EmbeddedFrame::is_extended(self)
+    }
+
+    /// Determines if this is a remote frame.
+    pub fn is_remote(&self) -> bool {
+        matches!(self, CanFrame::Remote(_))
+    }
+
+    /// Determines if this is an error frame.
+    pub fn is_error(&self) -> bool {
+        matches!(self, CanFrame::Error(_))
+    }
+
+    /// Determines if this is an FD frame, which a classic frame never is.
+    ///
+    /// This is for symmetry with `CanAnyFrame::is_fd()`.
+    pub fn is_fd(&self) -> bool {
+        false
+    }
+
+    /// Creates a frame from any other type of `embedded_can` frame.
+    ///
+    /// This keeps the ID type and the remote flag of the original, and
+    /// fails with `TooMuchData` if it holds more than 8 bytes of data.
+    pub fn from_embedded<F: EmbeddedFrame>(frame: &F) -> Result<Self, ConstructionError> {
+        let frame = if frame.is_remote_frame() {
+            CanFrame::new_remote(frame.id(), frame.dlc())
+        } else {
+            CanFrame::new(frame.id(), frame.data())
+        };
+        frame.ok_or(ConstructionError::TooMuchData)
+    }
+
+    /// Converts the frame into any other type of `embedded_can` frame.
+    ///
+    /// This fails with `TooMuchData` if the target type can't hold all
+    /// the data of the frame, and with `WrongFrameType` for an error frame,
+    /// as those have no equivalent in `embedded_can`.
+    pub fn to_embedded<F: EmbeddedFrame>(&self) -> Result<F, ConstructionError> {
+        let frame = match self {
+            CanFrame::Data(frame) => F::new(frame.id(), frame.data()),
+            CanFrame::Remote(frame) => F::new_remote(frame.id(), frame.dlc()),
+            CanFrame::Error(_) => return Err(ConstructionError::WrongFrameType),
+        };
+        frame.ok_or(ConstructionError::TooMuchData)
+    }
+}
+
 impl AsPtr for CanFrame {
     type Inner = can_frame;
 
@@ -1240,7 +1396,8 @@ const VALID_EXT_DLENGTHS: [usize; 7] = [12, 16, 20, 24, 32, 48, 64];
 ///
 /// Payload data that is greater than 8 bytes and whose data length does
 /// not match a valid CANFD data length is padded with 0 bytes to the
-/// next higher valid CANFD data length.
+/// next higher valid CANFD data length. A different pad byte can be used
+/// by creating the frame with [`CanFdFrame::new_padded()`].
 ///
 /// Note:
 ///   - The FDF flag is forced on when created.
@@ -1254,6 +1411,14 @@ impl CanFdFrame {
         Self::init(can_id, data, flags).ok()
     }
 
+    /// Create a new FD frame, filling any space left when the data is
+    /// rounded up to a valid FD length with the `pad` byte.
+    pub fn new_padded(id: impl Into<Id>, data: &[u8], pad: u8) -> Option<Self> {
+        let mut frame = <Self as EmbeddedFrame>::new(id, data)?;
+        frame.pad_data(data.len(), pad);
+        Some(frame)
+    }
+
     /// Initialize an FD frame from the raw components.
     pub(crate) fn init(
         can_id: u32,
@@ -1273,6 +1438,19 @@ impl CanFdFrame {
         }
     }
 
+    /// Widens a classic frame into the FD frame struct, without the FDF
+    /// flag, to mark that it is not a genuine FD frame.
+    ///
+    /// This keeps the ID word, with its EFF/RTR/ERR flags, along with the
+    /// length (or the requested DLC for a remote frame) and the data.
+    pub(crate) fn from_classic(frame: &can_frame) -> Self {
+        let mut fdframe = canfd_frame_default();
+        fdframe.can_id = frame.can_id;
+        fdframe.len = frame.can_dlc;
+        fdframe.data[..CAN_MAX_DLEN].copy_from_slice(&frame.data);
+        Self(fdframe)
+    }
+
     /// Gets the flags for the FD frame.
     ///
     /// These are the bits from the separate FD frame flags, not the flags
@@ -1310,6 +1488,20 @@ impl CanFdFrame {
         }
     }
 
+    /// Sets the data payload of the frame, filling any space left when the
+    /// data is rounded up to a valid FD length with the `pad` byte.
+    pub fn set_data_padded(&mut self, data: &[u8], pad: u8) -> Result<(), ConstructionError> {
+        self.set_data(data)?;
+        self.pad_data(data.len(), pad);
+        Ok(())
+    }
+
+    // Fills the data from `n` up to the frame length with the pad byte.
+    fn pad_data(&mut self, n: usize, pad: u8) {
+        let len = self.0.len as usize;
+        self.0.data[n..len].fill(pad);
+    }
+
     /// Checks whether a given length is a valid CANFD data length.
     ///
     /// Valid values are `0` - `8`, `12`, `16`, `20`, `24`, `32`, `48` or `64`.
@@ -1484,9 +1676,292 @@ impl AsRef<canfd_frame> for CanFdFrame {
     }
 }
 
+// ===== CanXlFrame =====
+
+/// The CAN XL frame with up to 2048 bytes of data.
+///
+/// This is highly compatible with the `canxl_frame` from libc.
+/// ([ref](https://docs.rs/libc/latest/libc/struct.canxl_frame.html))
+///
+/// Unlike classic and FD frames, an XL frame is sent to and received from
+/// the kernel using only the header and the payload actually in use, so
+/// the size of the frame on the wire is `CANXL_HDR_SIZE + len()`.
+///
+/// Note:
+///   - The XLF flag is forced on when created.
+#[derive(Clone, Copy)]
+pub struct CanXlFrame(canxl_frame);
+
+impl CanXlFrame {
+    /// Create a new XL frame.
+    ///
+    /// The `prio` is the 11-bit priority ID, `sdt` the SDU (service data
+    /// unit) type, and `af` the acceptance field. The data must be between
+    /// `CANXL_MIN_DLEN` and `CANXL_MAX_DLEN` bytes long.
+    pub fn new(prio: StandardId, sdt: u8, af: u32, data: &[u8]) -> Option<Self> {
+        Self::init(prio.as_raw() as canid_t, sdt, af, data, XlFlags::empty()).ok()
+    }
+
+    /// Create a new XL frame with XL flags
+    pub fn with_flags(
+        prio: StandardId,
+        sdt: u8,
+        af: u32,
+        data: &[u8],
+        flags: XlFlags,
+    ) -> Option<Self> {
+        Self::init(prio.as_raw() as canid_t, sdt, af, data, flags).ok()
+    }
+
+    /// Initialize an XL frame from the raw components.
+    pub(crate) fn init(
+        prio: canid_t,
+        sdt: u8,
+        af: u32,
+        data: &[u8],
+        xl_flags: XlFlags,
+    ) -> Result<Self, ConstructionError> {
+        match data.len() {
+            n if (CANXL_MIN_DLEN..=CANXL_MAX_DLEN).contains(&n) => {
+                let mut frame = canxl_frame_default();
+                frame.prio = prio & CANXL_PRIO_MASK;
+                frame.flags = (xl_flags | XlFlags::XLF).bits();
+                frame.sdt = sdt;
+                frame.af = af;
+                frame.len = n as u16;
+                frame.data[..n].copy_from_slice(data);
+                Ok(Self(frame))
+            }
+            0 => Err(ConstructionError::NotEnoughData),
+            _ => Err(ConstructionError::TooMuchData),
+        }
+    }
+
+    /// Gets the 11-bit priority ID of the frame.
+    pub fn prio(&self) -> canid_t {
+        self.0.prio & CANXL_PRIO_MASK
+    }
+
+    /// Sets the 11-bit priority ID of the frame.
+    pub fn set_prio(&mut self, prio: StandardId) {
+        self.0.prio = (self.0.prio & !CANXL_PRIO_MASK) | prio.as_raw() as canid_t;
+    }
+
+    /// Gets the virtual CAN network ID (VCID) of the frame.
+    ///
+    /// The VCID shares the priority word with the priority ID. The kernel
+    /// only passes it through on a socket that has been set up for it with
+    /// [`XlVcidOptions`](crate::XlVcidOptions), and clears it otherwise.
+    pub fn vcid(&self) -> u8 {
+        ((self.0.prio >> CANXL_VCID_OFFSET) & CANXL_VCID_VAL_MASK) as u8
+    }
+
+    /// Sets the virtual CAN network ID (VCID) of the frame.
+    pub fn set_vcid(&mut self, vcid: u8) {
+        self.0.prio = (self.0.prio & !CANXL_VCID_MASK) | (canid_t::from(vcid) << CANXL_VCID_OFFSET);
+    }
+
+    /// Gets the SDU (service data unit) type of the frame.
+    pub fn sdt(&self) -> u8 {
+        self.0.sdt
+    }
+
+    /// Sets the SDU (service data unit) type of the frame.
+    pub fn set_sdt(&mut self, sdt: u8) {
+        self.0.sdt = sdt;
+    }
+
+    /// Gets the acceptance field of the frame.
+    pub fn af(&self) -> u32 {
+        self.0.af
+    }
+
+    /// Sets the acceptance field of the frame.
+    pub fn set_af(&mut self, af: u32) {
+        self.0.af = af;
+    }
+
+    /// Gets the flags for the XL frame.
+    pub fn flags(&self) -> XlFlags {
+        XlFlags::from_bits_truncate(self.0.flags)
+    }
+
+    /// Whether the simple extended content (SEC) flag is set.
+    pub fn sec(&self) -> bool {
+        self.flags().contains(XlFlags::SEC)
+    }
+
+    /// Sets the simple extended content (SEC) flag.
+    pub fn set_sec(&mut self, on: bool) {
+        if on {
+            self.0.flags |= CANXL_SEC as u8;
+        } else {
+            self.0.flags &= !(CANXL_SEC as u8);
+        }
+    }
+
+    /// Get the data length
+    pub fn len(&self) -> usize {
+        self.0.len as usize
+    }
+
+    /// Whether the frame has no data.
+    ///
+    /// A valid XL frame always carries at least one byte of data.
+    pub fn is_empty(&self) -> bool {
+        self.0.len == 0
+    }
+
+    /// A slice into the actual data.
+    pub fn data(&self) -> &[u8] {
+        &self.0.data[..self.len().min(CANXL_MAX_DLEN)]
+    }
+
+    /// Sets the data payload of the frame.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<(), ConstructionError> {
+        match data.len() {
+            n if (CANXL_MIN_DLEN..=CANXL_MAX_DLEN).contains(&n) => {
+                self.0.data[..n].copy_from_slice(data);
+                self.0.data[n..].fill(0);
+                self.0.len = n as u16;
+                Ok(())
+            }
+            0 => Err(ConstructionError::NotEnoughData),
+            _ => Err(ConstructionError::TooMuchData),
+        }
+    }
+
+    /// Checks whether a given length is a valid CAN XL data length.
+    ///
+    /// Valid values are `1` - `2048`.
+    pub fn is_valid_data_len(len: usize) -> bool {
+        (CANXL_MIN_DLEN..=CANXL_MAX_DLEN).contains(&len)
+    }
+}
+
+impl AsPtr for CanXlFrame {
+    type Inner = canxl_frame;
+
+    /// Gets a pointer to the CAN frame structure that is compatible with
+    /// the Linux C API.
+    fn as_ptr(&self) -> *const Self::Inner {
+        &self.0
+    }
+
+    /// Gets a mutable pointer to the CAN frame structure that is compatible
+    /// with the Linux C API.
+    fn as_mut_ptr(&mut self) -> *mut Self::Inner {
+        &mut self.0
+    }
+
+    /// The size of the header plus the data in use.
+    fn size(&self) -> usize {
+        CANXL_HDR_SIZE + self.data().len()
+    }
+}
+
+impl fmt::Debug for CanXlFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CanXlFrame {{ ")?;
+        fmt::UpperHex::fmt(self, f)?;
+        write!(f, " }}")
+    }
+}
+
+impl fmt::UpperHex for CanXlFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(
+            f,
+            "{:03X}###{:02X}:{:02X}:{:08X} ",
+            self.prio(),
+            self.0.flags,
+            self.0.sdt,
+            self.0.af
+        )?;
+        let mut parts = self.data().iter().map(|v| format!("{:02X}", v));
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl TryFrom<canxl_frame> for CanXlFrame {
+    type Error = ConstructionError;
+
+    /// Validates the length of the raw frame, and forces the XLF flag on.
+    fn try_from(mut frame: canxl_frame) -> Result<Self, Self::Error> {
+        match frame.len as usize {
+            n if Self::is_valid_data_len(n) => {
+                frame.flags |= CANXL_XLF as u8;
+                Ok(Self(frame))
+            }
+            0 => Err(ConstructionError::NotEnoughData),
+            _ => Err(ConstructionError::TooMuchData),
+        }
+    }
+}
+
+impl AsRef<canxl_frame> for CanXlFrame {
+    fn as_ref(&self) -> &canxl_frame {
+        &self.0
+    }
+}
+
+// ===== CanMixedFrame =====
+
+/// A frame of any of the tiers: classic, FD, or XL.
+///
+/// This is what can be received by a socket that has XL frames enabled,
+/// along with the classic and FD frames. The XL frames are kept out of
+/// `CanAnyFrame` since they don't have a CAN ID, but a priority and an
+/// acceptance field instead.
+///
+/// The XL frame is held inline, like the kernel's own buffer, so reading
+/// doesn't need an allocation for each frame, at the cost of making all
+/// of the variants as large as an XL frame.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Copy, Debug)]
+pub enum CanMixedFrame {
+    /// A classic CAN 2.0 frame, with up to 8-bytes of data
+    Classic(CanFrame),
+    /// A flexible data rate frame, with up to 64-bytes of data
+    Fd(CanFdFrame),
+    /// An XL frame, with up to 2048-bytes of data
+    Xl(CanXlFrame),
+}
+
+impl From<CanFrame> for CanMixedFrame {
+    fn from(frame: CanFrame) -> Self {
+        Self::Classic(frame)
+    }
+}
+
+impl From<CanFdFrame> for CanMixedFrame {
+    fn from(frame: CanFdFrame) -> Self {
+        Self::Fd(frame)
+    }
+}
+
+impl From<CanXlFrame> for CanMixedFrame {
+    fn from(frame: CanXlFrame) -> Self {
+        Self::Xl(frame)
+    }
+}
+
+impl From<CanAnyFrame> for CanMixedFrame {
+    fn from(frame: CanAnyFrame) -> Self {
+        use CanAnyFrame::*;
+        match frame {
+            Normal(frame) => Self::Classic(frame.into()),
+            Remote(frame) => Self::Classic(frame.into()),
+            Error(frame) => Self::Classic(frame.into()),
+            Fd(frame) => Self::Fd(frame),
+        }
+    }
+}
+
 /////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use super::*;
     use crate::errors;
@@ -1813,6 +2288,49 @@ mod tests {
         assert_eq!(frame.dlc(), EXT_DATA_PADDED_DLC);
     }
 
+    #[test]
+    fn test_fd_frame_pad_byte() {
+        let n = EXT_DATA_INVALID_DLEN.len();
+
+        let mut frame = CanFdFrame::new_padded(STD_ID, EXT_DATA_INVALID_DLEN, 0xCC).unwrap();
+        assert_eq!(frame.len(), EXT_DATA_PADDED.len());
+        assert_eq!(&frame.data()[..n], EXT_DATA_INVALID_DLEN);
+        assert!(frame.data()[n..].iter().all(|&b| b == 0xCC));
+
+        // A valid length needs no padding
+        let frame2 = CanFdFrame::new_padded(STD_ID, EXT_DATA, 0xCC).unwrap();
+        assert_eq!(frame2.data(), EXT_DATA);
+
+        // Re-padding replaces the old pattern
+        frame.set_data_padded(EXT_DATA_INVALID_DLEN, 0xAA).unwrap();
+        assert_eq!(&frame.data()[..n], EXT_DATA_INVALID_DLEN);
+        assert!(frame.data()[n..].iter().all(|&b| b == 0xAA));
+
+        assert!(CanFdFrame::new_padded(STD_ID, &[0; 65], 0xCC).is_none());
+    }
+
+    #[test]
+    fn test_fd_frame_from_classic() {
+        let frame = CanDataFrame::new(EXT_ID, DATA).unwrap();
+        let fdframe = CanFdFrame::from_classic(frame.as_ref());
+        assert_eq!(fdframe.id(), EXT_ID);
+        assert!(fdframe.is_extended());
+        assert_eq!(fdframe.data(), DATA);
+        assert!(fdframe.flags().is_empty());
+
+        let frame = CanRemoteFrame::new_remote(STD_ID, 4).unwrap();
+        let fdframe = CanFdFrame::from_classic(frame.as_ref());
+        assert_eq!(fdframe.id(), STD_ID);
+        assert!(fdframe.id_flags().contains(IdFlags::RTR));
+        assert_eq!(fdframe.len(), 4);
+        assert!(fdframe.flags().is_empty());
+
+        let frame = CanErrorFrame::new_error(0x04, &[]).unwrap();
+        let fdframe = CanFdFrame::from_classic(frame.as_ref());
+        assert!(fdframe.is_error_frame());
+        assert!(fdframe.flags().is_empty());
+    }
+
     #[test]
     fn test_to_fd_frame() {
         let data_frame = CanDataFrame::new(STD_ID, DATA).unwrap();
@@ -1852,4 +2370,232 @@ mod tests {
             .clone_from_slice(crate::as_bytes(&frame.0));
         assert_eq!(fdframe.flags, 0);
     }
+
+    // A foreign frame type that can only hold 4 bytes
+    #[derive(Debug)]
+    struct SmallFrame {
+        id: Id,
+        remote: bool,
+        dlc: usize,
+        data: [u8; 4],
+    }
+
+    impl EmbeddedFrame for SmallFrame {
+        fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+            if data.len() > 4 {
+                return None;
+            }
+            let mut buf = [0; 4];
+            buf[..data.len()].copy_from_slice(data);
+            Some(Self {
+                id: id.into(),
+                remote: false,
+                dlc: data.len(),
+                data: buf,
+            })
+        }
+
+        fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+            Some(Self {
+                id: id.into(),
+                remote: true,
+                dlc,
+                data: [0; 4],
+            })
+        }
+
+        fn is_extended(&self) -> bool {
+            matches!(self.id, Id::Extended(_))
+        }
+
+        fn is_remote_frame(&self) -> bool {
+            self.remote
+        }
+
+        fn id(&self) -> Id {
+            self.id
+        }
+
+        fn dlc(&self) -> usize {
+            self.dlc
+        }
+
+        fn data(&self) -> &[u8] {
+            match self.remote {
+                true => &[],
+                false => &self.data[..self.dlc],
+            }
+        }
+    }
+
+    #[test]
+    fn test_embedded_conversions() {
+        let std_id = Id::from(StandardId::new(0x123).unwrap());
+        let ext_id = Id::from(ExtendedId::new(0x12345678).unwrap());
+
+        for id in [std_id, ext_id] {
+            // Data frame round trip
+            let small = SmallFrame::new(id, &[1, 2, 3]).unwrap();
+            let frame = CanFrame::from_embedded(&small).unwrap();
+            assert!(matches!(frame, CanFrame::Data(_)));
+            assert_eq!(frame.id(), id);
+            assert_eq!(frame.is_extended(), small.is_extended());
+            assert_eq!(frame.data(), &[1, 2, 3]);
+
+            let small: SmallFrame = frame.to_embedded().unwrap();
+            assert_eq!(small.id(), id);
+            assert!(!small.is_remote_frame());
+            assert_eq!(small.data(), &[1, 2, 3]);
+
+            // Remote frame round trip
+            let small = SmallFrame::new_remote(id, 2).unwrap();
+            let frame = CanFrame::from_embedded(&small).unwrap();
+            assert!(frame.is_remote_frame());
+            assert_eq!(frame.id(), id);
+            assert_eq!(frame.dlc(), 2);
+
+            let small: SmallFrame = frame.to_embedded().unwrap();
+            assert!(small.is_remote_frame());
+            assert_eq!(small.id(), id);
+            assert_eq!(small.dlc(), 2);
+
+            // Too much data for the target
+            let frame = CanFrame::new(id, &[0; 8]).unwrap();
+            assert_eq!(
+                frame.to_embedded::<SmallFrame>().unwrap_err(),
+                ConstructionError::TooMuchData
+            );
+        }
+
+        // Remote DLC too large for a classic frame
+        let small = SmallFrame::new_remote(std_id, 9).unwrap();
+        assert_eq!(
+            CanFrame::from_embedded(&small).unwrap_err(),
+            ConstructionError::TooMuchData
+        );
+
+        // Error frames don't convert
+        let frame = CanFrame::from(CanErrorFrame::new_error(0x0040, &[]).unwrap());
+        assert_eq!(
+            frame.to_embedded::<SmallFrame>().unwrap_err(),
+            ConstructionError::WrongFrameType
+        );
+    }
+
+    #[test]
+    fn test_any_frame_accessors() {
+        let frame = CanAnyFrame::from(CanDataFrame::new(STD_ID, DATA).unwrap());
+        assert_eq!(frame.as_data().unwrap().data(), DATA);
+        assert!(frame.as_remote().is_none());
+        assert!(frame.as_error().is_none());
+        assert!(frame.as_fd().is_none());
+        assert!(CanDataFrame::try_from(frame).is_ok());
+        assert!(CanFdFrame::try_from(frame).is_err());
+
+        let frame = CanAnyFrame::from(CanFdFrame::new(STD_ID, DATA).unwrap());
+        assert_eq!(frame.as_fd().unwrap().data(), DATA);
+        assert!(frame.as_data().is_none());
+        assert!(CanFdFrame::try_from(frame).is_ok());
+        assert!(matches!(
+            CanDataFrame::try_from(frame),
+            Err(ConstructionError::WrongFrameType)
+        ));
+
+        let frame = CanFrame::from(CanRemoteFrame::new_remote(STD_ID, 2).unwrap());
+        assert_eq!(frame.as_remote().unwrap().dlc(), 2);
+        assert!(frame.as_data().is_none());
+        assert!(frame.as_error().is_none());
+
+        let frame = CanFrame::from(CanErrorFrame::new_error(0x04, &[]).unwrap());
+        assert_eq!(frame.as_error().unwrap().error_bits(), 0x04);
+        assert!(frame.as_data().is_none());
+    }
+
+    #[test]
+    fn test_frame_predicates() {
+        let frame = CanFrame::new(EXT_ID, DATA).unwrap();
+        assert_eq!(frame.id(), EXT_ID);
+        assert!(frame.is_extended());
+        assert!(!frame.is_remote());
+        assert!(!frame.is_error());
+        assert!(!frame.is_fd());
+
+        let frame = CanFrame::new_remote(STD_ID, 2).unwrap();
+        assert_eq!(frame.id(), STD_ID);
+        assert!(!frame.is_extended());
+        assert!(frame.is_remote());
+        assert!(!frame.is_error());
+
+        let frame = CanFrame::from(CanErrorFrame::new_error(0x04, &[]).unwrap());
+        assert!(frame.is_error());
+        assert!(!frame.is_remote());
+        assert_eq!(frame.id(), Id::Standard(StandardId::new(0x04).unwrap()));
+
+        let frame = CanAnyFrame::from(CanFdFrame::new(EXT_LOW_ID, EXT_DATA).unwrap());
+        assert_eq!(frame.id(), EXT_LOW_ID);
+        assert!(frame.is_extended());
+        assert!(frame.is_fd());
+        assert!(!frame.is_remote());
+        assert!(!frame.is_error());
+
+        let frame = CanAnyFrame::from(CanFrame::new_remote(EXT_ID, 0).unwrap());
+        assert!(frame.is_remote());
+        assert!(!frame.is_fd());
+    }
+
+    #[test]
+    fn test_xl_frame() {
+        const PRIO: StandardId = StandardId::MAX;
+        let data = [0xA5u8; 64];
+
+        let frame = CanXlFrame::new(PRIO, 0x03, 0x1234_5678, &data).unwrap();
+        assert_eq!(frame.prio(), PRIO.as_raw() as canid_t);
+        assert_eq!(frame.sdt(), 0x03);
+        assert_eq!(frame.af(), 0x1234_5678);
+        assert!(frame.flags().contains(XlFlags::XLF));
+        assert!(!frame.sec());
+        assert_eq!(frame.len(), 64);
+        assert_eq!(frame.data(), data);
+
+        // Only the header and used data go to the kernel
+        let bytes = frame.as_bytes();
+        assert_eq!(bytes.len(), libc::CANXL_MIN_MTU);
+        assert_eq!(&bytes[0..4], &(PRIO.as_raw() as u32).to_ne_bytes());
+        assert_eq!(bytes[4], CANXL_XLF as u8);
+        assert_eq!(bytes[5], 0x03);
+        assert_eq!(&bytes[6..8], &64u16.to_ne_bytes());
+        assert_eq!(&bytes[8..12], &0x1234_5678u32.to_ne_bytes());
+        assert_eq!(&bytes[CANXL_HDR_SIZE..], data);
+
+        let mut frame = CanXlFrame::with_flags(PRIO, 0, 0, &[1], XlFlags::SEC).unwrap();
+        assert!(frame.sec());
+        assert_eq!(frame.as_bytes().len(), CANXL_HDR_SIZE + 1);
+        frame.set_sec(false);
+        assert!(!frame.sec());
+        assert!(frame.flags().contains(XlFlags::XLF));
+
+        assert!(CanXlFrame::new(PRIO, 0, 0, &[]).is_none());
+        assert!(CanXlFrame::new(PRIO, 0, 0, &[0; CANXL_MAX_DLEN]).is_some());
+        assert!(CanXlFrame::new(PRIO, 0, 0, &[0; CANXL_MAX_DLEN + 1]).is_none());
+
+        assert_eq!(frame.set_data(&[]), Err(ConstructionError::NotEnoughData));
+
+        let xlframe = canxl_frame_default();
+        assert!(CanXlFrame::try_from(xlframe).is_err());
+    }
+
+    #[test]
+    fn test_xl_vcid() {
+        let prio = StandardId::new(0x123).unwrap();
+        let mut frame = CanXlFrame::new(prio, 0x03, 0, &[1]).unwrap();
+        assert_eq!(frame.vcid(), 0);
+
+        frame.set_vcid(0xA5);
+        assert_eq!(frame.vcid(), 0xA5);
+        assert_eq!(frame.prio(), 0x123);
+        assert_eq!(frame.as_ref().prio, 0x00A5_0123);
+
+        frame.set_prio(StandardId::new(0x456).unwrap());
+        assert_eq!((frame.prio(), frame.vcid()), (0x456, 0xA5));
+    }
 }
@@ -17,15 +17,31 @@ use embedded_can::{ExtendedId, Id, StandardId};
 use libc::canid_t;
 use std::{io, ops};
 
+use libc::{
+    CAN_ERR_ACK, CAN_ERR_BUSERROR, CAN_ERR_BUSOFF, CAN_ERR_CNT, CAN_ERR_CRTL, CAN_ERR_LOSTARB,
+    CAN_ERR_PROT, CAN_ERR_RESTARTED, CAN_ERR_TRX, CAN_ERR_TX_TIMEOUT,
+};
+
 pub use libc::{
-    CANFD_BRS, CANFD_ESI, CANFD_MAX_DLEN, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_ERR_MASK,
-    CAN_MAX_DLEN, CAN_RTR_FLAG, CAN_SFF_MASK,
+    CANFD_BRS, CANFD_ESI, CANFD_MAX_DLEN, CANXL_MAX_DLEN, CANXL_MIN_DLEN, CANXL_PRIO_MASK,
+    CANXL_SEC, CANXL_XLF, CAN_EFF_FLAG, CAN_EFF_MASK, CAN_ERR_FLAG, CAN_ERR_MASK, CAN_MAX_DLEN,
+    CAN_RTR_FLAG, CAN_SFF_MASK,
 };
 
 // TODO: This was sent upstream to libc 2024-12-27
 /// Mark CAN FD for dual use of struct canfd_frame
 pub const CANFD_FDF: libc::c_int = 0x04;
 
+/// The offset of the virtual CAN network ID (VCID) in the priority word of
+/// an XL frame
+pub const CANXL_VCID_OFFSET: u32 = 16;
+
+/// The mask for the value of a VCID, once shifted down
+pub const CANXL_VCID_VAL_MASK: u32 = 0xFF;
+
+/// The mask for the VCID in the priority word of an XL frame
+pub const CANXL_VCID_MASK: u32 = CANXL_VCID_VAL_MASK << CANXL_VCID_OFFSET;
+
 /// An error mask that will cause SocketCAN to report all errors
 pub const ERR_MASK_ALL: u32 = CAN_ERR_MASK;
 
@@ -57,6 +73,46 @@ bitflags! {
         /// Added in Linux kernel v5.14
         const FDF = CANFD_FDF as u8;
     }
+
+    /// Bit flags for the CAN XL frames.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
+    pub struct XlFlags: u8 {
+        /// Simple extended content (security/segmentation)
+        const SEC = CANXL_SEC as u8;
+        /// Mark CAN XL for dual use of struct canfd_frame/canxl_frame
+        const XLF = CANXL_XLF as u8;
+    }
+
+    /// The classes of errors reported in error frames.
+    ///
+    /// These are the error bits in the ID word of an error frame, and are
+    /// also used as the error filter mask on a socket, to select the
+    /// classes of errors it receives.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct CanErrorMask: u32 {
+        /// TX timeout (by netdevice driver)
+        const TX_TIMEOUT = CAN_ERR_TX_TIMEOUT;
+        /// Lost arbitration
+        const LOST_ARBITRATION = CAN_ERR_LOSTARB;
+        /// Controller problems
+        const CONTROLLER = CAN_ERR_CRTL;
+        /// Protocol violations
+        const PROTOCOL = CAN_ERR_PROT;
+        /// Transceiver status
+        const TRANSCEIVER = CAN_ERR_TRX;
+        /// Received no ACK on transmission
+        const NO_ACK = CAN_ERR_ACK;
+        /// Bus off
+        const BUS_OFF = CAN_ERR_BUSOFF;
+        /// Bus error (may flood!)
+        const BUS_ERROR = CAN_ERR_BUSERROR;
+        /// Controller restarted
+        const RESTARTED = CAN_ERR_RESTARTED;
+        /// TX error counter / data[6], RX error counter / data[7]
+        const COUNTERS = CAN_ERR_CNT;
+    }
 }
 
 /// Gets the canid_t value from an Id
@@ -198,6 +254,16 @@ impl From<CanId> for Id {
     }
 }
 
+/// Gets the `canid_t` value for the ID, as used in the kernel structs.
+///
+/// If it's an extended ID, the CAN_EFF_FLAG bit is also set.
+impl From<CanId> for canid_t {
+    #[inline]
+    fn from(id: CanId) -> Self {
+        id_to_canid_t(id)
+    }
+}
+
 /// Creates a CAN ID from a raw integer value.
 ///
 /// If the `id` is <= 0x7FF, it's assumed to be a standard ID, otherwise
@@ -240,6 +306,7 @@ impl ops::AddAssign<u32> for CanId {
 /////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use super::*;
 
@@ -290,6 +357,31 @@ mod tests {
         assert_eq!(id.as_raw(), ID);
     }
 
+    #[test]
+    fn test_id_canid_t() {
+        let id = CanId::standard(0x7FF).unwrap();
+        assert_eq!(canid_t::from(id), 0x7FF);
+
+        // Extended IDs get the EFF flag, even if they're small
+        let id = CanId::extended(0x7FF).unwrap();
+        assert_eq!(canid_t::from(id), 0x7FF | CAN_EFF_FLAG);
+
+        let id = CanId::extended(CAN_EFF_MASK).unwrap();
+        assert_eq!(canid_t::from(id), CAN_EFF_MASK | CAN_EFF_FLAG);
+
+        assert!(CanId::standard(0x800).is_none());
+        assert!(CanId::extended(CAN_EFF_MASK + 1).is_none());
+    }
+
+    #[test]
+    fn test_error_mask() {
+        assert_eq!(CanErrorMask::all().bits(), 0x03FF);
+        assert_eq!(CanErrorMask::empty().bits(), ERR_MASK_NONE);
+
+        let mask = CanErrorMask::BUS_OFF | CanErrorMask::RESTARTED;
+        assert_eq!(mask.bits(), 0x0140);
+    }
+
     #[test]
     fn test_id_add() {
         let id = CanId::try_from(ID).unwrap();
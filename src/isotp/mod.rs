@@ -0,0 +1,807 @@
+// socketcan/src/isotp/mod.rs
+//
+// Sockets for the ISO-TP (ISO 15765-2) protocol.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! ISO-TP (ISO 15765-2) sockets.
+//!
+//! The kernel's `CAN_ISOTP` protocol, in mainline since Linux 5.10, does
+//! the segmentation, flow control, and reassembly of ISO-TP messages, so a
+//! [`CanIsoTpSocket`] reads and writes whole messages of up to several
+//! kilobytes, rather than individual frames. The socket is bound to an
+//! interface with a pair of CAN IDs: the one it receives on and the one it
+//! transmits with.
+//!
+//! The behavior of a socket is configured with a few structs of options,
+//! which need to be set before the socket is bound to its interface and
+//! CAN IDs: the general `can_isotp_options`, the flow control parameters
+//! that the socket sends to its peer in `can_isotp_fc_options`, and the
+//! link layer (CAN or CAN FD) in `can_isotp_ll_options`. [`IsoTpOptions`]
+//! is a builder for all of them.
+//!
+//! ```no_run
+//! use socketcan::{isotp::CanIsoTpSocket, StandardId};
+//!
+//! let rx_id = StandardId::new(0x7E8).unwrap();
+//! let tx_id = StandardId::new(0x7E0).unwrap();
+//! let sock = CanIsoTpSocket::open("vcan0", rx_id, tx_id).unwrap();
+//!
+//! sock.write(&[0x22, 0xF1, 0x90]).unwrap();
+//!
+//! let mut buf = [0u8; 4095];
+//! let n = sock.read(&mut buf).unwrap();
+//! println!("{:02X?}", &buf[..n]);
+//! ```
+//!
+//! On kernels without the `can-isotp` module, where creating the socket
+//! fails with `EPROTONOSUPPORT`, the socket falls back to running the
+//! protocol in userspace over a raw CAN socket, with the same API. This
+//! covers classic CAN frames, but not the CAN FD link layer options.
+//! [`CanIsoTpSocket::is_userspace()`] tells which is in use.
+//!
+//! Besides normal addressing, with a pair of CAN IDs, this covers extended
+//! addressing, where the first data byte of each frame is an address, and
+//! functional (1-to-N) addressing, which is used for requests broadcast to
+//! all the ECUs on a bus, such as the UDS functional requests.
+//!
+//! The definitions are in the kernel header:
+//!
+//! <https://github.com/torvalds/linux/blob/master/include/uapi/linux/can/isotp.h?ts=4>
+
+use crate::{
+    socket::{new_socket, open_socket, recv_checked, recvmsg_raw},
+    CanAddr, Socket, SocketError,
+};
+use bitflags::bitflags;
+use embedded_can::Id;
+use libc::{CANFD_BRS, CANFD_MTU, CAN_ISOTP, CAN_MAX_DLEN, CAN_MTU, SOL_CAN_BASE};
+use socket2::SockAddr;
+use std::{
+    io::{self, Write},
+    mem::size_of,
+    os::{
+        raw::c_void,
+        unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd},
+    },
+    time::Duration,
+};
+
+mod user;
+
+use user::UserIsoTp;
+
+/// The socket option level for ISO-TP
+pub const SOL_CAN_ISOTP: libc::c_int = SOL_CAN_BASE + CAN_ISOTP;
+
+/// The socket option to set the general ISO-TP options
+pub const CAN_ISOTP_OPTS: libc::c_int = 1;
+
+/// The socket option to set the flow control options sent to the peer
+pub const CAN_ISOTP_RECV_FC: libc::c_int = 2;
+
+/// The socket option to set the STmin used when sending, overriding the
+/// one from the peer's flow control frames
+pub const CAN_ISOTP_TX_STMIN: libc::c_int = 3;
+
+/// The socket option to set the link layer options
+pub const CAN_ISOTP_LL_OPTS: libc::c_int = 5;
+
+/// The default padding byte, chosen to prevent bit stuffing
+pub const CAN_ISOTP_DEFAULT_PAD_CONTENT: u8 = 0xCC;
+
+/// The default time between consecutive frames sent, in nanoseconds
+pub const CAN_ISOTP_DEFAULT_FRAME_TXTIME: u32 = 50_000;
+
+/// The frame transmit time value that requests no delay at all, since
+/// zero selects the default
+pub const CAN_ISOTP_FRAME_TXTIME_ZERO: u32 = 0xFFFF_FFFF;
+
+bitflags! {
+    /// Flags for the behavior of an ISO-TP socket.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct IsoTpFlags: u32 {
+        /// Listen only, without sending flow control frames
+        const LISTEN_MODE = 0x0001;
+        /// Use extended addressing
+        const EXTEND_ADDR = 0x0002;
+        /// Pad the frames that are sent
+        const TX_PADDING = 0x0004;
+        /// Expect received frames to be padded
+        const RX_PADDING = 0x0008;
+        /// Check the length of the padding of received frames
+        const CHK_PAD_LEN = 0x0010;
+        /// Check the content of the padding of received frames
+        const CHK_PAD_DATA = 0x0020;
+        /// Use half-duplex error state handling
+        const HALF_DUPLEX = 0x0040;
+        /// Ignore the STmin from received flow control frames
+        const FORCE_TXSTMIN = 0x0080;
+        /// Ignore consecutive frames received faster than the rx STmin
+        const FORCE_RXSTMIN = 0x0100;
+        /// Use a different extended address for received frames
+        const RX_EXT_ADDR = 0x0200;
+        /// Wait for the transmission to complete before returning
+        const WAIT_TX_DONE = 0x0400;
+        /// 1-to-N functional addressing, with single frames only
+        const SF_BROADCAST = 0x0800;
+        /// 1-to-N transmission, without flow control
+        const CF_BROADCAST = 0x1000;
+        /// Dynamic flow control parameters, BS and STmin
+        const DYN_FC_PARMS = 0x2000;
+    }
+}
+
+/// The kernel's general ISO-TP options, from `linux/can/isotp.h`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub struct can_isotp_options {
+    /// The `CAN_ISOTP_*` flags
+    pub flags: u32,
+    /// The time between consecutive frames, in nanoseconds
+    pub frame_txtime: u32,
+    /// The address for extended addressing
+    pub ext_address: u8,
+    /// The padding byte for frames that are sent
+    pub txpad_content: u8,
+    /// The padding byte expected in received frames
+    pub rxpad_content: u8,
+    /// The address for extended addressing of received frames
+    pub rx_ext_address: u8,
+}
+
+/// The kernel's ISO-TP flow control options, from `linux/can/isotp.h`.
+///
+/// These are the parameters sent to the peer in flow control frames, to
+/// pace the consecutive frames that it sends.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub struct can_isotp_fc_options {
+    /// The block size, as the number of consecutive frames between flow
+    /// control frames, or zero for no limit
+    pub bs: u8,
+    /// The minimum separation time between consecutive frames, as encoded
+    /// in the flow control frame
+    pub stmin: u8,
+    /// The maximum number of wait frames that are sent
+    pub wftmax: u8,
+}
+
+/// The kernel's ISO-TP link layer options, from `linux/can/isotp.h`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub struct can_isotp_ll_options {
+    /// The MTU of the frames, `CAN_MTU` or `CANFD_MTU`
+    pub mtu: u8,
+    /// The data length of the frames that are sent
+    pub tx_dl: u8,
+    /// The flags of the CAN FD frames that are sent, like `CANFD_BRS`
+    pub tx_flags: u8,
+}
+
+impl Default for can_isotp_ll_options {
+    fn default() -> Self {
+        Self {
+            mtu: CAN_MTU as u8,
+            tx_dl: CAN_MAX_DLEN as u8,
+            tx_flags: 0,
+        }
+    }
+}
+
+/// Encodes a separation time as an STmin byte, in the flow control frame.
+///
+/// Times of up to 127ms are given in milliseconds, and those under 1ms in
+/// steps of 100us. Anything longer is saturated.
+fn encode_stmin(time: Duration) -> u8 {
+    match time.as_micros() {
+        us if us < 100 => 0,
+        us if us < 1000 => 0xF0 + (us / 100) as u8,
+        us => (us / 1000).min(0x7F) as u8,
+    }
+}
+
+// ===== IsoTpOptions =====
+
+/// Options for an ISO-TP socket.
+///
+/// This starts with the same settings as the kernel has for a new socket,
+/// and is applied to the socket with [`IsoTpOptions::apply()`] before it
+/// is bound.
+///
+/// ```
+/// use socketcan::isotp::{IsoTpFlags, IsoTpOptions};
+///
+/// // A UDS functional request, broadcast with extended address 0x33
+/// let opts = IsoTpOptions::new()
+///     .tx_ext_address(0x33)
+///     .functional(true)
+///     .tx_padding(0xAA);
+///
+/// assert!(opts.flags().contains(IsoTpFlags::EXTEND_ADDR | IsoTpFlags::SF_BROADCAST));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsoTpOptions {
+    flags: IsoTpFlags,
+    frame_txtime: u32,
+    ext_address: u8,
+    tx_pad: u8,
+    rx_pad: u8,
+    rx_ext_address: u8,
+    tx_stmin: u32,
+    fc: can_isotp_fc_options,
+    ll: can_isotp_ll_options,
+}
+
+impl Default for IsoTpOptions {
+    fn default() -> Self {
+        Self {
+            flags: IsoTpFlags::empty(),
+            frame_txtime: CAN_ISOTP_DEFAULT_FRAME_TXTIME,
+            ext_address: 0,
+            tx_pad: CAN_ISOTP_DEFAULT_PAD_CONTENT,
+            rx_pad: CAN_ISOTP_DEFAULT_PAD_CONTENT,
+            rx_ext_address: 0,
+            tx_stmin: 0,
+            fc: can_isotp_fc_options::default(),
+            ll: can_isotp_ll_options::default(),
+        }
+    }
+}
+
+impl IsoTpOptions {
+    /// Creates the default set of options, the same as the kernel uses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the flags that are set.
+    pub fn flags(&self) -> IsoTpFlags {
+        self.flags
+    }
+
+    /// Sets or clears the flags given, leaving the others as they are.
+    pub fn set_flags(mut self, flags: IsoTpFlags, on: bool) -> Self {
+        self.flags.set(flags, on);
+        self
+    }
+
+    /// Uses extended addressing, with the address given in the first data
+    /// byte of each frame that is sent.
+    ///
+    /// The same address is expected in received frames, unless a separate
+    /// one is set with [`IsoTpOptions::rx_ext_address()`].
+    pub fn tx_ext_address(mut self, addr: u8) -> Self {
+        self.flags |= IsoTpFlags::EXTEND_ADDR;
+        self.ext_address = addr;
+        self
+    }
+
+    /// Sets a separate extended address for received frames.
+    ///
+    /// This only applies with extended addressing, so it also needs a
+    /// [`IsoTpOptions::tx_ext_address()`].
+    pub fn rx_ext_address(mut self, addr: u8) -> Self {
+        self.flags |= IsoTpFlags::RX_EXT_ADDR;
+        self.rx_ext_address = addr;
+        self
+    }
+
+    /// Enables or disables functional (1-to-N) addressing.
+    ///
+    /// With this, messages are broadcast as single frames, without waiting
+    /// for flow control, so they're limited to what fits in one frame. The
+    /// receive ID of the socket is not used.
+    pub fn functional(self, on: bool) -> Self {
+        self.set_flags(IsoTpFlags::SF_BROADCAST, on)
+    }
+
+    /// Enables or disables 1-to-N transmission of full messages, with
+    /// consecutive frames sent without waiting for flow control.
+    ///
+    /// This can't be combined with [`IsoTpOptions::functional()`].
+    pub fn cf_broadcast(self, on: bool) -> Self {
+        self.set_flags(IsoTpFlags::CF_BROADCAST, on)
+    }
+
+    /// Pads the frames that are sent to their full length, with the byte
+    /// given.
+    pub fn tx_padding(mut self, pad: u8) -> Self {
+        self.flags |= IsoTpFlags::TX_PADDING;
+        self.tx_pad = pad;
+        self
+    }
+
+    /// Expects received frames to be padded, with the byte given.
+    pub fn rx_padding(mut self, pad: u8) -> Self {
+        self.flags |= IsoTpFlags::RX_PADDING;
+        self.rx_pad = pad;
+        self
+    }
+
+    /// Sets the time between consecutive frames that are sent.
+    ///
+    /// This is the time that the frames are expected to take on the bus.
+    /// Times beyond the range that the kernel accepts are saturated.
+    pub fn frame_txtime(mut self, time: Duration) -> Self {
+        self.frame_txtime = match u32::try_from(time.as_nanos()) {
+            Ok(0) => CAN_ISOTP_FRAME_TXTIME_ZERO,
+            Ok(ns) => ns.min(CAN_ISOTP_FRAME_TXTIME_ZERO - 1),
+            Err(_) => CAN_ISOTP_FRAME_TXTIME_ZERO - 1,
+        };
+        self
+    }
+
+    /// Sets the minimum time between the consecutive frames that are sent,
+    /// ignoring the STmin in the flow control frames from the peer.
+    ///
+    /// This is for ECUs that ask for frames faster than they can take them.
+    pub fn tx_stmin(mut self, time: Duration) -> Self {
+        self.flags |= IsoTpFlags::FORCE_TXSTMIN;
+        self.tx_stmin = u32::try_from(time.as_nanos()).unwrap_or(u32::MAX);
+        self
+    }
+
+    /// Sets the block size sent to the peer in flow control frames: the
+    /// number of consecutive frames it sends before waiting for the next
+    /// flow control frame, or zero for no limit.
+    pub fn block_size(mut self, bs: u8) -> Self {
+        self.fc.bs = bs;
+        self
+    }
+
+    /// Sets the minimum separation time between consecutive frames, sent
+    /// to the peer in flow control frames.
+    ///
+    /// This is encoded as the kernel expects: in milliseconds up to 127ms,
+    /// or in steps of 100us under 1ms.
+    pub fn rx_stmin(mut self, time: Duration) -> Self {
+        self.fc.stmin = encode_stmin(time);
+        self
+    }
+
+    /// Sets the maximum number of flow control wait frames that are sent
+    /// before the reception is aborted.
+    pub fn max_wait_frames(mut self, wftmax: u8) -> Self {
+        self.fc.wftmax = wftmax;
+        self
+    }
+
+    /// Sends CAN FD frames, with the data length given, which needs to be
+    /// one of the FD frame sizes, from 8 up to 64 bytes.
+    ///
+    /// The interface must be in FD mode. Frames are still received in
+    /// either format.
+    pub fn fd_frames(mut self, tx_dl: u8) -> Self {
+        self.ll.mtu = CANFD_MTU as u8;
+        self.ll.tx_dl = tx_dl;
+        self
+    }
+
+    /// Enables or disables the bit rate switch on the CAN FD frames that
+    /// are sent.
+    ///
+    /// This needs [`fd_frames()`](Self::fd_frames) as well.
+    pub fn bit_rate_switch(mut self, on: bool) -> Self {
+        self.ll.tx_flags = match on {
+            true => self.ll.tx_flags | CANFD_BRS as u8,
+            false => self.ll.tx_flags & !(CANFD_BRS as u8),
+        };
+        self
+    }
+
+    /// Checks that the combination of options is one that the kernel
+    /// accepts and acts upon.
+    ///
+    /// The two broadcast modes can't be used together, a separate receive
+    /// extended address is ignored without extended addressing, the
+    /// transmit data length must be a valid frame size, and the bit rate
+    /// switch needs CAN FD frames.
+    pub fn validate(&self) -> Result<(), SocketError> {
+        if self
+            .flags
+            .contains(IsoTpFlags::SF_BROADCAST | IsoTpFlags::CF_BROADCAST)
+        {
+            return Err(SocketError::IsoTpBroadcastConflict);
+        }
+        if self.flags.contains(IsoTpFlags::RX_EXT_ADDR)
+            && !self.flags.contains(IsoTpFlags::EXTEND_ADDR)
+        {
+            return Err(SocketError::IsoTpRxExtAddrWithoutExtAddr);
+        }
+        if !matches!(self.ll.tx_dl, 8 | 12 | 16 | 20 | 24 | 32 | 48 | 64) {
+            return Err(SocketError::IsoTpInvalidTxDataLength(self.ll.tx_dl));
+        }
+        if self.ll.mtu == CAN_MTU as u8 && self.ll.tx_flags != 0 {
+            return Err(SocketError::IsoTpBrsWithoutFd);
+        }
+        Ok(())
+    }
+
+    /// Gets the options as the kernel struct, after checking that they
+    /// are valid.
+    pub fn to_raw(&self) -> Result<can_isotp_options, SocketError> {
+        self.validate()?;
+
+        // The kernel uses the tx address for both directions unless told
+        // otherwise, so report it the same way
+        let rx_ext_address = match self.flags.contains(IsoTpFlags::RX_EXT_ADDR) {
+            true => self.rx_ext_address,
+            false => self.ext_address,
+        };
+
+        Ok(can_isotp_options {
+            flags: self.flags.bits(),
+            frame_txtime: self.frame_txtime,
+            ext_address: self.ext_address,
+            txpad_content: self.tx_pad,
+            rxpad_content: self.rx_pad,
+            rx_ext_address,
+        })
+    }
+
+    /// Gets the flow control options as the kernel struct.
+    pub fn to_raw_fc(&self) -> can_isotp_fc_options {
+        self.fc
+    }
+
+    /// Gets the link layer options as the kernel struct.
+    pub fn to_raw_ll(&self) -> can_isotp_ll_options {
+        self.ll
+    }
+
+    /// Applies the options to an ISO-TP socket.
+    ///
+    /// This needs to be done before the socket is bound. An invalid
+    /// combination of options is reported as an `InvalidInput` error
+    /// wrapping the [`SocketError`], before any call is made to the kernel.
+    ///
+    /// The flow control, transmit STmin, and link layer options are only
+    /// set when they differ from the kernel defaults.
+    pub fn apply<S: AsRawFd>(&self, sock: &S) -> io::Result<()> {
+        let fd = sock.as_raw_fd();
+        set_isotp_opt(fd, CAN_ISOTP_OPTS, &self.to_raw()?)?;
+
+        if self.fc != can_isotp_fc_options::default() {
+            set_isotp_opt(fd, CAN_ISOTP_RECV_FC, &self.fc)?;
+        }
+        if self.flags.contains(IsoTpFlags::FORCE_TXSTMIN) {
+            set_isotp_opt(fd, CAN_ISOTP_TX_STMIN, &self.tx_stmin)?;
+        }
+        if self.ll != can_isotp_ll_options::default() {
+            set_isotp_opt(fd, CAN_ISOTP_LL_OPTS, &self.ll)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets an ISO-TP socket option to the value given.
+fn set_isotp_opt<T>(fd: RawFd, name: libc::c_int, val: &T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            SOL_CAN_ISOTP,
+            name,
+            val as *const _ as *const c_void,
+            size_of::<T>() as libc::socklen_t,
+        )
+    };
+
+    match ret {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+// ===== CanIsoTpSocket =====
+
+/// A socket for ISO-TP messages, using the kernel's `CAN_ISOTP` protocol.
+///
+/// Each read returns one complete message, reassembled from its frames,
+/// and each write sends one message, segmented as needed. Without the
+/// `can-isotp` kernel module, this is done in userspace over a raw CAN
+/// socket instead.
+#[allow(missing_copy_implementations)]
+#[derive(Debug)]
+pub struct CanIsoTpSocket(Inner);
+
+/// The implementation behind an ISO-TP socket
+#[derive(Debug)]
+enum Inner {
+    Kernel(socket2::Socket),
+    User(UserIsoTp),
+}
+
+/// Determines if the error is from creating a socket for a protocol that
+/// the kernel doesn't have.
+fn is_proto_not_supported(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EPROTONOSUPPORT)
+}
+
+impl CanIsoTpSocket {
+    /// Opens an ISO-TP socket on the named interface, receiving on
+    /// `rx_id` and transmitting with `tx_id`, with the default options.
+    pub fn open<R, T>(ifname: &str, rx_id: R, tx_id: T) -> io::Result<Self>
+    where
+        R: Into<Id>,
+        T: Into<Id>,
+    {
+        let addr = CanAddr::from_iface_isotp(ifname, rx_id, tx_id)?;
+        Self::open_addr(&addr)
+    }
+
+    /// Opens an ISO-TP socket on the named interface with the options
+    /// given.
+    pub fn open_with<R, T>(
+        ifname: &str,
+        rx_id: R,
+        tx_id: T,
+        opts: &IsoTpOptions,
+    ) -> io::Result<Self>
+    where
+        R: Into<Id>,
+        T: Into<Id>,
+    {
+        let addr = CanAddr::from_iface_isotp(ifname, rx_id, tx_id)?;
+        Self::open_addr_with(&addr, opts)
+    }
+
+    /// Opens an ISO-TP socket by address, with the default options.
+    ///
+    /// The address should be created with [`CanAddr::new_isotp()`] or
+    /// [`CanAddr::from_iface_isotp()`], to carry the CAN IDs.
+    pub fn open_addr(addr: &CanAddr) -> io::Result<Self> {
+        match open_socket(addr, CAN_ISOTP) {
+            Ok(sock) => Ok(Self(Inner::Kernel(sock))),
+            Err(err) if is_proto_not_supported(&err) => {
+                UserIsoTp::open(addr, &IsoTpOptions::default(), err).map(|tp| Self(Inner::User(tp)))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Opens an ISO-TP socket by address, with the options given applied
+    /// before it is bound.
+    pub fn open_addr_with(addr: &CanAddr, opts: &IsoTpOptions) -> io::Result<Self> {
+        let sock = match new_socket(CAN_ISOTP) {
+            Ok(sock) => sock,
+            Err(err) if is_proto_not_supported(&err) => {
+                return UserIsoTp::open(addr, opts, err).map(|tp| Self(Inner::User(tp)));
+            }
+            Err(err) => return Err(err),
+        };
+        opts.apply(&sock)?;
+        sock.bind(&SockAddr::from(*addr))?;
+        Ok(Self(Inner::Kernel(sock)))
+    }
+
+    /// Determines if the protocol is run in userspace, over a raw CAN
+    /// socket, because the kernel doesn't have `CAN_ISOTP`.
+    pub fn is_userspace(&self) -> bool {
+        matches!(self.0, Inner::User(_))
+    }
+
+    /// Gets a shared reference to the underlying socket object
+    ///
+    /// When the protocol is run in userspace, this is the raw CAN socket
+    /// that carries it.
+    pub fn as_raw_socket(&self) -> &socket2::Socket {
+        match &self.0 {
+            Inner::Kernel(sock) => sock,
+            Inner::User(tp) => tp.as_socket().as_raw_socket(),
+        }
+    }
+
+    /// Reads a complete message into the buffer, returning its length.
+    ///
+    /// This blocks, subject to the read timeout or non-blocking mode,
+    /// until a whole message has been received. If the message is larger
+    /// than the buffer, this fails with an `InvalidData` error wrapping
+    /// [`SocketError::Truncated`], with the real size of the message.
+    pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match &self.0 {
+            Inner::Kernel(sock) => recv_checked(sock.as_raw_fd(), buf),
+            Inner::User(tp) => tp.read(buf),
+        }
+    }
+
+    /// Reads a complete message into a new vector that fits it.
+    ///
+    /// This blocks in the same way as [`read()`](Self::read). With the
+    /// kernel protocol, the size of the waiting message is peeked first,
+    /// so that a message of any size the kernel accepts is read whole.
+    pub fn read_to_vec(&self) -> io::Result<Vec<u8>> {
+        let mut buf = match &self.0 {
+            Inner::Kernel(sock) => {
+                let flags = libc::MSG_PEEK | libc::MSG_TRUNC;
+                let len = recvmsg_raw(sock.as_raw_fd(), &mut [], &mut [], flags)?.len;
+                vec![0; len]
+            }
+            Inner::User(_) => vec![0; user::MAX_MSG_LEN],
+        };
+        let n = self.read(&mut buf)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    /// Writes a complete message, which the kernel segments into frames.
+    ///
+    /// This returns once the kernel has accepted the message. To block
+    /// until it has actually been sent, set the
+    /// [`IsoTpFlags::WAIT_TX_DONE`] option.
+    pub fn write(&self, data: &[u8]) -> io::Result<()> {
+        let mut sock = match &self.0 {
+            Inner::Kernel(sock) => sock,
+            Inner::User(tp) => return tp.write(data),
+        };
+        match sock.write(data)? {
+            n if n == data.len() => Ok(()),
+            _ => Err(io::ErrorKind::WriteZero.into()),
+        }
+    }
+
+    /// Change socket to non-blocking mode or back to blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.as_raw_socket().set_nonblocking(nonblocking)
+    }
+
+    /// Sets the read timeout on the socket
+    ///
+    /// For convenience, the result value can be checked using
+    /// `ShouldRetry::should_retry` when a timeout is set.
+    pub fn set_read_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.as_raw_socket().set_read_timeout(duration.into())
+    }
+
+    /// Sets the write timeout on the socket
+    pub fn set_write_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.as_raw_socket().set_write_timeout(duration.into())
+    }
+}
+
+impl AsRawFd for CanIsoTpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_raw_socket().as_raw_fd()
+    }
+}
+
+impl AsFd for CanIsoTpSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.as_raw_socket().as_fd()
+    }
+}
+
+impl IntoRawFd for CanIsoTpSocket {
+    fn into_raw_fd(self) -> RawFd {
+        match self.0 {
+            Inner::Kernel(sock) => sock.into_raw_fd(),
+            Inner::User(tp) => tp.into_socket().into_raw_fd(),
+        }
+    }
+}
+
+impl From<OwnedFd> for CanIsoTpSocket {
+    /// Wraps a kernel ISO-TP socket.
+    fn from(fd: OwnedFd) -> Self {
+        Self(Inner::Kernel(socket2::Socket::from(fd)))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(size_of::<can_isotp_options>(), 12);
+
+        let opts = IsoTpOptions::new().to_raw().unwrap();
+        assert_eq!(opts.flags, 0);
+        assert_eq!(opts.frame_txtime, 50_000);
+        assert_eq!(opts.txpad_content, 0xCC);
+        assert_eq!(opts.rxpad_content, 0xCC);
+    }
+
+    #[test]
+    fn test_ext_addresses() {
+        let opts = IsoTpOptions::new().tx_ext_address(0x33).to_raw().unwrap();
+        assert_eq!(opts.flags, IsoTpFlags::EXTEND_ADDR.bits());
+        assert_eq!((opts.ext_address, opts.rx_ext_address), (0x33, 0x33));
+
+        let opts = IsoTpOptions::new()
+            .tx_ext_address(0x33)
+            .rx_ext_address(0xF1)
+            .to_raw()
+            .unwrap();
+        assert_eq!(
+            opts.flags,
+            (IsoTpFlags::EXTEND_ADDR | IsoTpFlags::RX_EXT_ADDR).bits()
+        );
+        assert_eq!((opts.ext_address, opts.rx_ext_address), (0x33, 0xF1));
+
+        let res = IsoTpOptions::new().rx_ext_address(0xF1).validate();
+        assert_eq!(res, Err(SocketError::IsoTpRxExtAddrWithoutExtAddr));
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let opts = IsoTpOptions::new().functional(true);
+        assert_eq!(opts.flags(), IsoTpFlags::SF_BROADCAST);
+        assert!(opts.validate().is_ok());
+        assert!(opts.functional(false).flags().is_empty());
+
+        let res = opts.cf_broadcast(true).validate();
+        assert_eq!(res, Err(SocketError::IsoTpBroadcastConflict));
+    }
+
+    #[test]
+    fn test_frame_txtime() {
+        let opts = IsoTpOptions::new().frame_txtime(Duration::from_micros(100));
+        assert_eq!(opts.to_raw().unwrap().frame_txtime, 100_000);
+
+        let opts = IsoTpOptions::new().frame_txtime(Duration::ZERO);
+        assert_eq!(
+            opts.to_raw().unwrap().frame_txtime,
+            CAN_ISOTP_FRAME_TXTIME_ZERO
+        );
+    }
+
+    #[test]
+    fn test_flow_control() {
+        assert_eq!(size_of::<can_isotp_fc_options>(), 3);
+
+        let opts = IsoTpOptions::new()
+            .block_size(4)
+            .rx_stmin(Duration::from_millis(10))
+            .max_wait_frames(2);
+        let fc = opts.to_raw_fc();
+        assert_eq!((fc.bs, fc.stmin, fc.wftmax), (4, 10, 2));
+
+        assert_eq!(encode_stmin(Duration::from_micros(50)), 0);
+        assert_eq!(encode_stmin(Duration::from_micros(300)), 0xF3);
+        assert_eq!(encode_stmin(Duration::from_millis(127)), 0x7F);
+        assert_eq!(encode_stmin(Duration::from_secs(1)), 0x7F);
+
+        let opts = IsoTpOptions::new().tx_stmin(Duration::from_millis(1));
+        assert_eq!(opts.flags(), IsoTpFlags::FORCE_TXSTMIN);
+        assert_eq!(opts.tx_stmin, 1_000_000);
+    }
+
+    #[test]
+    fn test_link_layer() {
+        assert_eq!(size_of::<can_isotp_ll_options>(), 3);
+
+        let ll = IsoTpOptions::new().to_raw_ll();
+        assert_eq!((ll.mtu, ll.tx_dl, ll.tx_flags), (16, 8, 0));
+
+        let opts = IsoTpOptions::new().fd_frames(64).bit_rate_switch(true);
+        let ll = opts.to_raw_ll();
+        assert_eq!((ll.mtu, ll.tx_dl, ll.tx_flags), (72, 64, CANFD_BRS as u8));
+        assert!(opts.validate().is_ok());
+        assert_eq!(opts.bit_rate_switch(false).to_raw_ll().tx_flags, 0);
+
+        let res = IsoTpOptions::new().fd_frames(10).validate();
+        assert_eq!(res, Err(SocketError::IsoTpInvalidTxDataLength(10)));
+
+        let res = IsoTpOptions::new().bit_rate_switch(true).validate();
+        assert_eq!(res, Err(SocketError::IsoTpBrsWithoutFd));
+    }
+}
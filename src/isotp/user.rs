@@ -0,0 +1,530 @@
+// socketcan/src/isotp/user.rs
+//
+// A userspace ISO-TP implementation, for kernels without `can-isotp`.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! ISO-TP in userspace, over a raw CAN socket.
+//!
+//! This is what a [`CanIsoTpSocket`](super::CanIsoTpSocket) falls back to
+//! when the kernel has no `CAN_ISOTP` protocol. It segments messages into
+//! single, first, and consecutive frames, exchanges flow control frames
+//! with the peer, and reassembles messages on the way in, for classic CAN
+//! frames with normal or extended addressing.
+//!
+//! Since the state of a transfer only lives for the length of a `read()`
+//! or `write()` call, each call blocks until a message is complete. The
+//! read timeout and non-blocking mode of the socket apply to the wait for
+//! the first frame of a message; after that, the frames that follow have
+//! to arrive within the ISO 15765-2 timeouts.
+
+use super::{can_isotp_fc_options, can_isotp_ll_options, IsoTpFlags, IsoTpOptions};
+use crate::{
+    CanAddr, CanFilter, CanFrame, CanSocket, EmbeddedFrame, Id, Socket, SocketError, SocketOptions,
+};
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+/// How long to wait for a flow control frame from the peer (N_Bs)
+const N_BS_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// How long to wait for the next consecutive frame from the peer (N_Cr)
+const N_CR_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// The largest message that fits in a classic CAN first frame
+pub(super) const MAX_MSG_LEN: usize = 4095;
+
+// The protocol control information (PCI) types, in the high nibble
+const PCI_SF: u8 = 0x00;
+const PCI_FF: u8 = 0x10;
+const PCI_CF: u8 = 0x20;
+const PCI_FC: u8 = 0x30;
+
+// The flow status of a flow control frame
+const FC_CTS: u8 = 0;
+const FC_WAIT: u8 = 1;
+const FC_OVERFLOW: u8 = 2;
+
+/// Decodes an STmin byte, as sent by the peer in a flow control frame.
+///
+/// Reserved values are taken as the longest time, 127ms.
+fn decode_stmin(b: u8) -> Duration {
+    match b {
+        0x00..=0x7F => Duration::from_millis(u64::from(b)),
+        0xF1..=0xF9 => Duration::from_micros(100 * u64::from(b - 0xF0)),
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+/// Splits a message into the data of its frames, without any addressing
+/// byte or padding.
+///
+/// A message that fits in a single frame is one, otherwise it's a first
+/// frame and a run of consecutive frames. Each frame carries up to `room`
+/// bytes.
+fn segment(data: &[u8], room: usize) -> io::Result<Vec<Vec<u8>>> {
+    if data.is_empty() || data.len() > MAX_MSG_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid ISO-TP message length",
+        ));
+    }
+
+    if data.len() < room {
+        let mut sf = vec![PCI_SF | data.len() as u8];
+        sf.extend_from_slice(data);
+        return Ok(vec![sf]);
+    }
+
+    let len = data.len() as u16;
+    let (head, rest) = data.split_at(room - 2);
+    let mut ff = vec![PCI_FF | (len >> 8) as u8, len as u8];
+    ff.extend_from_slice(head);
+
+    let mut pdus = vec![ff];
+    for (i, chunk) in rest.chunks(room - 1).enumerate() {
+        let mut cf = vec![PCI_CF | ((i + 1) & 0x0F) as u8];
+        cf.extend_from_slice(chunk);
+        pdus.push(cf);
+    }
+    Ok(pdus)
+}
+
+/// What to do after a frame is received
+#[derive(Debug, PartialEq, Eq)]
+enum RxEvent {
+    // Nothing, yet
+    None,
+    // Send a flow control frame to the peer
+    FlowControl,
+    // The message is complete
+    Done(Vec<u8>),
+}
+
+/// Puts a message back together from its frames.
+#[derive(Debug, Default)]
+struct Reassembler {
+    data: Vec<u8>,
+    len: usize,
+    next_sn: u8,
+    // The consecutive frames received since the last flow control
+    block: u8,
+    active: bool,
+}
+
+impl Reassembler {
+    /// Takes the data of a frame, after any addressing byte.
+    ///
+    /// A single or first frame starts a new message, dropping one in
+    /// progress. Consecutive frames out of sequence are an `InvalidData`
+    /// error.
+    fn push(&mut self, pdu: &[u8], block_size: u8) -> io::Result<RxEvent> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid ISO-TP frame");
+        let &pci = pdu.first().ok_or_else(invalid)?;
+
+        match pci & 0xF0 {
+            PCI_SF => {
+                self.active = false;
+                let len = usize::from(pci & 0x0F);
+                match pdu.get(1..1 + len) {
+                    Some(data) if len > 0 => Ok(RxEvent::Done(data.to_vec())),
+                    _ => Err(invalid()),
+                }
+            }
+            PCI_FF => {
+                let len = usize::from(u16::from_be_bytes([
+                    pci & 0x0F,
+                    *pdu.get(1).ok_or_else(invalid)?,
+                ]));
+                if len <= pdu.len() - 2 {
+                    return Err(invalid());
+                }
+                self.data = pdu[2..].to_vec();
+                self.len = len;
+                self.next_sn = 1;
+                self.block = 0;
+                self.active = true;
+                Ok(RxEvent::FlowControl)
+            }
+            PCI_CF if self.active => {
+                if pci & 0x0F != self.next_sn {
+                    self.active = false;
+                    return Err(invalid());
+                }
+                self.next_sn = (self.next_sn + 1) & 0x0F;
+                let n = (self.len - self.data.len()).min(pdu.len() - 1);
+                self.data.extend_from_slice(&pdu[1..1 + n]);
+
+                if self.data.len() == self.len {
+                    self.active = false;
+                    return Ok(RxEvent::Done(std::mem::take(&mut self.data)));
+                }
+
+                self.block = self.block.wrapping_add(1);
+                match block_size {
+                    bs if bs != 0 && self.block == bs => {
+                        self.block = 0;
+                        Ok(RxEvent::FlowControl)
+                    }
+                    _ => Ok(RxEvent::None),
+                }
+            }
+            // Flow control, or a stray consecutive frame
+            _ => Ok(RxEvent::None),
+        }
+    }
+}
+
+// ===== UserIsoTp =====
+
+/// An ISO-TP endpoint, run in userspace over a raw CAN socket.
+#[derive(Debug)]
+pub(super) struct UserIsoTp {
+    sock: CanSocket,
+    rx_id: Id,
+    tx_id: Id,
+    opts: IsoTpOptions,
+}
+
+impl UserIsoTp {
+    /// Opens a raw socket on the interface of the ISO-TP address, to carry
+    /// the protocol with the options given.
+    ///
+    /// CAN FD link layer options can't be handled here, so `err`, from the
+    /// kernel's refusal of the ISO-TP socket, is returned for them.
+    pub(super) fn open(addr: &CanAddr, opts: &IsoTpOptions, err: io::Error) -> io::Result<Self> {
+        opts.validate()?;
+        if opts.ll != can_isotp_ll_options::default() {
+            return Err(err);
+        }
+
+        let sock = CanSocket::open_addr(&CanAddr::from_ifindex(addr.ifindex())?)?;
+        let rx_id = addr.isotp_rx_id();
+        sock.set_filters(&[CanFilter::exact(rx_id)])?;
+
+        Ok(Self {
+            sock,
+            rx_id,
+            tx_id: addr.isotp_tx_id(),
+            opts: *opts,
+        })
+    }
+
+    /// Gets a shared reference to the raw CAN socket
+    pub(super) fn as_socket(&self) -> &CanSocket {
+        &self.sock
+    }
+
+    /// Consumes the endpoint, giving back the raw CAN socket
+    pub(super) fn into_socket(self) -> CanSocket {
+        self.sock
+    }
+
+    /// Reads a complete message into the buffer, returning its length.
+    pub(super) fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let fc = self.opts.fc;
+        let mut rx = Reassembler::default();
+        let mut deadline: Option<Instant> = None;
+
+        loop {
+            let frame = match deadline {
+                None => self.sock.read_frame()?,
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    self.sock.read_frame_timeout(remaining)?
+                }
+            };
+            let pdu = match self.rx_pdu(&frame) {
+                Some(pdu) => pdu,
+                None => continue,
+            };
+
+            match rx.push(pdu, fc.bs)? {
+                RxEvent::None => (),
+                RxEvent::FlowControl => {
+                    if !self.opts.flags.contains(IsoTpFlags::LISTEN_MODE) {
+                        self.send_pdu(&[PCI_FC | FC_CTS, fc.bs, fc.stmin])?;
+                    }
+                }
+                RxEvent::Done(data) => {
+                    let n = data.len().min(buf.len());
+                    buf[..n].copy_from_slice(&data[..n]);
+                    if n < data.len() {
+                        return Err(SocketError::Truncated {
+                            received: buf.len(),
+                            actual: Some(data.len()),
+                        }
+                        .into());
+                    }
+                    return Ok(n);
+                }
+            }
+            // The N_Cr timeout only runs while a message is in progress,
+            // so stray frames on the ID don't end a blocking read
+            deadline = rx.active.then(|| Instant::now() + N_CR_TIMEOUT);
+        }
+    }
+
+    /// Writes a complete message, segmented as needed.
+    pub(super) fn write(&self, data: &[u8]) -> io::Result<()> {
+        let pdus = segment(data, self.room())?;
+        if pdus.len() > 1 && self.opts.flags.contains(IsoTpFlags::SF_BROADCAST) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "message too long for a single frame broadcast",
+            ));
+        }
+
+        let mut pdus = pdus.into_iter();
+        let first = pdus.next().unwrap_or_default();
+        self.send_pdu(&first)?;
+
+        if pdus.len() == 0 {
+            return Ok(());
+        }
+
+        // With CF broadcast, there's no flow control from the peers
+        let broadcast = self.opts.flags.contains(IsoTpFlags::CF_BROADCAST);
+        let mut fc = match broadcast {
+            true => can_isotp_fc_options::default(),
+            false => self.wait_fc()?,
+        };
+        let mut block = 0u8;
+
+        while let Some(cf) = pdus.next() {
+            let stmin = match self.opts.flags.contains(IsoTpFlags::FORCE_TXSTMIN) {
+                true => Duration::from_nanos(u64::from(self.opts.tx_stmin)),
+                false => decode_stmin(fc.stmin),
+            };
+            thread::sleep(stmin);
+            self.send_pdu(&cf)?;
+
+            block = block.wrapping_add(1);
+            if !broadcast && fc.bs != 0 && block == fc.bs && pdus.len() > 0 {
+                fc = self.wait_fc()?;
+                block = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // The number of bytes of ISO-TP data in a frame
+    fn room(&self) -> usize {
+        match self.opts.flags.contains(IsoTpFlags::EXTEND_ADDR) {
+            true => 7,
+            false => 8,
+        }
+    }
+
+    // Waits for the peer to clear us to send
+    fn wait_fc(&self) -> io::Result<can_isotp_fc_options> {
+        let mut deadline = Instant::now() + N_BS_TIMEOUT;
+        let mut waits = 0u8;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let frame = self.sock.read_frame_timeout(remaining)?;
+            let pdu = match self.rx_pdu(&frame) {
+                Some(pdu) if pdu.len() >= 3 && pdu[0] & 0xF0 == PCI_FC => pdu,
+                _ => continue,
+            };
+
+            match pdu[0] & 0x0F {
+                FC_CTS => {
+                    return Ok(can_isotp_fc_options {
+                        bs: pdu[1],
+                        stmin: pdu[2],
+                        wftmax: 0,
+                    })
+                }
+                FC_WAIT => {
+                    waits = waits.saturating_add(1);
+                    let max = self.opts.fc.wftmax;
+                    if max != 0 && waits > max {
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                    deadline = Instant::now() + N_BS_TIMEOUT;
+                }
+                FC_OVERFLOW => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::OutOfMemory,
+                        "ISO-TP peer buffer overflow",
+                    ))
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "invalid ISO-TP flow status",
+                    ))
+                }
+            }
+        }
+    }
+
+    // Gets the ISO-TP data of a frame for this endpoint, after any
+    // addressing byte
+    fn rx_pdu<'a>(&self, frame: &'a CanFrame) -> Option<&'a [u8]> {
+        if frame.id() != self.rx_id || !matches!(frame, CanFrame::Data(_)) {
+            return None;
+        }
+        let data = frame.data();
+        let flags = self.opts.flags;
+        if !flags.contains(IsoTpFlags::EXTEND_ADDR) {
+            return Some(data);
+        }
+
+        let addr = match flags.contains(IsoTpFlags::RX_EXT_ADDR) {
+            true => self.opts.rx_ext_address,
+            false => self.opts.ext_address,
+        };
+        match data.split_first() {
+            Some((&b, pdu)) if b == addr => Some(pdu),
+            _ => None,
+        }
+    }
+
+    // Sends ISO-TP data in a frame, with any addressing byte and padding
+    fn send_pdu(&self, pdu: &[u8]) -> io::Result<()> {
+        let mut data = Vec::with_capacity(8);
+        if self.opts.flags.contains(IsoTpFlags::EXTEND_ADDR) {
+            data.push(self.opts.ext_address);
+        }
+        data.extend_from_slice(pdu);
+        if self.opts.flags.contains(IsoTpFlags::TX_PADDING) {
+            data.resize(8, self.opts.tx_pad);
+        }
+
+        let frame = CanFrame::new(self.tx_id, &data)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.sock.write_frame_insist(&frame)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StandardId;
+    use std::os::unix::{io::OwnedFd, net::UnixDatagram};
+
+    // An endpoint over one end of a datagram pair, which stands in for
+    // the CAN bus, with the other end as the peer.
+    fn endpoint(flags: IsoTpFlags) -> (UserIsoTp, CanSocket) {
+        let (a, b) = UnixDatagram::pair().unwrap();
+        let peer = CanSocket::from(OwnedFd::from(b));
+        peer.set_nonblocking(true).unwrap();
+        let tp = UserIsoTp {
+            sock: CanSocket::from(OwnedFd::from(a)),
+            rx_id: StandardId::new(0x7E8).unwrap().into(),
+            tx_id: StandardId::new(0x7E0).unwrap().into(),
+            opts: IsoTpOptions::new().set_flags(flags, true),
+        };
+        (tp, peer)
+    }
+
+    #[test]
+    fn test_stmin() {
+        assert_eq!(decode_stmin(0), Duration::ZERO);
+        assert_eq!(decode_stmin(20), Duration::from_millis(20));
+        assert_eq!(decode_stmin(0xF3), Duration::from_micros(300));
+        assert_eq!(decode_stmin(0x80), Duration::from_millis(127));
+    }
+
+    #[test]
+    fn test_segment() {
+        let pdus = segment(&[0x22, 0xF1, 0x90], 8).unwrap();
+        assert_eq!(pdus, &[vec![0x03, 0x22, 0xF1, 0x90]]);
+
+        let data: Vec<u8> = (0..20).collect();
+        let pdus = segment(&data, 8).unwrap();
+        assert_eq!(pdus.len(), 3);
+        assert_eq!(pdus[0], [0x10, 20, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(pdus[1], [0x21, 6, 7, 8, 9, 10, 11, 12]);
+        assert_eq!(pdus[2], [0x22, 13, 14, 15, 16, 17, 18, 19]);
+
+        // Extended addressing leaves one less byte in each frame
+        let pdus = segment(&data[..7], 7).unwrap();
+        assert_eq!(pdus[0], [0x10, 7, 0, 1, 2, 3, 4]);
+        assert_eq!(pdus[1], [0x21, 5, 6]);
+
+        assert!(segment(&[], 8).is_err());
+        assert!(segment(&[0; 4096], 8).is_err());
+    }
+
+    #[test]
+    fn test_reassemble() {
+        let data: Vec<u8> = (0..50).collect();
+        let pdus = segment(&data, 8).unwrap();
+
+        let mut rx = Reassembler::default();
+        assert_eq!(rx.push(&pdus[0], 3).unwrap(), RxEvent::FlowControl);
+        assert_eq!(rx.push(&pdus[1], 3).unwrap(), RxEvent::None);
+        assert_eq!(rx.push(&pdus[2], 3).unwrap(), RxEvent::None);
+        assert_eq!(rx.push(&pdus[3], 3).unwrap(), RxEvent::FlowControl);
+        assert_eq!(rx.push(&pdus[4], 3).unwrap(), RxEvent::None);
+        assert_eq!(rx.push(&pdus[5], 3).unwrap(), RxEvent::None);
+        assert_eq!(rx.push(&pdus[6], 3).unwrap(), RxEvent::FlowControl);
+        assert_eq!(rx.push(&pdus[7], 3).unwrap(), RxEvent::Done(data));
+
+        // Padding after a single frame is dropped
+        let mut rx = Reassembler::default();
+        assert_eq!(
+            rx.push(&[0x02, 0x50, 0x01, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC], 0)
+                .unwrap(),
+            RxEvent::Done(vec![0x50, 0x01])
+        );
+
+        // Out of sequence
+        rx.push(&pdus[0], 0).unwrap();
+        assert!(rx.push(&pdus[2], 0).is_err());
+    }
+
+    #[test]
+    fn test_sf_broadcast_too_long() {
+        let (tp, peer) = endpoint(IsoTpFlags::SF_BROADCAST);
+
+        let err = tp.write(&[0x55; 20]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        // Nothing, not even a first frame, went out
+        let err = peer.read_frame().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        tp.write(&[0x3E, 0x80]).unwrap();
+        assert_eq!(peer.read_frame().unwrap().data(), &[0x02, 0x3E, 0x80]);
+    }
+
+    #[test]
+    fn test_read_after_stray_frame() {
+        let (tp, peer) = endpoint(IsoTpFlags::empty());
+        let id = StandardId::new(0x7E8).unwrap();
+
+        // A consecutive frame with no transfer in progress, then a single
+        // frame only after the N_Cr timeout would have passed
+        let writer = thread::spawn(move || {
+            let cf = CanFrame::new(id, &[0x21, 1, 2, 3]).unwrap();
+            peer.write_frame(&cf).unwrap();
+            thread::sleep(N_CR_TIMEOUT + Duration::from_millis(200));
+            let sf = CanFrame::new(id, &[0x02, 0x7E, 0x00]).unwrap();
+            peer.write_frame(&sf).unwrap();
+            peer
+        });
+
+        let mut buf = [0u8; 8];
+        let n = tp.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &[0x7E, 0x00]);
+        let _peer = writer.join().unwrap();
+    }
+}
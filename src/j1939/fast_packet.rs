@@ -0,0 +1,281 @@
+// socketcan/src/j1939/fast_packet.rs
+//
+// The NMEA 2000 fast-packet protocol, over J1939 sockets.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! The NMEA 2000 fast-packet protocol.
+//!
+//! NMEA 2000 sends many of its PGNs of more than 8 bytes as "fast
+//! packets", rather than with the J1939 transport protocol. A message of
+//! up to 223 bytes is split into as many as 32 frames, sent back to back
+//! with the same PGN. The first byte of each frame holds a 3-bit sequence
+//! counter, which tells the messages apart, and a 5-bit frame counter.
+//! The first frame also carries the length of the message, then 6 bytes
+//! of data, and the others carry 7 bytes each.
+//!
+//! The kernel doesn't know which PGNs are fast packets, so it passes the
+//! frames through a [`CanJ1939Socket`] as 8-byte messages. A
+//! [`FastPacketSocket`] splits and reassembles them. The socket should be
+//! filtered down to the fast-packet PGNs that are expected, as any other
+//! message read from it is taken to be a fast-packet frame.
+//!
+//! ```no_run
+//! use socketcan::{
+//!     j1939::{CanJ1939Socket, FastPacketSocket, J1939Filter, J1939_NO_NAME, J1939_NO_PGN},
+//!     CanAddr,
+//! };
+//!
+//! // PGN 129029, the GNSS position data
+//! let sock = CanJ1939Socket::open("can0", J1939_NO_NAME, J1939_NO_PGN, 0x80).unwrap();
+//! sock.set_filters(&[J1939Filter::new().pgn(129029)]).unwrap();
+//!
+//! let mut sock = FastPacketSocket::new(sock);
+//! let (data, from) = sock.recv_from().unwrap();
+//! println!("GNSS position from {:02X}: {:02X?}", from.j1939_addr(), data);
+//! ```
+
+use super::CanJ1939Socket;
+use crate::CanAddr;
+use std::{collections::HashMap, io};
+
+/// The largest message that fits in a fast packet
+pub const FAST_PACKET_MAX_SIZE: usize = 223;
+
+/// The most frames that a fast packet can take
+const MAX_FRAMES: u8 = 32;
+
+/// The byte used to pad the last frame of a message
+const PAD: u8 = 0xFF;
+
+/// Splits a message into its fast-packet frames, with the 3-bit sequence
+/// counter given.
+///
+/// The last frame is padded to 8 bytes. A message that's too large fails
+/// with an `InvalidInput` error.
+pub fn fast_packet_frames(seq: u8, data: &[u8]) -> io::Result<Vec<[u8; 8]>> {
+    if data.len() > FAST_PACKET_MAX_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "message too large for a fast packet",
+        ));
+    }
+
+    let seq = (seq & 0x07) << 5;
+    let split = data.len().min(6);
+    let (first, rest) = data.split_at(split);
+
+    let mut frame = [PAD; 8];
+    frame[0] = seq;
+    frame[1] = data.len() as u8;
+    frame[2..2 + first.len()].copy_from_slice(first);
+
+    let mut frames = vec![frame];
+    for (i, chunk) in rest.chunks(7).enumerate() {
+        let mut frame = [PAD; 8];
+        frame[0] = seq | (i as u8 + 1);
+        frame[1..1 + chunk.len()].copy_from_slice(chunk);
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+// ===== FastPacketAssembler =====
+
+#[derive(Debug, Clone)]
+struct Session {
+    seq: u8,
+    size: usize,
+    next_frame: u8,
+    data: Vec<u8>,
+}
+
+/// Reassembles fast-packet messages from their frames.
+///
+/// A message is tracked for each source address and PGN. A frame that's
+/// out of order, or from another sequence, drops the message in progress,
+/// and the first frame of a new message always starts over.
+#[derive(Debug, Default, Clone)]
+pub struct FastPacketAssembler {
+    sessions: HashMap<(u8, u32), Session>,
+}
+
+impl FastPacketAssembler {
+    /// Creates an assembler, with no messages in progress.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles a frame, returning the message when it's complete.
+    pub fn push(&mut self, src: u8, pgn: u32, frame: &[u8]) -> Option<Vec<u8>> {
+        let key = (src, pgn);
+        let (&ctrl, data) = frame.split_first()?;
+        let (seq, index) = (ctrl >> 5, ctrl & 0x1F);
+
+        if index == 0 {
+            let (&size, data) = data.split_first()?;
+            let size = usize::from(size);
+            if size > FAST_PACKET_MAX_SIZE {
+                self.sessions.remove(&key);
+                return None;
+            }
+            let mut session = Session {
+                seq,
+                size,
+                next_frame: 1,
+                data: Vec::with_capacity(size),
+            };
+            session
+                .data
+                .extend_from_slice(&data[..data.len().min(size)]);
+            if session.data.len() == size {
+                self.sessions.remove(&key);
+                return Some(session.data);
+            }
+            self.sessions.insert(key, session);
+            return None;
+        }
+
+        let session = self.sessions.get_mut(&key)?;
+        if seq != session.seq || index != session.next_frame || index >= MAX_FRAMES {
+            self.sessions.remove(&key);
+            return None;
+        }
+
+        let n = data.len().min(session.size - session.data.len());
+        session.data.extend_from_slice(&data[..n]);
+        session.next_frame += 1;
+
+        match session.data.len() == session.size {
+            true => self.sessions.remove(&key).map(|s| s.data),
+            false => None,
+        }
+    }
+}
+
+// ===== FastPacketSocket =====
+
+/// A J1939 socket that sends and receives NMEA 2000 fast packets.
+#[derive(Debug)]
+pub struct FastPacketSocket {
+    sock: CanJ1939Socket,
+    rx: FastPacketAssembler,
+    // The next sequence counter for each PGN sent
+    tx_seq: HashMap<u32, u8>,
+}
+
+impl FastPacketSocket {
+    /// Creates a fast-packet socket over a J1939 socket.
+    pub fn new(sock: CanJ1939Socket) -> Self {
+        Self {
+            sock,
+            rx: FastPacketAssembler::new(),
+            tx_seq: HashMap::new(),
+        }
+    }
+
+    /// Gets a shared reference to the underlying J1939 socket
+    pub fn as_socket(&self) -> &CanJ1939Socket {
+        &self.sock
+    }
+
+    /// Sends a message to the peer, as a fast packet with the PGN in the
+    /// peer's address.
+    ///
+    /// The sequence counter is kept for each PGN, and advances with each
+    /// message sent.
+    pub fn send_to(&mut self, data: &[u8], peer: &CanAddr) -> io::Result<()> {
+        let seq = self.tx_seq.entry(peer.j1939_pgn()).or_insert(0);
+        let frames = fast_packet_frames(*seq, data)?;
+        *seq = (*seq + 1) & 0x07;
+
+        for frame in &frames {
+            self.sock.send_to(frame, peer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads frames until a message is complete, returning it along with
+    /// the address of the sender, which carries the PGN.
+    pub fn recv_from(&mut self) -> io::Result<(Vec<u8>, CanAddr)> {
+        let mut buf = [0u8; 8];
+        loop {
+            let (n, from) = self.sock.recv_from(&mut buf)?;
+            if let Some(data) = self.rx.push(from.j1939_addr(), from.j1939_pgn(), &buf[..n]) {
+                return Ok((data, from));
+            }
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: u32 = 129029;
+
+    #[test]
+    fn test_frames() {
+        let data: Vec<u8> = (0..20).collect();
+        let frames = fast_packet_frames(2, &data).unwrap();
+        assert_eq!(
+            frames,
+            &[
+                [0x40, 20, 0, 1, 2, 3, 4, 5],
+                [0x41, 6, 7, 8, 9, 10, 11, 12],
+                [0x42, 13, 14, 15, 16, 17, 18, 19],
+            ]
+        );
+
+        let frames = fast_packet_frames(9, &[1, 2]).unwrap();
+        assert_eq!(frames, &[[0x20, 2, 1, 2, PAD, PAD, PAD, PAD]]);
+
+        assert_eq!(fast_packet_frames(0, &[0; 223]).unwrap().len(), 32);
+        assert!(fast_packet_frames(0, &[0; 224]).is_err());
+    }
+
+    #[test]
+    fn test_reassembly() {
+        let data: Vec<u8> = (0..223).map(|i| i as u8).collect();
+        let frames = fast_packet_frames(5, &data).unwrap();
+
+        let mut asm = FastPacketAssembler::new();
+        let (last, rest) = frames.split_last().unwrap();
+        for frame in rest {
+            assert_eq!(asm.push(0x10, PGN, frame), None);
+        }
+        assert_eq!(asm.push(0x10, PGN, last), Some(data));
+    }
+
+    #[test]
+    fn test_interleaved() {
+        let a = fast_packet_frames(1, &[0xAA; 10]).unwrap();
+        let b = fast_packet_frames(1, &[0xBB; 10]).unwrap();
+
+        // Messages from different sources don't mix
+        let mut asm = FastPacketAssembler::new();
+        assert_eq!(asm.push(0x10, PGN, &a[0]), None);
+        assert_eq!(asm.push(0x11, PGN, &b[0]), None);
+        assert_eq!(asm.push(0x11, PGN, &b[1]), Some(vec![0xBB; 10]));
+        assert_eq!(asm.push(0x10, PGN, &a[1]), Some(vec![0xAA; 10]));
+
+        // A missing frame drops the message
+        let c = fast_packet_frames(2, &[0xCC; 20]).unwrap();
+        assert_eq!(asm.push(0x10, PGN, &c[0]), None);
+        assert_eq!(asm.push(0x10, PGN, &c[2]), None);
+        assert_eq!(asm.push(0x10, PGN, &c[1]), None);
+
+        // As does one from another sequence
+        let d = fast_packet_frames(3, &[0xDD; 20]).unwrap();
+        assert_eq!(asm.push(0x10, PGN, &c[0]), None);
+        assert_eq!(asm.push(0x10, PGN, &d[1]), None);
+        assert_eq!(asm.push(0x10, PGN, &c[1]), None);
+    }
+}
@@ -0,0 +1,652 @@
+// socketcan/src/j1939/mod.rs
+//
+// Sockets for the kernel SAE J1939 protocol.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! SAE J1939 sockets.
+//!
+//! The kernel's `CAN_J1939` protocol, in mainline since Linux 5.4, handles
+//! the J1939 addressing and transport protocol, so a [`CanJ1939Socket`]
+//! sends and receives whole parameter group (PGN) payloads of up to
+//! several kilobytes, rather than individual frames.
+//!
+//! A socket is bound to an interface with its own NAME, PGN, and address,
+//! in a J1939 [`CanAddr`]. Any of these can be left out with the
+//! `J1939_NO_*` values. Messages are sent to, and received from, peers
+//! given by the same kind of address.
+//!
+//! The kernel keeps track of the addresses claimed on the bus, but leaves
+//! the claiming to the application. An [`AddressClaimer`] does that, for
+//! an ECU with a NAME, following SAE J1939-81.
+//!
+//! Without the kernel module, the transport protocol can also be handled
+//! in userspace: a [`TpReceiver`] reassembles the BAM broadcasts and the
+//! RTS/CTS connections read from a raw CAN socket.
+//!
+//! The NMEA 2000 fast-packet protocol, for messages of up to 223 bytes,
+//! is layered on a J1939 socket by a [`FastPacketSocket`].
+//!
+//! ```no_run
+//! use socketcan::{j1939::{CanJ1939Socket, J1939_NO_NAME, J1939_NO_PGN}, CanAddr};
+//!
+//! let sock = CanJ1939Socket::open("vcan0", J1939_NO_NAME, J1939_NO_PGN, 0x80).unwrap();
+//!
+//! let peer = CanAddr::from_iface_j1939("vcan0", J1939_NO_NAME, 0xFEF1, 0x20).unwrap();
+//! sock.send_to(&[0xFF; 8], &peer).unwrap();
+//!
+//! let mut buf = [0u8; 1785];
+//! let (n, from) = sock.recv_from(&mut buf).unwrap();
+//! println!("PGN {:05X} from {:02X}: {:02X?}", from.j1939_pgn(), from.j1939_addr(), &buf[..n]);
+//! ```
+//!
+//! The definitions are in the kernel header:
+//!
+//! <https://github.com/torvalds/linux/blob/master/include/uapi/linux/can/j1939.h?ts=4>
+
+use crate::{
+    socket::{check_truncated, open_socket, recv_checked, recvmsg_raw},
+    CanAddr, SocketError,
+};
+use libc::{c_int, socklen_t, CAN_J1939, MSG_PEEK, MSG_TRUNC, SOL_CAN_J1939};
+use socket2::SockAddr;
+use std::{
+    io::{self, Write},
+    mem::{size_of, size_of_val},
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd},
+    ptr,
+    time::{Duration, Instant},
+};
+
+mod fast_packet;
+mod tp;
+
+pub use fast_packet::{
+    fast_packet_frames, FastPacketAssembler, FastPacketSocket, FAST_PACKET_MAX_SIZE,
+};
+pub use tp::{
+    J1939Message, TpEvent, TpReassembler, TpReceiver, PGN_TP_CM, PGN_TP_DT, TP_MAX_SIZE, TP_TIMEOUT,
+};
+
+pub use libc::{
+    J1939_FILTER_MAX, J1939_IDLE_ADDR, J1939_MAX_UNICAST_ADDR, J1939_NO_ADDR, J1939_NO_NAME,
+    J1939_NO_PGN, J1939_PGN_ADDRESS_CLAIMED, J1939_PGN_ADDRESS_COMMANDED, J1939_PGN_MAX,
+    J1939_PGN_PDU1_MAX, J1939_PGN_REQUEST, SO_J1939_FILTER, SO_J1939_PROMISC, SO_J1939_SEND_PRIO,
+};
+
+/// The largest message the extended transport protocol can carry
+const ETP_MAX_SIZE: usize = 7 * 0xFF_FFFF;
+
+// ===== J1939Filter =====
+
+/// A filter for the messages received on a J1939 socket.
+///
+/// A message passes the filter if its NAME, PGN, and source address all
+/// match, under their masks. A new filter matches everything, and each of
+/// the fields can then be narrowed down.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone)]
+pub struct J1939Filter(libc::j1939_filter);
+
+impl J1939Filter {
+    /// Creates a filter that matches every message.
+    pub fn new() -> Self {
+        Self(libc::j1939_filter {
+            name: 0,
+            name_mask: 0,
+            pgn: 0,
+            pgn_mask: 0,
+            addr: 0,
+            addr_mask: 0,
+        })
+    }
+
+    /// Only matches messages from the ECU with the NAME given.
+    pub fn name(mut self, name: u64) -> Self {
+        self.0.name = name;
+        self.0.name_mask = !0;
+        self
+    }
+
+    /// Only matches messages with the PGN given.
+    pub fn pgn(mut self, pgn: u32) -> Self {
+        self.0.pgn = pgn;
+        self.0.pgn_mask = J1939_PGN_MAX;
+        self
+    }
+
+    /// Only matches messages from the source address given.
+    pub fn addr(mut self, addr: u8) -> Self {
+        self.0.addr = addr;
+        self.0.addr_mask = !0;
+        self
+    }
+}
+
+impl Default for J1939Filter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<libc::j1939_filter> for J1939Filter {
+    fn from(filt: libc::j1939_filter) -> Self {
+        Self(filt)
+    }
+}
+
+impl AsRef<libc::j1939_filter> for J1939Filter {
+    fn as_ref(&self) -> &libc::j1939_filter {
+        &self.0
+    }
+}
+
+// ===== CanJ1939Socket =====
+
+/// A socket for J1939 messages, using the kernel's `CAN_J1939` protocol.
+///
+/// This requires the `can-j1939` kernel module.
+#[allow(missing_copy_implementations)]
+#[derive(Debug)]
+pub struct CanJ1939Socket(socket2::Socket);
+
+impl CanJ1939Socket {
+    /// Opens a J1939 socket on the named interface, bound to the NAME,
+    /// PGN, and address given.
+    pub fn open(ifname: &str, name: u64, pgn: u32, addr: u8) -> io::Result<Self> {
+        let addr = CanAddr::from_iface_j1939(ifname, name, pgn, addr)?;
+        Self::open_addr(&addr)
+    }
+
+    /// Opens a J1939 socket bound to the address given.
+    ///
+    /// The address should be created with [`CanAddr::new_j1939()`] or
+    /// [`CanAddr::from_iface_j1939()`].
+    pub fn open_addr(addr: &CanAddr) -> io::Result<Self> {
+        open_socket(addr, CAN_J1939).map(Self)
+    }
+
+    /// Gets a shared reference to the underlying socket object
+    pub fn as_raw_socket(&self) -> &socket2::Socket {
+        &self.0
+    }
+
+    /// Binds the socket again, to change its NAME, PGN, or address.
+    ///
+    /// The kernel allows this as long as the interface stays the same.
+    pub fn rebind(&self, addr: &CanAddr) -> io::Result<()> {
+        self.0.bind(&SockAddr::from(*addr))
+    }
+
+    /// Sets the default peer for `send()` and `recv()`.
+    ///
+    /// Once connected, only messages from the peer are received.
+    pub fn connect(&self, peer: &CanAddr) -> io::Result<()> {
+        self.0.connect(&SockAddr::from(*peer))
+    }
+
+    /// Sends a message to the connected peer.
+    pub fn send(&self, data: &[u8]) -> io::Result<()> {
+        match (&self.0).write(data)? {
+            n if n == data.len() => Ok(()),
+            _ => Err(io::ErrorKind::WriteZero.into()),
+        }
+    }
+
+    /// Sends a message to the peer given.
+    ///
+    /// The PGN of the peer address is the PGN of the message, and its
+    /// NAME or address is the destination. Use `J1939_NO_ADDR` as the
+    /// address to broadcast, which needs [`CanJ1939Socket::set_broadcast()`].
+    pub fn send_to(&self, data: &[u8], peer: &CanAddr) -> io::Result<()> {
+        match self.0.send_to(data, &SockAddr::from(*peer))? {
+            n if n == data.len() => Ok(()),
+            _ => Err(io::ErrorKind::WriteZero.into()),
+        }
+    }
+
+    /// Receives a message into the buffer, returning its length.
+    ///
+    /// If the message is larger than the buffer, this fails with an
+    /// `InvalidData` error wrapping [`SocketError::Truncated`]. The kernel
+    /// doesn't report the real size of the message, but
+    /// [`recv_vec_from()`](Self::recv_vec_from) reads one of any size.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        recv_checked(self.as_raw_fd(), buf)
+    }
+
+    /// Receives a message into the buffer, returning its length and the
+    /// address of the sender.
+    ///
+    /// The address has the PGN of the message along with the NAME and
+    /// address of the sender. A message larger than the buffer is reported
+    /// as with `recv()`.
+    pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, CanAddr)> {
+        let res = recvmsg_raw(self.as_raw_fd(), buf, &mut [], MSG_TRUNC)?;
+        check_truncated(&res, buf.len())?;
+        Ok((res.len, res.addr))
+    }
+
+    /// Receives a message into a new vector that fits it, along with the
+    /// address of the sender.
+    ///
+    /// The kernel doesn't report the size of a waiting message, so it's
+    /// peeked into a buffer that grows from the largest TP message, until
+    /// the message fits. That way, a message of any length that the
+    /// transport protocols can carry is read whole.
+    pub fn recv_vec_from(&self) -> io::Result<(Vec<u8>, CanAddr)> {
+        let mut buf = vec![0; TP_MAX_SIZE];
+        loop {
+            let res = recvmsg_raw(self.as_raw_fd(), &mut buf, &mut [], MSG_PEEK | MSG_TRUNC)?;
+            if res.flags & MSG_TRUNC == 0 || buf.len() >= ETP_MAX_SIZE {
+                break;
+            }
+            buf.resize((2 * buf.len()).min(ETP_MAX_SIZE), 0);
+        }
+        let (n, addr) = self.recv_from(&mut buf)?;
+        buf.truncate(n);
+        Ok((buf, addr))
+    }
+
+    /// Sets the filters for the messages received on the socket.
+    ///
+    /// An empty set of filters removes any filtering. The kernel accepts
+    /// up to `J1939_FILTER_MAX` (512) filters, and passing more than that
+    /// fails with an `InvalidInput` error wrapping
+    /// [`SocketError::TooManyFilters`].
+    pub fn set_filters(&self, filters: &[J1939Filter]) -> io::Result<()> {
+        let max = J1939_FILTER_MAX as usize;
+        if filters.len() > max {
+            return Err(SocketError::TooManyFilters {
+                got: filters.len(),
+                max,
+            }
+            .into());
+        }
+
+        let (val, len) = match filters {
+            [] => (ptr::null(), 0),
+            _ => (filters.as_ptr().cast(), size_of_val(filters)),
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                self.as_raw_fd(),
+                SOL_CAN_J1939,
+                SO_J1939_FILTER,
+                val,
+                len as socklen_t,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Enables or disables promiscuous mode.
+    ///
+    /// In promiscuous mode, the socket receives all the messages on the
+    /// bus, not only those addressed to it, subject to its filters.
+    pub fn set_promisc(&self, enabled: bool) -> io::Result<()> {
+        self.set_int_option(SO_J1939_PROMISC, c_int::from(enabled))
+    }
+
+    /// Enables or disables sending broadcast messages, to `J1939_NO_ADDR`.
+    ///
+    /// This also enables receiving them.
+    pub fn set_broadcast(&self, enabled: bool) -> io::Result<()> {
+        self.0.set_broadcast(enabled)
+    }
+
+    /// Sets the priority of the messages sent, from 0 (highest) to 7.
+    pub fn set_send_priority(&self, prio: u8) -> io::Result<()> {
+        self.set_int_option(SO_J1939_SEND_PRIO, c_int::from(prio))
+    }
+
+    /// Change socket to non-blocking mode or back to blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.0.set_nonblocking(nonblocking)
+    }
+
+    /// Sets the read timeout on the socket
+    pub fn set_read_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.0.set_read_timeout(duration.into())
+    }
+
+    /// Sets the write timeout on the socket
+    pub fn set_write_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.0.set_write_timeout(duration.into())
+    }
+
+    // Sets an integer J1939 socket option.
+    fn set_int_option(&self, name: c_int, val: c_int) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                self.as_raw_fd(),
+                SOL_CAN_J1939,
+                name,
+                &val as *const c_int as *const _,
+                size_of::<c_int>() as socklen_t,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+impl AsRawFd for CanJ1939Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsFd for CanJ1939Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl IntoRawFd for CanJ1939Socket {
+    fn into_raw_fd(self) -> RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for CanJ1939Socket {
+    fn from(fd: OwnedFd) -> Self {
+        Self(socket2::Socket::from(fd))
+    }
+}
+
+// ===== AddressClaimer =====
+
+/// The time others have to contest an address claim
+pub const ADDRESS_CLAIM_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// The state of an address claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimState {
+    /// No address has been claimed yet
+    Unclaimed,
+    /// The address was claimed, and is held
+    Claimed(u8),
+    /// None of the addresses could be claimed
+    CannotClaim,
+}
+
+/// Determines if a claim with our NAME wins over one with theirs.
+///
+/// The NAME with the lower value has the higher priority.
+fn name_wins(ours: u64, theirs: u64) -> bool {
+    ours < theirs
+}
+
+/// Gets the NAME from an Address Claimed message, if it is one.
+fn claimed_name(pgn: u32, data: &[u8]) -> Option<u64> {
+    match (pgn & J1939_PGN_PDU1_MAX, data) {
+        (J1939_PGN_ADDRESS_CLAIMED, &[a, b, c, d, e, f, g, h]) => {
+            Some(u64::from_le_bytes([a, b, c, d, e, f, g, h]))
+        }
+        _ => None,
+    }
+}
+
+/// Determines if a message is a request for address claims.
+fn is_claim_request(pgn: u32, data: &[u8]) -> bool {
+    let requested = match *data {
+        [a, b, c] => u32::from_le_bytes([a, b, c, 0]),
+        _ => return false,
+    };
+    pgn & J1939_PGN_PDU1_MAX == J1939_PGN_REQUEST && requested == J1939_PGN_ADDRESS_CLAIMED
+}
+
+/// Claims an address on the bus for an ECU, and defends it.
+///
+/// The claimer tries each of its candidate addresses in turn. A claim
+/// that isn't contested within 250ms holds. If another ECU claims the
+/// same address, the one with the lower NAME keeps it, and the other moves
+/// on to its next address. When there are none left, a "cannot claim"
+/// message is sent.
+///
+/// Once an address is claimed, [`AddressClaimer::process()`] should be
+/// called in a loop, to answer requests for claims and to defend the
+/// address. If it's lost to a higher-priority ECU, the callback set by
+/// [`AddressClaimer::on_address_lost()`] is called, and a new address is
+/// claimed.
+#[allow(missing_debug_implementations)]
+pub struct AddressClaimer {
+    sock: CanJ1939Socket,
+    ifindex: u32,
+    name: u64,
+    candidates: Vec<u8>,
+    next: usize,
+    state: ClaimState,
+    on_lost: Option<Box<dyn FnMut(u8) + Send>>,
+}
+
+impl AddressClaimer {
+    /// Creates a claimer on the named interface, for the ECU with the
+    /// NAME, which tries the candidate addresses in order.
+    ///
+    /// An ECU that's arbitrary address capable would usually list its
+    /// preferred address, followed by the self-configurable range of
+    /// 128-247.
+    pub fn new<I>(ifname: &str, name: u64, candidates: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = u8>,
+    {
+        let addr = CanAddr::from_iface_j1939(ifname, name, J1939_NO_PGN, J1939_IDLE_ADDR)?;
+        let sock = CanJ1939Socket::open_addr(&addr)?;
+        sock.set_broadcast(true)?;
+        sock.set_filters(&[
+            J1939Filter::new().pgn(J1939_PGN_ADDRESS_CLAIMED),
+            J1939Filter::new().pgn(J1939_PGN_REQUEST),
+        ])?;
+
+        Ok(Self {
+            sock,
+            ifindex: addr.ifindex() as u32,
+            name,
+            candidates: candidates.into_iter().collect(),
+            next: 0,
+            state: ClaimState::Unclaimed,
+            on_lost: None,
+        })
+    }
+
+    /// Sets a function to be called with the address, when it's lost to
+    /// another ECU.
+    pub fn on_address_lost<F>(&mut self, f: F)
+    where
+        F: FnMut(u8) + Send + 'static,
+    {
+        self.on_lost = Some(Box::new(f));
+    }
+
+    /// Gets the state of the claim.
+    pub fn state(&self) -> ClaimState {
+        self.state
+    }
+
+    /// Gets the address that was claimed, if any.
+    pub fn address(&self) -> Option<u8> {
+        match self.state {
+            ClaimState::Claimed(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Gets the socket used for the claims.
+    ///
+    /// Once an address is claimed, this is bound to it.
+    pub fn as_socket(&self) -> &CanJ1939Socket {
+        &self.sock
+    }
+
+    /// Claims an address, blocking until one holds or all the candidates
+    /// are lost.
+    ///
+    /// This returns the address claimed, or fails with an
+    /// `AddrNotAvailable` error if none of them could be.
+    pub fn claim(&mut self) -> io::Result<u8> {
+        while let Some(&addr) = self.candidates.get(self.next) {
+            self.send_claim(addr)?;
+            if self.wait_uncontested(addr)? {
+                self.state = ClaimState::Claimed(addr);
+                return Ok(addr);
+            }
+            self.next += 1;
+        }
+
+        self.send_claim(J1939_IDLE_ADDR)?;
+        self.state = ClaimState::CannotClaim;
+        Err(io::ErrorKind::AddrNotAvailable.into())
+    }
+
+    /// Handles the next claim message received within the timeout, while
+    /// holding an address.
+    ///
+    /// This answers requests for claims, and defends the address against
+    /// lower-priority claims. If it's lost, the address-lost callback is
+    /// called, and a new address is claimed, with the result returned.
+    pub fn process(&mut self, timeout: Duration) -> io::Result<ClaimState> {
+        let addr = match self.state {
+            ClaimState::Claimed(addr) => addr,
+            state => return Ok(state),
+        };
+
+        if self.defend(addr, timeout)? == Some(false) {
+            self.state = ClaimState::Unclaimed;
+            if let Some(f) = self.on_lost.as_mut() {
+                f(addr);
+            }
+            self.next += 1;
+            // The error is already recorded in the state
+            let _ = self.claim();
+        }
+        Ok(self.state)
+    }
+
+    // Sends an Address Claimed message, from the address
+    fn send_claim(&self, addr: u8) -> io::Result<()> {
+        let src = CanAddr::new_j1939(self.ifindex, self.name, J1939_NO_PGN, addr);
+        self.sock.rebind(&src)?;
+        let dest = CanAddr::new_j1939(
+            self.ifindex,
+            J1939_NO_NAME,
+            J1939_PGN_ADDRESS_CLAIMED,
+            J1939_NO_ADDR,
+        );
+        self.sock.send_to(&self.name.to_le_bytes(), &dest)
+    }
+
+    // Waits out the contention period of a claim, defending it against
+    // lower-priority claims. Returns false if the address was lost.
+    fn wait_uncontested(&self, addr: u8) -> io::Result<bool> {
+        let deadline = Instant::now() + ADDRESS_CLAIM_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(true);
+            }
+            match self.defend(addr, remaining)? {
+                Some(true) => {}
+                Some(false) => return Ok(false),
+                None => return Ok(true),
+            }
+        }
+    }
+
+    // Handles the next claim message for the address, within the timeout.
+    // Returns whether the address is still held, or None on a timeout.
+    fn defend(&self, addr: u8, timeout: Duration) -> io::Result<Option<bool>> {
+        let mut buf = [0u8; 8];
+        self.sock.set_read_timeout(timeout)?;
+        let (n, from) = match self.sock.recv_from(&mut buf) {
+            Ok(res) => res,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(err),
+        };
+        let data = &buf[..n];
+
+        if is_claim_request(from.j1939_pgn(), data) {
+            self.send_claim(addr)?;
+        } else if let Some(theirs) = claimed_name(from.j1939_pgn(), data) {
+            if from.j1939_addr() == addr && theirs != self.name {
+                if !name_wins(self.name, theirs) {
+                    return Ok(Some(false));
+                }
+                self.send_claim(addr)?;
+            }
+        }
+        Ok(Some(true))
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_messages() {
+        let name = 0x8000_0012_3456_7890u64;
+        assert_eq!(
+            claimed_name(J1939_PGN_ADDRESS_CLAIMED, &name.to_le_bytes()),
+            Some(name)
+        );
+        // The destination address is in the low byte of a PDU1 PGN
+        assert_eq!(
+            claimed_name(J1939_PGN_ADDRESS_CLAIMED | 0xFF, &name.to_le_bytes()),
+            Some(name)
+        );
+        assert_eq!(claimed_name(J1939_PGN_REQUEST, &name.to_le_bytes()), None);
+        assert_eq!(claimed_name(J1939_PGN_ADDRESS_CLAIMED, &[0; 3]), None);
+
+        assert!(is_claim_request(J1939_PGN_REQUEST, &[0x00, 0xEE, 0x00]));
+        assert!(!is_claim_request(J1939_PGN_REQUEST, &[0xF1, 0xFE, 0x00]));
+        assert!(!is_claim_request(
+            J1939_PGN_ADDRESS_CLAIMED,
+            &[0x00, 0xEE, 0x00]
+        ));
+
+        assert!(name_wins(0x10, 0x20));
+        assert!(!name_wins(0x20, 0x10));
+    }
+
+    #[test]
+    fn test_filter() {
+        let filt = J1939Filter::new();
+        assert_eq!(filt.as_ref().pgn_mask, 0);
+        assert_eq!(filt.as_ref().addr_mask, 0);
+
+        let filt = J1939Filter::new().pgn(0xFEF1).addr(0x20);
+        assert_eq!(
+            (filt.as_ref().pgn, filt.as_ref().pgn_mask),
+            (0xFEF1, 0x3FFFF)
+        );
+        assert_eq!((filt.as_ref().addr, filt.as_ref().addr_mask), (0x20, 0xFF));
+        assert_eq!(filt.as_ref().name_mask, 0);
+    }
+}
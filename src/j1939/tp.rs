@@ -0,0 +1,565 @@
+// socketcan/src/j1939/tp.rs
+//
+// Reassembly of J1939 transport protocol messages, in userspace.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Reassembly of J1939 transport protocol (J1939-21) messages.
+//!
+//! Messages of more than 8 bytes are split into packets by the transport
+//! protocol. A broadcast is announced with a BAM, and its packets simply
+//! follow. A message to a single ECU is negotiated with an RTS/CTS
+//! connection, where the receiver asks for the packets in blocks, and
+//! acknowledges the whole message at the end.
+//!
+//! The kernel's `CAN_J1939` protocol does all of this for a
+//! [`CanJ1939Socket`](super::CanJ1939Socket). This is for when that isn't
+//! available, or when the traffic is read from a raw [`CanSocket`]. A
+//! [`TpReassembler`] is fed the frames from the bus, and hands back the
+//! complete messages, along with the frames to send to keep the
+//! connections going.
+
+use crate::{CanFrame, CanSocket, EmbeddedFrame, ExtendedId, Id, Socket};
+use std::{
+    collections::HashMap,
+    io,
+    time::{Duration, Instant},
+};
+
+/// The PGN of transport protocol connection management messages
+pub const PGN_TP_CM: u32 = 0xEC00;
+/// The PGN of transport protocol data transfer messages
+pub const PGN_TP_DT: u32 = 0xEB00;
+
+/// The largest message the transport protocol can carry
+pub const TP_MAX_SIZE: usize = 1785;
+
+/// The time allowed between the packets of a message
+pub const TP_TIMEOUT: Duration = Duration::from_millis(1250);
+
+// The connection management control bytes
+const TP_CM_RTS: u8 = 16;
+const TP_CM_CTS: u8 = 17;
+const TP_CM_END_OF_MSG_ACK: u8 = 19;
+const TP_CM_BAM: u8 = 32;
+const TP_CM_ABORT: u8 = 255;
+
+// The abort reasons that are sent
+const ABORT_TIMEOUT: u8 = 3;
+const ABORT_BAD_SEQUENCE: u8 = 7;
+
+/// The priority of the transport protocol messages that are sent
+const TP_PRIORITY: u8 = 7;
+
+/// The global (broadcast) destination address
+const GLOBAL_ADDR: u8 = 0xFF;
+
+// ===== Identifiers =====
+
+/// Breaks a 29-bit J1939 CAN ID into its priority, PGN, destination, and
+/// source address.
+///
+/// For a PDU1 (destination specific) PGN, the destination is taken out of
+/// the PGN. For a PDU2 (broadcast) one, it's the global address.
+fn parse_id(id: u32) -> (u8, u32, u8, u8) {
+    let prio = ((id >> 26) & 0x07) as u8;
+    let src = id as u8;
+    let pgn = (id >> 8) & 0x3FFFF;
+    match (pgn >> 8) & 0xFF {
+        pf if pf < 240 => (prio, pgn & 0x3FF00, pgn as u8, src),
+        _ => (prio, pgn, GLOBAL_ADDR, src),
+    }
+}
+
+/// Creates a 29-bit J1939 CAN ID for a PDU1 message.
+fn make_id(prio: u8, pgn: u32, dest: u8, src: u8) -> ExtendedId {
+    let id = (u32::from(prio & 0x07) << 26) | ((pgn & 0x3FF00) << 8) | (u32::from(dest) << 8);
+    ExtendedId::new(id | u32::from(src)).unwrap()
+}
+
+// ===== J1939Message =====
+
+/// A complete J1939 message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct J1939Message {
+    /// The priority, from 0 (highest) to 7
+    pub priority: u8,
+    /// The parameter group number
+    pub pgn: u32,
+    /// The destination address, or 0xFF for a broadcast
+    pub dest: u8,
+    /// The source address
+    pub src: u8,
+    /// The data of the message
+    pub data: Vec<u8>,
+}
+
+/// What the reassembler asks of the application, after a frame.
+#[derive(Debug, Clone)]
+pub enum TpEvent {
+    /// A frame to send to the bus, to keep a connection going
+    Send(CanFrame),
+    /// A message that's complete
+    Message(J1939Message),
+}
+
+// ===== TpReassembler =====
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SessionKind {
+    Bam,
+    // An RTS/CTS connection, with the last packet of the current block
+    Rts { max_per_cts: u8, block_end: u8 },
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    kind: SessionKind,
+    priority: u8,
+    pgn: u32,
+    size: usize,
+    total: u8,
+    next_seq: u8,
+    data: Vec<u8>,
+    last: Instant,
+}
+
+/// Reassembles the transport protocol messages from the frames on a bus.
+///
+/// Broadcasts (BAM) are always reassembled. Connections (RTS/CTS) are only
+/// accepted if they're addressed to the reassembler's own address, when it
+/// has one, since it takes part in the connection. Frames that aren't part
+/// of the transport protocol are passed through as single-frame messages.
+#[derive(Debug, Default, Clone)]
+pub struct TpReassembler {
+    addr: Option<u8>,
+    // The sessions, by source and destination address
+    sessions: HashMap<(u8, u8), Session>,
+}
+
+impl TpReassembler {
+    /// Creates a reassembler for broadcasts only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a reassembler that also accepts connections to the address.
+    pub fn with_address(addr: u8) -> Self {
+        Self {
+            addr: Some(addr),
+            ..Self::default()
+        }
+    }
+
+    /// Handles a frame from the bus.
+    ///
+    /// Remote and error frames, and those with standard IDs, are ignored,
+    /// as they aren't J1939.
+    pub fn push(&mut self, frame: &CanFrame) -> Vec<TpEvent> {
+        let now = Instant::now();
+        let mut events = self.expire(now);
+
+        let id = match (frame, frame.id()) {
+            (CanFrame::Data(_), Id::Extended(id)) => id.as_raw(),
+            _ => return events,
+        };
+
+        let (priority, pgn, dest, src) = parse_id(id);
+        let data = frame.data();
+        match pgn {
+            PGN_TP_CM => events.extend(self.on_cm(priority, dest, src, data, now)),
+            PGN_TP_DT => events.extend(self.on_dt(dest, src, data, now)),
+            _ => events.push(TpEvent::Message(J1939Message {
+                priority,
+                pgn,
+                dest,
+                src,
+                data: data.to_vec(),
+            })),
+        }
+        events
+    }
+
+    // Drops the sessions that timed out, aborting the connections
+    fn expire(&mut self, now: Instant) -> Vec<TpEvent> {
+        let expired: Vec<(u8, u8)> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| now.saturating_duration_since(s.last) > TP_TIMEOUT)
+            .map(|(&key, _)| key)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|(src, dest)| {
+                let session = self.sessions.remove(&(src, dest))?;
+                match session.kind {
+                    SessionKind::Rts { .. } => {
+                        Some(abort_frame(session.pgn, ABORT_TIMEOUT, dest, src))
+                    }
+                    SessionKind::Bam => None,
+                }
+            })
+            .map(TpEvent::Send)
+            .collect()
+    }
+
+    // Handles a connection management message
+    fn on_cm(
+        &mut self,
+        priority: u8,
+        dest: u8,
+        src: u8,
+        data: &[u8],
+        now: Instant,
+    ) -> Option<TpEvent> {
+        let (ctrl, size, total, max, pgn) = match *data {
+            [ctrl, lo, hi, total, max, p0, p1, p2] => (
+                ctrl,
+                usize::from(u16::from_le_bytes([lo, hi])),
+                total,
+                max,
+                u32::from_le_bytes([p0, p1, p2, 0]),
+            ),
+            _ => return None,
+        };
+
+        let kind = match ctrl {
+            TP_CM_BAM if dest == GLOBAL_ADDR => SessionKind::Bam,
+            TP_CM_RTS if Some(dest) == self.addr => {
+                let block = total.min(max).max(1);
+                SessionKind::Rts {
+                    max_per_cts: max,
+                    block_end: block,
+                }
+            }
+            TP_CM_ABORT => {
+                self.sessions.remove(&(src, dest));
+                return None;
+            }
+            _ => return None,
+        };
+
+        if size <= 8 || size > TP_MAX_SIZE || usize::from(total) != (size + 6) / 7 {
+            return None;
+        }
+
+        self.sessions.insert(
+            (src, dest),
+            Session {
+                kind,
+                priority,
+                pgn,
+                size,
+                total,
+                next_seq: 1,
+                data: Vec::with_capacity(size),
+                last: now,
+            },
+        );
+
+        match kind {
+            SessionKind::Rts { block_end, .. } => {
+                Some(TpEvent::Send(cts_frame(pgn, block_end, 1, dest, src)))
+            }
+            SessionKind::Bam => None,
+        }
+    }
+
+    // Handles a data transfer packet
+    fn on_dt(&mut self, dest: u8, src: u8, data: &[u8], now: Instant) -> Vec<TpEvent> {
+        let mut events = Vec::new();
+        let key = (src, dest);
+        let session = match self.sessions.get_mut(&key) {
+            Some(session) => session,
+            None => return events,
+        };
+
+        let (seq, payload) = match data.split_first() {
+            Some((&seq, payload)) => (seq, payload),
+            None => return events,
+        };
+        if seq != session.next_seq {
+            let session = self.sessions.remove(&key).unwrap();
+            if let SessionKind::Rts { .. } = session.kind {
+                events.push(TpEvent::Send(abort_frame(
+                    session.pgn,
+                    ABORT_BAD_SEQUENCE,
+                    dest,
+                    src,
+                )));
+            }
+            return events;
+        }
+
+        let n = payload.len().min(session.size - session.data.len());
+        session.data.extend_from_slice(&payload[..n]);
+        session.next_seq += 1;
+        session.last = now;
+
+        if seq == session.total {
+            let session = self.sessions.remove(&key).unwrap();
+            if let SessionKind::Rts { .. } = session.kind {
+                events.push(TpEvent::Send(end_of_msg_ack_frame(&session, dest, src)));
+            }
+            events.push(TpEvent::Message(J1939Message {
+                priority: session.priority,
+                pgn: session.pgn,
+                dest,
+                src,
+                data: session.data,
+            }));
+        } else if let SessionKind::Rts {
+            max_per_cts,
+            ref mut block_end,
+        } = session.kind
+        {
+            // Ask for the next block, once this one is in
+            if seq == *block_end {
+                let remaining = session.total - seq;
+                let block = remaining.min(max_per_cts).max(1);
+                *block_end = seq + block;
+                events.push(TpEvent::Send(cts_frame(
+                    session.pgn,
+                    block,
+                    seq + 1,
+                    dest,
+                    src,
+                )));
+            }
+        }
+        events
+    }
+}
+
+/// Creates a connection management frame, from `src` to `dest`.
+fn cm_frame(data: [u8; 5], pgn: u32, src: u8, dest: u8) -> CanFrame {
+    let [p0, p1, p2, _] = pgn.to_le_bytes();
+    let data = [data[0], data[1], data[2], data[3], data[4], p0, p1, p2];
+    CanFrame::new(make_id(TP_PRIORITY, PGN_TP_CM, dest, src), &data).unwrap()
+}
+
+/// Creates a CTS, from the receiver (`us`) to the sender (`them`).
+fn cts_frame(pgn: u32, count: u8, next: u8, us: u8, them: u8) -> CanFrame {
+    cm_frame([TP_CM_CTS, count, next, 0xFF, 0xFF], pgn, us, them)
+}
+
+/// Creates an End of Message Acknowledgment, from the receiver to the
+/// sender.
+fn end_of_msg_ack_frame(session: &Session, us: u8, them: u8) -> CanFrame {
+    let [lo, hi] = (session.size as u16).to_le_bytes();
+    cm_frame(
+        [TP_CM_END_OF_MSG_ACK, lo, hi, session.total, 0xFF],
+        session.pgn,
+        us,
+        them,
+    )
+}
+
+/// Creates a Connection Abort, from the receiver to the sender.
+fn abort_frame(pgn: u32, reason: u8, us: u8, them: u8) -> CanFrame {
+    cm_frame([TP_CM_ABORT, reason, 0xFF, 0xFF, 0xFF], pgn, us, them)
+}
+
+// ===== TpReceiver =====
+
+/// Receives complete J1939 messages from a raw CAN socket.
+///
+/// This reads the frames from the socket into a [`TpReassembler`], and
+/// sends the frames that it asks for.
+#[derive(Debug)]
+pub struct TpReceiver {
+    sock: CanSocket,
+    tp: TpReassembler,
+}
+
+impl TpReceiver {
+    /// Creates a receiver on the socket, with the reassembler given.
+    pub fn new(sock: CanSocket, tp: TpReassembler) -> Self {
+        Self { sock, tp }
+    }
+
+    /// Gets a shared reference to the underlying CAN socket
+    pub fn as_socket(&self) -> &CanSocket {
+        &self.sock
+    }
+
+    /// Reads frames from the socket until a message is complete.
+    pub fn recv(&mut self) -> io::Result<J1939Message> {
+        loop {
+            let frame = self.sock.read_frame()?;
+            let mut msg = None;
+            for event in self.tp.push(&frame) {
+                match event {
+                    TpEvent::Send(frame) => self.sock.write_frame(&frame)?,
+                    TpEvent::Message(m) => msg = Some(m),
+                }
+            }
+            if let Some(msg) = msg {
+                return Ok(msg);
+            }
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGN: u32 = 0xFEE3;
+
+    fn frame(pgn: u32, dest: u8, src: u8, data: &[u8]) -> CanFrame {
+        let id = match (pgn >> 8) & 0xFF {
+            pf if pf < 240 => make_id(6, pgn, dest, src),
+            _ => ExtendedId::new((6 << 26) | (pgn << 8) | u32::from(src)).unwrap(),
+        };
+        CanFrame::new(id, data).unwrap()
+    }
+
+    fn packets(data: &[u8], dest: u8, src: u8) -> Vec<CanFrame> {
+        data.chunks(7)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut buf = [0xFF; 8];
+                buf[0] = i as u8 + 1;
+                buf[1..1 + chunk.len()].copy_from_slice(chunk);
+                frame(PGN_TP_DT, dest, src, &buf)
+            })
+            .collect()
+    }
+
+    fn messages(events: Vec<TpEvent>) -> Vec<J1939Message> {
+        events
+            .into_iter()
+            .filter_map(|ev| match ev {
+                TpEvent::Message(msg) => Some(msg),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn sent(events: &[TpEvent]) -> Vec<(u32, u8, u8, Vec<u8>)> {
+        events
+            .iter()
+            .filter_map(|ev| match ev {
+                TpEvent::Send(f) => match f.id() {
+                    Id::Extended(id) => {
+                        let (_, pgn, dest, src) = parse_id(id.as_raw());
+                        Some((pgn, dest, src, f.data().to_vec()))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(parse_id(0x18EAFF00), (6, 0xEA00, 0xFF, 0x00));
+        assert_eq!(parse_id(0x0CF00400), (3, 0xF004, 0xFF, 0x00));
+        assert_eq!(make_id(7, PGN_TP_CM, 0x20, 0x80).as_raw(), 0x1CEC2080);
+    }
+
+    #[test]
+    fn test_single_frame() {
+        let mut tp = TpReassembler::new();
+        let msgs = messages(tp.push(&frame(0xF004, 0xFF, 0x00, &[1, 2, 3])));
+        assert_eq!(
+            msgs,
+            &[J1939Message {
+                priority: 6,
+                pgn: 0xF004,
+                dest: 0xFF,
+                src: 0x00,
+                data: vec![1, 2, 3]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_bam() {
+        let data: Vec<u8> = (0..20).collect();
+        let [p0, p1, p2, _] = PGN.to_le_bytes();
+        let bam = [TP_CM_BAM, 20, 0, 3, 0xFF, p0, p1, p2];
+
+        let mut tp = TpReassembler::new();
+        assert!(tp.push(&frame(PGN_TP_CM, 0xFF, 0x30, &bam)).is_empty());
+
+        let pkts = packets(&data, 0xFF, 0x30);
+        assert!(tp.push(&pkts[0]).is_empty());
+        assert!(tp.push(&pkts[1]).is_empty());
+        let msgs = messages(tp.push(&pkts[2]));
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].pgn, PGN);
+        assert_eq!(msgs[0].src, 0x30);
+        assert_eq!(msgs[0].data, data);
+
+        // A packet out of order drops the message
+        tp.push(&frame(PGN_TP_CM, 0xFF, 0x30, &bam));
+        tp.push(&pkts[0]);
+        assert!(tp.push(&pkts[2]).is_empty());
+        assert!(tp.push(&pkts[1]).is_empty());
+    }
+
+    #[test]
+    fn test_rts_cts() {
+        let data: Vec<u8> = (0..30).collect();
+        let [p0, p1, p2, _] = PGN.to_le_bytes();
+        // 5 packets, at most 2 for each CTS
+        let rts = [TP_CM_RTS, 30, 0, 5, 2, p0, p1, p2];
+
+        // Not for us
+        let mut tp = TpReassembler::new();
+        assert!(tp.push(&frame(PGN_TP_CM, 0x20, 0x30, &rts)).is_empty());
+
+        let mut tp = TpReassembler::with_address(0x20);
+        let events = tp.push(&frame(PGN_TP_CM, 0x20, 0x30, &rts));
+        assert_eq!(
+            sent(&events),
+            &[(
+                PGN_TP_CM,
+                0x30,
+                0x20,
+                vec![TP_CM_CTS, 2, 1, 0xFF, 0xFF, p0, p1, p2]
+            )]
+        );
+
+        let pkts = packets(&data, 0x20, 0x30);
+        assert!(tp.push(&pkts[0]).is_empty());
+        let events = tp.push(&pkts[1]);
+        assert_eq!(
+            sent(&events),
+            &[(
+                PGN_TP_CM,
+                0x30,
+                0x20,
+                vec![TP_CM_CTS, 2, 3, 0xFF, 0xFF, p0, p1, p2]
+            )]
+        );
+        tp.push(&pkts[2]);
+        let events = tp.push(&pkts[3]);
+        assert_eq!(sent(&events)[0].3[..3], [TP_CM_CTS, 1, 5]);
+
+        let events = tp.push(&pkts[4]);
+        assert_eq!(
+            sent(&events),
+            &[(
+                PGN_TP_CM,
+                0x30,
+                0x20,
+                vec![TP_CM_END_OF_MSG_ACK, 30, 0, 5, 0xFF, p0, p1, p2]
+            )]
+        );
+        let msgs = messages(events);
+        assert_eq!(msgs[0].data, data);
+        assert_eq!(msgs[0].dest, 0x20);
+    }
+}
@@ -77,11 +77,24 @@
 //!   network interfaces attached to the host. This brings in the dependency for
 //!   [libudev](https://crates.io/crates/libudev)
 //!
+//! * **stats** -
+//!   Keep lightweight, atomic, statistics counters of the frames and bytes
+//!   sent and received by each socket, available through `stats()`.
+//!
 //! * **utils** -
 //!   Whether to build command-line utilities. This brings in additional
 //!   dependencies like [anyhow](https://docs.rs/anyhow/latest/anyhow/) and
 //!   [clap](https://docs.rs/clap/latest/clap/)
 //!
+//! * **mio** -
+//!   Implement `mio::event::Source` for the raw sockets, to register them
+//!   in a [mio](https://crates.io/crates/mio) `Poll` alongside other sources.
+//!
+//! * **io-uring** -
+//!   Include the `uring` module, with `CanSocketUring`, which receives
+//!   bursts of frames through an `io_uring` with far fewer system calls.
+//!   This brings in the dependency for [rustix](https://crates.io/crates/rustix)
+//!
 //! * **tokio** -
 //!   Include support for async/await using [tokio](https://crates.io/crates/tokio).
 //!
@@ -136,33 +149,93 @@ pub use embedded_can::{
 
 pub mod errors;
 pub use errors::{
-    CanError, CanErrorDecodingFailure, ConstructionError, Error, IoError, IoErrorKind, IoResult,
-    Result,
+    is_interface_gone, CanError, CanErrorDecodingFailure, ConstructionError, Error, IoError,
+    IoErrorKind, IoResult, Result, SocketError,
 };
 
 pub mod addr;
 pub use addr::CanAddr;
 
 pub mod id;
-pub use id::CanId;
+pub use id::{CanErrorMask, CanId};
 
 pub mod frame;
 pub use frame::{
-    CanAnyFrame, CanDataFrame, CanErrorFrame, CanFdFrame, CanFrame, CanRawFrame, CanRemoteFrame,
-    Frame,
+    CanAnyFrame, CanDataFrame, CanErrorFrame, CanFdFrame, CanFrame, CanMixedFrame, CanRawFrame,
+    CanRemoteFrame, CanXlFrame, Frame,
 };
 
 #[cfg(feature = "dump")]
 pub mod dump;
 
+pub mod batch;
+pub use batch::FrameBatch;
+
+pub mod checksum;
+
+pub mod isotp;
+pub use isotp::{CanIsoTpSocket, IsoTpOptions};
+
+pub mod j1939;
+pub use j1939::{CanJ1939Socket, J1939Filter};
+
+pub mod bcm;
+pub use bcm::CanBcmSocket;
+
+pub mod uds;
+pub use uds::UdsClient;
+
+pub mod obd;
+pub use obd::ObdClient;
+
+pub mod canopen;
+pub use canopen::CanOpenClient;
+
+pub mod slcan;
+pub use slcan::SlcanInterface;
+
+pub mod socketcand;
+pub use socketcand::SocketcandClient;
+
+pub mod cannelloni;
+pub use cannelloni::CannelloniTunnel;
+
+pub mod bridge;
+
+pub mod tp20;
+pub use tp20::Tp20Channel;
+
+pub mod xcp;
+pub use xcp::XcpMaster;
+
+#[cfg(feature = "io-uring")]
+pub mod uring;
+#[cfg(feature = "io-uring")]
+pub use uring::CanSocketUring;
+
+pub mod codec;
+
+pub mod nonblocking;
+pub use nonblocking::NonBlockingCanSocket;
+
+pub mod poller;
+pub use poller::CanPoller;
+
 pub mod socket;
-pub use socket::{CanFdSocket, CanFilter, CanSocket, ShouldRetry, Socket, SocketOptions};
+pub use socket::{
+    CanFdSocket, CanFilter, CanReadHalf, CanRingReader, CanSocket, CanWriteHalf, FrameMeta,
+    OpenOptions, ReconnectingCanSocket, RecvMsgResult, ShouldRetry, Socket, SocketOptions,
+    TimestampMode, XlVcidFlags, XlVcidOptions,
+};
+
+#[cfg(feature = "stats")]
+pub use socket::SocketStats;
 
 #[cfg(feature = "netlink")]
 pub mod nl;
 
 #[cfg(feature = "netlink")]
-pub use nl::{CanCtrlMode, CanInterface, InterfaceCanParams};
+pub use nl::{BitrateMethod, CanCtrlMode, CanInterface, InterfaceCanParams};
 
 /// Optional tokio support
 #[cfg(feature = "tokio")]
@@ -0,0 +1,221 @@
+// socketcan/src/nl/bittiming.rs
+//
+// Calculates CAN bit-timing parameters from a bit rate.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Calculation of CAN bit-timing parameters.
+//!
+//! This is a port of the kernel's `can_calc_bittiming()`, from:
+//!
+//! <https://github.com/torvalds/linux/blob/master/drivers/net/can/dev/calc_bittiming.c?ts=4>
+//!
+//! It finds the segment lengths and prescaler that give the bit rate and
+//! sample point closest to the ones requested, for the clock and limits of
+//! a particular controller. That lets an application work out, and check,
+//! the full timing before it's handed to `CanInterface::set_bit_timing()`.
+
+use super::{CanBitTiming, CanBitTimingConst};
+
+/// The synchronization segment is always one time quantum
+const CAN_SYNC_SEG: u32 = 1;
+
+/// The maximum bit rate error accepted, in tenths of a percent
+const CAN_CALC_MAX_ERROR: u64 = 50;
+
+/// Finds the best split of `tseg` into the two time segments for the
+/// nominal sample point.
+///
+/// Returns the real sample point, its error from the nominal one, and the
+/// lengths of time segments 1 and 2. The error is `u32::MAX` if no split
+/// puts the sample point at or before the nominal one.
+fn update_sample_point(
+    btc: &CanBitTimingConst,
+    sample_point_nominal: u32,
+    tseg: u32,
+) -> (u32, u32, u32, u32) {
+    let mut best = (0, u32::MAX, 0, 0);
+
+    for i in 0..=1 {
+        let mut tseg2 = (tseg + CAN_SYNC_SEG)
+            .saturating_sub(sample_point_nominal * (tseg + CAN_SYNC_SEG) / 1000 + i)
+            .max(btc.tseg2_min)
+            .min(btc.tseg2_max);
+        let mut tseg1 = tseg.saturating_sub(tseg2);
+        if tseg1 > btc.tseg1_max {
+            tseg1 = btc.tseg1_max;
+            tseg2 = tseg - tseg1;
+        }
+
+        let sample_point =
+            1000 * (tseg + CAN_SYNC_SEG).saturating_sub(tseg2) / (tseg + CAN_SYNC_SEG);
+        let sample_point_error = sample_point_nominal.abs_diff(sample_point);
+
+        if sample_point <= sample_point_nominal && sample_point_error < best.1 {
+            best = (sample_point, sample_point_error, tseg1, tseg2);
+        }
+    }
+    best
+}
+
+/// Calculates the bit timing for a bit rate, on a controller with the
+/// clock frequency and bit-timing constants given.
+///
+/// The sample point is in tenths of a percent. If it's not given, the one
+/// recommended by CiA for the bit rate is used: 75% above 800 kbit/s, 80%
+/// above 500 kbit/s, and 87.5% otherwise.
+///
+/// This returns `None` if the controller can't get within 0.5% of the bit
+/// rate. Otherwise the timing has the real bit rate and sample point that
+/// were achieved, which may differ slightly from the ones requested.
+pub fn calc_bit_timing<P>(
+    bitrate: u32,
+    sample_point: P,
+    clock_freq: u32,
+    btc: &CanBitTimingConst,
+) -> Option<CanBitTiming>
+where
+    P: Into<Option<u32>>,
+{
+    if bitrate == 0 || btc.brp_inc == 0 {
+        return None;
+    }
+
+    let sample_point_nominal = match sample_point.into() {
+        Some(sp) if sp > 0 => sp,
+        _ if bitrate > 800_000 => 750,
+        _ if bitrate > 500_000 => 800,
+        _ => 875,
+    };
+
+    let mut best_bitrate_error = u32::MAX;
+    let mut best_sample_point_error = u32::MAX;
+    let mut best_tseg = 0;
+    let mut best_brp = 0;
+
+    // tseg even = round down, odd = round up
+    let tseg_max = (btc.tseg1_max + btc.tseg2_max) * 2 + 1;
+    let tseg_min = (btc.tseg1_min + btc.tseg2_min) * 2;
+
+    for tseg in (tseg_min..=tseg_max).rev() {
+        let tsegall = u64::from(CAN_SYNC_SEG + tseg / 2);
+
+        // The prescaler for this tseg, in the steps the hardware allows
+        let brp = u64::from(clock_freq) / (tsegall * u64::from(bitrate)) + u64::from(tseg % 2);
+        let brp = (brp / u64::from(btc.brp_inc)) * u64::from(btc.brp_inc);
+        if brp < u64::from(btc.brp_min) || brp > u64::from(btc.brp_max) || brp == 0 {
+            continue;
+        }
+
+        let real_bitrate = (u64::from(clock_freq) / (brp * tsegall)) as u32;
+        let bitrate_error = bitrate.abs_diff(real_bitrate);
+        if bitrate_error > best_bitrate_error {
+            continue;
+        }
+
+        // Reset the sample point error if we have a better bitrate
+        if bitrate_error < best_bitrate_error {
+            best_sample_point_error = u32::MAX;
+        }
+
+        let (_, sample_point_error, _, _) =
+            update_sample_point(btc, sample_point_nominal, tseg / 2);
+        if sample_point_error >= best_sample_point_error {
+            continue;
+        }
+
+        best_sample_point_error = sample_point_error;
+        best_bitrate_error = bitrate_error;
+        best_tseg = tseg / 2;
+        best_brp = brp as u32;
+
+        if bitrate_error == 0 && sample_point_error == 0 {
+            break;
+        }
+    }
+
+    if best_brp == 0
+        || u64::from(best_bitrate_error) * 1000 / u64::from(bitrate) > CAN_CALC_MAX_ERROR
+    {
+        return None;
+    }
+
+    let (sample_point, _, tseg1, tseg2) = update_sample_point(btc, sample_point_nominal, best_tseg);
+
+    let prop_seg = tseg1 / 2;
+    let phase_seg1 = tseg1 - prop_seg;
+    let phase_seg2 = tseg2;
+
+    let mut sjw = (phase_seg2 / 2).min(phase_seg1).max(1);
+    if btc.sjw_max > 0 {
+        sjw = sjw.min(btc.sjw_max);
+    }
+
+    Some(CanBitTiming {
+        bitrate: clock_freq / (best_brp * (CAN_SYNC_SEG + tseg1 + tseg2)),
+        sample_point,
+        tq: (u64::from(best_brp) * 1_000_000_000 / u64::from(clock_freq)) as u32,
+        prop_seg,
+        phase_seg1,
+        phase_seg2,
+        sjw,
+        brp: best_brp,
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The limits of an SJA1000 controller
+    const SJA1000: CanBitTimingConst = CanBitTimingConst {
+        name: [0; 16],
+        tseg1_min: 1,
+        tseg1_max: 16,
+        tseg2_min: 1,
+        tseg2_max: 8,
+        sjw_max: 4,
+        brp_min: 1,
+        brp_max: 64,
+        brp_inc: 1,
+    };
+
+    #[test]
+    fn test_calc_bit_timing() {
+        let bt = calc_bit_timing(500_000, None, 8_000_000, &SJA1000).unwrap();
+        assert_eq!(bt.bitrate, 500_000);
+        assert_eq!(bt.sample_point, 875);
+        assert_eq!(bt.tq, 125);
+        assert_eq!(bt.brp, 1);
+        assert_eq!((bt.prop_seg, bt.phase_seg1, bt.phase_seg2), (6, 7, 2));
+        assert_eq!(bt.sjw, 1);
+
+        let bt = calc_bit_timing(1_000_000, None, 8_000_000, &SJA1000).unwrap();
+        assert_eq!(bt.bitrate, 1_000_000);
+        assert_eq!(bt.sample_point, 750);
+        assert_eq!(bt.prop_seg + bt.phase_seg1 + bt.phase_seg2, 7);
+
+        // The sample point can't be hit exactly, so the closest one before it
+        let bt = calc_bit_timing(125_000, 800, 8_000_000, &SJA1000).unwrap();
+        assert_eq!(bt.bitrate, 125_000);
+        assert!(bt.sample_point <= 800 && bt.sample_point >= 750);
+        assert_eq!(
+            8_000_000 / (bt.brp * (1 + bt.prop_seg + bt.phase_seg1 + bt.phase_seg2)),
+            125_000
+        );
+    }
+
+    #[test]
+    fn test_calc_bit_timing_unreachable() {
+        // The clock is too slow for the bit rate
+        assert!(calc_bit_timing(1_000_000, None, 1_000_000, &SJA1000).is_none());
+        assert!(calc_bit_timing(0, None, 8_000_000, &SJA1000).is_none());
+    }
+}
@@ -57,27 +57,31 @@ use neli::{
     consts::{
         nl::{NlType, NlmF, NlmFFlags},
         rtnl::{Arphrd, RtAddrFamily, Rtm},
-        rtnl::{Iff, IffFlags, Ifla, IflaInfo},
+        rtnl::{Iff, IffFlags, Ifla, IflaInfo, Tca},
         socket::NlFamily,
     },
     err::NlError,
     nl::{NlPayload, Nlmsghdr},
-    rtnl::{Ifinfomsg, Rtattr},
+    rtnl::{Ifinfomsg, Rtattr, Tcmsg},
     socket::NlSocketHandle,
     types::{Buffer, RtBuffer},
     FromBytes, ToBytes,
 };
 use nix::{self, net::if_::if_nametoindex, unistd};
-use rt::IflaCan;
+use rt::{IflaCan, IflaCanCtrlMode};
 use std::{
     ffi::CStr,
     fmt::Debug,
-    os::raw::{c_int, c_uint},
+    io,
+    mem::size_of,
+    os::raw::{c_char, c_int, c_uint},
 };
 
 /// Low-level Netlink CAN struct bindings.
+mod bittiming;
 mod rt;
 
+pub use bittiming::calc_bit_timing;
 use rt::can_ctrlmode;
 pub use rt::CanState;
 
@@ -87,6 +91,34 @@ type NlResult<T> = Result<T, NlError>;
 /// A Netlink error from an info query
 type NlInfoError = NlError<Rtm, Ifinfomsg>;
 
+/// A Netlink error from a traffic control query
+type NlTcError = NlError<Rtm, Tcmsg>;
+
+/// The ioctl that legacy (pre-mainline) and some vendor SocketCAN drivers
+/// use to set the bit rate. This is `SIOCDEVPRIVATE`.
+const SIOCSCANBAUDRATE: u32 = 0x89F0;
+
+/// The request for the `SIOCSCANBAUDRATE` ioctl.
+///
+/// This is a `struct ifreq` with the bit rate at the start of the union.
+#[repr(C)]
+struct CanBaudrateReq {
+    name: [c_char; libc::IFNAMSIZ],
+    bitrate: u32,
+    _pad: [u8; 20],
+}
+
+const _: () = assert!(size_of::<CanBaudrateReq>() == size_of::<libc::ifreq>());
+
+/// The method that was used to set the bit rate of an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitrateMethod {
+    /// The bit timing was set with netlink, as for mainline drivers.
+    Netlink,
+    /// The bit rate was set with the legacy `SIOCSCANBAUDRATE` ioctl.
+    Ioctl,
+}
+
 /// CAN bit-timing parameters
 pub type CanBitTiming = rt::can_bittiming;
 /// CAN bit-timing const parameters
@@ -135,13 +167,13 @@ pub enum Mtu {
 }
 
 impl TryFrom<u32> for Mtu {
-    type Error = std::io::Error;
+    type Error = io::Error;
 
     fn try_from(val: u32) -> Result<Self, Self::Error> {
         match val {
             16 => Ok(Mtu::Standard),
             72 => Ok(Mtu::Fd),
-            _ => Err(std::io::Error::from(std::io::ErrorKind::InvalidData)),
+            _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
         }
     }
 }
@@ -412,6 +444,11 @@ impl CanInterface {
         Self { if_index }
     }
 
+    /// Gets the kernel's index for the interface.
+    pub fn index(&self) -> u32 {
+        self.if_index
+    }
+
     /// Creates an `Ifinfomsg` for this CAN interface from a buffer
     fn info_msg(&self, buf: RtBuffer<Ifla, Buffer>) -> Ifinfomsg {
         Ifinfomsg::new(
@@ -643,6 +680,69 @@ impl CanInterface {
         }
     }
 
+    /// Determines if the interface is up.
+    ///
+    /// This is a lighter query than `details()`, as it only checks the
+    /// interface flags in the reply. If the interface doesn't exist, this
+    /// returns an error, normally an `NlError::Nlmsgerr` with an `ENODEV`
+    /// error code from the kernel, rather than `Ok(false)`.
+    pub fn is_up(&self) -> Result<bool, NlInfoError> {
+        match self.query_details()? {
+            Some(msg_hdr) => msg_hdr
+                .get_payload()
+                .map(|payload| payload.ifi_flags.contains(&Iff::Up))
+                .map_err(|err| NlError::Msg(err.to_string())),
+            None => Err(NlError::NoAck),
+        }
+    }
+
+    /// Gets the kinds of the queueing disciplines (qdiscs) on the
+    /// interface, such as "pfifo_fast" or "etf".
+    ///
+    /// This is the `TCA_KIND` of each qdisc that `tc qdisc show` lists
+    /// for the interface.
+    pub fn qdisc_kinds(&self) -> Result<Vec<String>, NlTcError> {
+        let mut sock = Self::open_route_socket()?;
+
+        let tcmsg = Tcmsg::new(
+            u8::from(RtAddrFamily::Unspecified),
+            self.if_index as c_int,
+            0,
+            0,
+            0,
+            RtBuffer::new(),
+        );
+        let hdr = Nlmsghdr::new(
+            None,
+            Rtm::Getqdisc,
+            NlmFFlags::new(&[NlmF::Request, NlmF::Dump]),
+            None,
+            None,
+            NlPayload::Payload(tcmsg),
+        );
+        sock.send(hdr)?;
+
+        let mut kinds = Vec::new();
+        for msg in sock.iter::<Rtm, Tcmsg>(false) {
+            let msg = msg?;
+            let Ok(payload) = msg.get_payload() else {
+                continue;
+            };
+            // Older kernels dump the qdiscs of all the interfaces
+            if payload.tcm_ifindex != self.if_index as c_int {
+                continue;
+            }
+            for attr in payload.rtattrs.iter() {
+                if attr.rta_type == Tca::Kind {
+                    if let Ok(kind) = CStr::from_bytes_with_nul(attr.rta_payload.as_ref()) {
+                        kinds.push(kind.to_string_lossy().into_owned());
+                    }
+                }
+            }
+        }
+        Ok(kinds)
+    }
+
     /// Set the MTU of this interface.
     ///
     /// PRIVILEGED: This requires root privilege.
@@ -803,6 +903,72 @@ impl CanInterface {
         })
     }
 
+    /// Set the bitrate of this interface with the legacy `SIOCSCANBAUDRATE`
+    /// ioctl.
+    ///
+    /// This is only supported by some serial-line, vendor, and pre-mainline
+    /// drivers. Mainline drivers need [`CanInterface::set_bitrate()`].
+    ///
+    /// PRIVILEGED: This requires root privilege.
+    ///
+    pub fn set_bitrate_ioctl(&self, bitrate: u32) -> NlResult<()> {
+        let mut req = CanBaudrateReq {
+            name: [0; libc::IFNAMSIZ],
+            bitrate,
+            _pad: [0; 20],
+        };
+        if unsafe { libc::if_indextoname(self.if_index, req.name.as_mut_ptr()) }.is_null() {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        // Any socket can carry a device ioctl
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let ret =
+            unsafe { libc::ioctl(fd, SIOCSCANBAUDRATE as _, &mut req as *mut CanBaudrateReq) };
+        let res = match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error().into()),
+        };
+        unsafe { libc::close(fd) };
+        res
+    }
+
+    /// Set the bitrate of this interface, using whichever method the
+    /// driver supports.
+    ///
+    /// This tries netlink first, as with [`CanInterface::set_bitrate()`],
+    /// then falls back to the legacy `SIOCSCANBAUDRATE` ioctl if the
+    /// driver rejects the netlink request as unsupported or invalid
+    /// (`EOPNOTSUPP` or `EINVAL`). Any other netlink error, such as
+    /// `EPERM`, `EBUSY` for an interface that's up, or `ENODEV`, is
+    /// returned as it is. The sample point is only used with netlink. On
+    /// success it returns the method that worked, and if both fail, the
+    /// error from netlink.
+    ///
+    /// PRIVILEGED: This requires root privilege.
+    ///
+    pub fn set_bitrate_any<P>(&self, bitrate: u32, sample_point: P) -> NlResult<BitrateMethod>
+    where
+        P: Into<Option<u32>>,
+    {
+        match self.set_bitrate(bitrate, sample_point) {
+            Ok(()) => Ok(BitrateMethod::Netlink),
+            Err(NlError::Nlmsgerr(err))
+                if [libc::EOPNOTSUPP, libc::EINVAL].contains(&err.error.abs()) =>
+            {
+                match self.set_bitrate_ioctl(bitrate) {
+                    Ok(()) => Ok(BitrateMethod::Ioctl),
+                    Err(_) => Err(NlError::Nlmsgerr(err)),
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Gets the bit timing params for the interface
     pub fn bit_timing(&self) -> Result<Option<CanBitTiming>, NlInfoError> {
         self.can_param::<CanBitTiming>(IflaCan::BitTiming)
@@ -828,6 +994,27 @@ impl CanInterface {
             .map(|clk| clk.freq))
     }
 
+    /// Calculates the bit timing for a bit rate on this interface.
+    ///
+    /// This reads the controller's clock frequency and bit-timing constants
+    /// from the interface and passes them to [`calc_bit_timing()`], so the
+    /// result can be given straight to [`CanInterface::set_bit_timing()`].
+    /// It returns `None` if the interface doesn't report them, like a
+    /// virtual interface, or if the bit rate can't be reached.
+    pub fn calc_bit_timing<P>(
+        &self,
+        bitrate: u32,
+        sample_point: P,
+    ) -> Result<Option<CanBitTiming>, NlInfoError>
+    where
+        P: Into<Option<u32>>,
+    {
+        let (Some(clock_freq), Some(btc)) = (self.clock()?, self.bit_timing_const()?) else {
+            return Ok(None);
+        };
+        Ok(calc_bit_timing(bitrate, sample_point, clock_freq, &btc))
+    }
+
     /// Gets the state of the interface
     pub fn state(&self) -> Result<Option<CanState>, NlInfoError> {
         Ok(self
@@ -865,6 +1052,73 @@ impl CanInterface {
         self.set_ctrlmodes(CanCtrlModes::from_mode(mode, on))
     }
 
+    /// Set or clear the "presume ACK" control mode.
+    ///
+    /// With this mode on, the controller ignores missing ACKs for the
+    /// frames that it transmits. This lets a single node transmit on an
+    /// isolated bus, where no other node would acknowledge the frames.
+    ///
+    /// This is only meant for test and bench setups. It should not be used
+    /// on a real bus, where a missing ACK indicates a problem.
+    ///
+    /// The interface must be down to change the control modes.
+    ///
+    /// PRIVILEGED: This requires root privilege.
+    ///
+    pub fn set_presume_ack(&self, on: bool) -> NlResult<()> {
+        self.set_ctrlmode(CanCtrlMode::PresumeAck, on)
+    }
+
+    /// Gets the control modes supported by the interface, as a bit mask.
+    ///
+    /// This is read from the extended control mode attribute, which was
+    /// added in Linux 5.19. It is `None` if the kernel or driver doesn't
+    /// report it.
+    pub fn ctrlmode_supported(&self) -> Result<Option<u32>, NlInfoError> {
+        if let Some(hdr) = self.query_details()? {
+            if let Ok(payload) = hdr.get_payload() {
+                for top_attr in payload.rtattrs.iter() {
+                    if top_attr.rta_type == Ifla::Linkinfo {
+                        for info in top_attr.get_attr_handle::<IflaInfo>()?.get_attrs() {
+                            if info.rta_type == IflaInfo::Data {
+                                for attr in info.get_attr_handle::<IflaCan>()?.get_attrs() {
+                                    if attr.rta_type == IflaCan::CtrlModeExt {
+                                        let ext = attr.get_attr_handle::<IflaCanCtrlMode>()?;
+                                        for attr in ext.get_attrs() {
+                                            if attr.rta_type == IflaCanCtrlMode::Supported {
+                                                return Ok(Some(attr.get_payload_as::<u32>()?));
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(None)
+        } else {
+            Err(NlError::NoAck)
+        }
+    }
+
+    /// Determines if the interface is capable of CAN FD.
+    ///
+    /// This can be checked before enabling FD frames on a socket, rather
+    /// than finding out with an error when the first FD frame is written.
+    /// It uses the supported control modes if the kernel reports them, and
+    /// otherwise whether the driver has FD data bit timing constants.
+    ///
+    /// Virtual interfaces, like _vcan_, don't have any CAN parameters, and
+    /// report `false`, even though they can carry FD frames once their MTU
+    /// is set for it.
+    pub fn supports_fd(&self) -> Result<bool, NlInfoError> {
+        match self.ctrlmode_supported()? {
+            Some(modes) => Ok(modes & CanCtrlMode::Fd.mask() != 0),
+            None => Ok(self.data_bit_timing_const()?.is_some()),
+        }
+    }
+
     /// Gets the automatic CANbus restart time for the interface, in milliseconds.
     pub fn restart_ms(&self) -> Result<Option<u32>, NlInfoError> {
         self.can_param::<u32>(IflaCan::RestartMs)
@@ -909,6 +1163,13 @@ impl CanInterface {
         self.can_param::<CanBitTiming>(IflaCan::DataBitTiming)
     }
 
+    /// Gets the current data bit rate for the interface.
+    ///
+    /// This only applies to interfaces in FD mode.
+    pub fn data_bit_rate(&self) -> Result<Option<u32>, NlInfoError> {
+        Ok(self.data_bit_timing()?.map(|timing| timing.bitrate))
+    }
+
     /// Sets the data bit timing params for the interface
     ///
     /// PRIVILEGED: This requires root privilege.
@@ -1027,9 +1288,26 @@ pub mod tests {
 
         assert!(interface.bring_up().is_ok());
         assert!(interface.details().unwrap().is_up);
+        assert!(interface.is_up().unwrap());
 
         assert!(interface.bring_down().is_ok());
         assert!(!interface.details().unwrap().is_up);
+        assert!(!interface.is_up().unwrap());
+    }
+
+    #[test]
+    #[serial]
+    fn is_up_nonexistent() {
+        let interface = CanInterface::open_iface(u32::MAX >> 1);
+        assert!(interface.is_up().is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn supports_fd_vcan() {
+        let interface = TemporaryInterface::new("supports_fd").unwrap();
+        assert_eq!(interface.ctrlmode_supported().unwrap(), None);
+        assert!(!interface.supports_fd().unwrap());
     }
 
     #[test]
@@ -0,0 +1,349 @@
+// socketcan/src/nonblocking.rs
+//
+// Waker-based, non-blocking CAN sockets that don't need an async runtime.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Non-blocking CAN sockets for any executor.
+//!
+//! The **tokio** and **async-io** wrappers tie a socket to the reactor of
+//! their runtime. A [`NonBlockingCanSocket`] instead has plain
+//! `poll_read_frame()` and `poll_write_frame()` methods that take a task
+//! [`Context`], so that an embedded-style executor, or a custom runtime,
+//! can drive it without depending on either one.
+//!
+//! ```no_run
+//! use socketcan::{nonblocking::NonBlockingCanSocket, CanSocket};
+//! use std::future::poll_fn;
+//!
+//! # async fn relay() -> std::io::Result<()> {
+//! let sock = NonBlockingCanSocket::<CanSocket>::open("vcan0")?;
+//! loop {
+//!     let frame = poll_fn(|cx| sock.poll_read_frame(cx)).await?;
+//!     poll_fn(|cx| sock.poll_write_frame(cx, &frame)).await?;
+//! }
+//! # }
+//! ```
+//!
+//! When a socket isn't ready, its waker is handed to a small reactor: a
+//! single background thread, shared by all of the sockets and started by
+//! the first one, that waits on them with `epoll` and wakes each task
+//! when its socket becomes ready.
+//!
+//! As with the blocking socket, a write can fail with `ENOBUFS` when the
+//! interface's TX queue is full, even though the socket reported that it
+//! was writable. That error is returned to the caller, rather than
+//! retried.
+
+use crate::{frame::AsPtr, Socket};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult},
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+};
+
+/// The number of events that the reactor collects from each wait.
+const REACTOR_EVENTS: usize = 64;
+
+// ===== Reactor =====
+
+/// The wakers of the tasks waiting on one socket.
+#[derive(Debug, Default)]
+struct Wakers {
+    read: Option<Waker>,
+    write: Option<Waker>,
+}
+
+/// The shared reactor, that wakes the tasks when their sockets are ready.
+#[derive(Debug)]
+struct Reactor {
+    /// The epoll instance with all of the registered sockets
+    epoll: OwnedFd,
+    /// The wakers, by the token of their socket
+    wakers: Mutex<HashMap<u64, Arc<Mutex<Wakers>>>>,
+    /// The token for the next registration
+    next_token: AtomicU64,
+}
+
+impl Reactor {
+    /// Gets the reactor, starting it the first time.
+    fn get() -> IoResult<&'static Reactor> {
+        static REACTOR: OnceLock<Result<Reactor, i32>> = OnceLock::new();
+        REACTOR
+            .get_or_init(|| Reactor::new().map_err(|err| err.raw_os_error().unwrap_or(libc::EIO)))
+            .as_ref()
+            .map_err(|&errno| IoError::from_raw_os_error(errno))
+    }
+
+    /// Creates the epoll instance for the reactor, and starts its thread.
+    fn new() -> IoResult<Reactor> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(IoError::last_os_error());
+        }
+        let epoll = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        thread::Builder::new()
+            .name("socketcan-reactor".into())
+            .spawn(Reactor::run)?;
+
+        Ok(Reactor {
+            epoll,
+            wakers: Mutex::new(HashMap::new()),
+            next_token: AtomicU64::new(0),
+        })
+    }
+
+    /// The reactor thread, which waits on the sockets forever.
+    fn run() {
+        // This waits for the reactor to be stored, after which it can't fail
+        let Ok(reactor) = Self::get() else {
+            return;
+        };
+        let epoll = reactor.epoll.as_raw_fd();
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; REACTOR_EVENTS];
+        loop {
+            let n = unsafe {
+                libc::epoll_wait(
+                    epoll,
+                    events.as_mut_ptr(),
+                    REACTOR_EVENTS as libc::c_int,
+                    -1,
+                )
+            };
+            if n < 0 {
+                // Only EINTR is expected here
+                continue;
+            }
+            for ev in &events[..n as usize] {
+                let (flags, token) = (ev.events, ev.u64);
+                let Some(wakers) = reactor.wakers.lock().unwrap().get(&token).cloned() else {
+                    continue;
+                };
+                let err = flags & (libc::EPOLLERR | libc::EPOLLHUP) as u32 != 0;
+                // The wakers are taken out, and the lock released, before
+                // waking, since a task can be polled again from wake()
+                let (read, write) = {
+                    let mut wakers = wakers.lock().unwrap();
+                    let read = (err || flags & libc::EPOLLIN as u32 != 0)
+                        .then(|| wakers.read.take())
+                        .flatten();
+                    let write = (err || flags & libc::EPOLLOUT as u32 != 0)
+                        .then(|| wakers.write.take())
+                        .flatten();
+                    (read, write)
+                };
+                for waker in [read, write].into_iter().flatten() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Adds a socket to the reactor.
+    fn register(&'static self, fd: RawFd) -> IoResult<Registration> {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let wakers = Arc::new(Mutex::new(Wakers::default()));
+        self.wakers
+            .lock()
+            .unwrap()
+            .insert(token, Arc::clone(&wakers));
+
+        let mut ev = libc::epoll_event {
+            events: (libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLET) as u32,
+            u64: token,
+        };
+        if unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut ev) } < 0
+        {
+            self.wakers.lock().unwrap().remove(&token);
+            return Err(IoError::last_os_error());
+        }
+        Ok(Registration {
+            reactor: self,
+            fd,
+            token,
+            wakers,
+        })
+    }
+}
+
+/// A socket's place in the reactor, which is removed when it's dropped.
+#[derive(Debug)]
+struct Registration {
+    reactor: &'static Reactor,
+    fd: RawFd,
+    token: u64,
+    wakers: Arc<Mutex<Wakers>>,
+}
+
+impl Registration {
+    /// Polls a non-blocking operation on the socket.
+    ///
+    /// If the operation would block, the task's waker is stored for the
+    /// reactor, and it's tried again, in case the socket became ready
+    /// before the waker was in place. The sockets are edge-triggered, so
+    /// any readiness after that wakes the task.
+    fn poll_io<R>(
+        &self,
+        cx: &mut Context<'_>,
+        write: bool,
+        mut op: impl FnMut() -> IoResult<R>,
+    ) -> Poll<IoResult<R>> {
+        match op() {
+            Err(err) if err.kind() == IoErrorKind::WouldBlock => (),
+            res => return Poll::Ready(res),
+        }
+        {
+            let mut wakers = self.wakers.lock().unwrap();
+            let slot = if write {
+                &mut wakers.write
+            } else {
+                &mut wakers.read
+            };
+            match slot {
+                Some(waker) if waker.will_wake(cx.waker()) => (),
+                _ => *slot = Some(cx.waker().clone()),
+            }
+        }
+        match op() {
+            Err(err) if err.kind() == IoErrorKind::WouldBlock => Poll::Pending,
+            res => Poll::Ready(res),
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        unsafe {
+            libc::epoll_ctl(
+                self.reactor.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                self.fd,
+                std::ptr::null_mut(),
+            );
+        }
+        self.reactor.wakers.lock().unwrap().remove(&self.token);
+    }
+}
+
+// ===== NonBlockingCanSocket =====
+
+/// A non-blocking CAN socket with waker-based poll methods, that works
+/// with any executor.
+///
+/// This wraps any of the raw sockets, by default a [`CanSocket`](crate::CanSocket).
+pub struct NonBlockingCanSocket<T: Socket = crate::CanSocket> {
+    // Dropped before the socket, so the fd is removed while it's open
+    reg: Registration,
+    sock: T,
+}
+
+impl<T: Socket> NonBlockingCanSocket<T> {
+    /// Opens a named CAN interface such as "can0", "vcan0", etc.
+    pub fn open(ifname: &str) -> IoResult<Self> {
+        Self::new(T::open(ifname)?)
+    }
+
+    /// Wraps a socket, putting it into non-blocking mode.
+    pub fn new(sock: T) -> IoResult<Self> {
+        sock.set_nonblocking(true)?;
+        let reg = Reactor::get()?.register(sock.as_raw_fd())?;
+        Ok(Self { reg, sock })
+    }
+
+    /// Gets a reference to the underlying socket.
+    pub fn get_ref(&self) -> &T {
+        &self.sock
+    }
+
+    /// Returns the underlying socket, still in non-blocking mode.
+    pub fn into_inner(self) -> T {
+        let Self { reg, sock } = self;
+        drop(reg);
+        sock
+    }
+
+    /// Attempts to read a frame from the socket.
+    ///
+    /// If no frame is waiting, this returns `Poll::Pending`, and the task
+    /// is woken when one arrives. The frame is only taken from the socket
+    /// when this returns it, so a read that's abandoned never loses one.
+    pub fn poll_read_frame(&self, cx: &mut Context<'_>) -> Poll<IoResult<T::FrameType>> {
+        self.reg.poll_io(cx, false, || self.sock.read_frame())
+    }
+
+    /// Attempts to write a frame to the socket.
+    ///
+    /// If the socket isn't writable, this returns `Poll::Pending`, and
+    /// the task is woken when it is.
+    pub fn poll_write_frame<F>(&self, cx: &mut Context<'_>, frame: &F) -> Poll<IoResult<()>>
+    where
+        F: Into<T::FrameType> + AsPtr,
+    {
+        self.reg.poll_io(cx, true, || self.sock.write_frame(frame))
+    }
+}
+
+impl<T: Socket + fmt::Debug> fmt::Debug for NonBlockingCanSocket<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NonBlockingCanSocket")
+            .field("sock", &self.sock)
+            .field("token", &self.reg.token)
+            .finish()
+    }
+}
+
+impl<T: Socket> AsRawFd for NonBlockingCanSocket<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CanFrame, CanSocket, EmbeddedFrame, Frame, StandardId};
+    use futures::executor::block_on;
+    use std::{future::poll_fn, os::unix::net::UnixDatagram, time::Duration};
+
+    #[test]
+    fn test_poll_frames() {
+        // A datagram pair can stand in for a CAN socket
+        let (a, b) = UnixDatagram::pair().unwrap();
+        let tx = NonBlockingCanSocket::new(CanSocket::from(OwnedFd::from(a))).unwrap();
+        let rx = NonBlockingCanSocket::new(CanSocket::from(OwnedFd::from(b))).unwrap();
+
+        let id = StandardId::new(0x42).unwrap();
+        let writer = thread::spawn(move || {
+            // Give the reader time to park on the reactor
+            thread::sleep(Duration::from_millis(50));
+            for i in 0..3 {
+                let frame = CanFrame::new(id, &[i]).unwrap();
+                block_on(poll_fn(|cx| tx.poll_write_frame(cx, &frame))).unwrap();
+            }
+            tx
+        });
+
+        for i in 0..3 {
+            let frame = block_on(poll_fn(|cx| rx.poll_read_frame(cx))).unwrap();
+            assert_eq!(frame.raw_id(), 0x42);
+            assert_eq!(frame.data(), &[i]);
+        }
+        let tx = writer.join().unwrap();
+        let _sock = tx.into_inner();
+    }
+}
@@ -0,0 +1,388 @@
+// socketcan/src/obd.rs
+//
+// OBD-II (SAE J1979) requests and responses over ISO-TP.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! OBD-II (SAE J1979) diagnostics.
+//!
+//! On-board diagnostics is the standard set of emissions-related requests
+//! that every vehicle answers. The requests are sent over ISO-TP, to the
+//! functional address, 0x7DF, which is heard by all the emissions ECUs, or
+//! to a single ECU at 0x7E0-0x7E7. The ECUs respond from 0x7E8-0x7EF.
+//!
+//! This has request builders and response decoders for the current data
+//! (mode 01) and vehicle information (mode 09) services, which can be used
+//! with any transport, and an [`ObdClient`] that handles the requests over
+//! an ISO-TP socket.
+//!
+//! ```no_run
+//! use socketcan::obd::{ObdClient, PID_ENGINE_RPM};
+//!
+//! let client = ObdClient::open("vcan0").unwrap();
+//! println!("{:?}", client.current_data(PID_ENGINE_RPM).unwrap());
+//! ```
+//!
+//! The requests and responses share their format with UDS, so the client
+//! is built on a [`UdsClient`], and reports its errors the same way.
+
+use crate::{
+    uds::{strip_echo, UdsClient, UdsError, UdsResult},
+    StandardId,
+};
+use std::io;
+
+/// The functional address, for requests to all the emissions ECUs
+pub const FUNCTIONAL_REQUEST_ID: u16 = 0x7DF;
+/// The physical address of the first ECU, for requests to it alone
+pub const ECU_REQUEST_ID: u16 = 0x7E0;
+/// The address the first ECU responds from
+pub const ECU_RESPONSE_ID: u16 = 0x7E8;
+/// The offset between an ECU's request and response addresses
+const RESPONSE_ID_OFFSET: u16 = ECU_RESPONSE_ID - ECU_REQUEST_ID;
+
+/// The service for current powertrain data (mode 01)
+pub const MODE_CURRENT_DATA: u8 = 0x01;
+/// The service for vehicle information (mode 09)
+pub const MODE_VEHICLE_INFO: u8 = 0x09;
+
+/// The offset added to the mode in a response
+const RESPONSE_OFFSET: u8 = 0x40;
+
+/// PIDs supported, from 0x01 to 0x20 (mode 01)
+pub const PID_SUPPORTED_01_20: u8 = 0x00;
+/// Calculated engine load (mode 01)
+pub const PID_ENGINE_LOAD: u8 = 0x04;
+/// Engine coolant temperature (mode 01)
+pub const PID_COOLANT_TEMP: u8 = 0x05;
+/// Short term fuel trim, bank 1 (mode 01)
+pub const PID_SHORT_FUEL_TRIM_1: u8 = 0x06;
+/// Long term fuel trim, bank 1 (mode 01)
+pub const PID_LONG_FUEL_TRIM_1: u8 = 0x07;
+/// Short term fuel trim, bank 2 (mode 01)
+pub const PID_SHORT_FUEL_TRIM_2: u8 = 0x08;
+/// Long term fuel trim, bank 2 (mode 01)
+pub const PID_LONG_FUEL_TRIM_2: u8 = 0x09;
+/// Fuel pressure (mode 01)
+pub const PID_FUEL_PRESSURE: u8 = 0x0A;
+/// Intake manifold absolute pressure (mode 01)
+pub const PID_INTAKE_PRESSURE: u8 = 0x0B;
+/// Engine speed (mode 01)
+pub const PID_ENGINE_RPM: u8 = 0x0C;
+/// Vehicle speed (mode 01)
+pub const PID_VEHICLE_SPEED: u8 = 0x0D;
+/// Timing advance (mode 01)
+pub const PID_TIMING_ADVANCE: u8 = 0x0E;
+/// Intake air temperature (mode 01)
+pub const PID_INTAKE_AIR_TEMP: u8 = 0x0F;
+/// Mass air flow rate (mode 01)
+pub const PID_MAF: u8 = 0x10;
+/// Throttle position (mode 01)
+pub const PID_THROTTLE_POSITION: u8 = 0x11;
+/// Run time since engine start (mode 01)
+pub const PID_RUN_TIME: u8 = 0x1F;
+/// Distance traveled with the malfunction indicator lamp on (mode 01)
+pub const PID_DISTANCE_WITH_MIL: u8 = 0x21;
+/// Fuel tank level input (mode 01)
+pub const PID_FUEL_LEVEL: u8 = 0x2F;
+/// Distance traveled since the codes were cleared (mode 01)
+pub const PID_DISTANCE_SINCE_CLEAR: u8 = 0x31;
+/// Absolute barometric pressure (mode 01)
+pub const PID_BAROMETRIC_PRESSURE: u8 = 0x33;
+/// Ambient air temperature (mode 01)
+pub const PID_AMBIENT_AIR_TEMP: u8 = 0x46;
+/// Engine oil temperature (mode 01)
+pub const PID_OIL_TEMP: u8 = 0x5C;
+
+/// Vehicle identification number (mode 09)
+pub const PID_VIN: u8 = 0x02;
+/// Calibration ID (mode 09)
+pub const PID_CALIBRATION_ID: u8 = 0x04;
+/// ECU name (mode 09)
+pub const PID_ECU_NAME: u8 = 0x0A;
+
+// ===== Decoding =====
+
+/// A decoded value of a current data (mode 01) PID.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObdValue {
+    /// The PIDs supported, from a "PIDs supported" bitmap
+    SupportedPids(Vec<u8>),
+    /// A percentage, which can be negative for a fuel trim
+    Percent(f32),
+    /// A temperature, in degrees Celsius
+    Celsius(i16),
+    /// A pressure, in kPa
+    Kpa(u16),
+    /// An engine speed, in revolutions per minute
+    Rpm(f32),
+    /// A vehicle speed, in km/h
+    KmPerHour(u8),
+    /// An angle, in degrees before top dead center
+    Degrees(f32),
+    /// An air flow rate, in grams per second
+    GramsPerSec(f32),
+    /// A time, in seconds
+    Seconds(u16),
+    /// A distance, in kilometers
+    Kilometers(u16),
+    /// The raw data of a PID that isn't decoded
+    Raw(Vec<u8>),
+}
+
+/// Decodes the data of a current data (mode 01) PID, using the formulas
+/// from SAE J1979.
+///
+/// The data is what follows the mode and PID in the response. This
+/// returns `None` if there's too little data for the PID.
+pub fn decode_current_data(pid: u8, data: &[u8]) -> Option<ObdValue> {
+    use ObdValue::*;
+
+    let a = data.first().copied().map(f32::from);
+    let ab = match *data {
+        [a, b, ..] => Some(u16::from_be_bytes([a, b])),
+        _ => None,
+    };
+    let temp = || data.first().map(|&a| Celsius(i16::from(a) - 40));
+
+    let val = match pid {
+        0x00 | 0x20 | 0x40 | 0x60 | 0x80 | 0xA0 | 0xC0 => {
+            let bits = match *data {
+                [a, b, c, d, ..] => u32::from_be_bytes([a, b, c, d]),
+                _ => return None,
+            };
+            // The MSB is the PID after the one requested
+            SupportedPids(
+                (0..32)
+                    .filter(|i| bits & (0x8000_0000 >> i) != 0)
+                    .map(|i| pid + 1 + i as u8)
+                    .collect(),
+            )
+        }
+        PID_ENGINE_LOAD | PID_THROTTLE_POSITION | PID_FUEL_LEVEL => Percent(a? * 100.0 / 255.0),
+        PID_COOLANT_TEMP | PID_INTAKE_AIR_TEMP | PID_AMBIENT_AIR_TEMP | PID_OIL_TEMP => temp()?,
+        PID_SHORT_FUEL_TRIM_1..=PID_LONG_FUEL_TRIM_2 => Percent((a? - 128.0) * 100.0 / 128.0),
+        PID_FUEL_PRESSURE => Kpa(3 * u16::from(*data.first()?)),
+        PID_INTAKE_PRESSURE | PID_BAROMETRIC_PRESSURE => Kpa(u16::from(*data.first()?)),
+        PID_ENGINE_RPM => Rpm(f32::from(ab?) / 4.0),
+        PID_VEHICLE_SPEED => KmPerHour(*data.first()?),
+        PID_TIMING_ADVANCE => Degrees(a? / 2.0 - 64.0),
+        PID_MAF => GramsPerSec(f32::from(ab?) / 100.0),
+        PID_RUN_TIME => Seconds(ab?),
+        PID_DISTANCE_WITH_MIL | PID_DISTANCE_SINCE_CLEAR => Kilometers(ab?),
+        _ => Raw(data.to_vec()),
+    };
+    Some(val)
+}
+
+/// Decodes a vehicle identification number from the data of a mode 09
+/// response.
+///
+/// The VIN is 17 characters. Some ECUs put a count of data items before
+/// it, or pad it, so this takes the last 17 bytes.
+pub fn decode_vin(data: &[u8]) -> Option<String> {
+    let vin = data.get(data.len().checked_sub(17)?..)?;
+    match vin.iter().all(u8::is_ascii_alphanumeric) {
+        true => Some(String::from_utf8_lossy(vin).into_owned()),
+        false => None,
+    }
+}
+
+// ===== Requests and responses =====
+
+/// Creates a request for a current data (mode 01) PID.
+pub fn current_data_request(pid: u8) -> [u8; 2] {
+    [MODE_CURRENT_DATA, pid]
+}
+
+/// Creates a request for a vehicle information (mode 09) PID.
+pub fn vehicle_info_request(pid: u8) -> [u8; 2] {
+    [MODE_VEHICLE_INFO, pid]
+}
+
+/// Gets the data from a response to a request for the mode and PID.
+///
+/// The response starts with the mode, plus 0x40, and the PID. This returns
+/// `None` if it doesn't match the request.
+pub fn response_data(mode: u8, pid: u8, resp: &[u8]) -> Option<&[u8]> {
+    match *resp {
+        [m, p, ref data @ ..] if m == mode.wrapping_add(RESPONSE_OFFSET) && p == pid => Some(data),
+        _ => None,
+    }
+}
+
+// ===== ObdClient =====
+
+/// A client for the OBD-II services of a vehicle.
+///
+/// An ISO-TP socket receives from a single CAN ID, so a client only hears
+/// the responses of one ECU, even to requests sent to the functional
+/// address. The kernel also sends the flow control for a multi-frame
+/// response, like the VIN, to the address the request went to, which only
+/// works with a physical address. Open the client with
+/// [`ObdClient::open_ecu()`] for those.
+#[derive(Debug)]
+pub struct ObdClient(UdsClient);
+
+impl ObdClient {
+    /// Opens a client that sends requests to the functional address, and
+    /// receives the responses of the first ECU.
+    ///
+    /// The flow control for a multi-frame response also goes to the
+    /// functional address, rather than the ECU's physical 0x7E0, and an
+    /// ECU can time out waiting for it. Use [`ObdClient::open_ecu()`] for
+    /// requests like [`vin()`](Self::vin) that have long responses.
+    pub fn open(ifname: &str) -> io::Result<Self> {
+        Self::open_ids(ifname, FUNCTIONAL_REQUEST_ID, ECU_RESPONSE_ID)
+    }
+
+    /// Opens a client for one of the ECUs, numbered from 0 to 7, with
+    /// physical addressing.
+    pub fn open_ecu(ifname: &str, ecu: u8) -> io::Result<Self> {
+        let tx_id = ECU_REQUEST_ID + u16::from(ecu & 0x07);
+        Self::open_ids(ifname, tx_id, tx_id + RESPONSE_ID_OFFSET)
+    }
+
+    fn open_ids(ifname: &str, tx_id: u16, rx_id: u16) -> io::Result<Self> {
+        let invalid = || io::Error::from(io::ErrorKind::InvalidInput);
+        let tx_id = StandardId::new(tx_id).ok_or_else(invalid)?;
+        let rx_id = StandardId::new(rx_id).ok_or_else(invalid)?;
+        UdsClient::open(ifname, rx_id, tx_id).map(Self)
+    }
+
+    /// Gets a shared reference to the underlying UDS client
+    pub fn as_uds_client(&self) -> &UdsClient {
+        &self.0
+    }
+
+    /// Requests a current data (mode 01) PID, and decodes its value.
+    pub fn current_data(&self, pid: u8) -> UdsResult<ObdValue> {
+        let resp = self.0.request(&current_data_request(pid))?;
+        decode_current_data(pid, strip_echo(&resp, &[pid])?).ok_or(UdsError::UnexpectedResponse)
+    }
+
+    /// Gets all the current data (mode 01) PIDs that the ECU supports.
+    ///
+    /// This walks the "PIDs supported" ranges, for as long as the ECU
+    /// reports supporting the next one.
+    pub fn supported_pids(&self) -> UdsResult<Vec<u8>> {
+        let mut pids = Vec::new();
+        let mut range = PID_SUPPORTED_01_20;
+        loop {
+            match self.current_data(range)? {
+                ObdValue::SupportedPids(supported) => pids.extend(supported),
+                _ => return Err(UdsError::UnexpectedResponse),
+            }
+            match range.checked_add(0x20) {
+                Some(next) if pids.contains(&next) => range = next,
+                _ => break,
+            }
+        }
+        Ok(pids)
+    }
+
+    /// Requests a vehicle information (mode 09) PID, returning its raw data.
+    pub fn vehicle_info(&self, pid: u8) -> UdsResult<Vec<u8>> {
+        let resp = self.0.request(&vehicle_info_request(pid))?;
+        Ok(strip_echo(&resp, &[pid])?.to_vec())
+    }
+
+    /// Reads the vehicle identification number (VIN).
+    ///
+    /// The response takes several frames, so this needs a client with
+    /// physical addressing, from [`ObdClient::open_ecu()`].
+    pub fn vin(&self) -> UdsResult<String> {
+        decode_vin(&self.vehicle_info(PID_VIN)?).ok_or(UdsError::UnexpectedResponse)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_current_data() {
+        use ObdValue::*;
+
+        assert_eq!(
+            decode_current_data(PID_ENGINE_RPM, &[0x1A, 0xF8]),
+            Some(Rpm(1726.0))
+        );
+        assert_eq!(
+            decode_current_data(PID_COOLANT_TEMP, &[0x7B]),
+            Some(Celsius(83))
+        );
+        assert_eq!(
+            decode_current_data(PID_VEHICLE_SPEED, &[0x32]),
+            Some(KmPerHour(50))
+        );
+        assert_eq!(
+            decode_current_data(PID_ENGINE_LOAD, &[0xFF]),
+            Some(Percent(100.0))
+        );
+        assert_eq!(
+            decode_current_data(PID_SHORT_FUEL_TRIM_1, &[0x60]),
+            Some(Percent(-25.0))
+        );
+        assert_eq!(
+            decode_current_data(PID_MAF, &[0x01, 0x2C]),
+            Some(GramsPerSec(3.0))
+        );
+        assert_eq!(decode_current_data(0x99, &[1, 2]), Some(Raw(vec![1, 2])));
+
+        // Not enough data
+        assert_eq!(decode_current_data(PID_ENGINE_RPM, &[0x1A]), None);
+    }
+
+    #[test]
+    fn test_supported_pids() {
+        let val = decode_current_data(PID_SUPPORTED_01_20, &[0xBE, 0x1F, 0xA8, 0x13]).unwrap();
+        let pids = match val {
+            ObdValue::SupportedPids(pids) => pids,
+            val => panic!("unexpected value: {:?}", val),
+        };
+        assert_eq!(
+            pids,
+            &[
+                0x01, 0x03, 0x04, 0x05, 0x06, 0x07, 0x0C, 0x0D, 0x0E, 0x0F, 0x10, 0x11, 0x13, 0x15,
+                0x1C, 0x1F, 0x20
+            ]
+        );
+    }
+
+    #[test]
+    fn test_requests() {
+        assert_eq!(current_data_request(PID_ENGINE_RPM), [0x01, 0x0C]);
+        assert_eq!(vehicle_info_request(PID_VIN), [0x09, 0x02]);
+
+        let resp = [0x41, 0x0C, 0x1A, 0xF8];
+        assert_eq!(
+            response_data(MODE_CURRENT_DATA, PID_ENGINE_RPM, &resp),
+            Some(&[0x1A, 0xF8][..])
+        );
+        assert_eq!(response_data(MODE_CURRENT_DATA, PID_MAF, &resp), None);
+        assert_eq!(
+            response_data(MODE_VEHICLE_INFO, PID_ENGINE_RPM, &resp),
+            None
+        );
+
+        // Modes past 0xBF wrap, rather than overflowing
+        assert_eq!(response_data(0xC1, 0x00, &[0x01, 0x00]), Some(&[][..]));
+    }
+
+    #[test]
+    fn test_decode_vin() {
+        let mut data = vec![0x01];
+        data.extend_from_slice(b"1G1JC5444R7252367");
+        assert_eq!(decode_vin(&data).unwrap(), "1G1JC5444R7252367");
+        assert_eq!(decode_vin(&data[1..]).unwrap(), "1G1JC5444R7252367");
+        assert!(decode_vin(b"1G1JC").is_none());
+    }
+}
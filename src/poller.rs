@@ -0,0 +1,230 @@
+// socketcan/src/poller.rs
+//
+// Waiting on many CAN sockets at once from a single thread.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Waiting on many CAN sockets at once.
+//!
+//! A [`CanPoller`] lets a single thread block until any of a set of
+//! sockets has something to read, like a gateway that forwards frames
+//! between several buses, without a thread for each interface. Each
+//! socket is registered with a token, and a wait returns the tokens of
+//! the sockets that are readable.
+//!
+//! ```no_run
+//! use socketcan::{CanPoller, CanSocket, Socket};
+//!
+//! let socks = [
+//!     CanSocket::open("can0").unwrap(),
+//!     CanSocket::open("can1").unwrap(),
+//! ];
+//!
+//! let mut poller = CanPoller::new().unwrap();
+//! for (i, sock) in socks.iter().enumerate() {
+//!     poller.add(sock, i).unwrap();
+//! }
+//!
+//! loop {
+//!     for &i in poller.wait(None).unwrap() {
+//!         let frame = socks[i].read_frame().unwrap();
+//!         // Forward to the other bus
+//!         socks[1 - i].write_frame(&frame).unwrap();
+//!     }
+//! }
+//! ```
+//!
+//! The sockets are polled level-triggered, so one that still has frames
+//! queued after it's been read is reported again by the next wait.
+
+use std::{
+    fmt,
+    io::{Error as IoError, ErrorKind as IoErrorKind, Result as IoResult},
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+    time::{Duration, Instant},
+};
+
+/// The number of events that a single wait collects, by default.
+const DEFAULT_CAPACITY: usize = 64;
+
+/// A set of sockets that a thread can wait on together, using `epoll`.
+pub struct CanPoller {
+    /// The epoll instance
+    epoll: OwnedFd,
+    /// The buffer the kernel fills with the ready events
+    events: Vec<libc::epoll_event>,
+    /// The tokens of the sockets that were ready in the last wait
+    ready: Vec<usize>,
+}
+
+impl CanPoller {
+    /// Creates an empty poller.
+    pub fn new() -> IoResult<Self> {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Creates an empty poller that reports up to `n` ready sockets from
+    /// each wait.
+    ///
+    /// Any others are reported by the following waits.
+    pub fn with_capacity(n: usize) -> IoResult<Self> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(IoError::last_os_error());
+        }
+        let n = n.max(1);
+        Ok(Self {
+            epoll: unsafe { OwnedFd::from_raw_fd(fd) },
+            events: vec![libc::epoll_event { events: 0, u64: 0 }; n],
+            ready: Vec::with_capacity(n),
+        })
+    }
+
+    /// Adds a socket to the poller, to be reported with the token when
+    /// it's readable.
+    ///
+    /// The socket has to outlive its registration. If it's closed
+    /// without being removed, the kernel drops it from the set.
+    pub fn add<S: AsRawFd>(&mut self, sock: &S, token: usize) -> IoResult<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, sock.as_raw_fd(), token)
+    }
+
+    /// Changes the token of a socket that's already in the poller.
+    pub fn modify<S: AsRawFd>(&mut self, sock: &S, token: usize) -> IoResult<()> {
+        self.ctl(libc::EPOLL_CTL_MOD, sock.as_raw_fd(), token)
+    }
+
+    /// Removes a socket from the poller.
+    pub fn remove<S: AsRawFd>(&mut self, sock: &S) -> IoResult<()> {
+        self.ctl(libc::EPOLL_CTL_DEL, sock.as_raw_fd(), 0)
+    }
+
+    /// Waits for any of the sockets to be readable, and returns their
+    /// tokens.
+    ///
+    /// This blocks until at least one socket is readable, or the timeout
+    /// expires, in which case the list is empty. A timeout of `None`
+    /// waits forever. A socket with an error pending, such as one whose
+    /// interface went down, is also reported, so that the error is seen
+    /// by its next read.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> IoResult<&[usize]> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        self.ready.clear();
+
+        let n = loop {
+            let ms = match deadline {
+                None => -1,
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    // Round up, so a short timeout doesn't spin
+                    let ms = remaining.as_millis()
+                        + u128::from(remaining.subsec_nanos() % 1_000_000 != 0);
+                    ms.min(libc::c_int::MAX as u128) as libc::c_int
+                }
+            };
+            let n = unsafe {
+                libc::epoll_wait(
+                    self.epoll.as_raw_fd(),
+                    self.events.as_mut_ptr(),
+                    self.events.len() as libc::c_int,
+                    ms,
+                )
+            };
+            if n >= 0 {
+                break n as usize;
+            }
+            let err = IoError::last_os_error();
+            if err.kind() != IoErrorKind::Interrupted {
+                return Err(err);
+            }
+        };
+
+        self.ready
+            .extend(self.events[..n].iter().map(|ev| ev.u64 as usize));
+        Ok(&self.ready)
+    }
+
+    /// Gets the tokens of the sockets that were ready in the last wait.
+    pub fn ready(&self) -> &[usize] {
+        &self.ready
+    }
+
+    /// Adds, changes, or removes a socket in the epoll set.
+    fn ctl(&self, op: libc::c_int, fd: RawFd, token: usize) -> IoResult<()> {
+        let mut ev = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: token as u64,
+        };
+        if unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), op, fd, &mut ev) } < 0 {
+            return Err(IoError::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CanPoller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CanPoller")
+            .field("epoll", &self.epoll)
+            .field("capacity", &self.events.len())
+            .field("ready", &self.ready)
+            .finish()
+    }
+}
+
+impl AsRawFd for CanPoller {
+    /// Gets the epoll fd, which can itself be polled for readiness.
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll.as_raw_fd()
+    }
+}
+
+impl AsFd for CanPoller {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.epoll.as_fd()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_wait_readable() {
+        let (tx0, rx0) = UnixDatagram::pair().unwrap();
+        let (tx1, rx1) = UnixDatagram::pair().unwrap();
+
+        let mut poller = CanPoller::new().unwrap();
+        poller.add(&rx0, 10).unwrap();
+        poller.add(&rx1, 11).unwrap();
+
+        let timeout = Some(Duration::from_millis(10));
+        assert!(poller.wait(timeout).unwrap().is_empty());
+
+        tx1.send(&[1]).unwrap();
+        assert_eq!(poller.wait(timeout).unwrap(), &[11]);
+
+        // Level-triggered, so it's still ready until it's read
+        assert_eq!(poller.wait(timeout).unwrap(), &[11]);
+        rx1.recv(&mut [0; 1]).unwrap();
+
+        tx0.send(&[1]).unwrap();
+        tx1.send(&[1]).unwrap();
+        let mut ready = poller.wait(timeout).unwrap().to_vec();
+        ready.sort_unstable();
+        assert_eq!(ready, [10, 11]);
+        assert_eq!(poller.ready().len(), 2);
+
+        poller.modify(&rx0, 20).unwrap();
+        poller.remove(&rx1).unwrap();
+        assert_eq!(poller.wait(timeout).unwrap(), &[20]);
+    }
+}
@@ -0,0 +1,516 @@
+// socketcan/src/slcan.rs
+//
+// An interface for serial-line CAN (slcan) adapters.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! Serial-line CAN (slcan) interfaces.
+//!
+//! Many USB CAN dongles show up as a serial port, and speak the ASCII
+//! protocol from the LAWICEL CAN232 adapters. Each command is a line of
+//! text ending in a carriage return, and frames are sent and received as
+//! lines like `t1232AABB`: a type letter, the ID, the DLC, and the data,
+//! all in hex.
+//!
+//! The kernel's `slcan` line discipline can turn such a port into a
+//! SocketCAN interface, with `slcand`. An [`SlcanInterface`] talks to the
+//! adapter directly instead, which needs no setup as root, and reads and
+//! writes the same [`CanFrame`] types as a [`CanSocket`](crate::CanSocket).
+//!
+//! ```no_run
+//! use socketcan::{slcan::SlcanInterface, CanFrame, EmbeddedFrame, StandardId};
+//!
+//! let mut iface = SlcanInterface::open_serial("/dev/ttyACM0", 115_200).unwrap();
+//! iface.open_channel(500_000).unwrap();
+//!
+//! let id = StandardId::new(0x123).unwrap();
+//! iface.write_frame(&CanFrame::new(id, &[1, 2, 3]).unwrap()).unwrap();
+//!
+//! let frame = iface.read_frame().unwrap();
+//! println!("{:?}", frame);
+//! ```
+//!
+//! Only classic CAN frames are covered. The protocol is described at:
+//!
+//! <http://www.can232.com/docs/can232_v3.pdf>
+
+use crate::{CanFrame, EmbeddedFrame, Error, ExtendedId, Id, StandardId};
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    mem,
+    os::unix::io::AsRawFd,
+};
+
+/// The character that ends each command and response
+const CR: u8 = b'\r';
+
+/// The character that the adapter responds with to a bad command
+const BELL: u8 = 0x07;
+
+/// The longest line the adapter sends: an extended frame with 8 bytes of
+/// data and a timestamp
+const MAX_LINE: usize = 1 + 8 + 1 + 16 + 4;
+
+/// Gets the command to set up the adapter for a standard bit rate.
+///
+/// The protocol only has commands for the bit rates from 10 kbit/s to
+/// 1 Mbit/s in the CiA table.
+fn bitrate_command(bitrate: u32) -> io::Result<&'static str> {
+    Ok(match bitrate {
+        10_000 => "S0",
+        20_000 => "S1",
+        50_000 => "S2",
+        100_000 => "S3",
+        125_000 => "S4",
+        250_000 => "S5",
+        500_000 => "S6",
+        800_000 => "S7",
+        1_000_000 => "S8",
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not an slcan bit rate",
+            ))
+        }
+    })
+}
+
+/// Encodes a frame as an slcan transmit command, without the ending CR.
+///
+/// Error frames can't be sent, and fail with an `InvalidInput` error.
+pub fn encode_frame(frame: &CanFrame) -> io::Result<String> {
+    let mut s = String::with_capacity(MAX_LINE);
+    let remote = match frame {
+        CanFrame::Data(_) => false,
+        CanFrame::Remote(_) => true,
+        CanFrame::Error(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "error frames can't be sent over slcan",
+            ))
+        }
+    };
+
+    let _ = match (frame.id(), remote) {
+        (Id::Standard(id), false) => write!(s, "t{:03X}", id.as_raw()),
+        (Id::Standard(id), true) => write!(s, "r{:03X}", id.as_raw()),
+        (Id::Extended(id), false) => write!(s, "T{:08X}", id.as_raw()),
+        (Id::Extended(id), true) => write!(s, "R{:08X}", id.as_raw()),
+    };
+    let _ = write!(s, "{:X}", frame.dlc());
+    if !remote {
+        for b in frame.data() {
+            let _ = write!(s, "{:02X}", b);
+        }
+    }
+    Ok(s)
+}
+
+/// Decodes a frame from an slcan line, without the ending CR.
+///
+/// A timestamp after the data, if the adapter has them enabled, is
+/// ignored. Returns `None` if the line isn't a valid frame.
+pub fn decode_frame(line: &[u8]) -> Option<CanFrame> {
+    // Anything else is line noise, and can't be sliced as hex digits
+    if !line.is_ascii() {
+        return None;
+    }
+    let line = std::str::from_utf8(line).ok()?;
+    let (kind, rest) = (line.as_bytes().first()?, line.get(1..)?);
+    let (id_len, remote) = match kind {
+        b't' => (3, false),
+        b'r' => (3, true),
+        b'T' => (8, false),
+        b'R' => (8, true),
+        _ => return None,
+    };
+
+    let raw_id = u32::from_str_radix(rest.get(..id_len)?, 16).ok()?;
+    let id: Id = match id_len {
+        3 => StandardId::new(raw_id as u16)?.into(),
+        _ => ExtendedId::new(raw_id)?.into(),
+    };
+
+    let dlc = usize::from_str_radix(rest.get(id_len..id_len + 1)?, 16).ok()?;
+    if dlc > 8 {
+        return None;
+    }
+    if remote {
+        return CanFrame::new_remote(id, dlc);
+    }
+
+    let hex = rest.get(id_len + 1..id_len + 1 + 2 * dlc)?;
+    let mut data = [0u8; 8];
+    for (i, b) in data.iter_mut().take(dlc).enumerate() {
+        *b = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    CanFrame::new(id, &data[..dlc])
+}
+
+// ===== SlcanInterface =====
+
+/// An interface to an slcan adapter, over a serial port or any other
+/// byte stream.
+///
+/// The channel needs to be opened, with [`SlcanInterface::open_channel()`],
+/// before frames can be sent or received.
+#[derive(Debug)]
+pub struct SlcanInterface<T> {
+    port: T,
+    // Bytes of a line received so far
+    line: Vec<u8>,
+    // Frames received while waiting for the response to a command
+    pending: VecDeque<CanFrame>,
+    // Frames written that the adapter hasn't yet acknowledged
+    unacked: usize,
+}
+
+impl SlcanInterface<File> {
+    /// Opens the serial port at the path given, in raw mode at the baud
+    /// rate given.
+    ///
+    /// Most USB adapters ignore the baud rate, but those on a real UART
+    /// need it to match their own.
+    pub fn open_serial(path: &str, baud: u32) -> io::Result<Self> {
+        let speed = match baud {
+            9600 => libc::B9600,
+            19_200 => libc::B19200,
+            38_400 => libc::B38400,
+            57_600 => libc::B57600,
+            115_200 => libc::B115200,
+            230_400 => libc::B230400,
+            460_800 => libc::B460800,
+            921_600 => libc::B921600,
+            1_000_000 => libc::B1000000,
+            2_000_000 => libc::B2000000,
+            3_000_000 => libc::B3000000,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "unsupported baud rate",
+                ))
+            }
+        };
+
+        let port = OpenOptions::new().read(true).write(true).open(path)?;
+        let fd = port.as_raw_fd();
+
+        unsafe {
+            let mut tio: libc::termios = mem::zeroed();
+            if libc::tcgetattr(fd, &mut tio) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut tio);
+            // Block until at least one byte is available
+            tio.c_cc[libc::VMIN] = 1;
+            tio.c_cc[libc::VTIME] = 0;
+            if libc::cfsetspeed(&mut tio, speed) < 0 || libc::tcsetattr(fd, libc::TCSANOW, &tio) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(Self::new(port))
+    }
+}
+
+impl<T: Read + Write> SlcanInterface<T> {
+    /// Creates an interface over a port that's already open and set up.
+    pub fn new(port: T) -> Self {
+        Self {
+            port,
+            line: Vec::with_capacity(MAX_LINE),
+            pending: VecDeque::new(),
+            unacked: 0,
+        }
+    }
+
+    /// Gets a shared reference to the underlying port
+    pub fn as_port(&self) -> &T {
+        &self.port
+    }
+
+    /// Consumes the interface, returning the underlying port
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+
+    /// Sets the bit rate, and opens the CAN channel.
+    ///
+    /// The channel is closed first, in case it was left open, since the
+    /// bit rate can only be changed while it's closed.
+    pub fn open_channel(&mut self, bitrate: u32) -> io::Result<()> {
+        let cmd = bitrate_command(bitrate)?;
+        // This fails if the channel is already closed, which is fine
+        let _ = self.command("C");
+        self.command(cmd)?;
+        self.command("O")
+    }
+
+    /// Opens the CAN channel in listen-only mode, with the bit rate given.
+    ///
+    /// The adapter doesn't send frames or acknowledge those it receives.
+    pub fn open_listen_only(&mut self, bitrate: u32) -> io::Result<()> {
+        let cmd = bitrate_command(bitrate)?;
+        let _ = self.command("C");
+        self.command(cmd)?;
+        self.command("L")
+    }
+
+    /// Closes the CAN channel.
+    pub fn close_channel(&mut self) -> io::Result<()> {
+        self.command("C")
+    }
+
+    /// Sends a command to the adapter, and waits for it to be accepted.
+    ///
+    /// Frames received in the meantime are kept, to be returned by
+    /// [`SlcanInterface::read_frame()`]. A command that's rejected fails
+    /// with an `Other` error.
+    pub fn command(&mut self, cmd: &str) -> io::Result<()> {
+        self.port.write_all(cmd.as_bytes())?;
+        self.port.write_all(&[CR])?;
+        self.port.flush()?;
+
+        loop {
+            match self.read_line()? {
+                Line::Ok => return Ok(()),
+                Line::Nack => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        "slcan command rejected",
+                    ))
+                }
+                Line::Frame(frame) => self.pending.push_back(frame),
+                Line::Other => (),
+            }
+        }
+    }
+
+    /// Writes a frame to the bus.
+    ///
+    /// This doesn't wait for the adapter to acknowledge the frame, since
+    /// with many adapters that only comes back after it has been sent.
+    /// The adapter answers in order, so the acknowledgement is matched to
+    /// the frame when it arrives, and isn't taken as the answer to a later
+    /// command.
+    pub fn write_frame(&mut self, frame: &CanFrame) -> io::Result<()> {
+        let mut cmd = encode_frame(frame)?.into_bytes();
+        cmd.push(CR);
+        self.port.write_all(&cmd)?;
+        self.port.flush()?;
+        self.unacked += 1;
+        Ok(())
+    }
+
+    /// Reads the next frame received from the bus.
+    pub fn read_frame(&mut self) -> io::Result<CanFrame> {
+        if let Some(frame) = self.pending.pop_front() {
+            return Ok(frame);
+        }
+        loop {
+            if let Line::Frame(frame) = self.read_line()? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    // Reads a line from the adapter, up to a CR or BELL
+    //
+    // An answer that belongs to a frame written earlier is returned as
+    // `Line::Other`, so it can't be mistaken for a command's.
+    fn read_line(&mut self) -> io::Result<Line> {
+        let line = self.read_raw_line()?;
+        match line {
+            Line::Ok | Line::Nack if self.unacked > 0 => {
+                self.unacked -= 1;
+                Ok(Line::Other)
+            }
+            line => Ok(line),
+        }
+    }
+
+    // Reads a line from the adapter, up to a CR or BELL, as it was sent
+    fn read_raw_line(&mut self) -> io::Result<Line> {
+        let mut byte = [0u8; 1];
+        loop {
+            if self.port.read(&mut byte)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            match byte[0] {
+                BELL => {
+                    self.line.clear();
+                    return Ok(Line::Nack);
+                }
+                CR => {
+                    let line = Line::parse(&self.line);
+                    self.line.clear();
+                    return Ok(line);
+                }
+                b if self.line.len() < MAX_LINE => self.line.push(b),
+                // Too long to be anything we know; drop it
+                _ => self.line.clear(),
+            }
+        }
+    }
+}
+
+/// A line received from the adapter
+#[derive(Debug)]
+enum Line {
+    // A command was accepted
+    Ok,
+    // A command was rejected
+    Nack,
+    // A frame was received
+    Frame(CanFrame),
+    // Anything else, like a version or status
+    Other,
+}
+
+impl Line {
+    fn parse(line: &[u8]) -> Self {
+        match line {
+            // An empty line, or a 'z' or 'Z' after sending a frame
+            [] | [b'z'] | [b'Z'] => Line::Ok,
+            _ => match decode_frame(line) {
+                Some(frame) => Line::Frame(frame),
+                None => Line::Other,
+            },
+        }
+    }
+}
+
+impl<T: Read + Write> embedded_can::blocking::Can for SlcanInterface<T> {
+    type Frame = CanFrame;
+    type Error = Error;
+
+    /// Blocking call to receive the next frame from the bus.
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        Ok(self.read_frame()?)
+    }
+
+    /// Blocking transmit of a frame to the bus.
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        Ok(self.write_frame(frame)?)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A fake adapter, with the bytes it sends and those it was sent
+    #[derive(Debug, Default)]
+    struct Port {
+        rx: Cursor<Vec<u8>>,
+        tx: Vec<u8>,
+    }
+
+    impl Read for Port {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.rx.read(buf)
+        }
+    }
+
+    impl Write for Port {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.tx.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn port(rx: &[u8]) -> Port {
+        Port {
+            rx: Cursor::new(rx.to_vec()),
+            tx: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let id = StandardId::new(0x123).unwrap();
+        let frame = CanFrame::new(id, &[0x11, 0x22, 0xAB]).unwrap();
+        assert_eq!(encode_frame(&frame).unwrap(), "t12331122AB");
+
+        let id = ExtendedId::new(0x12345678).unwrap();
+        let frame = CanFrame::new(id, &[]).unwrap();
+        assert_eq!(encode_frame(&frame).unwrap(), "T123456780");
+
+        let frame = CanFrame::new_remote(id, 4).unwrap();
+        assert_eq!(encode_frame(&frame).unwrap(), "R123456784");
+    }
+
+    #[test]
+    fn test_decode() {
+        let frame = decode_frame(b"t1233112233").unwrap();
+        assert!(matches!(frame, CanFrame::Data(_)));
+        assert_eq!(frame.id(), Id::from(StandardId::new(0x123).unwrap()));
+        assert_eq!(frame.data(), &[0x11, 0x22, 0x33]);
+
+        // With a timestamp
+        let frame = decode_frame(b"T1ABCDEF021234BEEF").unwrap();
+        assert_eq!(frame.id(), Id::from(ExtendedId::new(0x1ABCDEF0).unwrap()));
+        assert_eq!(frame.data(), &[0x12, 0x34]);
+
+        let frame = decode_frame(b"r7FF8").unwrap();
+        assert!(matches!(frame, CanFrame::Remote(_)));
+        assert_eq!(frame.dlc(), 8);
+
+        assert!(decode_frame(b"t12331122").is_none());
+        assert!(decode_frame(b"t8001").is_none());
+        assert!(decode_frame(b"t1239").is_none());
+        assert!(decode_frame(b"V1013").is_none());
+
+        // Line noise
+        assert!(decode_frame(b"t1232\xC3\xA9AB").is_none());
+        assert!(decode_frame(b"t12321\xC3\xA9").is_none());
+    }
+
+    #[test]
+    fn test_interface() {
+        // Close fails, as the channel was closed, then S6 and O succeed,
+        // with a frame received in between
+        let mut iface = SlcanInterface::new(port(b"\x07\rt1231AA\r\rz\rt4560\r"));
+        iface.open_channel(500_000).unwrap();
+        assert_eq!(iface.as_port().tx, b"C\rS6\rO\r");
+
+        let id = StandardId::new(0x321).unwrap();
+        iface
+            .write_frame(&CanFrame::new(id, &[1, 2]).unwrap())
+            .unwrap();
+        assert!(iface.as_port().tx.ends_with(b"t32120102\r"));
+
+        assert_eq!(iface.read_frame().unwrap().data(), &[0xAA]);
+        let frame = iface.read_frame().unwrap();
+        assert_eq!(frame.id(), Id::from(StandardId::new(0x456).unwrap()));
+        assert!(iface.read_frame().is_err());
+
+        assert!(iface.open_channel(33_333).is_err());
+    }
+
+    #[test]
+    fn test_frame_ack_not_taken_by_command() {
+        // The frame's ack arrives after the close command is sent, which
+        // is then rejected
+        let mut iface = SlcanInterface::new(port(b"z\r\x07"));
+        let id = StandardId::new(0x321).unwrap();
+        iface
+            .write_frame(&CanFrame::new(id, &[1, 2]).unwrap())
+            .unwrap();
+        assert!(iface.close_channel().is_err());
+    }
+}
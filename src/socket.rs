@@ -13,31 +13,53 @@
 
 use crate::{
     as_bytes, as_bytes_mut,
-    frame::{can_frame_default, canfd_frame_default, AsPtr},
-    id::CAN_ERR_MASK,
-    CanAnyFrame, CanFdFrame, CanFrame, CanRawFrame, Error, IoError, IoErrorKind, IoResult, Result,
+    errors::{is_interface_gone, SocketError},
+    frame::{can_frame_default, canfd_frame_default, canxl_frame_default, AsPtr},
+    id::{id_to_canid_t, CanErrorMask, CANXL_XLF, CAN_ERR_MASK, CAN_MAX_DLEN},
+    CanAnyFrame, CanFdFrame, CanFrame, CanMixedFrame, CanRawFrame, CanXlFrame, ConstructionError,
+    Error, IoError, IoErrorKind, IoResult, Result,
 };
+use bitflags::bitflags;
 pub use embedded_can::{
     self, blocking::Can as BlockingCan, nb::Can as NonBlockingCan, ExtendedId,
     Frame as EmbeddedFrame, Id, StandardId,
 };
-use libc::{canid_t, socklen_t, AF_CAN, EINPROGRESS};
+use libc::{
+    canid_t, socklen_t, AF_CAN, CANXL_HDR_SIZE, CAN_BCM, EINPROGRESS, MSG_CONFIRM, MSG_DONTROUTE,
+    MSG_TRUNC, SCM_TIMESTAMP, SCM_TIMESTAMPING, SCM_TIMESTAMPNS, SCM_TXTIME,
+    SOF_TIMESTAMPING_RAW_HARDWARE, SOF_TIMESTAMPING_RX_HARDWARE, SOF_TIMESTAMPING_RX_SOFTWARE,
+    SOF_TIMESTAMPING_SOFTWARE, SOL_SOCKET, SO_RXQ_OVFL, SO_TIMESTAMP, SO_TIMESTAMPING,
+    SO_TIMESTAMPNS, SO_TXTIME,
+};
 use socket2::SockAddr;
 use std::{
+    collections::{HashMap, HashSet},
     fmt,
+    fs::File,
     io::{Read, Write},
-    mem::{size_of, size_of_val},
+    mem::{self, size_of, size_of_val},
+    net::Shutdown,
     os::{
-        raw::{c_int, c_void},
+        raw::{c_int, c_uint, c_void},
         unix::io::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd},
     },
+    path::Path,
     ptr,
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(feature = "stats")]
+use std::sync::atomic::AtomicU64;
+
 pub use libc::{
     CANFD_MTU, CAN_MTU, CAN_RAW, CAN_RAW_ERR_FILTER, CAN_RAW_FD_FRAMES, CAN_RAW_FILTER,
-    CAN_RAW_JOIN_FILTERS, CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS, SOL_CAN_BASE, SOL_CAN_RAW,
+    CAN_RAW_FILTER_MAX, CAN_RAW_JOIN_FILTERS, CAN_RAW_LOOPBACK, CAN_RAW_RECV_OWN_MSGS,
+    CAN_RAW_XL_FRAMES, SOL_CAN_BASE, SOL_CAN_RAW,
 };
 
 // TODO: This can be removed on the next major version update
@@ -83,16 +105,465 @@ impl<E: fmt::Debug> ShouldRetry for IoResult<E> {
 
 // ===== Private local helper functions =====
 
-/// Tries to open the CAN socket by the interface number.
-fn raw_open_socket(addr: &CanAddr) -> IoResult<socket2::Socket> {
+/// Creates a CAN socket for the protocol that is not yet bound to an
+/// interface.
+///
+/// Raw sockets are of type `SOCK_RAW`, while the other CAN protocols (BCM,
+/// ISO-TP, J1939) use `SOCK_DGRAM`.
+pub(crate) fn new_socket(protocol: c_int) -> IoResult<socket2::Socket> {
     let af_can = socket2::Domain::from(AF_CAN);
-    let can_raw = socket2::Protocol::from(CAN_RAW);
+    let sock_type = match protocol {
+        CAN_RAW => socket2::Type::RAW,
+        _ => socket2::Type::DGRAM,
+    };
+    socket2::Socket::new_raw(af_can, sock_type, Some(socket2::Protocol::from(protocol)))
+}
 
-    let sock = socket2::Socket::new_raw(af_can, socket2::Type::RAW, Some(can_raw))?;
-    sock.bind(&SockAddr::from(*addr))?;
+/// Tries to open a CAN socket for the protocol on the address.
+///
+/// The broadcast manager (BCM) is "connected" to the interface, while all
+/// the other protocols are bound to it.
+pub(crate) fn open_socket(addr: &CanAddr, protocol: c_int) -> IoResult<socket2::Socket> {
+    let sock = new_socket(protocol)?;
+    let addr = SockAddr::from(*addr);
+    match protocol {
+        CAN_BCM => sock.connect(&addr)?,
+        _ => sock.bind(&addr)?,
+    }
     Ok(sock)
 }
 
+/// Determines if the error is from opening an FD socket on a kernel that
+/// has no FD support.
+fn is_fd_not_supported(err: &IoError) -> bool {
+    err.get_ref()
+        .and_then(|err| err.downcast_ref::<SocketError>())
+        .is_some_and(|err| *err == SocketError::FdNotSupported)
+}
+
+/// Waits, with `ppoll()`, for up to the timeout for the socket to become
+/// readable. This returns false if the timeout elapsed first.
+fn ppoll_readable(fd: RawFd, timeout: Duration) -> IoResult<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ts = libc::timespec {
+        tv_sec: timeout.as_secs().min(i32::MAX as u64) as _,
+        tv_nsec: timeout.subsec_nanos() as _,
+    };
+
+    match unsafe { libc::ppoll(&mut pollfd, 1, &ts, ptr::null()) } {
+        n if n < 0 => Err(IoError::last_os_error()),
+        n => Ok(n > 0),
+    }
+}
+
+/// Creates a raw CAN socket that is not yet bound to an interface.
+fn raw_socket() -> IoResult<socket2::Socket> {
+    new_socket(CAN_RAW)
+}
+
+/// Tries to open the raw CAN socket by the interface number.
+fn raw_open_socket(addr: &CanAddr) -> IoResult<socket2::Socket> {
+    open_socket(addr, CAN_RAW)
+}
+
+/// Keeps calling the `open` function while it fails because the interface
+/// doesn't exist, every `interval`, until the `timeout` elapses.
+fn retry_open<T, F>(timeout: Duration, interval: Duration, mut open: F) -> IoResult<T>
+where
+    F: FnMut() -> IoResult<T>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        match open() {
+            Err(err) if is_interface_gone(&err) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(err);
+                }
+                thread::sleep(interval.min(deadline - now));
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Moves the calling thread into the network namespace at `netns`.
+fn set_netns(netns: &File) -> IoResult<()> {
+    match unsafe { libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) } {
+        0 => Ok(()),
+        _ => Err(IoError::last_os_error()),
+    }
+}
+
+/// Runs the function with the calling thread in the network namespace at
+/// the path given, such as `/var/run/netns/<name>`.
+///
+/// A socket belongs to the namespace in which it was created, and stays
+/// there after the thread leaves it. The original namespace of the thread
+/// is restored whether or not the function succeeds, and a failure to
+/// restore it is reported as an error.
+fn in_netns<T, F>(netns: &Path, f: F) -> IoResult<T>
+where
+    F: FnOnce() -> IoResult<T>,
+{
+    let orig = File::open("/proc/thread-self/ns/net")?;
+    let target = File::open(netns)?;
+
+    set_netns(&target)?;
+    let res = f();
+    set_netns(&orig)?;
+    res
+}
+
+/// Space for the ancillary (control) data received along with a frame.
+///
+/// This is aligned for the `cmsghdr` structs that the kernel places in it.
+#[repr(C, align(8))]
+struct CmsgBuffer([u8; 256]);
+
+/// The information gathered from a single `recvmsg()` call on a socket.
+#[derive(Debug, Default)]
+struct RecvInfo {
+    /// The number of bytes received
+    len: usize,
+    /// The address of the interface that received the frame.
+    addr: CanAddr,
+    /// The flags returned in the message header.
+    flags: c_int,
+    /// The time the frame was received, if reported by the kernel.
+    timestamp: Option<SystemTime>,
+    /// The count of frames dropped by the socket, if reported by the kernel.
+    drops: Option<u32>,
+}
+
+/// Converts a kernel `timespec` into a `SystemTime`.
+///
+/// A zero value means that the timestamp was not provided, and gives `None`.
+fn system_time_from_timespec(ts: libc::timespec) -> Option<SystemTime> {
+    if ts.tv_sec == 0 && ts.tv_nsec == 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// The result of a raw `recvmsg()` call on a socket.
+///
+/// This is returned by the `recvmsg()` functions of the sockets.
+#[derive(Debug, Clone, Copy)]
+pub struct RecvMsgResult {
+    /// The number of bytes received into the data buffer.
+    pub len: usize,
+    /// The flags returned in the message header, such as `MSG_DONTROUTE`
+    /// for a frame that was looped back by the local host, or
+    /// `MSG_CTRUNC` if the control buffer was too small.
+    pub flags: c_int,
+    /// The address of the interface that received the message.
+    pub addr: CanAddr,
+    /// The number of bytes of control messages placed into the control
+    /// buffer.
+    pub cmsg_len: usize,
+}
+
+/// Receives a message into the buffers with `recvmsg()`, leaving any
+/// ancillary data in the control buffer unparsed.
+pub(crate) fn recvmsg_raw(
+    fd: RawFd,
+    buf: &mut [u8],
+    cmsg_buf: &mut [u8],
+    flags: c_int,
+) -> IoResult<RecvMsgResult> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut addr: libc::sockaddr_can = unsafe { mem::zeroed() };
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = (&mut addr as *mut libc::sockaddr_can).cast();
+    msg.msg_namelen = size_of::<libc::sockaddr_can>() as socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if !cmsg_buf.is_empty() {
+        msg.msg_control = cmsg_buf.as_mut_ptr().cast();
+        msg.msg_controllen = cmsg_buf.len() as _;
+    }
+
+    let n = unsafe { libc::recvmsg(fd, &mut msg, flags) };
+    if n < 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(RecvMsgResult {
+        len: n as usize,
+        flags: msg.msg_flags,
+        addr: CanAddr::from(addr),
+        cmsg_len: msg.msg_controllen as usize,
+    })
+}
+
+/// Receives a single frame into the buffer, failing if it didn't fit.
+///
+/// A frame that was cut short is flagged by the kernel with `MSG_TRUNC`,
+/// so it can't be mistaken for a smaller one. It's reported as an
+/// `InvalidData` error wrapping [`SocketError::Truncated`].
+pub(crate) fn recv_checked(fd: RawFd, buf: &mut [u8]) -> IoResult<usize> {
+    let res = recvmsg_raw(fd, buf, &mut [], MSG_TRUNC)?;
+    check_truncated(&res, buf.len())?;
+    Ok(res.len)
+}
+
+/// Checks whether a message received into a buffer of `buf_len` bytes,
+/// with `MSG_TRUNC` in the flags, was truncated.
+///
+/// Only some protocols, like ISO-TP, honour `MSG_TRUNC` on input and
+/// return the full size of the message. The others, like raw CAN, J1939,
+/// and BCM, flag the truncation but return the truncated length, so the
+/// real size isn't known.
+pub(crate) fn check_truncated(res: &RecvMsgResult, buf_len: usize) -> IoResult<()> {
+    if res.flags & MSG_TRUNC == 0 {
+        return Ok(());
+    }
+    Err(SocketError::Truncated {
+        received: buf_len,
+        actual: Some(res.len).filter(|&n| n > buf_len),
+    }
+    .into())
+}
+
+/// Receives a single frame into the buffer with `recvmsg()`, collecting
+/// any ancillary data that the kernel sends along with it.
+fn recv_msg(fd: RawFd, buf: &mut [u8], flags: c_int) -> IoResult<RecvInfo> {
+    let mut cmsg_buf = CmsgBuffer([0; 256]);
+    let res = recvmsg_raw(fd, buf, &mut cmsg_buf.0, flags)?;
+
+    // A header over just the control messages, for the CMSG macros
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_control = cmsg_buf.0.as_mut_ptr().cast();
+    msg.msg_controllen = res.cmsg_len as _;
+
+    // SO_TIMESTAMPING is preferred if the legacy timestamps also came in
+    let mut timestamping = None;
+    let mut legacy = None;
+    let mut drops = None;
+
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while let Some(hdr) = unsafe { cmsg.as_ref() } {
+        let data = unsafe { libc::CMSG_DATA(cmsg) };
+
+        if hdr.cmsg_level == SOL_SOCKET {
+            match hdr.cmsg_type {
+                // [software, deprecated, raw hardware]
+                SCM_TIMESTAMPING => {
+                    let ts: [libc::timespec; 3] = unsafe { ptr::read_unaligned(data.cast()) };
+                    timestamping =
+                        system_time_from_timespec(ts[2]).or(system_time_from_timespec(ts[0]));
+                }
+                SCM_TIMESTAMPNS => {
+                    let ts: libc::timespec = unsafe { ptr::read_unaligned(data.cast()) };
+                    legacy = system_time_from_timespec(ts);
+                }
+                SCM_TIMESTAMP => {
+                    let tv: libc::timeval = unsafe { ptr::read_unaligned(data.cast()) };
+                    legacy = system_time_from_timespec(libc::timespec {
+                        tv_sec: tv.tv_sec,
+                        tv_nsec: 1000 * tv.tv_usec as libc::c_long,
+                    });
+                }
+                SO_RXQ_OVFL => {
+                    drops = Some(unsafe { ptr::read_unaligned(data.cast::<u32>()) });
+                }
+                _ => (),
+            }
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok(RecvInfo {
+        len: res.len,
+        addr: res.addr,
+        flags: res.flags,
+        timestamp: timestamping.or(legacy),
+        drops,
+    })
+}
+
+/// Reads the current time on the clock, in nanoseconds.
+fn clock_now_ns(clockid: libc::clockid_t) -> IoResult<i128> {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    if unsafe { libc::clock_gettime(clockid, &mut ts) } < 0 {
+        return Err(IoError::last_os_error());
+    }
+    Ok(i128::from(ts.tv_sec) * 1_000_000_000 + i128::from(ts.tv_nsec))
+}
+
+/// Checks that the interface a socket is bound to has an ETF qdisc, which
+/// is what holds frames sent with `SO_TXTIME` until their transmit time.
+#[cfg(feature = "netlink")]
+fn check_etf_qdisc<S: Socket>(sock: &S) -> IoResult<()> {
+    let ifindex = sock.local_addr()?.ifindex();
+    if ifindex == 0 {
+        return Err(SocketError::NoEtfQdisc.into());
+    }
+    let kinds = crate::CanInterface::open_iface(ifindex as u32)
+        .qdisc_kinds()
+        .map_err(|err| IoError::new(IoErrorKind::Other, err.to_string()))?;
+    if !kinds.iter().any(|kind| kind == "etf") {
+        return Err(SocketError::NoEtfQdisc.into());
+    }
+    Ok(())
+}
+
+/// Without netlink there's no way to look up the qdiscs, so scheduled
+/// transmission is refused, rather than risk sending frames immediately.
+#[cfg(not(feature = "netlink"))]
+fn check_etf_qdisc<S: Socket>(_sock: &S) -> IoResult<()> {
+    Err(IoError::new(
+        IoErrorKind::Unsupported,
+        "checking for an ETF qdisc needs the netlink feature",
+    ))
+}
+
+/// Converts a system (wall clock) time into nanoseconds on another clock,
+/// such as the `CLOCK_TAI` normally used for scheduled transmission.
+///
+/// The offset between the clocks is measured at the time of the call.
+fn system_time_to_clock_ns(time: SystemTime, clockid: libc::clockid_t) -> IoResult<u64> {
+    let ns = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| IoError::new(IoErrorKind::InvalidInput, "time is before the epoch"))?
+        .as_nanos() as i128;
+
+    let offset = match clockid {
+        libc::CLOCK_REALTIME => 0,
+        _ => clock_now_ns(clockid)? - clock_now_ns(libc::CLOCK_REALTIME)?,
+    };
+
+    u64::try_from(ns + offset)
+        .map_err(|_| IoError::new(IoErrorKind::InvalidInput, "time is out of range"))
+}
+
+/// Sends a frame with `sendmsg()`, along with the time at which it should
+/// be transmitted, in nanoseconds on the socket's `SO_TXTIME` clock.
+fn send_at(fd: RawFd, bytes: &[u8], txtime: u64) -> IoResult<()> {
+    let mut iov = libc::iovec {
+        iov_base: bytes.as_ptr() as *mut c_void,
+        iov_len: bytes.len(),
+    };
+    let mut cmsg_buf = CmsgBuffer([0; 256]);
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.0.as_mut_ptr().cast();
+    msg.msg_controllen = unsafe { libc::CMSG_SPACE(size_of::<u64>() as c_uint) } as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = SOL_SOCKET;
+        (*cmsg).cmsg_type = SCM_TXTIME;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<u64>() as c_uint) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<u64>(), txtime);
+    }
+
+    match unsafe { libc::sendmsg(fd, &msg, 0) } {
+        n if n < 0 => Err(IoError::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Converts the bytes read into an FD frame buffer into either type of frame.
+fn any_frame_from_buf(fdframe: libc::canfd_frame, n: usize) -> IoResult<CanAnyFrame> {
+    match n {
+        // If we only get 'can_frame' number of bytes, then the return is,
+        // by definition, a can_frame, so we just copy the bytes into the
+        // proper type.
+        CAN_MTU => {
+            let mut frame = can_frame_default();
+            as_bytes_mut(&mut frame)[..CAN_MTU].copy_from_slice(&as_bytes(&fdframe)[..CAN_MTU]);
+            Ok(CanFrame::from(frame).into())
+        }
+        CANFD_MTU => Ok(CanFdFrame::from(fdframe).into()),
+        _ => Err(IoErrorKind::InvalidData.into()),
+    }
+}
+
+/// Converts the bytes read into an XL frame buffer into a frame of any tier.
+///
+/// An XL frame can be the same size as a classic or FD frame, so it's
+/// recognized by its XLF flag. That lies in the length byte of the other
+/// two, where it can never be set.
+fn mixed_frame_from_buf(xlframe: &libc::canxl_frame, n: usize) -> IoResult<CanMixedFrame> {
+    let bytes = &as_bytes(xlframe)[..n];
+
+    if n >= CANXL_HDR_SIZE && bytes[4] & CANXL_XLF as u8 != 0 {
+        if n != CANXL_HDR_SIZE + xlframe.len as usize {
+            return Err(IoErrorKind::InvalidData.into());
+        }
+        return CanXlFrame::try_from(*xlframe)
+            .map(CanMixedFrame::from)
+            .map_err(|err| IoError::new(IoErrorKind::InvalidData, err));
+    }
+
+    match n {
+        CAN_MTU => {
+            let mut frame = can_frame_default();
+            as_bytes_mut(&mut frame).copy_from_slice(bytes);
+            Ok(CanFrame::from(frame).into())
+        }
+        CANFD_MTU => {
+            let mut frame = canfd_frame_default();
+            as_bytes_mut(&mut frame).copy_from_slice(bytes);
+            Ok(CanFdFrame::from(frame).into())
+        }
+        _ => Err(IoErrorKind::InvalidData.into()),
+    }
+}
+
+/// Checks that the socket can send the type of frame given.
+///
+/// Classic frames can be sent on any socket, but FD frames require that
+/// FD mode is enabled on it.
+fn check_frame_kind<S: SocketOptions>(sock: &S, frame: &CanAnyFrame) -> IoResult<()> {
+    match frame {
+        CanAnyFrame::Fd(_) if !sock.fd_frames_enabled()? => {
+            Err(SocketError::FdFramesDisabled.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates the data length in the bytes of a frame about to be sent.
+///
+/// A classic frame can hold up to 8 bytes, and an FD frame can only hold
+/// one of the discrete FD lengths, up to 64 bytes. The length is the byte
+/// immediately after the 32-bit ID word in both `can_frame` and
+/// `canfd_frame`. In an XL frame, that byte holds the flags, with the XLF
+/// flag set, and is followed by the SDU type and a 16-bit length. Only
+/// the header and the data in use are sent for an XL frame.
+fn check_frame_len(bytes: &[u8]) -> IoResult<()> {
+    let valid = match bytes.len() {
+        n if n >= CANXL_HDR_SIZE && bytes[4] & CANXL_XLF as u8 != 0 => {
+            let len = u16::from_ne_bytes([bytes[6], bytes[7]]) as usize;
+            CanXlFrame::is_valid_data_len(len) && n == CANXL_HDR_SIZE + len
+        }
+        CAN_MTU => bytes[4] as usize <= CAN_MAX_DLEN,
+        CANFD_MTU => CanFdFrame::is_valid_data_len(bytes[4] as usize),
+        _ => true,
+    };
+
+    match valid {
+        true => Ok(()),
+        false => Err(IoError::new(
+            IoErrorKind::InvalidInput,
+            ConstructionError::TooMuchData,
+        )),
+    }
+}
+
 /// `setsockopt` wrapper
 ///
 /// The libc `setsockopt` function is set to set various options on a socket.
@@ -178,9 +649,42 @@ pub trait Socket: AsRawFd {
         Self::open_addr(&addr)
     }
 
+    /// Open a named CAN device, retrying until the interface appears.
+    ///
+    /// This is useful at boot, when an application may start before the
+    /// driver has created the interface. While the interface doesn't exist,
+    /// the open is retried every `interval` until the `timeout` elapses,
+    /// after which the last error is returned. Any other error, such as a
+    /// permission problem, is returned immediately.
+    fn open_retry(ifname: &str, timeout: Duration, interval: Duration) -> IoResult<Self>
+    where
+        Self: Sized,
+    {
+        retry_open(timeout, interval, || Self::open(ifname))
+    }
+
+    /// Open a named CAN device in another network namespace.
+    ///
+    /// The `netns` is the path to the namespace, such as
+    /// `/var/run/netns/<name>` for one created by `ip netns add`. The
+    /// calling thread enters the namespace to find the interface and open
+    /// the socket, and is then returned to its original namespace, even if
+    /// the open fails. The socket remains bound to the interface in the
+    /// other namespace.
+    ///
+    /// This normally requires the `CAP_SYS_ADMIN` capability.
+    fn open_in_netns(ifname: &str, netns: &Path) -> IoResult<Self>
+    where
+        Self: Sized,
+    {
+        in_netns(netns, || Self::open(ifname))
+    }
+
     /// Open CAN device by interface number.
     ///
-    /// Opens a CAN device by kernel interface number.
+    /// Opens a CAN device by kernel interface number. An index of zero
+    /// binds to all interfaces. See [`CanAddr::from_ifindex()`] to open
+    /// by an index that was already looked up.
     fn open_iface(ifindex: u32) -> IoResult<Self>
     where
         Self: Sized,
@@ -194,6 +698,17 @@ pub trait Socket: AsRawFd {
     where
         Self: Sized;
 
+    /// Gets the address to which the socket is bound.
+    ///
+    /// This is useful to find the interface for a socket that was opened
+    /// elsewhere, such as one inherited from a parent process. The name of
+    /// the interface can then be found with [`CanAddr::iface_name()`].
+    /// For a socket bound to all interfaces, this is the "any" address.
+    fn local_addr(&self) -> IoResult<CanAddr> {
+        let addr = self.as_raw_socket().local_addr()?;
+        CanAddr::try_from(&addr)
+    }
+
     /// Gets a shared reference to the underlying socket object
     fn as_raw_socket(&self) -> &socket2::Socket;
 
@@ -251,6 +766,43 @@ pub trait Socket: AsRawFd {
         self.as_raw_socket().set_write_timeout(duration.into())
     }
 
+    /// Gets the size of the socket's send buffer, in bytes.
+    fn send_buffer_size(&self) -> IoResult<usize> {
+        self.as_raw_socket().send_buffer_size()
+    }
+
+    /// Sets the size of the socket's send buffer (`SO_SNDBUF`).
+    ///
+    /// The kernel doubles the requested value to leave room for its own
+    /// bookkeeping, and limits it by `net.core.wmem_max`. A smaller buffer
+    /// limits the number of frames that can be queued ahead of the
+    /// driver's TX queue.
+    fn set_send_buffer_size(&self, size: usize) -> IoResult<()> {
+        self.as_raw_socket().set_send_buffer_size(size)
+    }
+
+    /// Waits for the socket to become writable.
+    ///
+    /// This polls the socket for `POLLOUT`, returning `true` if it became
+    /// writable before the timeout elapsed, or `false` if it timed out.
+    /// A timeout of `None` waits indefinitely. This can be used to pace
+    /// transmissions when the TX queue is full, rather than busy-looping
+    /// on `ENOBUFS`.
+    fn wait_writable(&self, timeout: Option<Duration>) -> IoResult<bool> {
+        use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+        let pollfd = PollFd::new(
+            unsafe { BorrowedFd::borrow_raw(self.as_raw_fd()) },
+            PollFlags::POLLOUT,
+        );
+
+        let timeout = match timeout {
+            Some(timeout) => timeout.try_into().unwrap_or(PollTimeout::MAX),
+            None => PollTimeout::NONE,
+        };
+
+        Ok(poll(&mut [pollfd], timeout)? != 0)
+    }
+
     /// Blocking read a single can frame.
     fn read_frame(&self) -> IoResult<Self::FrameType>;
 
@@ -280,10 +832,26 @@ pub trait Socket: AsRawFd {
     // fn write_frame(&self, frame: &Self::FrameType) -> IoResult<()>;
 
     /// Writes a normal CAN 2.0 frame to the socket.
+    ///
+    /// The frame's data length is checked before it is sent, and an
+    /// `InvalidInput` error is returned if it is not valid for the type of
+    /// frame.
     fn write_frame<F>(&self, frame: &F) -> IoResult<()>
     where
         F: Into<Self::FrameType> + AsPtr;
 
+    /// Writes a frame to the socket without validating it first.
+    ///
+    /// This skips the data length check done by `write_frame()`, for
+    /// performance-sensitive callers that have already validated their
+    /// frames. An invalid frame is passed to the kernel as-is.
+    fn write_frame_unchecked<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<Self::FrameType> + AsPtr,
+    {
+        self.as_raw_socket().write_all(frame.as_bytes())
+    }
+
     /// Blocking write a single can frame, retrying until it gets sent
     /// successfully.
     fn write_frame_insist<F>(&self, frame: &F) -> IoResult<()>
@@ -298,6 +866,92 @@ pub trait Socket: AsRawFd {
             }
         }
     }
+
+    /// Splits the socket into owned read and write halves.
+    ///
+    /// The halves share the socket, and can be moved to different
+    /// threads, so that one can receive while the other sends, without
+    /// wrapping the socket in a mutex. The options that the socket had
+    /// stay in effect for both.
+    fn into_split(self) -> (CanReadHalf<Self>, CanWriteHalf<Self>)
+    where
+        Self: Sized,
+    {
+        let sock = Arc::new(self);
+        (CanReadHalf(Arc::clone(&sock)), CanWriteHalf(sock))
+    }
+}
+
+// ===== Frame metadata =====
+
+/// Information about a received frame, beyond the frame itself.
+///
+/// This is returned by the `read_frame_meta()` functions of the sockets,
+/// from a single `recvmsg()` call. Some of the fields are only filled in
+/// if the corresponding socket option is enabled, and are `None`
+/// otherwise.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameMeta {
+    /// The time the frame was received. This requires timestamps to be
+    /// enabled with [`SocketOptions::enable_timestamps`] or
+    /// [`SocketOptions::set_timestamp_mode`].
+    pub timestamp: Option<SystemTime>,
+    /// The index of the interface that received the frame.
+    pub ifindex: c_int,
+    /// The number of frames that were dropped by the socket because its
+    /// receive queue was full. This is a running total since the socket
+    /// was opened, and requires [`SocketOptions::set_rxq_overflow`]. The
+    /// kernel only reports it once something has been dropped.
+    pub drops: Option<u32>,
+    /// The frame was looped back by the local host, rather than received
+    /// from the bus. This is reported for frames sent by any socket on the
+    /// host when loopback is enabled (the default). The kernel sets the
+    /// `MSG_DONTROUTE` flag for these.
+    pub loopback: bool,
+    /// The frame was sent by this very socket. This is only seen if
+    /// receiving our own messages is enabled with
+    /// [`SocketOptions::set_recv_own_msgs`]. The kernel sets the
+    /// `MSG_CONFIRM` flag for these.
+    pub own: bool,
+}
+
+impl FrameMeta {
+    /// Creates the metadata from the flags returned by `recvmsg()`.
+    fn from_info(info: &RecvInfo) -> Self {
+        Self {
+            timestamp: info.timestamp,
+            ifindex: info.addr.ifindex(),
+            drops: info.drops,
+            loopback: info.flags & MSG_DONTROUTE != 0,
+            own: info.flags & MSG_CONFIRM != 0,
+        }
+    }
+
+    /// Determines if the frame came from another node on the bus.
+    pub fn is_from_bus(&self) -> bool {
+        !self.loopback
+    }
+}
+
+// ===== Timestamps =====
+
+/// The method used to timestamp the frames received on a socket.
+///
+/// The preferred mechanism is `SO_TIMESTAMPING`, which reports nanosecond
+/// software or hardware timestamps. Some older kernels and drivers only
+/// support the legacy `SO_TIMESTAMP` option, which reports a microsecond
+/// timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimestampMode {
+    /// Timestamping is disabled.
+    Disabled,
+    /// Software receive timestamps, using `SO_TIMESTAMPING`.
+    Software,
+    /// Hardware receive timestamps, using `SO_TIMESTAMPING`.
+    /// Frames without a hardware timestamp report the software one.
+    Hardware,
+    /// Legacy microsecond timestamps, using `SO_TIMESTAMP`.
+    Legacy,
 }
 
 /// Traits for setting CAN socket options.
@@ -361,6 +1015,39 @@ pub trait SocketOptions: AsRawFd {
         }
     }
 
+    /// Gets an option from the socket.
+    ///
+    /// This is the counterpart to `set_socket_option()`, and the same care
+    /// must be taken to use the type that the kernel expects for the
+    /// option. An `InvalidData` error is returned if the kernel reports a
+    /// value of a different size.
+    ///
+    /// Example use:
+    ///
+    /// ```text
+    /// let loopback: c_int = sock.get_socket_option(SOL_CAN_RAW, CAN_RAW_LOOPBACK)?;
+    /// ```
+    fn get_socket_option<T: Copy>(&self, level: c_int, name: c_int) -> IoResult<T> {
+        let mut val = mem::MaybeUninit::<T>::zeroed();
+        let mut len = size_of::<T>() as socklen_t;
+
+        let ret = unsafe {
+            libc::getsockopt(
+                self.as_raw_fd(),
+                level,
+                name,
+                val.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+
+        match ret {
+            0 if len as usize == size_of::<T>() => Ok(unsafe { val.assume_init() }),
+            0 => Err(IoErrorKind::InvalidData.into()),
+            _ => Err(IoError::last_os_error()),
+        }
+    }
+
     /// Sets CAN ID filters on the socket.
     ///
     /// CAN packages received by SocketCAN are matched against these filters,
@@ -368,14 +1055,75 @@ pub trait SocketOptions: AsRawFd {
     ///
     /// See `CanFilter` for details on how filtering works. By default, all
     /// single filter matching all incoming frames is installed.
+    ///
+    /// The kernel accepts up to `CAN_RAW_FILTER_MAX` (512) filters on a
+    /// socket. Passing more than that fails with an `InvalidInput` error
+    /// wrapping [`SocketError::TooManyFilters`], before any call is made to
+    /// the kernel. Note that the practical limit may be lower on some
+    /// kernels, which then report a plain `EINVAL`.
+    ///
+    /// The filters are installed with a single `CAN_RAW_FILTER` option,
+    /// which the kernel swaps for the old ones atomically: it registers the
+    /// new filters before removing the old ones, so while the change is
+    /// being made a frame may pass either set, but there is never a window
+    /// in which the socket is left unfiltered.
     fn set_filters<F>(&self, filters: &[F]) -> IoResult<()>
     where
         F: Into<CanFilter> + Copy,
     {
+        let max = CAN_RAW_FILTER_MAX as usize;
+        if filters.len() > max {
+            return Err(SocketError::TooManyFilters {
+                got: filters.len(),
+                max,
+            }
+            .into());
+        }
+
         let filters: Vec<CanFilter> = filters.iter().map(|f| (*f).into()).collect();
         self.set_socket_option_mult(SOL_CAN_RAW, CAN_RAW_FILTER, &filters)
     }
 
+    /// Atomically replaces the CAN ID filters on the socket.
+    ///
+    /// This is the same as `set_filters()`, which already makes the change
+    /// in a single call to the kernel, but makes the intent clear where the
+    /// filters of a live socket are reconfigured. There is no need to clear
+    /// the old filters first; doing so would briefly accept every frame, or
+    /// drop them all. Passing an empty set of filters drops all frames.
+    fn replace_filters<F>(&self, new: &[F]) -> IoResult<()>
+    where
+        F: Into<CanFilter> + Copy,
+    {
+        self.set_filters(new)
+    }
+
+    /// Sets CAN ID filters on the socket from an iterator.
+    ///
+    /// This is like `set_filters()`, but any duplicate filters are removed,
+    /// keeping the order of the first of each, so that they don't use up
+    /// the kernel's limited filter slots. This helps when the filters are
+    /// assembled from several sources that may overlap. On success, it
+    /// returns the number of filters that were installed.
+    ///
+    /// Note that, as with `set_filters()`, an empty set of filters drops
+    /// all frames.
+    fn set_filters_from<I>(&self, filters: I) -> IoResult<usize>
+    where
+        I: IntoIterator,
+        I::Item: Into<CanFilter>,
+    {
+        let mut seen = HashSet::new();
+        let filters: Vec<CanFilter> = filters
+            .into_iter()
+            .map(Into::into)
+            .filter(|f| seen.insert(*f))
+            .collect();
+
+        self.set_filters(&filters)?;
+        Ok(filters.len())
+    }
+
     /// Disable reception of CAN frames.
     ///
     /// Sets a completely empty filter; disabling all CAN frame reception.
@@ -393,6 +1141,18 @@ pub trait SocketOptions: AsRawFd {
         self.set_filters(&[(0, 0)])
     }
 
+    /// Subscribes the socket to all classes of error frames.
+    ///
+    /// This sets the error filter to accept every error condition, after
+    /// which any error frames received are returned from the socket's reads
+    /// decoded as the error variant of the frame type, i.e.
+    /// `CanFrame::Error` for a `CanSocket` or `CanAnyFrame::Error` for a
+    /// `CanFdSocket`. These can be converted to a `CanError` to determine
+    /// the specific problem.
+    fn enable_error_frames(&self) -> IoResult<()> {
+        self.set_error_filter_accept_all()
+    }
+
     /// Sets the error mask on the socket.
     ///
     /// By default (`ERR_MASK_NONE`) no error conditions are reported as
@@ -403,6 +1163,22 @@ pub trait SocketOptions: AsRawFd {
         self.set_socket_option(SOL_CAN_RAW, CAN_RAW_ERR_FILTER, &mask)
     }
 
+    /// Sets the classes of errors that are reported to the socket as error
+    /// frames.
+    ///
+    /// This is the same as `set_error_filter()`, but takes a typed mask,
+    /// which can have any combination of the error classes.
+    fn set_error_filter_mask(&self, mask: CanErrorMask) -> IoResult<()> {
+        self.set_error_filter(mask.bits())
+    }
+
+    /// Gets the classes of errors that are currently reported to the
+    /// socket as error frames.
+    fn error_filter_mask(&self) -> IoResult<CanErrorMask> {
+        let mask: u32 = self.get_socket_option(SOL_CAN_RAW, CAN_RAW_ERR_FILTER)?;
+        Ok(CanErrorMask::from_bits_retain(mask))
+    }
+
     /// Sets the error mask on the socket to reject all errors.
     #[inline(always)]
     fn set_error_filter_drop_all(&self) -> IoResult<()> {
@@ -444,6 +1220,74 @@ pub trait SocketOptions: AsRawFd {
         self.set_socket_option(SOL_CAN_RAW, CAN_RAW_RECV_OWN_MSGS, &recv_own_msgs)
     }
 
+    /// Enable or disable reporting of the receive queue drop count.
+    ///
+    /// When enabled, the kernel sends the number of frames dropped by the
+    /// socket, because its receive queue was full, along with each frame.
+    /// This is reported in the [`FrameMeta`] from `read_frame_meta()`.
+    fn set_rxq_overflow(&self, enabled: bool) -> IoResult<()> {
+        let rxq_ovfl = c_int::from(enabled);
+        self.set_socket_option(SOL_SOCKET, SO_RXQ_OVFL, &rxq_ovfl)
+    }
+
+    /// Determines if the socket is able to send and receive FD frames.
+    ///
+    /// This is always true for a `CanFdSocket`, unless it was turned off
+    /// manually, and false for a `CanSocket`.
+    fn fd_frames_enabled(&self) -> IoResult<bool> {
+        let enabled: c_int = self.get_socket_option(SOL_CAN_RAW, CAN_RAW_FD_FRAMES)?;
+        Ok(enabled != 0)
+    }
+
+    /// Enable or disable CAN XL frames on the socket.
+    ///
+    /// As a policy of this crate, rather than the kernel, XL frames are
+    /// only enabled on a socket that also has FD frames enabled, so that it
+    /// gets all three kinds of frame, as read by
+    /// `CanFdSocket::read_any_frame()`. Enabling them on one that doesn't,
+    /// like a `CanSocket`, fails with an `InvalidInput` error wrapping
+    /// [`SocketError::XlRequiresFd`]. XL frames are supported by kernels
+    /// since Linux 6.2.
+    fn set_xl_frames(&self, enabled: bool) -> IoResult<()> {
+        if enabled && !self.fd_frames_enabled()? {
+            return Err(SocketError::XlRequiresFd.into());
+        }
+        let xl_frames = c_int::from(enabled);
+        self.set_socket_option(SOL_CAN_RAW, CAN_RAW_XL_FRAMES, &xl_frames)
+    }
+
+    /// Determines if XL frames are enabled on the socket.
+    fn xl_frames_enabled(&self) -> IoResult<bool> {
+        let enabled: c_int = self.get_socket_option(SOL_CAN_RAW, CAN_RAW_XL_FRAMES)?;
+        Ok(enabled != 0)
+    }
+
+    /// Enables CAN XL frames on the socket.
+    ///
+    /// This is the same as `set_xl_frames(true)`, so it requires FD frames
+    /// to be enabled as well.
+    fn enable_xl_frames(&self) -> IoResult<()> {
+        self.set_xl_frames(true)
+    }
+
+    /// Sets how the socket handles the virtual CAN network IDs (VCIDs) of
+    /// XL frames.
+    ///
+    /// By default, the kernel clears the VCID of the frames that are sent
+    /// and received. These options let a socket send with a VCID, and
+    /// receive the frames of some virtual networks, so that several can
+    /// share one physical XL bus. This is supported by kernels since Linux
+    /// 6.9.
+    fn set_xl_vcid_options(&self, opts: &XlVcidOptions) -> IoResult<()> {
+        self.set_socket_option(SOL_CAN_RAW, CAN_RAW_XL_VCID_OPTS, &opts.0)
+    }
+
+    /// Gets the VCID options that are set on the socket.
+    fn xl_vcid_options(&self) -> IoResult<XlVcidOptions> {
+        let opts = self.get_socket_option(SOL_CAN_RAW, CAN_RAW_XL_VCID_OPTS)?;
+        Ok(XlVcidOptions(opts))
+    }
+
     /// Enable or disable join filters.
     ///
     /// By default a frame is accepted if it matches any of the filters set
@@ -453,58 +1297,801 @@ pub trait SocketOptions: AsRawFd {
         let join_filters = c_int::from(enabled);
         self.set_socket_option(SOL_CAN_RAW, CAN_RAW_JOIN_FILTERS, &join_filters)
     }
-}
-
-// TODO: We need to restore this, but preferably with TIMESTAMPING
 
-/*
-impl CanSocket {
-
-    /// Blocking read a single can frame with timestamp
+    /// Sets the method used to timestamp received frames.
     ///
-    /// Note that reading a frame and retrieving the timestamp requires two
-    /// consecutive syscalls. To avoid race conditions, exclusive access
-    /// to the socket is enforce through requiring a `mut &self`.
-    pub fn read_frame_with_timestamp(&mut self) -> IoResult<(CanFrame, time::SystemTime)> {
-        let frame = self.read_frame()?;
-
-        let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
-        let ret = unsafe {
-            libc::ioctl(self.fd, SIOCGSTAMPNS as c_ulong, &mut ts as *mut timespec)
+    /// The timestamps can then be retrieved with `read_frame_with_timestamp()`.
+    /// Any of the other timestamp options that were on, including
+    /// `SO_TIMESTAMPNS`, are turned off.
+    fn set_timestamp_mode(&self, mode: TimestampMode) -> IoResult<()> {
+        use TimestampMode::*;
+
+        const SOFTWARE: c_uint = SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE;
+        const HARDWARE: c_uint = SOF_TIMESTAMPING_RX_HARDWARE | SOF_TIMESTAMPING_RAW_HARDWARE;
+
+        // Clearing SO_TIMESTAMPING fails if the kernel doesn't support it,
+        // but then it can't be on. Turning off SO_TIMESTAMPNS also turns
+        // off SO_TIMESTAMP, so it's cleared before that's set.
+        let clear_timestamping = || {
+            let _ = self.set_socket_option::<c_uint>(SOL_SOCKET, SO_TIMESTAMPING, &0);
         };
 
-        if ret == -1 {
-            return Err(IoError::last_os_error());
-        }
+        let flags = match mode {
+            Disabled => {
+                self.set_socket_option(SOL_SOCKET, SO_TIMESTAMPNS, &0)?;
+                self.set_socket_option(SOL_SOCKET, SO_TIMESTAMP, &0)?;
+                clear_timestamping();
+                return Ok(());
+            }
+            Legacy => {
+                clear_timestamping();
+                self.set_socket_option(SOL_SOCKET, SO_TIMESTAMPNS, &0)?;
+                return self.set_socket_option(SOL_SOCKET, SO_TIMESTAMP, &1);
+            }
+            Software => SOFTWARE,
+            Hardware => SOFTWARE | HARDWARE,
+        };
 
-        Ok((frame, system_time_from_timespec(ts)))
+        self.set_socket_option(SOL_SOCKET, SO_TIMESTAMPING, &flags)?;
+        self.set_socket_option(SOL_SOCKET, SO_TIMESTAMPNS, &0)?;
+        self.set_socket_option(SOL_SOCKET, SO_TIMESTAMP, &0)
     }
 
+    /// Enables software timestamps on received frames.
+    ///
+    /// This tries `SO_TIMESTAMPING` first, and falls back to the legacy
+    /// `SO_TIMESTAMP` option if the kernel doesn't support it. On success,
+    /// this returns the mode that was selected.
+    fn enable_timestamps(&self) -> IoResult<TimestampMode> {
+        match self.set_timestamp_mode(TimestampMode::Software) {
+            Ok(()) => Ok(TimestampMode::Software),
+            Err(err)
+                if matches!(
+                    err.raw_os_error(),
+                    Some(libc::ENOPROTOOPT) | Some(libc::EINVAL) | Some(libc::EOPNOTSUPP)
+                ) =>
+            {
+                self.set_timestamp_mode(TimestampMode::Legacy)?;
+                Ok(TimestampMode::Legacy)
+            }
+            Err(err) => Err(err),
+        }
+    }
 }
-*/
 
-// ===== CanSocket =====
+// ===== Socket state & statistics =====
 
-/// A socket for classic CAN 2.0 devices.
-///
-/// This provides an interface to read and write classic CAN 2.0 frames to
-/// the bus, with up to 8 bytes of data per frame. It wraps a Linux socket
-/// descriptor to a Raw SocketCAN socket.
+/// A snapshot of the statistics counters for a socket.
 ///
-/// The socket is automatically closed when the object is dropped. To close
+/// These are only updated by the frame-level reads and writes of the
+/// socket, like `read_frame()` and `write_frame()`, and not by the
+/// byte-level `Read` and `Write` traits.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SocketStats {
+    /// The number of frames successfully sent
+    pub frames_sent: u64,
+    /// The number of frames received
+    pub frames_received: u64,
+    /// The number of bytes successfully sent
+    pub bytes_sent: u64,
+    /// The number of bytes received
+    pub bytes_received: u64,
+    /// The number of failed writes
+    pub write_errors: u64,
+}
+
+/// The atomic counters behind the statistics for a socket.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default)]
+struct StatsCounters {
+    frames_sent: AtomicU64,
+    frames_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    write_errors: AtomicU64,
+}
+
+/// Internal state kept alongside the OS socket.
+#[derive(Debug, Default)]
+struct SocketState {
+    /// Cache of interface names, by index, for `read_frame_named()`
+    names: Mutex<HashMap<c_int, String>>,
+    /// Whether the write side of the socket was shut down
+    write_shut: AtomicBool,
+    /// The byte used to pad FD frames created by the socket
+    tx_pad: AtomicU8,
+    /// Whether scheduled transmission was enabled
+    txtime_enabled: AtomicBool,
+    /// The clock for scheduled transmission, once it's enabled
+    txtime_clock: AtomicI32,
+    #[cfg(feature = "stats")]
+    stats: StatsCounters,
+}
+
+#[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+impl SocketState {
+    /// Gets the clock for scheduled transmission, if it was enabled.
+    fn txtime_clock(&self) -> Option<libc::clockid_t> {
+        self.txtime_enabled
+            .load(Ordering::Acquire)
+            .then(|| self.txtime_clock.load(Ordering::Relaxed))
+    }
+
+    /// Sets the clock for scheduled transmission.
+    fn set_txtime_clock(&self, clockid: libc::clockid_t) {
+        self.txtime_clock.store(clockid, Ordering::Relaxed);
+        self.txtime_enabled.store(true, Ordering::Release);
+    }
+
+    /// Checks that the write side of the socket hasn't been shut down.
+    #[inline]
+    fn check_write(&self) -> IoResult<()> {
+        if self.write_shut.load(Ordering::Relaxed) {
+            return Err(IoError::new(
+                IoErrorKind::BrokenPipe,
+                "socket is shut down for writing",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Shuts down one or both halves of the socket.
+    ///
+    /// Raw CAN sockets don't support `shutdown(2)`, so when the kernel
+    /// refuses it, the shutdown is emulated here.
+    fn shutdown<S>(&self, sock: &S, how: Shutdown) -> IoResult<()>
+    where
+        S: Socket + SocketOptions,
+    {
+        match sock.as_raw_socket().shutdown(how) {
+            Err(err) if err.raw_os_error() == Some(libc::EOPNOTSUPP) => (),
+            res => return res,
+        }
+
+        if matches!(how, Shutdown::Read | Shutdown::Both) {
+            sock.set_filter_drop_all()?;
+        }
+        if matches!(how, Shutdown::Write | Shutdown::Both) {
+            self.write_shut.store(true, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Records the result of writing a frame of `len` bytes.
+    #[inline]
+    fn on_write(&self, res: &IoResult<()>, len: usize) {
+        #[cfg(feature = "stats")]
+        match res {
+            Ok(_) => {
+                self.stats.frames_sent.fetch_add(1, Ordering::Relaxed);
+                self.stats
+                    .bytes_sent
+                    .fetch_add(len as u64, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.stats.write_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Records that a frame of `len` bytes was received.
+    #[inline]
+    fn on_read(&self, len: usize) {
+        #[cfg(feature = "stats")]
+        {
+            self.stats.frames_received.fetch_add(1, Ordering::Relaxed);
+            self.stats
+                .bytes_received
+                .fetch_add(len as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Gets the name of the interface with the specified index.
+    ///
+    /// Names are cached after the first lookup. If a lookup fails, the
+    /// whole cache is cleared, since the interfaces have changed and any
+    /// of the other indexes may since have been reused.
+    fn iface_name(&self, ifindex: c_int) -> IoResult<String> {
+        let mut names = self.names.lock().unwrap_or_else(|err| err.into_inner());
+
+        if let Some(name) = names.get(&ifindex) {
+            return Ok(name.clone());
+        }
+
+        let name = CanAddr::new(ifindex as u32).iface_name().map_err(|err| {
+            names.clear();
+            err
+        })?;
+        names.insert(ifindex, name.clone());
+        Ok(name)
+    }
+
+    /// Clears the cache of interface names.
+    fn clear_names(&self) {
+        self.names
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+
+    /// Gets a snapshot of the statistics counters.
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> SocketStats {
+        let c = &self.stats;
+        SocketStats {
+            frames_sent: c.frames_sent.load(Ordering::Relaxed),
+            frames_received: c.frames_received.load(Ordering::Relaxed),
+            bytes_sent: c.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: c.bytes_received.load(Ordering::Relaxed),
+            write_errors: c.write_errors.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// ===== OpenOptions =====
+
+/// Options that are applied to a socket as it is opened.
+///
+/// The options are set on the socket _before_ it is bound to the
+/// interface, so they are already in effect when the first frame is
+/// delivered to it. In particular, filters set here close the window
+/// between opening a socket and calling `set_filters()`, during which an
+/// application on a busy bus would otherwise receive unfiltered frames.
+///
+/// ```no_run
+/// use socketcan::{CanFilter, CanSocket, OpenOptions};
+///
+/// let opts = OpenOptions::new()
+///     .filters(&[CanFilter::new(0x100, 0x7FF)])
+///     .recv_own_msgs(true);
+///
+/// let sock = CanSocket::open_with("vcan0", &opts).unwrap();
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct OpenOptions {
+    filters: Option<Vec<CanFilter>>,
+    error_filter: Option<u32>,
+    loopback: Option<bool>,
+    recv_own_msgs: Option<bool>,
+    join_filters: Option<bool>,
+    xl_frames: Option<bool>,
+    nonblocking: bool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl OpenOptions {
+    /// Creates a default set of options, which leaves the socket in the
+    /// same state as a regular `open()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the CAN ID filters for the socket.
+    ///
+    /// See [`SocketOptions::set_filters()`].
+    pub fn filters<F>(mut self, filters: &[F]) -> Self
+    where
+        F: Into<CanFilter> + Copy,
+    {
+        self.filters = Some(filters.iter().map(|f| (*f).into()).collect());
+        self
+    }
+
+    /// Sets the error filter mask for the socket.
+    ///
+    /// See [`SocketOptions::set_error_filter()`].
+    pub fn error_filter(mut self, mask: u32) -> Self {
+        self.error_filter = Some(mask);
+        self
+    }
+
+    /// Enables or disables loopback.
+    ///
+    /// See [`SocketOptions::set_loopback()`].
+    pub fn loopback(mut self, enabled: bool) -> Self {
+        self.loopback = Some(enabled);
+        self
+    }
+
+    /// Enables or disables receiving of own frames.
+    ///
+    /// See [`SocketOptions::set_recv_own_msgs()`].
+    pub fn recv_own_msgs(mut self, enabled: bool) -> Self {
+        self.recv_own_msgs = Some(enabled);
+        self
+    }
+
+    /// Enables or disables join filters.
+    ///
+    /// See [`SocketOptions::set_join_filters()`].
+    pub fn join_filters(mut self, enabled: bool) -> Self {
+        self.join_filters = Some(enabled);
+        self
+    }
+
+    /// Enables or disables XL frames.
+    ///
+    /// This is only valid for a `CanFdSocket`, which can then receive all
+    /// three tiers of frames with `read_any_frame()`.
+    /// See [`SocketOptions::set_xl_frames()`].
+    pub fn xl_frames(mut self, enabled: bool) -> Self {
+        self.xl_frames = Some(enabled);
+        self
+    }
+
+    /// Opens the socket in non-blocking mode.
+    pub fn nonblocking(mut self, nonblocking: bool) -> Self {
+        self.nonblocking = nonblocking;
+        self
+    }
+
+    /// Sets the read timeout for the socket.
+    ///
+    /// See [`Socket::set_read_timeout()`].
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the write timeout for the socket.
+    ///
+    /// See [`Socket::set_write_timeout()`].
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Applies the options to a socket that hasn't been bound yet.
+    fn apply<S>(&self, sock: &S) -> IoResult<()>
+    where
+        S: Socket + SocketOptions,
+    {
+        if let Some(filters) = &self.filters {
+            sock.set_filters(filters)?;
+        }
+        if let Some(mask) = self.error_filter {
+            sock.set_error_filter(mask)?;
+        }
+        if let Some(enabled) = self.loopback {
+            sock.set_loopback(enabled)?;
+        }
+        if let Some(enabled) = self.recv_own_msgs {
+            sock.set_recv_own_msgs(enabled)?;
+        }
+        if let Some(enabled) = self.join_filters {
+            sock.set_join_filters(enabled)?;
+        }
+        if let Some(enabled) = self.xl_frames {
+            sock.set_xl_frames(enabled)?;
+        }
+        if self.nonblocking {
+            sock.set_nonblocking(true)?;
+        }
+        if let Some(timeout) = self.read_timeout {
+            sock.set_read_timeout(timeout)?;
+        }
+        if let Some(timeout) = self.write_timeout {
+            sock.set_write_timeout(timeout)?;
+        }
+        Ok(())
+    }
+}
+
+// ===== CanSocket =====
+
+/// A socket for classic CAN 2.0 devices.
+///
+/// This provides an interface to read and write classic CAN 2.0 frames to
+/// the bus, with up to 8 bytes of data per frame. It wraps a Linux socket
+/// descriptor to a Raw SocketCAN socket.
+///
+/// The socket is automatically closed when the object is dropped. To close
 /// manually, use std::drop::Drop. Internally this is just a wrapped socket
 /// (file) descriptor.
 #[allow(missing_copy_implementations)]
 #[derive(Debug)]
-pub struct CanSocket(socket2::Socket);
+pub struct CanSocket(socket2::Socket, SocketState);
 
 impl CanSocket {
+    /// Opens a named CAN device with the specified options already applied.
+    ///
+    /// See [`OpenOptions`].
+    pub fn open_with(ifname: &str, opts: &OpenOptions) -> IoResult<Self> {
+        let addr = CanAddr::from_iface(ifname)?;
+        Self::open_addr_with(&addr, opts)
+    }
+
+    /// Opens a CAN socket by address with the specified options already
+    /// applied.
+    ///
+    /// See [`OpenOptions`].
+    pub fn open_addr_with(addr: &CanAddr, opts: &OpenOptions) -> IoResult<Self> {
+        let sock = Self(raw_socket()?, SocketState::default());
+        opts.apply(&sock)?;
+        sock.0.bind(&SockAddr::from(*addr))?;
+        Ok(sock)
+    }
+
+    /// Records a frame of `len` bytes that was received outside of the
+    /// socket's own read calls.
+    #[cfg(feature = "io-uring")]
+    pub(crate) fn on_read(&self, len: usize) {
+        self.1.on_read(len);
+    }
+
     /// Reads a low-level libc `can_frame` from the socket.
     pub fn read_raw_frame(&self) -> IoResult<libc::can_frame> {
         let mut frame = can_frame_default();
         self.as_raw_socket().read_exact(as_bytes_mut(&mut frame))?;
+        self.1.on_read(CAN_MTU);
         Ok(frame)
     }
+
+    /// Reads a low-level libc `can_frame` from the socket, without zeroing
+    /// the frame beforehand.
+    ///
+    /// This receives straight into uninitialized memory, and only takes
+    /// the frame as initialized if the kernel filled all of it. Anything
+    /// short of a full classic frame is an `InvalidData` error. It saves a
+    /// small amount of time per frame, which can add up when reading
+    /// millions of them. Otherwise, it's the same as `read_raw_frame()`.
+    pub fn read_raw_frame_uninit(&self) -> IoResult<libc::can_frame> {
+        let mut frame = mem::MaybeUninit::<libc::can_frame>::uninit();
+        let buf = unsafe {
+            std::slice::from_raw_parts_mut(
+                frame.as_mut_ptr().cast::<mem::MaybeUninit<u8>>(),
+                CAN_MTU,
+            )
+        };
+
+        let n = self.as_raw_socket().recv(buf)?;
+        if n != CAN_MTU {
+            return Err(IoErrorKind::InvalidData.into());
+        }
+        self.1.on_read(n);
+        // SAFETY: The kernel wrote all CAN_MTU bytes of the frame
+        Ok(unsafe { frame.assume_init() })
+    }
+
+    /// Reads frames into the buffer until it's full or the deadline passes.
+    ///
+    /// This waits for frames with `ppoll()`, for the time left until the
+    /// deadline, and receives each burst of them with a single `recvmmsg()`
+    /// call. It returns the number of frames placed at the start of the
+    /// buffer, which is less than its length, possibly zero, if the deadline
+    /// passed first. Frames already queued are collected even if the
+    /// deadline has passed. Any message that isn't the size of a classic CAN
+    /// frame is skipped.
+    ///
+    /// An error is only returned if no frames were received, so that frames
+    /// already taken from the socket aren't lost.
+    pub fn recv_frames_timeout(&self, buf: &mut [CanFrame], deadline: Instant) -> IoResult<usize> {
+        let fd = self.as_raw_fd();
+        let mut bufs = MmsgBuffers::<RECV_BURST>::new();
+        let mut count = 0;
+        let mut res = Ok(());
+
+        while count < buf.len() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match ppoll_readable(fd, timeout) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) if err.kind() == IoErrorKind::Interrupted => continue,
+                Err(err) => {
+                    res = Err(err);
+                    break;
+                }
+            }
+
+            match bufs.recv(
+                self,
+                &mut buf[count..],
+                libc::MSG_WAITFORONE | libc::MSG_DONTWAIT,
+            ) {
+                Ok(n) => count += n,
+                Err(err) if err.should_retry() => (),
+                Err(err) => {
+                    res = Err(err);
+                    break;
+                }
+            }
+        }
+
+        match res {
+            Err(err) if count == 0 => Err(err),
+            _ => Ok(count),
+        }
+    }
+
+    /// Reads a frame from the socket, along with the number of bytes that
+    /// the read actually returned.
+    ///
+    /// This is meant for debugging odd driver or MTU behavior. The read is
+    /// done into a buffer larger than a classic frame, so an oversize read
+    /// from an odd driver shows up as more than `CAN_MTU` bytes, and a
+    /// truncated frame reports less. In any case, the frame
+    /// is decoded from the first `CAN_MTU` bytes, with any missing bytes
+    /// left as zero.
+    pub fn read_frame_raw_len(&self) -> IoResult<(CanFrame, usize)> {
+        let mut fdframe = canfd_frame_default();
+        let n = self.as_raw_socket().read(as_bytes_mut(&mut fdframe))?;
+        self.1.on_read(n);
+
+        let mut frame = can_frame_default();
+        as_bytes_mut(&mut frame).copy_from_slice(&as_bytes(&fdframe)[..CAN_MTU]);
+        Ok((frame.into(), n))
+    }
+
+    /// Writes a low-level libc `can_frame` to the socket.
+    ///
+    /// The data length code is checked first, and an `InvalidInput` error
+    /// is returned if it is larger than 8.
+    pub fn write_raw_frame(&self, frame: &libc::can_frame) -> IoResult<()> {
+        let bytes = as_bytes(frame);
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let res = self.as_raw_socket().write_all(bytes);
+        self.1.on_write(&res, bytes.len());
+        res
+    }
+
+    /// Writes any type of frame to the socket.
+    ///
+    /// Classic frames are written as usual, but an FD frame fails with an
+    /// `InvalidInput` error wrapping [`SocketError::FdFramesDisabled`]
+    /// unless FD mode was enabled on the socket.
+    pub fn write_any_frame(&self, frame: &CanAnyFrame) -> IoResult<()> {
+        check_frame_kind(self, frame)?;
+        let bytes = frame.as_bytes();
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let res = self.as_raw_socket().write_all(bytes);
+        self.1.on_write(&res, bytes.len());
+        res
+    }
+
+    /// Writes a frame and waits for the kernel to confirm that it was
+    /// transmitted.
+    ///
+    /// A successful `write_frame()` only means that the frame was queued.
+    /// This sends the frame from a temporary socket on the same interface,
+    /// with reception of its own messages enabled, and waits for the copy
+    /// that the kernel echoes back once the frame has gone out on the bus.
+    /// If that doesn't arrive before the `timeout`, a `TimedOut` error is
+    /// returned, although the frame may still be sent later. This socket's
+    /// settings and received frames are not affected, but as the frame
+    /// comes from another socket, it is received here as a looped-back
+    /// frame if loopback is enabled.
+    ///
+    /// This costs a socket open and a round trip through the driver for
+    /// each frame, adding a good deal of latency, so is best kept for
+    /// frames that really need the assurance, such as control commands.
+    /// The socket must be bound to a single interface.
+    pub fn send_confirmed<F>(&self, frame: &F, timeout: Duration) -> IoResult<()>
+    where
+        F: Into<CanFrame> + AsPtr,
+    {
+        let bytes = frame.as_bytes();
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+
+        let deadline = Instant::now() + timeout;
+        let confirm = Self::open_addr(&self.local_addr()?)?;
+        confirm.set_recv_own_msgs(true)?;
+
+        // Only the frame itself needs to come back on this socket. The
+        // ID word leads the frame struct.
+        let id_word = canid_t::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let mask = libc::CAN_EFF_FLAG | libc::CAN_RTR_FLAG | libc::CAN_EFF_MASK;
+        confirm.set_filters(&[CanFilter::new(id_word, mask)])?;
+
+        let res = confirm.as_raw_socket().write_all(bytes);
+        self.1.on_write(&res, bytes.len());
+        res?;
+
+        let mut rx_frame = can_frame_default();
+        loop {
+            let remaining = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|d| !d.is_zero())
+                .ok_or(IoErrorKind::TimedOut)?;
+            confirm.set_read_timeout(remaining)?;
+
+            match recv_msg(confirm.as_raw_fd(), as_bytes_mut(&mut rx_frame), 0) {
+                Ok(info) if info.flags & MSG_CONFIRM != 0 => return Ok(()),
+                Ok(_) => (),
+                Err(err) if err.should_retry() => return Err(IoErrorKind::TimedOut.into()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Gets a snapshot of the statistics counters for the socket.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> SocketStats {
+        self.1.stats()
+    }
+
+    /// Reads a frame from the socket, along with the time it was received.
+    ///
+    /// Timestamps must first be enabled on the socket with
+    /// [`SocketOptions::enable_timestamps`] or
+    /// [`SocketOptions::set_timestamp_mode`], otherwise the time will be
+    /// `None`.
+    pub fn read_frame_with_timestamp(&self) -> IoResult<(CanFrame, Option<SystemTime>)> {
+        let mut frame = can_frame_default();
+        let info = recv_msg(self.as_raw_fd(), as_bytes_mut(&mut frame), 0)?;
+
+        if info.len != CAN_MTU {
+            return Err(IoErrorKind::InvalidData.into());
+        }
+        self.1.on_read(info.len);
+        Ok((frame.into(), info.timestamp))
+    }
+
+    /// Reads the raw bytes of a single frame into the buffer, returning
+    /// the number of bytes read.
+    ///
+    /// Unlike a plain `read()`, this fails with an `InvalidData` error
+    /// wrapping [`SocketError::Truncated`] if the frame was larger than the
+    /// buffer, rather than silently dropping the rest of it. The kernel
+    /// doesn't report the real size of a raw CAN frame that was cut short.
+    pub fn read_checked(&self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = recv_checked(self.as_raw_fd(), buf)?;
+        self.1.on_read(n);
+        Ok(n)
+    }
+
+    /// Receives a message from the socket with a raw `recvmsg()` call.
+    ///
+    /// This is a low-level escape hatch for handling any of the ancillary
+    /// (control) messages from the kernel that this crate doesn't parse.
+    /// The data is received into `buf`, which should be large enough for
+    /// a frame, and the control messages are placed into the start of
+    /// `cmsg_buf`, as `cmsghdr` structs, for the caller to parse. The
+    /// size of those is in the `cmsg_len` of the result, and the
+    /// `MSG_CTRUNC` flag is set if they didn't fit. To parse the buffer in
+    /// place, it should be aligned for a `cmsghdr`.
+    ///
+    /// The data is not checked to be a valid frame.
+    pub fn recvmsg(&self, buf: &mut [u8], cmsg_buf: &mut [u8]) -> IoResult<RecvMsgResult> {
+        let res = recvmsg_raw(self.as_raw_fd(), buf, cmsg_buf, 0)?;
+        self.1.on_read(res.len);
+        Ok(res)
+    }
+
+    /// Enables scheduled transmission of frames with `write_frame_at()`.
+    ///
+    /// This sets `SO_TXTIME` on the socket, with the clock on which the
+    /// transmit times are given to the kernel. That has to match the clock
+    /// of the ETF qdisc on the interface, which is normally `CLOCK_TAI`.
+    ///
+    /// The kernel only holds each frame until its time if the interface
+    /// has such a qdisc, and otherwise sends frames right away. So this
+    /// looks up the qdiscs of the interface the socket is bound to, and
+    /// fails with [`SocketError::NoEtfQdisc`] if there isn't an ETF one.
+    /// That needs the **netlink** feature; without it, this fails with an
+    /// `Unsupported` error.
+    pub fn enable_txtime(&self, clockid: libc::clockid_t) -> IoResult<()> {
+        check_etf_qdisc(self)?;
+        let txtime = libc::sock_txtime { clockid, flags: 0 };
+        self.set_socket_option(SOL_SOCKET, SO_TXTIME, &txtime)?;
+        self.1.set_txtime_clock(clockid);
+        Ok(())
+    }
+
+    /// Writes a frame to be transmitted at the time given.
+    ///
+    /// The time is converted to the clock set with `enable_txtime()`. If
+    /// that wasn't called, this fails with an `InvalidInput` error wrapping
+    /// [`SocketError::TxTimeDisabled`], rather than sending the frame
+    /// immediately.
+    pub fn write_frame_at<F>(&self, frame: &F, txtime: SystemTime) -> IoResult<()>
+    where
+        F: Into<CanFrame> + AsPtr,
+    {
+        let clockid = self.1.txtime_clock().ok_or(SocketError::TxTimeDisabled)?;
+        let bytes = frame.as_bytes();
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let txtime = system_time_to_clock_ns(txtime, clockid)?;
+        let res = send_at(self.as_raw_fd(), bytes, txtime);
+        self.1.on_write(&res, bytes.len());
+        res
+    }
+
+    /// Writes a frame to a specific interface.
+    ///
+    /// This is the write-side counterpart to `read_frame_from()`, sending
+    /// the frame with `sendto()` to the interface with the index given. It
+    /// lets a single socket bound to all interfaces (with an index of zero)
+    /// transmit on any of them, but also works on a socket bound to one
+    /// interface. The kernel has no interface to send to for an index of
+    /// zero, and fails with an `ENXIO` error.
+    pub fn write_frame_to<F>(&self, frame: &F, ifindex: u32) -> IoResult<()>
+    where
+        F: Into<CanFrame> + AsPtr,
+    {
+        let bytes = frame.as_bytes();
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let addr = SockAddr::from(CanAddr::new(ifindex));
+        let res = self.as_raw_socket().send_to(bytes, &addr).map(|_| ());
+        self.1.on_write(&res, bytes.len());
+        res
+    }
+
+    /// Reads a frame along with the address of the interface that
+    /// received it.
+    ///
+    /// This is mainly of use for a socket bound to all interfaces (with an
+    /// interface index of zero) to determine the source of each frame.
+    pub fn read_frame_from(&self) -> IoResult<(CanFrame, CanAddr)> {
+        let mut frame = can_frame_default();
+        let info = recv_msg(self.as_raw_fd(), as_bytes_mut(&mut frame), 0)?;
+
+        if info.len != CAN_MTU {
+            return Err(IoErrorKind::InvalidData.into());
+        }
+        self.1.on_read(info.len);
+        Ok((frame.into(), info.addr))
+    }
+
+    /// Reads a frame along with its metadata.
+    ///
+    /// This gets all of the information the kernel reports with the frame
+    /// in one call: the receive time and drop count (if enabled), the
+    /// interface the frame came from, and whether it was looped back by
+    /// the local host, or sent by this socket, rather than coming in off
+    /// the bus.
+    pub fn read_frame_meta(&self) -> IoResult<(CanFrame, FrameMeta)> {
+        let mut frame = can_frame_default();
+        let info = recv_msg(self.as_raw_fd(), as_bytes_mut(&mut frame), 0)?;
+
+        if info.len != CAN_MTU {
+            return Err(IoErrorKind::InvalidData.into());
+        }
+        self.1.on_read(info.len);
+        Ok((frame.into(), FrameMeta::from_info(&info)))
+    }
+
+    /// Reads a frame along with the name of the interface that received it.
+    ///
+    /// The names are cached by the socket, so the interface is only looked
+    /// up the first time a frame is received from it. The cache is cleared
+    /// if a lookup fails, as when an interface is removed, but a renamed
+    /// interface keeps its old name until `clear_name_cache()` is called.
+    pub fn read_frame_named(&self) -> IoResult<(CanFrame, String)> {
+        let (frame, addr) = self.read_frame_from()?;
+        let name = self.1.iface_name(addr.ifindex())?;
+        Ok((frame, name))
+    }
+
+    /// Clears the cache of interface names used by `read_frame_named()`.
+    pub fn clear_name_cache(&self) {
+        self.1.clear_names();
+    }
+
+    /// Shuts down the read, write, or both halves of the socket.
+    ///
+    /// CAN is a broadcast bus, not a connection-oriented protocol like TCP,
+    /// so this doesn't signal anything to the other nodes on the bus. It
+    /// only affects this socket, and the kernel's raw CAN sockets don't
+    /// support `shutdown(2)` at all, so it's emulated:
+    ///
+    /// - Write: any further frame writes fail with a `BrokenPipe` error.
+    /// - Read: all frames are filtered out, so no more are queued for the
+    ///   socket. Frames that were already received can still be read,
+    ///   after which reads block (or time out) as they would on an idle
+    ///   bus.
+    ///
+    /// A common use is to shut down the write side of a bridge while
+    /// continuing to drain the frames it has already received.
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        self.1.shutdown(self, how)
+    }
 }
 
 impl Socket for CanSocket {
@@ -514,7 +2101,7 @@ impl Socket for CanSocket {
     /// Opens the socket by interface index.
     fn open_addr(addr: &CanAddr) -> IoResult<Self> {
         let sock = raw_open_socket(addr)?;
-        Ok(Self(sock))
+        Ok(Self(sock, SocketState::default()))
     }
 
     /// Gets a shared reference to the underlying socket object
@@ -532,7 +2119,20 @@ impl Socket for CanSocket {
     where
         F: Into<CanFrame> + AsPtr,
     {
-        self.as_raw_socket().write_all(frame.as_bytes())
+        check_frame_len(frame.as_bytes())?;
+        self.write_frame_unchecked(frame)
+    }
+
+    /// Writes a normal CAN 2.0 frame to the socket without validating it.
+    fn write_frame_unchecked<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<CanFrame> + AsPtr,
+    {
+        let bytes = frame.as_bytes();
+        self.1.check_write()?;
+        let res = self.as_raw_socket().write_all(bytes);
+        self.1.on_write(&res, bytes.len());
+        res
     }
 
     /// Reads a normal CAN 2.0 frame from the socket.
@@ -607,7 +2207,7 @@ impl AsRawFd for CanSocket {
 
 impl From<OwnedFd> for CanSocket {
     fn from(fd: OwnedFd) -> Self {
-        Self(socket2::Socket::from(fd))
+        Self(socket2::Socket::from(fd), SocketState::default())
     }
 }
 
@@ -631,6 +2231,7 @@ impl Read for CanSocket {
 
 impl Write for CanSocket {
     fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.1.check_write()?;
         self.0.write(buf)
     }
 
@@ -639,18 +2240,89 @@ impl Write for CanSocket {
     }
 }
 
+/// Registers the socket with a `mio::Poll`, by its file descriptor.
+///
+/// The socket should be put in non-blocking mode, so that reads and
+/// writes can be retried when they would block.
+#[cfg(feature = "mio")]
+impl mio::event::Source for CanSocket {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> IoResult<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> IoResult<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> IoResult<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
+
 // ===== CanFdSocket =====
 
 /// A socket for CAN FD devices.
 ///
 /// This can transmit and receive CAN 2.0 frames with up to 8-bytes of data,
 /// or CAN Flexible Data (FD) frames with up to 64-bytes of data.
+///
+/// On a kernel without FD support, opening the socket fails with an
+/// `Unsupported` error wrapping [`SocketError::FdNotSupported`]. Use
+/// [`CanFdSocket::fd_frames_supported()`] to check for it beforehand.
 #[allow(missing_copy_implementations)]
 #[derive(Debug)]
-pub struct CanFdSocket(socket2::Socket);
+pub struct CanFdSocket(socket2::Socket, SocketState);
 
 impl CanFdSocket {
+    /// Opens a named CAN device with the specified options already applied.
+    ///
+    /// See [`OpenOptions`].
+    pub fn open_with(ifname: &str, opts: &OpenOptions) -> IoResult<Self> {
+        let addr = CanAddr::from_iface(ifname)?;
+        Self::open_addr_with(&addr, opts)
+    }
+
+    /// Opens a CAN FD socket by address with the specified options already
+    /// applied.
+    ///
+    /// See [`OpenOptions`].
+    pub fn open_addr_with(addr: &CanAddr, opts: &OpenOptions) -> IoResult<Self> {
+        let sock = Self::set_fd_mode(raw_socket()?, true)?;
+        let sock = Self(sock, SocketState::default());
+        opts.apply(&sock)?;
+        sock.0.bind(&SockAddr::from(*addr))?;
+        Ok(sock)
+    }
+
+    /// Determines if the kernel supports CAN FD frames on raw sockets.
+    ///
+    /// This opens an unbound raw CAN socket and tries to put it into FD
+    /// mode, so it doesn't need a CAN interface. It can be used to decide
+    /// at runtime between a `CanFdSocket` and a classic `CanSocket` on
+    /// systems where FD support varies. An error is returned if a raw CAN
+    /// socket can't be created at all.
+    pub fn fd_frames_supported() -> IoResult<bool> {
+        match Self::set_fd_mode(raw_socket()?, true) {
+            Ok(_) => Ok(true),
+            Err(err) if is_fd_not_supported(&err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
     // Enable or disable FD mode on a socket.
+    //
+    // Kernels without FD support reject the option with ENOPROTOOPT,
+    // which is reported as `SocketError::FdNotSupported`.
     fn set_fd_mode(sock: socket2::Socket, enable: bool) -> IoResult<socket2::Socket> {
         let enable = enable as c_int;
 
@@ -666,7 +2338,12 @@ impl CanFdSocket {
 
         match ret {
             0 => Ok(sock),
-            _ => Err(IoError::last_os_error()),
+            _ => match IoError::last_os_error() {
+                err if err.raw_os_error() == Some(libc::ENOPROTOOPT) => {
+                    Err(SocketError::FdNotSupported.into())
+                }
+                err => Err(err),
+            },
         }
     }
 
@@ -676,67 +2353,384 @@ impl CanFdSocket {
     /// or an FD frame.
     pub fn read_raw_frame(&self) -> IoResult<CanRawFrame> {
         let mut fdframe = canfd_frame_default();
+        let n = recv_checked(self.as_raw_fd(), as_bytes_mut(&mut fdframe))?;
 
-        match self.as_raw_socket().read(as_bytes_mut(&mut fdframe))? {
+        let frame = match n {
             // If we only get 'can_frame' number of bytes, then the return is,
             // by definition, a can_frame, so we just copy the bytes into the
             // proper type.
             CAN_MTU => {
                 let mut frame = can_frame_default();
                 as_bytes_mut(&mut frame)[..CAN_MTU].copy_from_slice(&as_bytes(&fdframe)[..CAN_MTU]);
-                Ok(frame.into())
+                frame.into()
             }
-            CANFD_MTU => Ok(fdframe.into()),
-            _ => Err(IoError::last_os_error()),
-        }
+            CANFD_MTU => fdframe.into(),
+            _ => return Err(IoErrorKind::InvalidData.into()),
+        };
+        self.1.on_read(n);
+        Ok(frame)
     }
-}
 
-impl Socket for CanFdSocket {
-    /// CanFdSocket can read/write classic CAN 2.0 or FD frames.
-    type FrameType = CanAnyFrame;
+    /// Writes a raw CAN frame to the socket.
+    ///
+    /// This might be either type of CAN frame. The data length is checked
+    /// first, and an `InvalidInput` error is returned if it is not valid
+    /// for the type of frame.
+    pub fn write_raw_frame(&self, frame: &CanRawFrame) -> IoResult<()> {
+        let bytes = match frame {
+            CanRawFrame::Classic(frame) => as_bytes(frame),
+            CanRawFrame::Fd(frame) => as_bytes(frame),
+        };
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let res = self.as_raw_socket().write_all(bytes);
+        self.1.on_write(&res, bytes.len());
+        res
+    }
 
-    /// Opens the FD socket by interface index.
-    fn open_addr(addr: &CanAddr) -> IoResult<Self> {
-        raw_open_socket(addr)
-            .and_then(|sock| Self::set_fd_mode(sock, true))
-            .map(Self)
+    /// Writes a frame of any tier to the socket: classic, FD, or XL.
+    ///
+    /// This first checks that the socket can still send the frame, failing
+    /// with an `InvalidInput` error wrapping
+    /// [`SocketError::FdFramesDisabled`] for an FD frame if FD mode was
+    /// turned off, or [`SocketError::XlFramesDisabled`] for an XL frame if
+    /// XL frames weren't enabled.
+    pub fn write_any_frame<F>(&self, frame: F) -> IoResult<()>
+    where
+        F: Into<CanMixedFrame>,
+    {
+        match frame.into() {
+            CanMixedFrame::Classic(frame) => self.write_frame(&frame),
+            CanMixedFrame::Fd(frame) => {
+                if !self.fd_frames_enabled()? {
+                    return Err(SocketError::FdFramesDisabled.into());
+                }
+                self.write_frame(&frame)
+            }
+            CanMixedFrame::Xl(frame) => {
+                if !self.xl_frames_enabled()? {
+                    return Err(SocketError::XlFramesDisabled.into());
+                }
+                self.write_xl_frame(&frame)
+            }
+        }
     }
 
-    /// Gets a shared reference to the underlying socket object
-    fn as_raw_socket(&self) -> &socket2::Socket {
-        &self.0
+    /// Reads a frame of any tier from the socket: classic, FD, or XL.
+    ///
+    /// XL frames are only received if they were enabled on the socket, as
+    /// with [`OpenOptions::xl_frames()`]. Frames are told apart by the
+    /// number of bytes received, and the XL flag.
+    pub fn read_any_frame(&self) -> IoResult<CanMixedFrame> {
+        let mut xlframe = canxl_frame_default();
+        let n = recv_checked(self.as_raw_fd(), as_bytes_mut(&mut xlframe))?;
+        let frame = mixed_frame_from_buf(&xlframe, n)?;
+        self.1.on_read(n);
+        Ok(frame)
     }
 
-    /// Gets a mutable reference to the underlying socket object
-    fn as_raw_socket_mut(&mut self) -> &mut socket2::Socket {
-        &mut self.0
+    /// Reads an XL frame from the socket.
+    ///
+    /// XL frames must first be enabled on the socket. If a classic or FD
+    /// frame is received instead, this fails with an `InvalidData` error,
+    /// so a bus with mixed traffic should be read with `read_any_frame()`.
+    pub fn read_xl_frame(&self) -> IoResult<CanXlFrame> {
+        match self.read_any_frame()? {
+            CanMixedFrame::Xl(frame) => Ok(frame),
+            _ => Err(IoError::new(
+                IoErrorKind::InvalidData,
+                "received a classic or FD frame, not an XL frame",
+            )),
+        }
     }
 
-    /// Writes any type of CAN frame to the socket.
+    /// Writes an XL frame to the socket.
+    ///
+    /// Only the header and the data in use are sent. XL frames must first
+    /// be enabled on the socket, otherwise the kernel rejects the frame
+    /// with an `EINVAL` error.
+    pub fn write_xl_frame(&self, frame: &CanXlFrame) -> IoResult<()> {
+        let bytes = frame.as_bytes();
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let res = self.as_raw_socket().write_all(bytes);
+        self.1.on_write(&res, bytes.len());
+        res
+    }
+
+    /// Reads either type of frame from the socket, always returning it
+    /// as an FD frame.
+    ///
+    /// This is for pipelines that only handle FD frames. A classic frame
+    /// is widened into an FD frame without the FDF or BRS flags, so
+    /// genuine FD frames can still be told apart by their `flags()`. The
+    /// EFF/RTR/ERR flags of the ID word are kept, so a classic remote or
+    /// error frame still shows as one in `id_flags()`, and the length of a
+    /// remote frame is the DLC that it requested.
+    pub fn read_frame_as_fd(&self) -> IoResult<CanFdFrame> {
+        Ok(match self.read_frame()? {
+            CanAnyFrame::Normal(frame) => CanFdFrame::from_classic(frame.as_ref()),
+            CanAnyFrame::Remote(frame) => CanFdFrame::from_classic(frame.as_ref()),
+            CanAnyFrame::Error(frame) => CanFdFrame::from_classic(frame.as_ref()),
+            CanAnyFrame::Fd(frame) => frame,
+        })
+    }
+
+    /// Gets a snapshot of the statistics counters for the socket.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> SocketStats {
+        self.1.stats()
+    }
+
+    /// Reads either type of frame from the socket, along with the time it
+    /// was received.
+    ///
+    /// Timestamps must first be enabled on the socket with
+    /// [`SocketOptions::enable_timestamps`] or
+    /// [`SocketOptions::set_timestamp_mode`], otherwise the time will be
+    /// `None`.
+    pub fn read_frame_with_timestamp(&self) -> IoResult<(CanAnyFrame, Option<SystemTime>)> {
+        let mut fdframe = canfd_frame_default();
+        let info = recv_msg(self.as_raw_fd(), as_bytes_mut(&mut fdframe), 0)?;
+        let frame = any_frame_from_buf(fdframe, info.len)?;
+        self.1.on_read(info.len);
+        Ok((frame, info.timestamp))
+    }
+
+    /// Reads the raw bytes of a single frame into the buffer, returning
+    /// the number of bytes read.
+    ///
+    /// Unlike a plain `read()`, this fails with an `InvalidData` error
+    /// wrapping [`SocketError::Truncated`] if the frame was larger than the
+    /// buffer, rather than silently dropping the rest of it. The kernel
+    /// doesn't report the real size of a raw CAN frame that was cut short.
+    pub fn read_checked(&self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = recv_checked(self.as_raw_fd(), buf)?;
+        self.1.on_read(n);
+        Ok(n)
+    }
+
+    /// Receives a message from the socket with a raw `recvmsg()` call.
+    ///
+    /// This is a low-level escape hatch for handling any of the ancillary
+    /// (control) messages from the kernel that this crate doesn't parse.
+    /// The data is received into `buf`, which should be large enough for
+    /// a frame, and the control messages are placed into the start of
+    /// `cmsg_buf`, as `cmsghdr` structs, for the caller to parse. The
+    /// size of those is in the `cmsg_len` of the result, and the
+    /// `MSG_CTRUNC` flag is set if they didn't fit. To parse the buffer in
+    /// place, it should be aligned for a `cmsghdr`.
+    ///
+    /// The data is not checked to be a valid frame.
+    pub fn recvmsg(&self, buf: &mut [u8], cmsg_buf: &mut [u8]) -> IoResult<RecvMsgResult> {
+        let res = recvmsg_raw(self.as_raw_fd(), buf, cmsg_buf, 0)?;
+        self.1.on_read(res.len);
+        Ok(res)
+    }
+
+    /// Enables scheduled transmission of frames with `write_frame_at()`.
+    ///
+    /// This sets `SO_TXTIME` on the socket, with the clock on which the
+    /// transmit times are given to the kernel. That has to match the clock
+    /// of the ETF qdisc on the interface, which is normally `CLOCK_TAI`.
+    ///
+    /// The kernel only holds each frame until its time if the interface
+    /// has such a qdisc, and otherwise sends frames right away. So this
+    /// looks up the qdiscs of the interface the socket is bound to, and
+    /// fails with [`SocketError::NoEtfQdisc`] if there isn't an ETF one.
+    /// That needs the **netlink** feature; without it, this fails with an
+    /// `Unsupported` error.
+    pub fn enable_txtime(&self, clockid: libc::clockid_t) -> IoResult<()> {
+        check_etf_qdisc(self)?;
+        let txtime = libc::sock_txtime { clockid, flags: 0 };
+        self.set_socket_option(SOL_SOCKET, SO_TXTIME, &txtime)?;
+        self.1.set_txtime_clock(clockid);
+        Ok(())
+    }
+
+    /// Writes a frame to be transmitted at the time given.
+    ///
+    /// The time is converted to the clock set with `enable_txtime()`. If
+    /// that wasn't called, this fails with an `InvalidInput` error wrapping
+    /// [`SocketError::TxTimeDisabled`], rather than sending the frame
+    /// immediately.
+    pub fn write_frame_at<F>(&self, frame: &F, txtime: SystemTime) -> IoResult<()>
+    where
+        F: Into<CanAnyFrame> + AsPtr,
+    {
+        let clockid = self.1.txtime_clock().ok_or(SocketError::TxTimeDisabled)?;
+        let bytes = frame.as_bytes();
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let txtime = system_time_to_clock_ns(txtime, clockid)?;
+        let res = send_at(self.as_raw_fd(), bytes, txtime);
+        self.1.on_write(&res, bytes.len());
+        res
+    }
+
+    /// Writes a frame to a specific interface.
+    ///
+    /// This is the write-side counterpart to `read_frame_from()`, sending
+    /// the frame with `sendto()` to the interface with the index given. It
+    /// lets a single socket bound to all interfaces (with an index of zero)
+    /// transmit on any of them, but also works on a socket bound to one
+    /// interface. The kernel has no interface to send to for an index of
+    /// zero, and fails with an `ENXIO` error.
+    pub fn write_frame_to<F>(&self, frame: &F, ifindex: u32) -> IoResult<()>
+    where
+        F: Into<CanAnyFrame> + AsPtr,
+    {
+        let bytes = frame.as_bytes();
+        check_frame_len(bytes)?;
+        self.1.check_write()?;
+        let addr = SockAddr::from(CanAddr::new(ifindex));
+        let res = self.as_raw_socket().send_to(bytes, &addr).map(|_| ());
+        self.1.on_write(&res, bytes.len());
+        res
+    }
+
+    /// Reads a frame along with the address of the interface that
+    /// received it.
+    ///
+    /// This is mainly of use for a socket bound to all interfaces (with an
+    /// interface index of zero) to determine the source of each frame.
+    pub fn read_frame_from(&self) -> IoResult<(CanAnyFrame, CanAddr)> {
+        let mut fdframe = canfd_frame_default();
+        let info = recv_msg(self.as_raw_fd(), as_bytes_mut(&mut fdframe), 0)?;
+        let frame = any_frame_from_buf(fdframe, info.len)?;
+        self.1.on_read(info.len);
+        Ok((frame, info.addr))
+    }
+
+    /// Reads a frame along with its metadata.
+    ///
+    /// This gets all of the information the kernel reports with the frame
+    /// in one call: the receive time and drop count (if enabled), the
+    /// interface the frame came from, and whether it was looped back by
+    /// the local host, or sent by this socket, rather than coming in off
+    /// the bus.
+    pub fn read_frame_meta(&self) -> IoResult<(CanAnyFrame, FrameMeta)> {
+        let mut fdframe = canfd_frame_default();
+        let info = recv_msg(self.as_raw_fd(), as_bytes_mut(&mut fdframe), 0)?;
+        let frame = any_frame_from_buf(fdframe, info.len)?;
+        self.1.on_read(info.len);
+        Ok((frame, FrameMeta::from_info(&info)))
+    }
+
+    /// Reads a frame along with the name of the interface that received it.
+    ///
+    /// The names are cached by the socket, so the interface is only looked
+    /// up the first time a frame is received from it. The cache is cleared
+    /// if a lookup fails, as when an interface is removed, but a renamed
+    /// interface keeps its old name until `clear_name_cache()` is called.
+    pub fn read_frame_named(&self) -> IoResult<(CanAnyFrame, String)> {
+        let (frame, addr) = self.read_frame_from()?;
+        let name = self.1.iface_name(addr.ifindex())?;
+        Ok((frame, name))
+    }
+
+    /// Clears the cache of interface names used by `read_frame_named()`.
+    pub fn clear_name_cache(&self) {
+        self.1.clear_names();
+    }
+
+    /// Sets the byte used to pad the FD frames created by the socket.
+    ///
+    /// This is applied by [`CanFdSocket::write_padded()`] when the data
+    /// is rounded up to a valid FD length. The default is zero. It has no
+    /// effect on frames created separately and passed to `write_frame()`,
+    /// as those are padded when they are created; use
+    /// [`CanFdFrame::new_padded()`] for them.
+    pub fn set_tx_pad_byte(&self, pad: u8) {
+        self.1.tx_pad.store(pad, Ordering::Relaxed);
+    }
+
+    /// Gets the byte used to pad the FD frames created by the socket.
+    pub fn tx_pad_byte(&self) -> u8 {
+        self.1.tx_pad.load(Ordering::Relaxed)
+    }
+
+    /// Creates an FD frame from the ID and data, and writes it to the
+    /// socket.
+    ///
+    /// If the data needs to be rounded up to a valid FD length, it is
+    /// padded with the socket's pad byte, set with `set_tx_pad_byte()`.
+    pub fn write_padded(&self, id: impl Into<Id>, data: &[u8]) -> IoResult<()> {
+        let frame = CanFdFrame::new_padded(id, data, self.tx_pad_byte()).ok_or_else(|| {
+            IoError::new(IoErrorKind::InvalidInput, ConstructionError::TooMuchData)
+        })?;
+        self.write_frame(&frame)
+    }
+
+    /// Shuts down the read, write, or both halves of the socket.
+    ///
+    /// CAN is a broadcast bus, not a connection-oriented protocol like TCP,
+    /// so this doesn't signal anything to the other nodes on the bus. It
+    /// only affects this socket, and the kernel's raw CAN sockets don't
+    /// support `shutdown(2)` at all, so it's emulated:
+    ///
+    /// - Write: any further frame writes fail with a `BrokenPipe` error.
+    /// - Read: all frames are filtered out, so no more are queued for the
+    ///   socket. Frames that were already received can still be read,
+    ///   after which reads block (or time out) as they would on an idle
+    ///   bus.
+    ///
+    /// A common use is to shut down the write side of a bridge while
+    /// continuing to drain the frames it has already received.
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        self.1.shutdown(self, how)
+    }
+}
+
+impl Socket for CanFdSocket {
+    /// CanFdSocket can read/write classic CAN 2.0 or FD frames.
+    type FrameType = CanAnyFrame;
+
+    /// Opens the FD socket by interface index.
+    fn open_addr(addr: &CanAddr) -> IoResult<Self> {
+        raw_open_socket(addr)
+            .and_then(|sock| Self::set_fd_mode(sock, true))
+            .map(|sock| Self(sock, SocketState::default()))
+    }
+
+    /// Gets a shared reference to the underlying socket object
+    fn as_raw_socket(&self) -> &socket2::Socket {
+        &self.0
+    }
+
+    /// Gets a mutable reference to the underlying socket object
+    fn as_raw_socket_mut(&mut self) -> &mut socket2::Socket {
+        &mut self.0
+    }
+
+    /// Writes any type of CAN frame to the socket.
     fn write_frame<F>(&self, frame: &F) -> IoResult<()>
     where
         F: Into<Self::FrameType> + AsPtr,
     {
-        self.as_raw_socket().write_all(frame.as_bytes())
+        check_frame_len(frame.as_bytes())?;
+        self.write_frame_unchecked(frame)
+    }
+
+    /// Writes any type of CAN frame to the socket without validating it.
+    fn write_frame_unchecked<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<Self::FrameType> + AsPtr,
+    {
+        let bytes = frame.as_bytes();
+        self.1.check_write()?;
+        let res = self.as_raw_socket().write_all(bytes);
+        self.1.on_write(&res, bytes.len());
+        res
     }
 
     /// Reads either type of CAN frame from the socket.
     fn read_frame(&self) -> IoResult<CanAnyFrame> {
         let mut fdframe = canfd_frame_default();
-
-        match self.as_raw_socket().read(as_bytes_mut(&mut fdframe))? {
-            // If we only get 'can_frame' number of bytes, then the return is,
-            // by definition, a can_frame, so we just copy the bytes into the
-            // proper type.
-            CAN_MTU => {
-                let mut frame = can_frame_default();
-                as_bytes_mut(&mut frame)[..CAN_MTU].copy_from_slice(&as_bytes(&fdframe)[..CAN_MTU]);
-                Ok(CanFrame::from(frame).into())
-            }
-            CANFD_MTU => Ok(CanFdFrame::from(fdframe).into()),
-            _ => Err(IoError::last_os_error()),
-        }
+        let n = recv_checked(self.as_raw_fd(), as_bytes_mut(&mut fdframe))?;
+        let frame = any_frame_from_buf(fdframe, n)?;
+        self.1.on_read(n);
+        Ok(frame)
     }
 }
 
@@ -803,7 +2797,7 @@ impl AsRawFd for CanFdSocket {
 
 impl From<OwnedFd> for CanFdSocket {
     fn from(fd: OwnedFd) -> CanFdSocket {
-        Self(socket2::Socket::from(fd))
+        Self(socket2::Socket::from(fd), SocketState::default())
     }
 }
 
@@ -811,9 +2805,9 @@ impl TryFrom<CanSocket> for CanFdSocket {
     type Error = IoError;
 
     fn try_from(sock: CanSocket) -> std::result::Result<Self, Self::Error> {
-        let CanSocket(sock2) = sock;
+        let CanSocket(sock2, state) = sock;
         let sock = CanFdSocket::set_fd_mode(sock2, true)?;
-        Ok(CanFdSocket(sock))
+        Ok(CanFdSocket(sock, state))
     }
 }
 
@@ -837,6 +2831,7 @@ impl Read for CanFdSocket {
 
 impl Write for CanFdSocket {
     fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.1.check_write()?;
         self.0.write(buf)
     }
 
@@ -845,6 +2840,540 @@ impl Write for CanFdSocket {
     }
 }
 
+/// Registers the socket with a `mio::Poll`, by its file descriptor.
+///
+/// The socket should be put in non-blocking mode, so that reads and
+/// writes can be retried when they would block.
+///
+/// This covers CAN XL traffic too, which is read and written on an FD
+/// socket with XL frames enabled.
+#[cfg(feature = "mio")]
+impl mio::event::Source for CanFdSocket {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> IoResult<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> IoResult<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> IoResult<()> {
+        mio::unix::SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
+
+// ===== ReconnectingCanSocket =====
+
+/// A CAN socket that transparently re-opens itself if its interface goes
+/// away.
+///
+/// This is meant for unattended devices with hot-pluggable, or flaky,
+/// USB-CAN adapters. When a read or write fails because the interface is
+/// gone (see [`is_interface_gone`](crate::is_interface_gone)), the socket
+/// waits for the interface to come back, re-opens it with the same
+/// [`OpenOptions`], and retries the operation. This is done up to a
+/// limited number of times for each operation, after which the error is
+/// returned to the caller.
+///
+/// Note that frames sent or received by other applications while the
+/// interface was down are lost.
+#[derive(Debug)]
+pub struct ReconnectingCanSocket {
+    /// The name of the interface
+    ifname: String,
+    /// The options to apply each time the socket is opened
+    opts: OpenOptions,
+    /// The current socket
+    sock: CanSocket,
+    /// The max number of reconnects for a single operation
+    max_reconnects: usize,
+    /// How long to wait for the interface to come back, on each reconnect
+    retry_timeout: Duration,
+    /// How often to check for the interface to come back
+    retry_interval: Duration,
+    /// The total number of times the socket was reconnected
+    reconnects: u64,
+}
+
+impl ReconnectingCanSocket {
+    /// The default max number of reconnects for a single operation.
+    pub const DEFAULT_MAX_RECONNECTS: usize = 3;
+
+    /// Opens the named CAN interface with the specified options.
+    ///
+    /// The initial open is not retried, so an error is returned if the
+    /// interface doesn't exist. Use [`Self::open_retry()`] to wait for it.
+    pub fn open(ifname: &str, opts: &OpenOptions) -> IoResult<Self> {
+        let sock = CanSocket::open_with(ifname, opts)?;
+        Ok(Self::new(ifname, opts, sock))
+    }
+
+    /// Opens the named CAN interface with the specified options, waiting
+    /// up to `timeout` for the interface to appear.
+    pub fn open_retry(
+        ifname: &str,
+        opts: &OpenOptions,
+        timeout: Duration,
+        interval: Duration,
+    ) -> IoResult<Self> {
+        let sock = retry_open(timeout, interval, || CanSocket::open_with(ifname, opts))?;
+        Ok(Self::new(ifname, opts, sock))
+    }
+
+    fn new(ifname: &str, opts: &OpenOptions, sock: CanSocket) -> Self {
+        Self {
+            ifname: ifname.to_string(),
+            opts: opts.clone(),
+            sock,
+            max_reconnects: Self::DEFAULT_MAX_RECONNECTS,
+            retry_timeout: Duration::from_secs(5),
+            retry_interval: Duration::from_millis(100),
+            reconnects: 0,
+        }
+    }
+
+    /// Sets the max number of times the socket will reconnect during a
+    /// single read or write before giving up and returning the error.
+    pub fn max_reconnects(mut self, n: usize) -> Self {
+        self.max_reconnects = n;
+        self
+    }
+
+    /// Sets how long each reconnect waits for the interface to come back,
+    /// and how often it checks for it.
+    ///
+    /// The default is to wait up to 5 seconds, checking every 100ms.
+    pub fn retry_timing(mut self, timeout: Duration, interval: Duration) -> Self {
+        self.retry_timeout = timeout;
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Gets the name of the interface.
+    pub fn ifname(&self) -> &str {
+        &self.ifname
+    }
+
+    /// Gets a reference to the current underlying socket.
+    ///
+    /// Note that this is replaced whenever the socket reconnects.
+    pub fn socket(&self) -> &CanSocket {
+        &self.sock
+    }
+
+    /// Gets the total number of times the socket has reconnected.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Re-opens the socket, waiting for the interface to come back.
+    pub fn reconnect(&mut self) -> IoResult<()> {
+        let (ifname, opts) = (&self.ifname, &self.opts);
+        self.sock = retry_open(self.retry_timeout, self.retry_interval, || {
+            CanSocket::open_with(ifname, opts)
+        })?;
+        self.reconnects += 1;
+        Ok(())
+    }
+
+    /// Runs an operation on the socket, reconnecting and retrying it if it
+    /// fails because the interface went away.
+    fn with_reconnect<T, F>(&mut self, mut op: F) -> IoResult<T>
+    where
+        F: FnMut(&CanSocket) -> IoResult<T>,
+    {
+        let mut n = 0;
+        loop {
+            match op(&self.sock) {
+                Err(err) if is_interface_gone(&err) && n < self.max_reconnects => {
+                    n += 1;
+                    self.reconnect()?;
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Blocking read of a single frame, reconnecting as needed.
+    pub fn read_frame(&mut self) -> IoResult<CanFrame> {
+        self.with_reconnect(|sock| sock.read_frame())
+    }
+
+    /// Writes a single frame, reconnecting as needed.
+    pub fn write_frame<F>(&mut self, frame: &F) -> IoResult<()>
+    where
+        F: Into<CanFrame> + AsPtr,
+    {
+        self.with_reconnect(|sock| sock.write_frame(frame))
+    }
+}
+
+impl AsRawFd for ReconnectingCanSocket {
+    /// Gets the file descriptor of the current socket.
+    ///
+    /// Note that this changes whenever the socket reconnects.
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+// ===== MmsgBuffers =====
+
+/// The number of frames that `recv_frames_timeout()` receives with each
+/// `recvmmsg()` call.
+const RECV_BURST: usize = 32;
+
+/// The buffers for receiving a burst of classic frames with `recvmmsg()`.
+///
+/// The message headers point to the iovecs, and the iovecs into the raw
+/// frames, and they're pointed again before each receive, so that the
+/// buffers can be moved in between.
+#[derive(Clone)]
+struct MmsgBuffers<const N: usize> {
+    raw: [libc::can_frame; N],
+    iovs: [libc::iovec; N],
+    msgs: [libc::mmsghdr; N],
+}
+
+// The raw pointers all refer to memory owned by the struct, and are only
+// used through a mutable reference.
+unsafe impl<const N: usize> Send for MmsgBuffers<N> {}
+unsafe impl<const N: usize> Sync for MmsgBuffers<N> {}
+
+impl<const N: usize> MmsgBuffers<N> {
+    /// Creates the buffers for a burst of up to `N` frames.
+    fn new() -> Self {
+        Self {
+            raw: [can_frame_default(); N],
+            // SAFETY: These are plain C structs, for which null pointers
+            // and zero lengths are valid.
+            iovs: unsafe { mem::zeroed() },
+            msgs: unsafe { mem::zeroed() },
+        }
+    }
+
+    /// Receives up to `N` frames, or as many as fit in `out`, with a single
+    /// `recvmmsg()` call, and returns the number placed at the start of
+    /// `out`. Any message that isn't the size of a classic CAN frame is
+    /// skipped.
+    fn recv(&mut self, sock: &CanSocket, out: &mut [CanFrame], flags: c_int) -> IoResult<usize> {
+        let max = out.len().min(N);
+        for ((iov, msg), frame) in self
+            .iovs
+            .iter_mut()
+            .zip(self.msgs.iter_mut())
+            .zip(self.raw.iter_mut())
+            .take(max)
+        {
+            iov.iov_base = (frame as *mut libc::can_frame).cast();
+            iov.iov_len = CAN_MTU;
+            msg.msg_hdr.msg_iov = iov;
+            msg.msg_hdr.msg_iovlen = 1;
+        }
+
+        let n = unsafe {
+            libc::recvmmsg(
+                sock.as_raw_fd(),
+                self.msgs.as_mut_ptr(),
+                max as _,
+                flags as _,
+                ptr::null_mut(),
+            )
+        };
+        if n < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        let mut count = 0;
+        for (msg, frame) in self.msgs.iter().zip(&self.raw).take(n as usize) {
+            if msg.msg_len as usize == CAN_MTU {
+                sock.1.on_read(CAN_MTU);
+                out[count] = CanFrame::from(*frame);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+// ===== CanRingReader =====
+
+/// A reader that receives bursts of frames into a fixed-size buffer.
+///
+/// The reader owns space for `N` frames, and fills as many of them as are
+/// available with a single `recvmmsg()` call. No memory is allocated after
+/// it's created, so it can be used in a capture loop with bounded memory,
+/// as long as `N` is sized for the worst-case burst of frames between reads.
+///
+/// The buffers are part of the struct, so a reader for a large `N` should
+/// be boxed or kept in a static rather than placed on the stack.
+///
+/// ```no_run
+/// use socketcan::{CanRingReader, CanSocket, Socket};
+///
+/// let sock = CanSocket::open("vcan0").unwrap();
+/// let mut reader = CanRingReader::<64>::new();
+///
+/// loop {
+///     for frame in reader.read(&sock).unwrap() {
+///         println!("{:X}", frame);
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct CanRingReader<const N: usize> {
+    /// The buffers that the kernel reads into
+    bufs: MmsgBuffers<N>,
+    /// The frames from the last read
+    frames: [CanFrame; N],
+    /// The number of valid frames from the last read
+    len: usize,
+}
+
+impl<const N: usize> CanRingReader<N> {
+    /// Creates a new reader with space for `N` frames.
+    pub fn new() -> Self {
+        Self {
+            bufs: MmsgBuffers::new(),
+            frames: [CanFrame::default(); N],
+            len: 0,
+        }
+    }
+
+    /// The maximum number of frames that can be received in a single read.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Reads a burst of frames from the socket.
+    ///
+    /// This blocks, subject to the socket's read timeout or non-blocking
+    /// mode, until at least one frame is available, then returns all the
+    /// frames that were queued, up to the capacity of the reader. Any
+    /// message that isn't the size of a classic CAN frame is skipped.
+    ///
+    /// The frames are valid until the next read.
+    pub fn read(&mut self, sock: &CanSocket) -> IoResult<&[CanFrame]> {
+        self.len = 0;
+        self.len = self
+            .bufs
+            .recv(sock, &mut self.frames, libc::MSG_WAITFORONE)?;
+        Ok(self.frames())
+    }
+
+    /// Gets the frames from the last read.
+    pub fn frames(&self) -> &[CanFrame] {
+        &self.frames[..self.len]
+    }
+}
+
+impl<const N: usize> Default for CanRingReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Debug for CanRingReader<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CanRingReader")
+            .field("capacity", &N)
+            .field("frames", &self.frames())
+            .finish()
+    }
+}
+
+// ===== CanReadHalf / CanWriteHalf =====
+
+/// The read half of a socket, from [`Socket::into_split()`].
+#[derive(Debug)]
+pub struct CanReadHalf<T: Socket = CanSocket>(Arc<T>);
+
+impl<T: Socket> CanReadHalf<T> {
+    /// Gets a reference to the socket, for its options.
+    pub fn as_socket(&self) -> &T {
+        &self.0
+    }
+
+    /// Blocking read a single can frame.
+    pub fn read_frame(&self) -> IoResult<T::FrameType> {
+        self.0.read_frame()
+    }
+
+    /// Blocking read a single can frame with timeout.
+    pub fn read_frame_timeout(&self, timeout: Duration) -> IoResult<T::FrameType> {
+        self.0.read_frame_timeout(timeout)
+    }
+
+    /// Determines if the halves are from the same socket.
+    pub fn is_pair_of(&self, other: &CanWriteHalf<T>) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Puts the socket back together from its two halves.
+    ///
+    /// If the halves aren't from the same socket, they're given back.
+    pub fn reunite(
+        self,
+        other: CanWriteHalf<T>,
+    ) -> std::result::Result<T, (Self, CanWriteHalf<T>)> {
+        if !self.is_pair_of(&other) {
+            return Err((self, other));
+        }
+        drop(other);
+        // The write half was the only other reference
+        Ok(Arc::try_unwrap(self.0).unwrap_or_else(|_| unreachable!("socket half still shared")))
+    }
+}
+
+impl<T: Socket> AsRawFd for CanReadHalf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// The write half of a socket, from [`Socket::into_split()`].
+#[derive(Debug)]
+pub struct CanWriteHalf<T: Socket = CanSocket>(Arc<T>);
+
+impl<T: Socket> CanWriteHalf<T> {
+    /// Gets a reference to the socket, for its options.
+    pub fn as_socket(&self) -> &T {
+        &self.0
+    }
+
+    /// Writes a frame to the socket.
+    ///
+    /// See [`Socket::write_frame()`].
+    pub fn write_frame<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<T::FrameType> + AsPtr,
+    {
+        self.0.write_frame(frame)
+    }
+
+    /// Blocking write a single can frame, retrying until it gets sent
+    /// successfully.
+    pub fn write_frame_insist<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<T::FrameType> + AsPtr,
+    {
+        self.0.write_frame_insist(frame)
+    }
+}
+
+impl<T: Socket> AsRawFd for CanWriteHalf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+// ===== XlVcidOptions =====
+
+/// The socket option to set the XL virtual CAN network ID options
+pub const CAN_RAW_XL_VCID_OPTS: c_int = 8;
+
+bitflags! {
+    /// Flags for the handling of XL virtual CAN network IDs on a socket.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct XlVcidFlags: u8 {
+        /// Set the VCID of the frames sent to `tx_vcid`
+        const TX_SET = 0x01;
+        /// Pass the VCID of the frames sent through, as they are
+        const TX_PASS = 0x02;
+        /// Only receive the frames with a VCID that matches the filter
+        const RX_FILTER = 0x04;
+    }
+}
+
+/// The kernel's XL VCID options, from `linux/can/raw.h`.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub struct can_raw_vcid_options {
+    /// The `CAN_RAW_XL_VCID_*` flags
+    pub flags: u8,
+    /// The VCID set on frames sent
+    pub tx_vcid: u8,
+    /// The VCID that received frames are filtered on
+    pub rx_vcid: u8,
+    /// The mask for the VCID filter
+    pub rx_vcid_mask: u8,
+}
+
+/// Options for the virtual CAN network IDs (VCIDs) of the XL frames on a
+/// socket.
+///
+/// ```
+/// use socketcan::{XlVcidFlags, XlVcidOptions};
+///
+/// // Send on virtual network 3, and receive from networks 2 and 3
+/// let opts = XlVcidOptions::new().tx_vcid(3).rx_filter(2, 0xFE);
+/// assert_eq!(opts.flags(), XlVcidFlags::TX_SET | XlVcidFlags::RX_FILTER);
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct XlVcidOptions(can_raw_vcid_options);
+
+impl XlVcidOptions {
+    /// Creates the default options, where the kernel clears the VCIDs of
+    /// the frames sent and received.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the flags that are set.
+    pub fn flags(&self) -> XlVcidFlags {
+        XlVcidFlags::from_bits_truncate(self.0.flags)
+    }
+
+    /// Sets the VCID of all the frames sent, overriding the one in each
+    /// frame.
+    pub fn tx_vcid(mut self, vcid: u8) -> Self {
+        self.0.flags |= XlVcidFlags::TX_SET.bits();
+        self.0.tx_vcid = vcid;
+        self
+    }
+
+    /// Enables or disables sending the VCID in each frame as it is.
+    pub fn tx_pass(mut self, on: bool) -> Self {
+        let mut flags = self.flags();
+        flags.set(XlVcidFlags::TX_PASS, on);
+        self.0.flags = flags.bits();
+        self
+    }
+
+    /// Only receives the frames whose VCID matches the one given, under
+    /// the mask, and passes their VCIDs through.
+    pub fn rx_filter(mut self, vcid: u8, mask: u8) -> Self {
+        self.0.flags |= XlVcidFlags::RX_FILTER.bits();
+        self.0.rx_vcid = vcid;
+        self.0.rx_vcid_mask = mask;
+        self
+    }
+}
+
+impl From<can_raw_vcid_options> for XlVcidOptions {
+    fn from(opts: can_raw_vcid_options) -> Self {
+        Self(opts)
+    }
+}
+
+impl AsRef<can_raw_vcid_options> for XlVcidOptions {
+    fn as_ref(&self) -> &can_raw_vcid_options {
+        &self.0
+    }
+}
+
 // ===== CanFilter =====
 
 /// The CAN filter defines which ID's can be accepted on a socket.
@@ -871,6 +3400,87 @@ impl CanFilter {
     pub fn new_inverted(id: canid_t, mask: canid_t) -> Self {
         Self::new(id | libc::CAN_INV_FILTER, mask)
     }
+
+    /// Construct a filter that only matches data frames with exactly the
+    /// ID given.
+    ///
+    /// The mask covers all the bits of the ID, along with the EFF flag, so
+    /// that a standard ID doesn't also match the extended ID with the same
+    /// value, or vice versa, and the RTR flag, so that remote frames for
+    /// the ID are not matched.
+    pub fn exact(id: impl Into<Id>) -> Self {
+        let id = id.into();
+        let id_mask = match id {
+            Id::Standard(_) => libc::CAN_SFF_MASK,
+            Id::Extended(_) => libc::CAN_EFF_MASK,
+        };
+        let mask = libc::CAN_EFF_FLAG | libc::CAN_RTR_FLAG | id_mask;
+        Self::new(id_to_canid_t(id), mask)
+    }
+
+    /// Determines if a frame with the ID word, which is the ID along with
+    /// its EFF, RTR, and ERR flags, passes the filter, as it would in the
+    /// kernel.
+    pub fn matches(&self, id_word: canid_t) -> bool {
+        let inverted = self.0.can_id & libc::CAN_INV_FILTER != 0;
+        let id = self.0.can_id & !libc::CAN_INV_FILTER;
+        let hit = id_word & self.0.can_mask == id & self.0.can_mask;
+        hit != inverted
+    }
+}
+
+/// Creates an exact-match filter for each of the IDs, with
+/// [`CanFilter::exact()`].
+pub fn filters_for_ids(ids: &[Id]) -> Vec<CanFilter> {
+    ids.iter().map(|id| CanFilter::exact(*id)).collect()
+}
+
+/// Creates the fewest mask filters that together pass the data frames
+/// with the IDs in the range, from `start` to `end`, inclusive.
+///
+/// A range from a standard ID to an extended one covers the standard IDs
+/// from the start up, and the extended IDs up to the end. A range that
+/// ends before it starts is empty, and has no filters.
+pub fn filters_for_id_range(start: Id, end: Id) -> Vec<CanFilter> {
+    let mut filters = Vec::new();
+    match (start, end) {
+        (Id::Standard(lo), Id::Standard(hi)) => {
+            push_range_filters(&mut filters, lo.as_raw().into(), hi.as_raw().into(), false)
+        }
+        (Id::Extended(lo), Id::Extended(hi)) => {
+            push_range_filters(&mut filters, lo.as_raw(), hi.as_raw(), true)
+        }
+        (Id::Standard(lo), Id::Extended(hi)) => {
+            push_range_filters(&mut filters, lo.as_raw().into(), libc::CAN_SFF_MASK, false);
+            push_range_filters(&mut filters, 0, hi.as_raw(), true);
+        }
+        (Id::Extended(_), Id::Standard(_)) => (),
+    }
+    filters
+}
+
+/// Adds filters for the IDs from `lo` up to `hi`, splitting the range
+/// into aligned, power-of-two sized blocks that can each be matched with
+/// a mask.
+fn push_range_filters(filters: &mut Vec<CanFilter>, mut lo: u32, hi: u32, extended: bool) {
+    let (id_mask, eff) = if extended {
+        (libc::CAN_EFF_MASK, libc::CAN_EFF_FLAG)
+    } else {
+        (libc::CAN_SFF_MASK, 0)
+    };
+    while lo <= hi {
+        let mut size = if lo == 0 {
+            id_mask + 1
+        } else {
+            1 << lo.trailing_zeros()
+        };
+        while lo + (size - 1) > hi {
+            size >>= 1;
+        }
+        let mask = libc::CAN_EFF_FLAG | libc::CAN_RTR_FLAG | (id_mask & !(size - 1));
+        filters.push(CanFilter::new(lo | eff, mask));
+        lo += size;
+    }
 }
 
 impl From<libc::can_filter> for CanFilter {
@@ -890,3 +3500,367 @@ impl AsRef<libc::can_filter> for CanFilter {
         &self.0
     }
 }
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::can_frame_default;
+
+    impl SocketOptions for std::net::UdpSocket {}
+
+    #[test]
+    fn test_system_time_to_clock_ns() {
+        let time = UNIX_EPOCH + Duration::from_nanos(1_700_000_000_123_456_789);
+        let ns = system_time_to_clock_ns(time, libc::CLOCK_REALTIME).unwrap();
+        assert_eq!(ns, 1_700_000_000_123_456_789);
+
+        // TAI is ahead of UTC, by a whole number of leap seconds
+        let ns = system_time_to_clock_ns(time, libc::CLOCK_TAI).unwrap();
+        assert!(ns >= 1_700_000_000_000_000_000);
+
+        let time = UNIX_EPOCH - Duration::from_secs(1);
+        assert!(system_time_to_clock_ns(time, libc::CLOCK_REALTIME).is_err());
+    }
+
+    #[test]
+    fn test_mixed_frame_from_buf() {
+        let id = StandardId::new(0x123).unwrap();
+
+        // An XL frame with 4 bytes of data is the same size as a classic one
+        let frame = CanXlFrame::new(id, 0x01, 0, &[1, 2, 3, 4]).unwrap();
+        let n = frame.as_bytes().len();
+        assert_eq!(n, CAN_MTU);
+        check_frame_len(frame.as_bytes()).unwrap();
+        match mixed_frame_from_buf(frame.as_ref(), n).unwrap() {
+            CanMixedFrame::Xl(frame) => assert_eq!(frame.data(), &[1, 2, 3, 4]),
+            frame => panic!("expected an XL frame, got {:?}", frame),
+        }
+
+        // But the size has to match its length
+        assert!(mixed_frame_from_buf(frame.as_ref(), n + 1).is_err());
+        assert!(check_frame_len(&frame.as_bytes()[..n - 1]).is_err());
+
+        let mut xlframe = canxl_frame_default();
+        let frame = CanFrame::new(id, &[1, 2]).unwrap();
+        as_bytes_mut(&mut xlframe)[..CAN_MTU].copy_from_slice(frame.as_bytes());
+        assert!(matches!(
+            mixed_frame_from_buf(&xlframe, CAN_MTU).unwrap(),
+            CanMixedFrame::Classic(_)
+        ));
+
+        let frame = CanFdFrame::new(id, &[1; 12]).unwrap();
+        as_bytes_mut(&mut xlframe)[..CANFD_MTU].copy_from_slice(frame.as_bytes());
+        assert!(matches!(
+            mixed_frame_from_buf(&xlframe, CANFD_MTU).unwrap(),
+            CanMixedFrame::Fd(_)
+        ));
+
+        assert!(mixed_frame_from_buf(&xlframe, 10).is_err());
+    }
+
+    #[test]
+    fn test_exact_filter() {
+        let std_id = StandardId::new(0x123).unwrap();
+        let ext_id = ExtendedId::new(0x123).unwrap();
+        let eff = libc::CAN_EFF_FLAG;
+
+        let filter = CanFilter::exact(std_id);
+        assert_eq!(filter.as_ref().can_id, 0x123);
+        assert!(filter.matches(0x123));
+        assert!(!filter.matches(0x123 | eff));
+        assert!(!filter.matches(0x123 | libc::CAN_RTR_FLAG));
+        assert!(!filter.matches(0x124));
+        assert!(CanFilter::new_inverted(0x123, libc::CAN_SFF_MASK).matches(0x124));
+
+        let filter = CanFilter::exact(ext_id);
+        assert_eq!(filter.as_ref().can_id, 0x123 | eff);
+        assert!(filter.matches(0x123 | eff));
+        assert!(!filter.matches(0x123));
+        assert!(!filter.matches(0x1000_0123 | eff));
+
+        let ids = [Id::from(std_id), Id::from(ext_id)];
+        let filters = filters_for_ids(&ids);
+        assert_eq!(filters.len(), 2);
+        assert_eq!(filters[0], CanFilter::exact(std_id));
+        assert_eq!(filters[1], CanFilter::exact(ext_id));
+    }
+
+    #[test]
+    fn test_filters_for_id_range() {
+        use libc::{CAN_EFF_FLAG, CAN_EFF_MASK, CAN_RTR_FLAG, CAN_SFF_MASK};
+
+        let std = |id| Id::from(StandardId::new(id).unwrap());
+        let ext = |id| Id::from(ExtendedId::new(id).unwrap());
+        let passes = |filters: &[CanFilter], id_word| filters.iter().any(|f| f.matches(id_word));
+
+        // 0x100-0x10F is a single block
+        let filters = filters_for_id_range(std(0x100), std(0x10F));
+        assert_eq!(
+            filters,
+            [CanFilter::new(0x100, CAN_EFF_FLAG | CAN_RTR_FLAG | 0x7F0)]
+        );
+
+        let filters = filters_for_id_range(std(0x0FF), std(0x123));
+        assert_eq!(filters.len(), 3);
+        for id in 0..=CAN_SFF_MASK {
+            let inside = (0x0FF..=0x123).contains(&id);
+            assert_eq!(passes(&filters, id), inside, "{:X}", id);
+            assert!(!passes(&filters, id | CAN_EFF_FLAG));
+            assert!(!passes(&filters, id | CAN_RTR_FLAG));
+        }
+
+        // Every extended ID is a single block
+        let filters = filters_for_id_range(ext(0), ext(CAN_EFF_MASK));
+        assert_eq!(filters.len(), 1);
+        assert!(passes(&filters, 0x1234_5678 | CAN_EFF_FLAG));
+        assert!(!passes(&filters, 0x123));
+
+        let filters = filters_for_id_range(std(0x7FE), ext(1));
+        assert!(passes(&filters, 0x7FE) && passes(&filters, 0x7FF));
+        assert!(passes(&filters, CAN_EFF_FLAG) && passes(&filters, 1 | CAN_EFF_FLAG));
+        assert!(!passes(&filters, 0x7FD) && !passes(&filters, 2 | CAN_EFF_FLAG));
+
+        assert!(filters_for_id_range(std(0x200), std(0x100)).is_empty());
+        assert!(filters_for_id_range(ext(0), std(0x100)).is_empty());
+    }
+
+    #[test]
+    fn test_in_netns_missing() {
+        let res = in_netns(Path::new("/var/run/netns/no-such-netns"), || Ok(()));
+        assert_eq!(res.unwrap_err().kind(), IoErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_get_socket_option() {
+        let sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        sock.set_socket_option(SOL_SOCKET, libc::SO_BROADCAST, &(1 as c_int))
+            .unwrap();
+
+        let val: c_int = sock
+            .get_socket_option(SOL_SOCKET, libc::SO_BROADCAST)
+            .unwrap();
+        assert_eq!(val, 1);
+
+        // The kernel only fills in an int
+        let res = sock.get_socket_option::<u64>(SOL_SOCKET, libc::SO_BROADCAST);
+        assert_eq!(res.unwrap_err().kind(), IoErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_timestamp_mode() {
+        let sock = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let opt = |name| sock.get_socket_option::<c_int>(SOL_SOCKET, name).unwrap();
+
+        sock.set_socket_option(SOL_SOCKET, SO_TIMESTAMPNS, &1)
+            .unwrap();
+        sock.set_timestamp_mode(TimestampMode::Software).unwrap();
+        assert_ne!(opt(SO_TIMESTAMPING), 0);
+        assert_eq!(opt(SO_TIMESTAMPNS), 0);
+
+        // Switching to legacy timestamps turns off the others
+        sock.set_timestamp_mode(TimestampMode::Legacy).unwrap();
+        assert_eq!(opt(SO_TIMESTAMPING), 0);
+        assert_eq!(opt(SO_TIMESTAMPNS), 0);
+        assert_eq!(opt(SO_TIMESTAMP), 1);
+
+        sock.set_timestamp_mode(TimestampMode::Disabled).unwrap();
+        assert_eq!(opt(SO_TIMESTAMP), 0);
+    }
+
+    #[test]
+    fn test_ring_reader() {
+        let reader = CanRingReader::<16>::new();
+        assert_eq!(reader.capacity(), 16);
+        assert!(reader.frames().is_empty());
+
+        let reader = CanRingReader::<0>::default();
+        assert_eq!(reader.capacity(), 0);
+    }
+
+    #[test]
+    fn test_ring_reader_read() {
+        use std::os::unix::net::UnixDatagram;
+
+        // A datagram pair can stand in for a CAN socket
+        let (a, b) = UnixDatagram::pair().unwrap();
+        let tx = CanSocket::from(OwnedFd::from(a));
+        let rx = CanSocket::from(OwnedFd::from(b));
+
+        let id = StandardId::new(0x42).unwrap();
+        for i in 0..5 {
+            tx.write_frame(&CanFrame::new(id, &[i]).unwrap()).unwrap();
+        }
+
+        // The reader can be moved between reads
+        let mut reader = Box::new(CanRingReader::<4>::new());
+        let frames = reader.read(&rx).unwrap();
+        assert_eq!(frames.len(), 4);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.id(), Id::Standard(id));
+            assert_eq!(frame.data(), &[i as u8]);
+        }
+
+        let mut reader = *reader;
+        let frames = reader.read(&rx).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data(), &[4]);
+    }
+
+    #[test]
+    fn test_recv_frames_timeout() {
+        use std::os::unix::net::UnixDatagram;
+
+        let (a, b) = UnixDatagram::pair().unwrap();
+        let tx = CanSocket::from(OwnedFd::from(a));
+        let rx = CanSocket::from(OwnedFd::from(b));
+
+        // More than one burst's worth
+        let id = StandardId::new(0x42).unwrap();
+        let total = RECV_BURST + 8;
+        for i in 0..total {
+            tx.write_frame(&CanFrame::new(id, &[i as u8]).unwrap())
+                .unwrap();
+        }
+
+        let mut buf = vec![CanFrame::default(); total + 4];
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let n = rx.recv_frames_timeout(&mut buf, deadline).unwrap();
+        assert_eq!(n, total);
+        for (i, frame) in buf[..n].iter().enumerate() {
+            assert_eq!(frame.data(), &[i as u8]);
+        }
+    }
+
+    #[test]
+    fn test_into_split() {
+        use std::os::unix::net::UnixDatagram;
+
+        // A datagram pair can stand in for a CAN socket
+        let (a, b) = UnixDatagram::pair().unwrap();
+        let (_, tx) = CanSocket::from(OwnedFd::from(a)).into_split();
+        let (rx, rx_tx) = CanSocket::from(OwnedFd::from(b)).into_split();
+
+        let id = StandardId::new(0x42).unwrap();
+        let writer = thread::spawn(move || {
+            let frame = CanFrame::new(id, &[1, 2, 3]).unwrap();
+            tx.write_frame(&frame).unwrap();
+            tx
+        });
+        let frame = rx.read_frame_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(frame.data(), &[1, 2, 3]);
+
+        let tx = writer.join().unwrap();
+        assert!(!rx.is_pair_of(&tx));
+        let (rx, _tx) = rx.reunite(tx).unwrap_err();
+        assert!(rx.reunite(rx_tx).is_ok());
+    }
+
+    #[test]
+    fn test_retry_open() {
+        let timeout = Duration::from_secs(1);
+        let interval = Duration::from_millis(1);
+
+        // Retries while the interface is gone
+        let mut n = 0;
+        let res = retry_open(timeout, interval, || {
+            n += 1;
+            match n {
+                1 | 2 => Err(IoError::from_raw_os_error(libc::ENODEV)),
+                _ => Ok(n),
+            }
+        });
+        assert_eq!(res.unwrap(), 3);
+
+        // Other errors are returned immediately
+        let mut n = 0;
+        let res: IoResult<()> = retry_open(timeout, interval, || {
+            n += 1;
+            Err(IoError::from_raw_os_error(libc::EPERM))
+        });
+        assert!(res.is_err());
+        assert_eq!(n, 1);
+
+        // Gives up after the timeout
+        let res: IoResult<()> = retry_open(Duration::ZERO, interval, || {
+            Err(IoError::from_raw_os_error(libc::ENODEV))
+        });
+        assert!(is_interface_gone(&res.unwrap_err()));
+    }
+
+    #[test]
+    fn test_iface_name_cache() {
+        let state = SocketState::default();
+        let lo = nix::net::if_::if_nametoindex("lo").unwrap() as c_int;
+
+        assert_eq!(state.iface_name(lo).unwrap(), "lo");
+        assert!(state.names.lock().unwrap().contains_key(&lo));
+
+        // A failed lookup clears the cache
+        assert!(state.iface_name(c_int::MAX).is_err());
+        assert!(state.names.lock().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats() {
+        let state = SocketState::default();
+        assert_eq!(state.stats(), SocketStats::default());
+
+        state.on_write(&Ok(()), CAN_MTU);
+        state.on_write(&Ok(()), CANFD_MTU);
+        state.on_write(&Err(IoErrorKind::WouldBlock.into()), CAN_MTU);
+        state.on_read(CAN_MTU);
+
+        let stats = state.stats();
+        assert_eq!(stats.frames_sent, 2);
+        assert_eq!(stats.bytes_sent, (CAN_MTU + CANFD_MTU) as u64);
+        assert_eq!(stats.write_errors, 1);
+        assert_eq!(stats.frames_received, 1);
+        assert_eq!(stats.bytes_received, CAN_MTU as u64);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats_invalid_read() {
+        use std::os::unix::net::UnixDatagram;
+
+        let (a, b) = UnixDatagram::pair().unwrap();
+        let rx = CanFdSocket::from(OwnedFd::from(b));
+
+        // A message that's neither frame size isn't counted
+        a.send(&[0; 3]).unwrap();
+        let err = rx.read_raw_frame().err().unwrap();
+        assert_eq!(err.kind(), IoErrorKind::InvalidData);
+        assert_eq!(rx.stats().frames_received, 0);
+
+        a.send(as_bytes(&can_frame_default())).unwrap();
+        assert!(rx.read_raw_frame().is_ok());
+        assert_eq!(rx.stats().frames_received, 1);
+    }
+
+    #[test]
+    fn test_check_frame_len() {
+        let mut frame = can_frame_default();
+        frame.can_dlc = 8;
+        assert!(check_frame_len(as_bytes(&frame)).is_ok());
+
+        frame.can_dlc = 9;
+        let err = check_frame_len(as_bytes(&frame)).unwrap_err();
+        assert_eq!(err.kind(), IoErrorKind::InvalidInput);
+
+        let mut frame = canfd_frame_default();
+        frame.len = 12;
+        assert!(check_frame_len(as_bytes(&frame)).is_ok());
+
+        frame.len = 64;
+        assert!(check_frame_len(as_bytes(&frame)).is_ok());
+
+        frame.len = 13;
+        assert!(check_frame_len(as_bytes(&frame)).is_err());
+
+        frame.len = 65;
+        assert!(check_frame_len(as_bytes(&frame)).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+// socketcan/src/socketcand.rs
+//
+// A client for the socketcand network protocol.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A client for remote CAN buses exported by `socketcand`.
+//!
+//! The `socketcand` daemon makes the SocketCAN interfaces of a Linux host
+//! available over TCP, with a simple text protocol. Each message is
+//! wrapped in angle brackets, like `< open can0 >`. A client opens one
+//! bus on a connection, and switches it to raw mode, after which the
+//! frames received on the bus are sent to the client, and the client can
+//! send frames of its own.
+//!
+//! A [`SocketcandClient`] reads and writes the same [`CanFrame`] types as
+//! a [`CanSocket`](crate::CanSocket), so that an application can work with
+//! a bus on another machine much as it would with a local one.
+//!
+//! ```no_run
+//! use socketcan::{socketcand::SocketcandClient, CanFrame, EmbeddedFrame, StandardId};
+//!
+//! let mut client = SocketcandClient::connect("192.168.1.10:29536", "can0").unwrap();
+//!
+//! let id = StandardId::new(0x123).unwrap();
+//! client.write_frame(&CanFrame::new(id, &[1, 2, 3]).unwrap()).unwrap();
+//!
+//! let frame = client.read_frame().unwrap();
+//! println!("{:?}", frame);
+//! ```
+//!
+//! The protocol is described at:
+//!
+//! <https://github.com/linux-can/socketcand/blob/master/doc/protocol.md>
+
+use crate::{CanFrame, EmbeddedFrame, Error, ExtendedId, Id, StandardId};
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The TCP port that `socketcand` listens on by default
+pub const DEFAULT_PORT: u16 = 29536;
+
+/// The longest message accepted from the server, which is well beyond the
+/// largest frame message
+const MAX_MSG: usize = 1024;
+
+/// Creates an `InvalidData` error, for a message that breaks the protocol
+fn protocol_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("socketcand: {}", msg))
+}
+
+/// Encodes a frame as a `send` command.
+///
+/// Only data frames can be sent. Others fail with an `InvalidInput`
+/// error.
+pub fn encode_send(frame: &CanFrame) -> io::Result<String> {
+    if !matches!(frame, CanFrame::Data(_)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only data frames can be sent to socketcand",
+        ));
+    }
+
+    let mut s = String::from("< send ");
+    let _ = match frame.id() {
+        Id::Standard(id) => write!(s, "{:03X} ", id.as_raw()),
+        Id::Extended(id) => write!(s, "{:08X} ", id.as_raw()),
+    };
+    let _ = write!(s, "{}", frame.data().len());
+    for b in frame.data() {
+        let _ = write!(s, " {:02X}", b);
+    }
+    s.push_str(" >");
+    Ok(s)
+}
+
+/// Decodes a `frame` message, as sent in raw mode, without its brackets.
+///
+/// An ID of more than three hex digits is an extended one. The data may
+/// be given as separate bytes, or run together. Returns `None` if the
+/// message isn't a valid frame.
+pub fn decode_frame(msg: &str) -> Option<(CanFrame, SystemTime)> {
+    let mut fields = msg.split_whitespace();
+    if fields.next()? != "frame" {
+        return None;
+    }
+
+    let id_str = fields.next()?;
+    let raw_id = u32::from_str_radix(id_str, 16).ok()?;
+    let id: Id = match id_str.len() {
+        n if n <= 3 => StandardId::new(raw_id as u16)?.into(),
+        _ => ExtendedId::new(raw_id)?.into(),
+    };
+
+    let (secs, usecs) = fields.next()?.split_once('.')?;
+    let ts = Duration::new(secs.parse().ok()?, 0) + Duration::from_micros(usecs.parse().ok()?);
+
+    let hex: String = fields.collect();
+    if hex.len() % 2 != 0 || hex.len() > 16 {
+        return None;
+    }
+    let data = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some((CanFrame::new(id, &data)?, UNIX_EPOCH + ts))
+}
+
+// ===== SocketcandClient =====
+
+/// A connection to a CAN bus on a `socketcand` server, in raw mode.
+#[derive(Debug)]
+pub struct SocketcandClient {
+    stream: TcpStream,
+    // Bytes received, that aren't yet part of a whole message
+    buf: Vec<u8>,
+}
+
+impl SocketcandClient {
+    /// Connects to the server, and opens the named bus in raw mode.
+    pub fn connect<A: ToSocketAddrs>(addr: A, bus: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Self::from_stream(stream, bus)
+    }
+
+    /// Opens the named bus in raw mode, on a stream that's already
+    /// connected to the server.
+    pub fn from_stream(stream: TcpStream, bus: &str) -> io::Result<Self> {
+        let mut client = Self {
+            stream,
+            buf: Vec::new(),
+        };
+
+        if client.read_msg()? != "hi" {
+            return Err(protocol_error("no greeting from the server"));
+        }
+        client.command(&format!("< open {} >", bus))?;
+        client.command("< rawmode >")?;
+        Ok(client)
+    }
+
+    /// Gets a shared reference to the underlying TCP stream
+    pub fn as_stream(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Sends a frame to the bus.
+    pub fn write_frame(&mut self, frame: &CanFrame) -> io::Result<()> {
+        let cmd = encode_send(frame)?;
+        self.stream.write_all(cmd.as_bytes())
+    }
+
+    /// Reads the next frame from the bus.
+    pub fn read_frame(&mut self) -> io::Result<CanFrame> {
+        self.read_frame_with_timestamp().map(|(frame, _)| frame)
+    }
+
+    /// Reads the next frame from the bus, with the time at which the
+    /// server received it.
+    ///
+    /// Messages other than frames are skipped, except for errors, which
+    /// are returned as `Other` errors.
+    pub fn read_frame_with_timestamp(&mut self) -> io::Result<(CanFrame, SystemTime)> {
+        loop {
+            let msg = self.read_msg()?;
+            if let Some(frame) = decode_frame(&msg) {
+                return Ok(frame);
+            }
+            if msg.starts_with("error") {
+                return Err(io::Error::new(io::ErrorKind::Other, msg));
+            }
+        }
+    }
+
+    /// Sets the read timeout on the connection
+    pub fn set_read_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.stream.set_read_timeout(duration.into())
+    }
+
+    /// Sets the write timeout on the connection
+    pub fn set_write_timeout<D>(&self, duration: D) -> io::Result<()>
+    where
+        D: Into<Option<Duration>>,
+    {
+        self.stream.set_write_timeout(duration.into())
+    }
+
+    // Sends a command, and waits for the server to accept it
+    fn command(&mut self, cmd: &str) -> io::Result<()> {
+        self.stream.write_all(cmd.as_bytes())?;
+        match self.read_msg()?.as_str() {
+            "ok" => Ok(()),
+            msg => Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("socketcand rejected '{}': {}", cmd, msg),
+            )),
+        }
+    }
+
+    // Reads the next message from the server, without its brackets
+    fn read_msg(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(end) = self.buf.iter().position(|&b| b == b'>') {
+                let raw: Vec<u8> = self.buf.drain(..=end).collect();
+                let start = raw
+                    .iter()
+                    .position(|&b| b == b'<')
+                    .ok_or_else(|| protocol_error("message without an opening bracket"))?;
+                let msg = std::str::from_utf8(&raw[start + 1..end])
+                    .map_err(|_| protocol_error("message is not text"))?;
+                return Ok(msg.trim().to_string());
+            }
+            if self.buf.len() > MAX_MSG {
+                return Err(protocol_error("message too long"));
+            }
+
+            let mut chunk = [0u8; 256];
+            match self.stream.read(&mut chunk)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => self.buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+}
+
+impl embedded_can::blocking::Can for SocketcandClient {
+    type Frame = CanFrame;
+    type Error = Error;
+
+    /// Blocking call to receive the next frame from the bus.
+    fn receive(&mut self) -> Result<Self::Frame, Self::Error> {
+        Ok(self.read_frame()?)
+    }
+
+    /// Blocking transmit of a frame to the bus.
+    fn transmit(&mut self, frame: &Self::Frame) -> Result<(), Self::Error> {
+        Ok(self.write_frame(frame)?)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_encode() {
+        let id = StandardId::new(0x123).unwrap();
+        let frame = CanFrame::new(id, &[0x11, 0x22, 0xAB]).unwrap();
+        assert_eq!(encode_send(&frame).unwrap(), "< send 123 3 11 22 AB >");
+
+        let id = ExtendedId::new(0x1ABCDEF0).unwrap();
+        let frame = CanFrame::new(id, &[]).unwrap();
+        assert_eq!(encode_send(&frame).unwrap(), "< send 1ABCDEF0 0 >");
+
+        let frame = CanFrame::new_remote(id, 1).unwrap();
+        assert!(encode_send(&frame).is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let (frame, ts) = decode_frame("frame 123 23.424242 11 22 33 44").unwrap();
+        assert_eq!(frame.id(), Id::from(StandardId::new(0x123).unwrap()));
+        assert_eq!(frame.data(), &[0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(ts, UNIX_EPOCH + Duration::from_micros(23_424_242));
+
+        let (frame, _) = decode_frame("frame 1ABCDEF0 1.000001 AABB").unwrap();
+        assert_eq!(frame.id(), Id::from(ExtendedId::new(0x1ABCDEF0).unwrap()));
+        assert_eq!(frame.data(), &[0xAA, 0xBB]);
+
+        let (frame, _) = decode_frame("frame 7FF 1.0").unwrap();
+        assert!(frame.data().is_empty());
+
+        assert!(decode_frame("frame 800 1.0").is_none());
+        assert!(decode_frame("frame 123 1.0 ABC").is_none());
+        assert!(decode_frame("ok").is_none());
+    }
+
+    #[test]
+    fn test_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut rx = Vec::new();
+            let mut expect = |conn: &mut TcpStream, cmd: &[u8], reply: &[u8]| {
+                let mut buf = vec![0u8; cmd.len()];
+                conn.read_exact(&mut buf).unwrap();
+                rx.extend_from_slice(&buf);
+                conn.write_all(reply).unwrap();
+            };
+            conn.write_all(b"< hi >").unwrap();
+            expect(&mut conn, b"< open vcan0 >", b"< ok >");
+            expect(
+                &mut conn,
+                b"< rawmode >",
+                b"< ok >< frame 321 5.000000 0102 >",
+            );
+            expect(&mut conn, b"< send 123 1 AA >", b"< error bus down >");
+            rx
+        });
+
+        let mut client = SocketcandClient::connect(addr, "vcan0").unwrap();
+        let frame = client.read_frame().unwrap();
+        assert_eq!(frame.data(), &[1, 2]);
+
+        let id = StandardId::new(0x123).unwrap();
+        client
+            .write_frame(&CanFrame::new(id, &[0xAA]).unwrap())
+            .unwrap();
+        assert!(client.read_frame().is_err());
+
+        let rx = server.join().unwrap();
+        assert_eq!(rx, b"< open vcan0 >< rawmode >< send 123 1 AA >");
+    }
+}
@@ -26,22 +26,55 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! # Cancellation
+//!
+//! The reads only take a frame, or on an ISO-TP socket a message, from
+//! the socket in the same step that returns it. So a read future, or a
+//! `next()` on the stream, can be dropped at any point, such as by losing
+//! a `select!` against a shutdown signal, or by timing out, without
+//! losing a frame. The next read gets it instead.
 use crate::{
-    frame::AsPtr, CanAddr, CanAnyFrame, CanFrame, Error, IoResult, Result, Socket, SocketOptions,
+    bcm::BcmMsg,
+    codec::{CanFrameCodec, FrameCodec},
+    errors::is_interface_gone,
+    frame::AsPtr,
+    isotp::IsoTpOptions,
+    socket::filters_for_id_range,
+    CanAddr, CanAnyFrame, CanFilter, CanFrame, Error, Frame, Id, IoError, IoErrorKind, IoResult,
+    Result, Socket, SocketOptions,
 };
 use futures::{prelude::*, ready, task::Context};
+use libc::canid_t;
 use std::{
     io::{Read, Write},
+    net::Shutdown,
+    ops::RangeInclusive,
     os::unix::{
         io::{AsRawFd, OwnedFd},
         prelude::RawFd,
     },
     pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
     task::Poll,
+    time::{Duration, SystemTime},
 };
 use tokio::io::unix::AsyncFd;
 use tokio::io::Interest;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc::{self, error::TrySendError},
+};
+use tokio::task::JoinHandle;
+
+#[cfg(feature = "netlink")]
+use crate::nl::{InterfaceCanParams, InterfaceDetails};
+#[cfg(feature = "netlink")]
+use neli::{consts::rtnl::Rtm, err::NlError, rtnl::Ifinfomsg};
 
 /// An asynchronous I/O wrapped CanSocket
 #[derive(Debug)]
@@ -70,6 +103,30 @@ impl<T: Socket + From<OwnedFd>> AsyncCanSocket<T> {
     }
 }
 
+impl<T: Socket> AsyncCanSocket<T> {
+    /// Reads a CAN frame asynchronously, failing with a `TimedOut` error
+    /// if none arrives within the timeout.
+    ///
+    /// This is [cancellation-safe](crate::tokio#cancellation).
+    ///
+    /// # Panics
+    ///
+    /// This panics if the tokio runtime wasn't built with the time driver
+    /// enabled, as with `enable_time()` or `enable_all()`.
+    pub async fn read_frame_timeout(&self, timeout: Duration) -> IoResult<T::FrameType> {
+        read_frame_timeout(&self.0, timeout).await
+    }
+
+    /// Splits the socket into owned read and write halves.
+    ///
+    /// The halves share the socket, so an RX task and a TX task can each
+    /// own one, without wrapping the socket in a mutex.
+    pub fn into_split(self) -> (CanReadHalf<T>, CanWriteHalf<T>) {
+        let fd = Arc::new(self.0);
+        (CanReadHalf(Arc::clone(&fd)), CanWriteHalf(fd))
+    }
+}
+
 impl<T: Socket> SocketOptions for AsyncCanSocket<T> {}
 
 impl<T: Socket> AsRawFd for AsyncCanSocket<T> {
@@ -78,6 +135,131 @@ impl<T: Socket> AsRawFd for AsyncCanSocket<T> {
     }
 }
 
+/// Reads a frame from the socket, within the timeout.
+async fn read_frame_timeout<T: Socket>(
+    fd: &AsyncFd<T>,
+    timeout: Duration,
+) -> IoResult<T::FrameType> {
+    let read = fd.async_io(Interest::READABLE, |inner| inner.read_frame());
+    match tokio::time::timeout(timeout, read).await {
+        Ok(res) => res,
+        Err(_elapsed) => Err(IoErrorKind::TimedOut.into()),
+    }
+}
+
+/// The read half of an asynchronous socket, from
+/// [`AsyncCanSocket::into_split()`].
+#[derive(Debug)]
+pub struct CanReadHalf<T: Socket = crate::CanSocket>(Arc<AsyncFd<T>>);
+
+impl<T: Socket> CanReadHalf<T> {
+    /// Gets a reference to the blocking socket, for its options.
+    pub fn get_ref(&self) -> &T {
+        self.0.get_ref()
+    }
+
+    /// Read a CAN frame from the socket asynchronously
+    ///
+    /// This is [cancellation-safe](crate::tokio#cancellation).
+    pub async fn read_frame(&self) -> IoResult<T::FrameType> {
+        self.0
+            .async_io(Interest::READABLE, |inner| inner.read_frame())
+            .await
+    }
+
+    /// Reads a CAN frame asynchronously, failing with a `TimedOut` error
+    /// if none arrives within the timeout.
+    ///
+    /// This is [cancellation-safe](crate::tokio#cancellation).
+    ///
+    /// # Panics
+    ///
+    /// This panics if the tokio runtime wasn't built with the time driver
+    /// enabled, as with `enable_time()` or `enable_all()`.
+    pub async fn read_frame_timeout(&self, timeout: Duration) -> IoResult<T::FrameType> {
+        read_frame_timeout(&self.0, timeout).await
+    }
+}
+
+/// The stream of frames ends, returning `None`, if the CAN interface goes
+/// away (see [`is_interface_gone`](crate::is_interface_gone)).
+impl<T: Socket> Stream for CanReadHalf<T> {
+    type Item = Result<T::FrameType>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut ready_guard = ready!(self.0.poll_read_ready(cx))?;
+            match ready_guard.try_io(|inner| inner.get_ref().read_frame()) {
+                Ok(Err(err)) if is_interface_gone(&err) => return Poll::Ready(None),
+                Ok(result) => return Poll::Ready(Some(result.map_err(|e| e.into()))),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl<T: Socket> AsRawFd for CanReadHalf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// The write half of an asynchronous socket, from
+/// [`AsyncCanSocket::into_split()`].
+#[derive(Debug)]
+pub struct CanWriteHalf<T: Socket = crate::CanSocket>(Arc<AsyncFd<T>>);
+
+impl<T: Socket> CanWriteHalf<T> {
+    /// Gets a reference to the blocking socket, for its options.
+    pub fn get_ref(&self) -> &T {
+        self.0.get_ref()
+    }
+
+    /// Write a CAN frame to the socket asynchronously
+    pub async fn write_frame<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<T::FrameType> + AsPtr,
+    {
+        self.0
+            .async_io(Interest::WRITABLE, |inner| inner.write_frame(frame))
+            .await
+    }
+}
+
+impl<T> Sink<T::FrameType> for CanWriteHalf<T>
+where
+    T: Socket,
+    T::FrameType: AsPtr,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let _ = ready!(self.0.poll_write_ready(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut ready_guard = ready!(self.0.poll_write_ready(cx))?;
+        ready_guard.clear_ready();
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T::FrameType) -> Result<()> {
+        self.0.get_ref().write_frame_insist(&item)?;
+        Ok(())
+    }
+}
+
+impl<T: Socket> AsRawFd for CanWriteHalf<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
 /// Asynchronous Can Socket
 pub type CanSocket = AsyncCanSocket<crate::CanSocket>;
 
@@ -90,13 +272,91 @@ impl CanSocket {
     }
 
     /// Read a CAN frame from the socket asynchronously
+    ///
+    /// This is [cancellation-safe](crate::tokio#cancellation).
     pub async fn read_frame(&self) -> IoResult<CanFrame> {
         self.0
             .async_io(Interest::READABLE, |inner| inner.read_frame())
             .await
     }
+
+    /// Reads a CAN frame from the socket asynchronously, along with the
+    /// time it was received.
+    ///
+    /// Timestamps are enabled with the same [`SocketOptions`] calls as on
+    /// the blocking socket. If they weren't enabled, or the kernel didn't
+    /// send one with the frame, the time is `None`.
+    pub async fn read_frame_with_timestamp(&self) -> IoResult<(CanFrame, Option<SystemTime>)> {
+        self.0
+            .async_io(Interest::READABLE, |inner| {
+                inner.read_frame_with_timestamp()
+            })
+            .await
+    }
+
+    /// Sends a request, and waits for the first reply that passes the
+    /// filter.
+    ///
+    /// Frames that don't pass the filter, such as other traffic on the
+    /// bus, are read and dropped while it waits. If no reply arrives
+    /// within the timeout, this fails with a `TimedOut` error.
+    ///
+    /// ```no_run
+    /// use socketcan::{tokio::CanSocket, CanFilter, CanFrame, EmbeddedFrame, StandardId};
+    /// use std::time::Duration;
+    ///
+    /// # async fn request() -> socketcan::IoResult<()> {
+    /// let sock = CanSocket::open("can0")?;
+    ///
+    /// // An OBD-II request for the engine speed, and its reply
+    /// let req = CanFrame::new(StandardId::new(0x7DF).unwrap(), &[2, 0x01, 0x0C]).unwrap();
+    /// let filter = CanFilter::exact(StandardId::new(0x7E8).unwrap());
+    ///
+    /// let reply = sock.transact(req, filter, Duration::from_millis(100)).await?;
+    /// println!("{:?}", reply.data());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Frames are taken from the same queue as the socket's other reads,
+    /// so any other task reading from the socket at the same time can
+    /// take the reply instead.
+    ///
+    /// # Panics
+    ///
+    /// This panics if the tokio runtime wasn't built with the time driver
+    /// enabled, as with `enable_time()` or `enable_all()`.
+    pub async fn transact(
+        &self,
+        request: CanFrame,
+        reply_filter: CanFilter,
+        timeout: Duration,
+    ) -> IoResult<CanFrame> {
+        self.write_frame(request).await?;
+        let reply = async {
+            loop {
+                let frame = self.read_frame().await?;
+                if reply_filter.matches(frame.id_word()) {
+                    return Ok(frame);
+                }
+            }
+        };
+        tokio::time::timeout(timeout, reply)
+            .await
+            .map_err(|_| IoError::from(IoErrorKind::TimedOut))?
+    }
+
+    /// Shuts down the read, write, or both halves of the socket.
+    ///
+    /// See [`crate::CanSocket::shutdown()`] for the semantics on a CAN
+    /// socket.
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        self.0.get_ref().shutdown(how)
+    }
 }
 
+/// The stream of frames ends, returning `None`, if the CAN interface goes
+/// away (see [`is_interface_gone`](crate::is_interface_gone)).
 impl Stream for CanSocket {
     type Item = Result<CanFrame>;
 
@@ -104,6 +364,8 @@ impl Stream for CanSocket {
         loop {
             let mut ready_guard = ready!(self.0.poll_read_ready(cx))?;
             match ready_guard.try_io(|inner| inner.get_ref().read_frame()) {
+                // The interface is gone, so the stream is over
+                Ok(Err(err)) if is_interface_gone(&err) => return Poll::Ready(None),
                 Ok(result) => return Poll::Ready(Some(result.map_err(|e| e.into()))),
                 Err(_would_block) => continue,
             }
@@ -129,163 +391,1316 @@ impl Sink<CanFrame> for CanSocket {
         Poll::Ready(Ok(()))
     }
 
-    fn start_send(self: Pin<&mut Self>, item: CanFrame) -> Result<()> {
-        self.0.get_ref().write_frame_insist(&item)?;
-        Ok(())
+    fn start_send(self: Pin<&mut Self>, item: CanFrame) -> Result<()> {
+        self.0.get_ref().write_frame_insist(&item)?;
+        Ok(())
+    }
+}
+
+impl AsyncRead for CanSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IoResult<()>> {
+        loop {
+            let mut guard = ready!(self.0.poll_read_ready_mut(cx))?;
+
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|inner| inner.get_mut().read(unfilled)) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for CanSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<IoResult<usize>> {
+        loop {
+            let mut guard = ready!(self.0.poll_write_ready_mut(cx))?;
+
+            match guard.try_io(|inner| inner.get_mut().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Shuts down the write side of the socket.
+    ///
+    /// See [`crate::CanSocket::shutdown()`].
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Poll::Ready(self.0.get_ref().shutdown(Shutdown::Write))
+    }
+}
+
+/// An Asynchronous CAN FD Socket
+pub type CanFdSocket = AsyncCanSocket<crate::CanFdSocket>;
+
+impl CanFdSocket {
+    /// Write a CAN FD frame to the socket asynchronously
+    pub async fn write_frame<F>(&self, frame: &F) -> IoResult<()>
+    where
+        F: Into<CanAnyFrame> + AsPtr,
+    {
+        self.0
+            .async_io(Interest::WRITABLE, |inner| inner.write_frame(frame))
+            .await
+    }
+
+    /// Reads a CAN FD frame from the socket asynchronously
+    ///
+    /// This is [cancellation-safe](crate::tokio#cancellation).
+    pub async fn read_frame(&self) -> IoResult<CanAnyFrame> {
+        self.0
+            .async_io(Interest::READABLE, |inner| inner.read_frame())
+            .await
+    }
+
+    /// Reads a CAN FD frame from the socket asynchronously, along with the
+    /// time it was received.
+    ///
+    /// Timestamps are enabled with the same [`SocketOptions`] calls as on
+    /// the blocking socket. If they weren't enabled, or the kernel didn't
+    /// send one with the frame, the time is `None`.
+    pub async fn read_frame_with_timestamp(&self) -> IoResult<(CanAnyFrame, Option<SystemTime>)> {
+        self.0
+            .async_io(Interest::READABLE, |inner| {
+                inner.read_frame_with_timestamp()
+            })
+            .await
+    }
+
+    /// Shuts down the read, write, or both halves of the socket.
+    ///
+    /// See [`crate::CanFdSocket::shutdown()`] for the semantics on a CAN
+    /// socket.
+    pub fn shutdown(&self, how: Shutdown) -> IoResult<()> {
+        self.0.get_ref().shutdown(how)
+    }
+}
+
+/// The stream of frames ends, returning `None`, if the CAN interface goes
+/// away (see [`is_interface_gone`](crate::is_interface_gone)).
+impl Stream for CanFdSocket {
+    type Item = Result<CanAnyFrame>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut ready_guard = ready!(self.0.poll_read_ready(cx))?;
+            match ready_guard.try_io(|inner| inner.get_ref().read_frame()) {
+                // The interface is gone, so the stream is over
+                Ok(Err(err)) if is_interface_gone(&err) => return Poll::Ready(None),
+                Ok(result) => return Poll::Ready(Some(result.map_err(|e| e.into()))),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl Sink<CanAnyFrame> for CanFdSocket {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let _ = ready!(self.0.poll_write_ready(cx))?;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut ready_guard = ready!(self.0.poll_write_ready(cx))?;
+        ready_guard.clear_ready();
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: CanAnyFrame) -> Result<()> {
+        self.0.get_ref().write_frame_insist(&item)?;
+        Ok(())
+    }
+}
+
+impl AsyncRead for CanFdSocket {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IoResult<()>> {
+        loop {
+            let mut guard = ready!(self.0.poll_read_ready_mut(cx))?;
+
+            let unfilled = buf.initialize_unfilled();
+            match guard.try_io(|inner| inner.get_mut().read(unfilled)) {
+                Ok(Ok(len)) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(err)) => return Poll::Ready(Err(err)),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for CanFdSocket {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<IoResult<usize>> {
+        loop {
+            let mut guard = ready!(self.0.poll_write_ready_mut(cx))?;
+
+            match guard.try_io(|inner| inner.get_mut().write(buf)) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Shuts down the write side of the socket.
+    ///
+    /// See [`crate::CanFdSocket::shutdown()`].
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        Poll::Ready(self.0.get_ref().shutdown(Shutdown::Write))
+    }
+}
+
+/// An asynchronous ISO-TP socket
+///
+/// Each [`recv()`](Self::recv) resolves to one complete message, and each
+/// [`send()`](Self::send) hands one message to the kernel to segment.
+/// The kernel only reports the socket as readable once a whole message
+/// has been reassembled, and as writable once the previous message has
+/// been sent, so the futures never see part of a transfer. If a wakeup
+/// turns out to be spurious, they go back to waiting.
+///
+/// This needs the kernel's `CAN_ISOTP` protocol. The userspace fallback
+/// of [`crate::CanIsoTpSocket`] runs the protocol inside its blocking
+/// calls, so it can't be driven by readiness, and is refused.
+#[derive(Debug)]
+pub struct CanIsoTpSocket(AsyncFd<crate::CanIsoTpSocket>);
+
+impl CanIsoTpSocket {
+    /// Opens an ISO-TP socket on the named interface, receiving on
+    /// `rx_id` and transmitting with `tx_id`, with the default options.
+    pub fn open<R, T>(ifname: &str, rx_id: R, tx_id: T) -> IoResult<Self>
+    where
+        R: Into<Id>,
+        T: Into<Id>,
+    {
+        Self::new(crate::CanIsoTpSocket::open(ifname, rx_id, tx_id)?)
+    }
+
+    /// Opens an ISO-TP socket on the named interface with the options
+    /// given.
+    pub fn open_with<R, T>(ifname: &str, rx_id: R, tx_id: T, opts: &IsoTpOptions) -> IoResult<Self>
+    where
+        R: Into<Id>,
+        T: Into<Id>,
+    {
+        Self::new(crate::CanIsoTpSocket::open_with(
+            ifname, rx_id, tx_id, opts,
+        )?)
+    }
+
+    /// Opens an ISO-TP socket by address, with the default options.
+    pub fn open_addr(addr: &CanAddr) -> IoResult<Self> {
+        Self::new(crate::CanIsoTpSocket::open_addr(addr)?)
+    }
+
+    /// Wraps a blocking ISO-TP socket, putting it into non-blocking mode.
+    ///
+    /// This fails with an `Unsupported` error if the socket is running
+    /// the protocol in userspace.
+    pub fn new(sock: crate::CanIsoTpSocket) -> IoResult<Self> {
+        if sock.is_userspace() {
+            return Err(IoErrorKind::Unsupported.into());
+        }
+        sock.set_nonblocking(true)?;
+        Ok(Self(AsyncFd::new(sock)?))
+    }
+
+    /// Gets a reference to the blocking socket.
+    pub fn get_ref(&self) -> &crate::CanIsoTpSocket {
+        self.0.get_ref()
+    }
+
+    /// Sends a complete message asynchronously.
+    ///
+    /// This waits until the kernel is ready to take a new message, which
+    /// is once the previous one has been sent.
+    pub async fn send(&self, data: &[u8]) -> IoResult<()> {
+        self.0
+            .async_io(Interest::WRITABLE, |inner| inner.write(data))
+            .await
+    }
+
+    /// Receives a complete message asynchronously.
+    ///
+    /// This is [cancellation-safe](crate::tokio#cancellation).
+    pub async fn recv(&self) -> IoResult<Vec<u8>> {
+        self.0
+            .async_io(Interest::READABLE, |inner| inner.read_to_vec())
+            .await
+    }
+
+    /// Receives a complete message into the buffer asynchronously,
+    /// returning its length.
+    ///
+    /// This is the async counterpart of [`crate::CanIsoTpSocket::read()`],
+    /// and is [cancellation-safe](crate::tokio#cancellation) too.
+    pub async fn read(&self, buf: &mut [u8]) -> IoResult<usize> {
+        self.0
+            .async_io(Interest::READABLE, |inner| inner.read(buf))
+            .await
+    }
+
+    /// Sends a complete message asynchronously.
+    ///
+    /// This is the same as [`send()`](Self::send), named to match
+    /// [`crate::CanIsoTpSocket::write()`].
+    pub async fn write(&self, data: &[u8]) -> IoResult<()> {
+        self.send(data).await
+    }
+}
+
+/// The asynchronous ISO-TP socket, by the name used alongside
+/// [`AsyncCanSocket`].
+pub type AsyncCanIsoTpSocket = CanIsoTpSocket;
+
+impl AsRawFd for CanIsoTpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// An asynchronous BCM socket
+///
+/// The messages from the broadcast manager, such as changed content and
+/// timeouts of the subscriptions, are read with
+/// [`read_msg()`](Self::read_msg), or as a [`Stream`] of [`BcmMsg`]. The
+/// jobs are set up through the blocking socket, from
+/// [`get_ref()`](Self::get_ref), since that never has to wait.
+///
+/// ```no_run
+/// use futures_util::stream::StreamExt;
+/// use socketcan::{bcm::{BcmMsg, RxJob}, tokio::CanBcmSocket, StandardId};
+/// use std::time::Duration;
+///
+/// # async fn supervise() -> std::io::Result<()> {
+/// let mut sock = CanBcmSocket::open("vcan0")?;
+/// let id = StandardId::new(0x100).unwrap();
+/// sock.get_ref().rx_setup(&RxJob::new(id).timeout(Duration::from_millis(500)))?;
+///
+/// while let Some(Ok(msg)) = sock.next().await {
+///     match msg {
+///         BcmMsg::RxChanged(frame) => println!("Changed: {:?}", frame),
+///         BcmMsg::RxTimeout(id) => println!("Timed out: {:?}", id),
+///         _ => (),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CanBcmSocket(AsyncFd<crate::CanBcmSocket>);
+
+impl CanBcmSocket {
+    /// Opens a BCM socket on the named interface.
+    pub fn open(ifname: &str) -> IoResult<Self> {
+        Self::new(crate::CanBcmSocket::open(ifname)?)
+    }
+
+    /// Opens a BCM socket on the interface of the address.
+    pub fn open_addr(addr: &CanAddr) -> IoResult<Self> {
+        Self::new(crate::CanBcmSocket::open_addr(addr)?)
+    }
+
+    /// Wraps a blocking BCM socket, putting it into non-blocking mode.
+    pub fn new(sock: crate::CanBcmSocket) -> IoResult<Self> {
+        sock.set_nonblocking(true)?;
+        Ok(Self(AsyncFd::new(sock)?))
+    }
+
+    /// Gets a reference to the blocking socket, to set up the jobs.
+    pub fn get_ref(&self) -> &crate::CanBcmSocket {
+        self.0.get_ref()
+    }
+
+    /// Reads the next message from the broadcast manager asynchronously.
+    pub async fn read_msg(&self) -> IoResult<BcmMsg> {
+        self.0
+            .async_io(Interest::READABLE, |inner| inner.read_msg())
+            .await
+    }
+}
+
+/// The stream of messages ends, returning `None`, if the CAN interface
+/// goes away (see [`is_interface_gone`](crate::is_interface_gone)).
+impl Stream for CanBcmSocket {
+    type Item = Result<BcmMsg>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut ready_guard = ready!(self.0.poll_read_ready(cx))?;
+            match ready_guard.try_io(|inner| inner.get_ref().read_msg()) {
+                Ok(Err(err)) if is_interface_gone(&err) => return Poll::Ready(None),
+                Ok(result) => return Poll::Ready(Some(result.map_err(|e| e.into()))),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsRawFd for CanBcmSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// An asynchronous J1939 socket
+///
+/// Besides the `send_to()` and `recv_from()` futures, the socket is a
+/// [`Stream`] of the messages that it receives, each as the address of
+/// the sender, the PGN, and the payload.
+///
+/// ```no_run
+/// use futures_util::stream::StreamExt;
+/// use socketcan::{j1939::{J1939_NO_NAME, J1939_NO_PGN}, tokio::CanJ1939Socket};
+///
+/// # async fn monitor() -> std::io::Result<()> {
+/// let mut sock = CanJ1939Socket::open("vcan0", J1939_NO_NAME, J1939_NO_PGN, 0x20)?;
+///
+/// while let Some(Ok((addr, pgn, data))) = sock.next().await {
+///     println!("{:02X} {:05X}: {:02X?}", addr.j1939_addr(), pgn, data);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CanJ1939Socket(AsyncFd<crate::CanJ1939Socket>);
+
+impl CanJ1939Socket {
+    /// Opens a J1939 socket on the named interface, bound to the NAME,
+    /// PGN, and address given.
+    pub fn open(ifname: &str, name: u64, pgn: u32, addr: u8) -> IoResult<Self> {
+        Self::new(crate::CanJ1939Socket::open(ifname, name, pgn, addr)?)
+    }
+
+    /// Opens a J1939 socket bound to the address given.
+    pub fn open_addr(addr: &CanAddr) -> IoResult<Self> {
+        Self::new(crate::CanJ1939Socket::open_addr(addr)?)
+    }
+
+    /// Wraps a blocking J1939 socket, putting it into non-blocking mode.
+    pub fn new(sock: crate::CanJ1939Socket) -> IoResult<Self> {
+        sock.set_nonblocking(true)?;
+        Ok(Self(AsyncFd::new(sock)?))
+    }
+
+    /// Gets a reference to the blocking socket, for its options.
+    pub fn get_ref(&self) -> &crate::CanJ1939Socket {
+        self.0.get_ref()
+    }
+
+    /// Sends a message to the peer given, asynchronously.
+    ///
+    /// See [`crate::CanJ1939Socket::send_to()`].
+    pub async fn send_to(&self, data: &[u8], peer: &CanAddr) -> IoResult<()> {
+        self.0
+            .async_io(Interest::WRITABLE, |inner| inner.send_to(data, peer))
+            .await
+    }
+
+    /// Receives a message into the buffer asynchronously, returning its
+    /// length and the address of the sender.
+    ///
+    /// See [`crate::CanJ1939Socket::recv_from()`].
+    pub async fn recv_from(&self, buf: &mut [u8]) -> IoResult<(usize, CanAddr)> {
+        self.0
+            .async_io(Interest::READABLE, |inner| inner.recv_from(buf))
+            .await
+    }
+}
+
+/// The stream of messages ends, returning `None`, if the CAN interface
+/// goes away (see [`is_interface_gone`](crate::is_interface_gone)).
+impl Stream for CanJ1939Socket {
+    type Item = Result<(CanAddr, u32, Vec<u8>)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut ready_guard = ready!(self.0.poll_read_ready(cx))?;
+            match ready_guard.try_io(|inner| inner.get_ref().recv_vec_from()) {
+                Ok(Err(err)) if is_interface_gone(&err) => return Poll::Ready(None),
+                Ok(result) => {
+                    let msg = result.map(|(data, addr)| (addr, addr.j1939_pgn(), data));
+                    return Poll::Ready(Some(msg.map_err(|e| e.into())));
+                }
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsRawFd for CanJ1939Socket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// Filtering of the frames from a socket by their IDs.
+///
+/// On a socket, the filters are pushed into the kernel with
+/// `CAN_RAW_FILTER`, so that the frames that don't pass are never copied
+/// out to the process. This replaces any filters the socket already had.
+/// If the kernel won't take them, such as when a set of ranges needs more
+/// than `CAN_RAW_FILTER_MAX` masks to express, the frames are filtered in
+/// the stream instead. The filters of a stream that's already filtered
+/// are always applied in the stream, so that both sets have to pass.
+///
+/// As in the kernel, error frames are never filtered out, although the
+/// socket only gets them if it asked for them, with an error filter.
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use socketcan::{tokio::{CanFilterExt, CanSocket}, Id, StandardId};
+///
+/// # async fn watch() -> socketcan::IoResult<()> {
+/// let lo = Id::from(StandardId::new(0x100).unwrap());
+/// let hi = Id::from(StandardId::new(0x1FF).unwrap());
+///
+/// let mut frames = CanSocket::open("vcan0")?.filter_ids([lo..=hi]);
+/// while let Some(Ok(frame)) = frames.next().await {
+///     println!("{:?}", frame);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub trait CanFilterExt: Sized {
+    /// Passes only the frames that pass any of the filters.
+    fn filter_frames(self, filters: Vec<CanFilter>) -> FilteredStream<Self>;
+
+    /// Passes only the data frames with an ID in any of the ranges.
+    ///
+    /// See [`filters_for_id_range()`](crate::socket::filters_for_id_range)
+    /// for how a range is turned into filters.
+    fn filter_ids<I>(self, ranges: I) -> FilteredStream<Self>
+    where
+        I: IntoIterator<Item = RangeInclusive<Id>>,
+    {
+        let filters = ranges
+            .into_iter()
+            .flat_map(|range| filters_for_id_range(*range.start(), *range.end()))
+            .collect();
+        self.filter_frames(filters)
+    }
+
+    /// Passes only the frames for which the ID word, under the mask,
+    /// matches the ID, like a [`CanFilter::new()`].
+    fn match_mask(self, id: canid_t, mask: canid_t) -> FilteredStream<Self> {
+        self.filter_frames(vec![CanFilter::new(id, mask)])
+    }
+}
+
+impl<T: Socket> CanFilterExt for AsyncCanSocket<T> {
+    fn filter_frames(self, filters: Vec<CanFilter>) -> FilteredStream<Self> {
+        let filters = match self.set_filters(&filters) {
+            Ok(()) => None,
+            Err(_) => Some(filters),
+        };
+        FilteredStream {
+            inner: self,
+            filters,
+        }
+    }
+}
+
+impl<S> CanFilterExt for FilteredStream<S> {
+    fn filter_frames(self, filters: Vec<CanFilter>) -> FilteredStream<Self> {
+        FilteredStream {
+            inner: self,
+            filters: Some(filters),
+        }
+    }
+}
+
+/// A stream of frames, filtered by their IDs.
+///
+/// This is made by the methods of [`CanFilterExt`].
+#[derive(Debug)]
+pub struct FilteredStream<S> {
+    inner: S,
+    /// The filters applied in the stream, if the kernel isn't doing so
+    filters: Option<Vec<CanFilter>>,
+}
+
+impl<S> FilteredStream<S> {
+    /// Determines if the filtering is done by the kernel.
+    pub fn is_in_kernel(&self) -> bool {
+        self.filters.is_none()
+    }
+
+    /// Gets a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the underlying stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the underlying stream.
+    ///
+    /// Any filters that were pushed into the kernel stay on the socket.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F> Stream for FilteredStream<S>
+where
+    S: Stream<Item = Result<F>> + Unpin,
+    F: Frame,
+{
+    type Item = Result<F>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            let item = ready!(Pin::new(&mut self.inner).poll_next(cx));
+            match (&item, &self.filters) {
+                (Some(Ok(frame)), Some(filters))
+                    if !frame.is_error_frame()
+                        && !filters.iter().any(|f| f.matches(frame.id_word())) =>
+                {
+                    continue
+                }
+                _ => return Poll::Ready(item),
+            }
+        }
+    }
+}
+
+/// A task that reads the frames from one socket, and fans them out to
+/// any number of subscribers.
+///
+/// Each [`CanSubscriber`] has its own set of ID filters, applied as it
+/// receives, so many tasks can each watch their part of the same bus
+/// without opening a socket apiece.
+///
+/// ```no_run
+/// use socketcan::{tokio::{CanBroadcaster, CanSocket}, CanFilter, StandardId};
+///
+/// # async fn watch() -> socketcan::IoResult<()> {
+/// let bus = CanBroadcaster::new(CanSocket::open("vcan0")?, 1024);
+///
+/// let id = StandardId::new(0x100).unwrap();
+/// let mut sub = bus.subscribe(&[CanFilter::exact(id)]);
+/// while let Ok(frame) = sub.recv().await {
+///     println!("{:?}", frame);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The task is spawned on the current tokio runtime, and runs until the
+/// broadcaster is dropped, or the socket has a read error, such as when
+/// the interface goes away. Either way, the subscribers then get
+/// `RecvError::Closed` once they've received the frames already sent.
+#[derive(Debug)]
+pub struct CanBroadcaster {
+    tx: broadcast::Sender<CanFrame>,
+    task: JoinHandle<IoResult<()>>,
+}
+
+impl CanBroadcaster {
+    /// Starts fanning out the frames from the socket.
+    ///
+    /// Each subscriber can fall up to `capacity` frames behind, counting
+    /// those that its filters reject, before it starts missing them.
+    ///
+    /// # Panics
+    ///
+    /// This panics if it isn't called from within a tokio runtime, or if
+    /// the capacity is zero.
+    pub fn new(sock: CanSocket, capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        let task_tx = tx.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let frame = sock.read_frame().await?;
+                // No subscribers just means nobody is listening yet
+                let _ = task_tx.send(frame);
+            }
+        });
+        Self { tx, task }
+    }
+
+    /// Adds a subscriber, that receives the frames that pass any of the
+    /// filters, from now on.
+    ///
+    /// With no filters, it receives every frame.
+    pub fn subscribe(&self, filters: &[CanFilter]) -> CanSubscriber {
+        CanSubscriber {
+            rx: self.tx.subscribe(),
+            filters: filters.to_vec(),
+        }
+    }
+
+    /// The number of subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// Determines if the task is still reading from the socket.
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+}
+
+impl Drop for CanBroadcaster {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A receiver of the frames from a [`CanBroadcaster`].
+#[derive(Debug)]
+pub struct CanSubscriber {
+    rx: broadcast::Receiver<CanFrame>,
+    filters: Vec<CanFilter>,
+}
+
+impl CanSubscriber {
+    /// Receives the next frame that passes the filters.
+    ///
+    /// If the subscriber fell too far behind, this fails once with
+    /// `RecvError::Lagged`, with the number of frames it missed, and then
+    /// carries on from the oldest frame that's still held. Once the
+    /// broadcaster has stopped, and the frames it sent are used up, this
+    /// fails with `RecvError::Closed`.
+    pub async fn recv(&mut self) -> std::result::Result<CanFrame, RecvError> {
+        loop {
+            let frame = self.rx.recv().await?;
+            if self.accepts(&frame) {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Determines if the frame passes the subscriber's filters.
+    fn accepts(&self, frame: &CanFrame) -> bool {
+        self.filters.is_empty() || self.filters.iter().any(|f| f.matches(frame.id_word()))
+    }
+}
+
+/// The first wait before retrying a write that failed with `ENOBUFS`.
+const TX_INITIAL_BACKOFF: Duration = Duration::from_millis(1);
+
+/// The longest wait before retrying a write that failed with `ENOBUFS`.
+const TX_MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// A snapshot of the counters of a [`CanTxQueue`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TxQueueMetrics {
+    /// The number of frames waiting in the queue
+    pub depth: usize,
+    /// The most frames that have been waiting in the queue at once
+    pub peak_depth: usize,
+    /// The number of frames written to the socket
+    pub sent: u64,
+    /// The number of writes retried after the kernel ran out of buffers
+    pub retries: u64,
+}
+
+/// The counters shared between a [`CanTxQueue`] and its task.
+#[derive(Debug, Default)]
+struct TxQueueCounters {
+    peak_depth: AtomicUsize,
+    sent: AtomicU64,
+    retries: AtomicU64,
+}
+
+/// A bounded queue of frames, written to a socket by a background task.
+///
+/// Writing frames faster than the bus can carry them eventually fills
+/// the socket's send buffer and the interface's TX queue, at which point
+/// the kernel fails the write with `ENOBUFS`, rather than blocking or
+/// reporting that the socket is not writable. The queue's task retries
+/// those writes, backing off exponentially from 1ms up to 100ms, so the
+/// frames go out in order once there's room again.
+///
+/// Meanwhile, the frames wait in the queue, and once it's full,
+/// [`send()`](Self::send) waits for room, pushing back on the producer.
+///
+/// ```no_run
+/// use socketcan::{tokio::{CanSocket, CanTxQueue}, CanFrame, EmbeddedFrame, StandardId};
+///
+/// # async fn flood() -> socketcan::IoResult<()> {
+/// let queue = CanTxQueue::new(CanSocket::open("vcan0")?, 256);
+///
+/// let id = StandardId::new(0x100).unwrap();
+/// for i in 0..10_000u16 {
+///     queue.send(CanFrame::new(id, &i.to_be_bytes()).unwrap()).await?;
+/// }
+/// println!("{:?}", queue.metrics());
+///
+/// // Waits for the rest of the frames to be written
+/// queue.close().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Any other write error stops the task. The frames still queued are
+/// dropped, further sends fail with `BrokenPipe`, and the error itself
+/// is returned by [`close()`](Self::close).
+#[derive(Debug)]
+pub struct CanTxQueue {
+    tx: mpsc::Sender<CanFrame>,
+    counters: Arc<TxQueueCounters>,
+    task: JoinHandle<IoResult<()>>,
+}
+
+impl CanTxQueue {
+    /// Starts a task that writes the queued frames to the socket, with
+    /// room for `capacity` frames to wait in the queue.
+    ///
+    /// # Panics
+    ///
+    /// This panics if it isn't called from within a tokio runtime, or if
+    /// the capacity is zero.
+    pub fn new(sock: CanSocket, capacity: usize) -> Self {
+        let (tx, mut rx) = mpsc::channel::<CanFrame>(capacity);
+        let counters = Arc::new(TxQueueCounters::default());
+        let task_counters = Arc::clone(&counters);
+        let task = tokio::spawn(async move {
+            while let Some(frame) = rx.recv().await {
+                let mut backoff = TX_INITIAL_BACKOFF;
+                loop {
+                    match sock.write_frame(frame).await {
+                        Ok(()) => break,
+                        Err(err) if err.raw_os_error() == Some(libc::ENOBUFS) => {
+                            task_counters.retries.fetch_add(1, Ordering::Relaxed);
+                            tokio::time::sleep(backoff).await;
+                            backoff = (backoff * 2).min(TX_MAX_BACKOFF);
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                task_counters.sent.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(())
+        });
+        Self { tx, counters, task }
+    }
+
+    /// Queues a frame to be written, waiting for room if the queue is
+    /// full.
+    ///
+    /// This fails with a `BrokenPipe` error if the task has stopped.
+    pub async fn send(&self, frame: CanFrame) -> IoResult<()> {
+        self.tx
+            .send(frame)
+            .await
+            .map_err(|_| IoError::from(IoErrorKind::BrokenPipe))?;
+        self.note_depth();
+        Ok(())
+    }
+
+    /// Queues a frame to be written, if there's room for it.
+    ///
+    /// This fails with a `WouldBlock` error if the queue is full, or a
+    /// `BrokenPipe` error if the task has stopped.
+    pub fn try_send(&self, frame: CanFrame) -> IoResult<()> {
+        self.tx.try_send(frame).map_err(|err| match err {
+            TrySendError::Full(_) => IoError::from(IoErrorKind::WouldBlock),
+            TrySendError::Closed(_) => IoError::from(IoErrorKind::BrokenPipe),
+        })?;
+        self.note_depth();
+        Ok(())
+    }
+
+    /// The number of frames waiting in the queue.
+    pub fn depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    /// The most frames that can wait in the queue.
+    pub fn capacity(&self) -> usize {
+        self.tx.max_capacity()
+    }
+
+    /// Gets a snapshot of the queue's counters.
+    pub fn metrics(&self) -> TxQueueMetrics {
+        TxQueueMetrics {
+            depth: self.depth(),
+            peak_depth: self.counters.peak_depth.load(Ordering::Relaxed),
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            retries: self.counters.retries.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Closes the queue, and waits for the frames in it to be written.
+    ///
+    /// This returns the error that stopped the task, if any.
+    pub async fn close(self) -> IoResult<()> {
+        drop(self.tx);
+        self.task
+            .await
+            .map_err(|err| IoError::new(IoErrorKind::Other, err))?
+    }
+
+    /// Records the depth of the queue after a frame was put in it.
+    fn note_depth(&self) {
+        self.counters
+            .peak_depth
+            .fetch_max(self.depth(), Ordering::Relaxed);
+    }
+}
+
+/// The size of the reads from the byte stream of a [`CanFramed`]
+const FRAMED_READ_LEN: usize = 4096;
+
+/// The amount of encoded frames that a [`CanFramed`] buffers before it
+/// makes the sink wait for them to be written
+const FRAMED_WRITE_LEN: usize = 8192;
+
+/// A `Stream` and `Sink` of CAN frames over a byte stream.
+///
+/// This carries the frames as fixed-size records, using one of the
+/// codecs from [`crate::codec`], over anything that's `AsyncRead` and
+/// `AsyncWrite`, like a TCP connection or a Unix socket, to tunnel CAN
+/// between hosts.
+///
+/// ```no_run
+/// use futures_util::{SinkExt, StreamExt};
+/// use socketcan::{codec::CanFrameCodec, tokio::{CanFramed, CanSocket}};
+/// use tokio::net::TcpStream;
+///
+/// # async fn tunnel() -> socketcan::Result<()> {
+/// let sock = CanSocket::open("vcan0")?;
+/// let stream = TcpStream::connect("192.168.1.2:5000").await?;
+/// let mut framed = CanFramed::new(stream, CanFrameCodec);
+///
+/// let (_sink, mut rx) = sock.split();
+/// while let Some(frame) = rx.next().await {
+///     framed.send(frame?).await?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CanFramed<S, C = CanFrameCodec> {
+    io: S,
+    codec: C,
+    /// The bytes read from the stream that haven't been decoded
+    rd: Vec<u8>,
+    /// The start of the bytes in `rd` that haven't been decoded
+    rd_pos: usize,
+    /// The encoded frames that haven't been written to the stream
+    wr: Vec<u8>,
+    /// The start of the bytes in `wr` that haven't been written
+    wr_pos: usize,
+}
+
+impl<S, C: FrameCodec> CanFramed<S, C> {
+    /// Creates a stream and sink of frames over the byte stream.
+    pub fn new(io: S, codec: C) -> Self {
+        Self {
+            io,
+            codec,
+            rd: Vec::new(),
+            rd_pos: 0,
+            wr: Vec::new(),
+            wr_pos: 0,
+        }
+    }
+
+    /// Gets a reference to the byte stream.
+    pub fn get_ref(&self) -> &S {
+        &self.io
+    }
+
+    /// Gets a mutable reference to the byte stream.
+    ///
+    /// Reading or writing it directly can lose track of the records.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.io
+    }
+
+    /// Gets the byte stream back.
+    ///
+    /// Any frames that were buffered, but not yet read or written, are
+    /// lost.
+    pub fn into_inner(self) -> S {
+        self.io
     }
 }
 
-impl AsyncRead for CanSocket {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut ReadBuf<'_>,
-    ) -> Poll<IoResult<()>> {
+impl<S, C> Stream for CanFramed<S, C>
+where
+    S: AsyncRead + Unpin,
+    C: FrameCodec + Unpin,
+{
+    type Item = Result<C::Item>;
+
+    /// The stream ends when the byte stream does. If it ends part way
+    /// through a record, that's an `UnexpectedEof` error.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let len = this.codec.record_len();
+
         loop {
-            let mut guard = ready!(self.0.poll_read_ready_mut(cx))?;
+            if this.rd.len() - this.rd_pos >= len {
+                let record = &this.rd[this.rd_pos..this.rd_pos + len];
+                let res = this.codec.decode(record);
+                this.rd_pos += len;
+                return Poll::Ready(Some(res.map_err(Error::from)));
+            }
 
-            let unfilled = buf.initialize_unfilled();
-            match guard.try_io(|inner| inner.get_mut().read(unfilled)) {
-                Ok(Ok(len)) => {
-                    buf.advance(len);
-                    return Poll::Ready(Ok(()));
+            // Keep only the partial record, and read more after it
+            this.rd.drain(..this.rd_pos);
+            this.rd_pos = 0;
+            let filled = this.rd.len();
+            this.rd.resize(filled + FRAMED_READ_LEN.max(len), 0);
+
+            let mut buf = ReadBuf::new(&mut this.rd[filled..]);
+            let res = Pin::new(&mut this.io).poll_read(cx, &mut buf);
+            let n = buf.filled().len();
+            this.rd.truncate(filled + n);
+
+            match res {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                Poll::Ready(Ok(())) if n == 0 => {
+                    return match filled {
+                        0 => Poll::Ready(None),
+                        _ => {
+                            this.rd.clear();
+                            Poll::Ready(Some(Err(IoErrorKind::UnexpectedEof.into())))
+                        }
+                    };
                 }
-                Ok(Err(err)) => return Poll::Ready(Err(err)),
-                Err(_would_block) => continue,
+                Poll::Ready(Ok(())) => (),
             }
         }
     }
 }
 
-impl AsyncWrite for CanSocket {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<IoResult<usize>> {
-        loop {
-            let mut guard = ready!(self.0.poll_write_ready_mut(cx))?;
+impl<S, C> Sink<C::Item> for CanFramed<S, C>
+where
+    S: AsyncWrite + Unpin,
+    C: FrameCodec + Unpin,
+{
+    type Error = Error;
 
-            match guard.try_io(|inner| inner.get_mut().write(buf)) {
-                Ok(result) => return Poll::Ready(result),
-                Err(_would_block) => continue,
-            }
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.wr.len() - self.wr_pos >= FRAMED_WRITE_LEN {
+            return self.poll_flush(cx);
         }
+        Poll::Ready(Ok(()))
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+    fn start_send(self: Pin<&mut Self>, item: C::Item) -> Result<()> {
+        let this = self.get_mut();
+        this.codec.encode(&item, &mut this.wr);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        while this.wr_pos < this.wr.len() {
+            let n = ready!(Pin::new(&mut this.io).poll_write(cx, &this.wr[this.wr_pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(IoErrorKind::WriteZero.into()));
+            }
+            this.wr_pos += n;
+        }
+        this.wr.clear();
+        this.wr_pos = 0;
+        ready!(Pin::new(&mut this.io).poll_flush(cx))?;
         Poll::Ready(Ok(()))
     }
 
-    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        ready!(Pin::new(&mut self.io).poll_shutdown(cx))?;
         Poll::Ready(Ok(()))
     }
 }
 
-/// An Asynchronous CAN FD Socket
-pub type CanFdSocket = AsyncCanSocket<crate::CanFdSocket>;
+/// A rule for [`forward()`], that picks out the frames to pass, and
+/// optionally gives them a new ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardRule {
+    filter: CanFilter,
+    id: Option<Id>,
+}
 
-impl CanFdSocket {
-    /// Write a CAN FD frame to the socket asynchronously
-    pub async fn write_frame<F>(&self, frame: &F) -> IoResult<()>
-    where
-        F: Into<CanAnyFrame> + AsPtr,
-    {
-        self.0
-            .async_io(Interest::WRITABLE, |inner| inner.write_frame(frame))
-            .await
+impl ForwardRule {
+    /// Creates a rule that passes the frames that pass the filter,
+    /// unchanged.
+    pub fn new(filter: CanFilter) -> Self {
+        Self { filter, id: None }
     }
 
-    /// Reads a CAN FD frame from the socket asynchronously
-    pub async fn read_frame(&self) -> IoResult<CanAnyFrame> {
-        self.0
+    /// Gives the frames that pass the rule a new ID.
+    pub fn rewrite_id(mut self, id: impl Into<Id>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Applies the rule to the frame, returning whether it passed.
+    fn apply<F: Frame>(&self, frame: &mut F) -> bool {
+        if !self.filter.matches(frame.id_word()) {
+            return false;
+        }
+        if let Some(id) = self.id {
+            frame.set_id(id);
+        }
+        true
+    }
+}
+
+/// Copies frames from one socket to another, until the source interface
+/// goes away.
+///
+/// This is the core of a software gateway between two buses. Each frame
+/// is checked against the rules in turn, and the first that passes it
+/// decides its ID on the destination. A frame that passes none of the
+/// rules is dropped, although with no rules at all, every frame is
+/// copied as it is. Error frames are never copied.
+///
+/// ```no_run
+/// use socketcan::{
+///     tokio::{forward, CanSocket, ForwardRule},
+///     CanFilter, StandardId,
+/// };
+///
+/// # async fn gateway() -> socketcan::IoResult<()> {
+/// let src = CanSocket::open("can0")?;
+/// let dst = CanSocket::open("can1")?;
+///
+/// // Pass the IDs with 0x100 in the 0x700 bits, and move 0x7DF to 0x6DF
+/// let rules = [
+///     ForwardRule::new(CanFilter::new(0x100, 0x700)),
+///     ForwardRule::new(CanFilter::exact(StandardId::new(0x7DF).unwrap()))
+///         .rewrite_id(StandardId::new(0x6DF).unwrap()),
+/// ];
+/// let n = forward(src, dst, rules).await?;
+/// println!("Forwarded {} frames", n);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The frames are read and written one at a time, so a slow destination
+/// pushes back on the source. If the destination's TX queue is full, and
+/// the write fails with `ENOBUFS`, the frame is dropped, as a hardware
+/// gateway would. Any other error stops the copying, and is returned.
+/// Otherwise, this returns the number of frames copied.
+///
+/// Forwarding between two sockets on the same interface loops the frames
+/// back around, unless the rules stop them.
+pub async fn forward<T, R>(
+    src: AsyncCanSocket<T>,
+    dst: AsyncCanSocket<T>,
+    rules: R,
+) -> IoResult<u64>
+where
+    T: Socket,
+    T::FrameType: Frame + AsPtr,
+    R: IntoIterator<Item = ForwardRule>,
+{
+    let rules: Vec<ForwardRule> = rules.into_iter().collect();
+    let mut n = 0;
+    loop {
+        let mut frame = match src
+            .0
             .async_io(Interest::READABLE, |inner| inner.read_frame())
             .await
+        {
+            Ok(frame) => frame,
+            Err(err) if is_interface_gone(&err) => return Ok(n),
+            Err(err) => return Err(err),
+        };
+        if frame.is_error_frame() {
+            continue;
+        }
+        if !rules.is_empty() && !rules.iter().any(|rule| rule.apply(&mut frame)) {
+            continue;
+        }
+        match dst
+            .0
+            .async_io(Interest::WRITABLE, |inner| inner.write_frame(&frame))
+            .await
+        {
+            Ok(()) => n += 1,
+            Err(err) if err.raw_os_error() == Some(libc::ENOBUFS) => (),
+            Err(err) => return Err(err),
+        }
     }
 }
 
-impl Stream for CanFdSocket {
-    type Item = Result<CanAnyFrame>;
+/// Asynchronous control of a CAN interface through Netlink.
+///
+/// This has async versions of the [`crate::CanInterface`] calls that a
+/// service needs to manage its devices, such as bringing up a USB adapter
+/// when it's plugged in. Each request runs on tokio's blocking thread
+/// pool, so that waiting for the kernel to reply doesn't stall the
+/// runtime.
+///
+/// ```no_run
+/// use socketcan::tokio::CanInterface;
+///
+/// # async fn setup() -> Result<(), Box<dyn std::error::Error>> {
+/// let iface = CanInterface::open("can0")?;
+/// iface.bring_down().await?;
+/// iface.set_bitrate(500_000, None).await?;
+/// iface.bring_up().await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Some of the calls require the `CAP_NET_ADMIN` capability, as noted on
+/// the blocking versions.
+#[cfg(feature = "netlink")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanInterface {
+    if_index: u32,
+}
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        loop {
-            let mut ready_guard = ready!(self.0.poll_read_ready(cx))?;
-            match ready_guard.try_io(|inner| inner.get_ref().read_frame()) {
-                Ok(result) => return Poll::Ready(Some(result.map_err(|e| e.into()))),
-                Err(_would_block) => continue,
-            }
-        }
+#[cfg(feature = "netlink")]
+impl CanInterface {
+    /// Opens a CAN interface by name.
+    pub fn open(ifname: &str) -> std::result::Result<Self, nix::Error> {
+        crate::CanInterface::open(ifname).map(Self::from)
     }
-}
 
-impl Sink<CanAnyFrame> for CanFdSocket {
-    type Error = Error;
+    /// Opens a CAN interface by its kernel index.
+    ///
+    /// This doesn't check that the interface exists.
+    pub fn open_iface(if_index: u32) -> Self {
+        Self { if_index }
+    }
 
-    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
-        let _ = ready!(self.0.poll_write_ready(cx))?;
-        Poll::Ready(Ok(()))
+    /// Gets the kernel's index for the interface.
+    pub fn index(&self) -> u32 {
+        self.if_index
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
-        Poll::Ready(Ok(()))
+    /// Brings the interface up.
+    ///
+    /// See [`crate::CanInterface::bring_up()`].
+    pub async fn bring_up(&self) -> std::result::Result<(), NlError> {
+        self.blocking(|iface| iface.bring_up()).await
     }
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
-        let mut ready_guard = ready!(self.0.poll_write_ready(cx))?;
-        ready_guard.clear_ready();
-        Poll::Ready(Ok(()))
+    /// Brings the interface down.
+    ///
+    /// See [`crate::CanInterface::bring_down()`].
+    pub async fn bring_down(&self) -> std::result::Result<(), NlError> {
+        self.blocking(|iface| iface.bring_down()).await
     }
 
-    fn start_send(self: Pin<&mut Self>, item: CanAnyFrame) -> Result<()> {
-        self.0.get_ref().write_frame_insist(&item)?;
-        Ok(())
+    /// Determines if the interface is up.
+    ///
+    /// See [`crate::CanInterface::is_up()`].
+    pub async fn is_up(&self) -> std::result::Result<bool, NlError<Rtm, Ifinfomsg>> {
+        self.blocking(|iface| iface.is_up()).await
     }
-}
 
-impl AsyncRead for CanFdSocket {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut ReadBuf<'_>,
-    ) -> Poll<IoResult<()>> {
-        loop {
-            let mut guard = ready!(self.0.poll_read_ready_mut(cx))?;
+    /// Queries the details of the interface.
+    ///
+    /// See [`crate::CanInterface::details()`].
+    pub async fn details(&self) -> std::result::Result<InterfaceDetails, NlError<Rtm, Ifinfomsg>> {
+        self.blocking(|iface| iface.details()).await
+    }
 
-            let unfilled = buf.initialize_unfilled();
-            match guard.try_io(|inner| inner.get_mut().read(unfilled)) {
-                Ok(Ok(len)) => {
-                    buf.advance(len);
-                    return Poll::Ready(Ok(()));
-                }
-                Ok(Err(err)) => return Poll::Ready(Err(err)),
-                Err(_would_block) => continue,
-            }
-        }
+    /// Sets the bitrate, and optionally the sample point, which can only
+    /// be done while the interface is down.
+    ///
+    /// See [`crate::CanInterface::set_bitrate()`].
+    pub async fn set_bitrate<P>(
+        &self,
+        bitrate: u32,
+        sample_point: P,
+    ) -> std::result::Result<(), NlError>
+    where
+        P: Into<Option<u32>>,
+    {
+        let sample_point = sample_point.into();
+        self.blocking(move |iface| iface.set_bitrate(bitrate, sample_point))
+            .await
     }
-}
 
-impl AsyncWrite for CanFdSocket {
-    fn poll_write(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<IoResult<usize>> {
-        loop {
-            let mut guard = ready!(self.0.poll_write_ready_mut(cx))?;
+    /// Sets any of the CAN parameters of the interface, in one request.
+    ///
+    /// See [`crate::CanInterface::set_can_params()`].
+    pub async fn set_can_params(
+        &self,
+        params: &InterfaceCanParams,
+    ) -> std::result::Result<(), NlError> {
+        let params = params.clone();
+        self.blocking(move |iface| iface.set_can_params(&params))
+            .await
+    }
 
-            match guard.try_io(|inner| inner.get_mut().write(buf)) {
-                Ok(result) => return Poll::Ready(result),
-                Err(_would_block) => continue,
-            }
+    /// Restarts the interface after a bus-off.
+    ///
+    /// See [`crate::CanInterface::restart()`].
+    pub async fn restart(&self) -> std::result::Result<(), NlError> {
+        self.blocking(|iface| iface.restart()).await
+    }
+
+    /// Runs a blocking Netlink call on the blocking thread pool.
+    async fn blocking<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&crate::CanInterface) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let if_index = self.if_index;
+        let task =
+            tokio::task::spawn_blocking(move || f(&crate::CanInterface::open_iface(if_index)));
+        match task.await {
+            Ok(res) => res,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => panic!("Netlink request was cancelled: {}", err),
         }
     }
+}
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
-        Poll::Ready(Ok(()))
+#[cfg(feature = "netlink")]
+impl From<crate::CanInterface> for CanInterface {
+    fn from(iface: crate::CanInterface) -> Self {
+        Self::open_iface(iface.index())
     }
+}
 
-    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<IoResult<()>> {
-        Poll::Ready(Ok(()))
+#[cfg(feature = "netlink")]
+impl From<CanInterface> for crate::CanInterface {
+    fn from(iface: CanInterface) -> Self {
+        Self::open_iface(iface.if_index)
     }
 }
 
@@ -413,6 +1828,27 @@ mod tests {
         Ok(())
     }
 
+    #[serial]
+    #[tokio::test]
+    async fn test_read_frame_with_timestamp() -> Result<()> {
+        let socket1 = CanSocket::open("vcan0").unwrap();
+        let socket2 = CanSocket::open("vcan0").unwrap();
+
+        // Enabled on the async socket, just as with a blocking one
+        socket2.enable_timestamps()?;
+        write_frame(&socket1).await?;
+        let (_, ts) = socket2.read_frame_with_timestamp().await?;
+        assert!(ts.is_some());
+
+        // No timestamp, but the frame still comes through
+        socket2.set_timestamp_mode(crate::TimestampMode::Disabled)?;
+        write_frame(&socket1).await?;
+        let (_, ts) = socket2.read_frame_with_timestamp().await?;
+        assert!(ts.is_none());
+
+        Ok(())
+    }
+
     #[serial]
     #[tokio::test]
     async fn test_receive_with_stream() -> Result<()> {
@@ -665,4 +2101,367 @@ mod tests {
 
         Ok(())
     }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_isotp_send_recv() -> Result<()> {
+        let tester = StandardId::new(0x7E0).unwrap();
+        let ecu = StandardId::new(0x7E8).unwrap();
+
+        let tx_sock = CanIsoTpSocket::open("vcan0", ecu, tester)?;
+        let rx_sock = CanIsoTpSocket::open("vcan0", tester, ecu)?;
+
+        // Multi-frame, so the receiver has to send flow control while the
+        // sender waits for it.
+        let msg: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let (_, data) = try_join!(tx_sock.send(&msg), async {
+            select!(
+                data = rx_sock.recv().fuse() => data,
+                _timeout = Delay::new(Duration::from_secs(2)).fuse() => Err(IoErrorKind::TimedOut.into()),
+            )
+        })?;
+        assert_eq!(data, msg);
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_isotp_read_write() -> Result<()> {
+        let tester = StandardId::new(0x7E0).unwrap();
+        let ecu = StandardId::new(0x7E8).unwrap();
+
+        let tx_sock = AsyncCanIsoTpSocket::open("vcan0", ecu, tester)?;
+        let rx_sock = AsyncCanIsoTpSocket::open("vcan0", tester, ecu)?;
+
+        let msg: Vec<u8> = (0..100).collect();
+        let mut buf = [0u8; 256];
+        let (_, n) = try_join!(tx_sock.write(&msg), async {
+            select!(
+                n = rx_sock.read(&mut buf).fuse() => n,
+                _timeout = Delay::new(Duration::from_secs(2)).fuse() => Err(IoErrorKind::TimedOut.into()),
+            )
+        })?;
+        assert_eq!(&buf[..n], &msg[..]);
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_bcm_stream() -> Result<()> {
+        use crate::bcm::RxJob;
+
+        let id = StandardId::new(0x123).unwrap();
+        let mut bcm = CanBcmSocket::open("vcan0")?;
+        bcm.get_ref().rx_setup(&RxJob::new(id).timeout(TIMEOUT))?;
+
+        let socket = CanSocket::open("vcan0")?;
+        socket
+            .write_frame(CanFrame::new(id, &[1, 2]).unwrap())
+            .await?;
+
+        match bcm.next().await {
+            Some(Ok(BcmMsg::RxChanged(frame))) => assert_eq!(frame.data(), &[1, 2]),
+            msg => panic!("unexpected {:?}", msg),
+        }
+        // Nothing else is sent, so the subscription times out.
+        match bcm.next().await {
+            Some(Ok(BcmMsg::RxTimeout(rx_id))) => assert_eq!(rx_id, Id::Standard(id)),
+            msg => panic!("unexpected {:?}", msg),
+        }
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_j1939_stream() -> Result<()> {
+        use crate::j1939::{J1939_NO_NAME, J1939_NO_PGN};
+
+        const PGN: u32 = 0xFEF1;
+
+        let mut rx_sock = CanJ1939Socket::open("vcan0", J1939_NO_NAME, J1939_NO_PGN, 0x20)?;
+        let tx_sock = CanJ1939Socket::open("vcan0", J1939_NO_NAME, J1939_NO_PGN, 0x80)?;
+
+        // Multi-frame, through the transport protocol
+        let msg: Vec<u8> = (0..20).collect();
+        let peer = CanAddr::from_iface_j1939("vcan0", J1939_NO_NAME, PGN, 0x20)?;
+
+        let (sent, received) = future::join(tx_sock.send_to(&msg, &peer), rx_sock.next()).await;
+        sent?;
+        let (from, pgn, data) = received.unwrap()?;
+        assert_eq!(from.j1939_addr(), 0x80);
+        assert_eq!(pgn, PGN);
+        assert_eq!(data, msg);
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_into_split() -> Result<()> {
+        let (_, mut tx) = CanSocket::open("vcan0")?.into_split();
+        let (mut rx, _) = CanSocket::open("vcan0")?.into_split();
+
+        let frame = CanFrame::new(StandardId::new(0x7).unwrap(), &[7]).unwrap();
+        let writer = tokio::spawn(async move {
+            tx.send(frame).await?;
+            Ok::<_, Error>(tx)
+        });
+
+        let received = select!(
+            frame = rx.next().fuse() => frame.unwrap()?,
+            _timeout = Delay::new(TIMEOUT).fuse() => return Err(IoErrorKind::TimedOut.into()),
+        );
+        assert_eq!(received.raw_id(), 0x7);
+        writer.await.unwrap()?;
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_read_cancellation() -> Result<()> {
+        let socket1 = CanSocket::open("vcan0")?;
+        let socket2 = CanSocket::open("vcan0")?;
+
+        assert_eq!(
+            socket2
+                .read_frame_timeout(TIMEOUT)
+                .await
+                .unwrap_err()
+                .kind(),
+            IoErrorKind::TimedOut
+        );
+
+        // Reads that keep losing a race, while the frames arrive
+        write_frame(&socket1).await?;
+        write_frame(&socket1).await?;
+        let mut n = 0;
+        for _ in 0..10 {
+            select!(
+                frame = socket2.read_frame().fuse() => {
+                    frame?;
+                    n += 1;
+                    break;
+                },
+                _ready = future::ready(()).fuse() => (),
+            );
+        }
+
+        // Whatever the cancelled reads didn't return is still queued
+        while socket2.read_frame_timeout(TIMEOUT).await.is_ok() {
+            n += 1;
+        }
+        assert_eq!(n, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_framed() -> Result<()> {
+        use crate::codec::CanFdFrameCodec;
+
+        let (a, b) = tokio::io::duplex(64);
+        let mut tx = CanFramed::new(a, CanFdFrameCodec);
+        let mut rx = CanFramed::new(b, CanFdFrameCodec);
+
+        let id = StandardId::new(0x42).unwrap();
+        let frames: Vec<CanAnyFrame> = vec![
+            CanFrame::new(id, &[1]).unwrap().into(),
+            CanFdFrame::new(id, &[2; 20]).unwrap().into(),
+        ];
+
+        // The pipe is smaller than a record, so both sides have to wait
+        let send = async {
+            for frame in frames.clone() {
+                tx.feed(frame).await?;
+            }
+            tx.close().await
+        };
+        let (_, received) = try_join!(send, rx.by_ref().try_collect::<Vec<_>>())?;
+
+        assert_eq!(received.len(), 2);
+        assert!(matches!(&received[0], CanAnyFrame::Normal(f) if f.data() == [1]));
+        assert!(matches!(&received[1], CanAnyFrame::Fd(f) if f.data() == [2; 20]));
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_broadcaster() -> Result<()> {
+        let id1 = StandardId::new(0x101).unwrap();
+        let id2 = StandardId::new(0x102).unwrap();
+
+        let bus = CanBroadcaster::new(CanSocket::open("vcan0")?, 16);
+        let mut all = bus.subscribe(&[]);
+        let mut only2 = bus.subscribe(&[CanFilter::exact(id2)]);
+        assert_eq!(bus.subscriber_count(), 2);
+
+        let socket = CanSocket::open("vcan0")?;
+        socket
+            .write_frame(CanFrame::new(id1, &[1]).unwrap())
+            .await?;
+        socket
+            .write_frame(CanFrame::new(id2, &[2]).unwrap())
+            .await?;
+
+        async fn recv(sub: &mut CanSubscriber) -> CanFrame {
+            ::tokio::time::timeout(TIMEOUT, sub.recv())
+                .await
+                .unwrap()
+                .unwrap()
+        }
+        assert_eq!(recv(&mut all).await.raw_id(), 0x101);
+        assert_eq!(recv(&mut all).await.raw_id(), 0x102);
+        assert_eq!(recv(&mut only2).await.raw_id(), 0x102);
+
+        drop(bus);
+        assert!(matches!(all.recv().await, Err(RecvError::Closed)));
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_interface_is_up() {
+        let iface = CanInterface::open("vcan0").unwrap();
+        assert!(iface.is_up().await.unwrap());
+        assert!(CanInterface::open_iface(u32::MAX >> 1)
+            .is_up()
+            .await
+            .is_err());
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_filter_ids() -> Result<()> {
+        let std = |id| Id::from(StandardId::new(id).unwrap());
+
+        let frames = CanSocket::open("vcan0")?.filter_ids([std(0x100)..=std(0x10F)]);
+        assert!(frames.is_in_kernel());
+
+        // Only the frames that pass both sets of filters get through
+        let mut frames = frames.match_mask(0x101, 0x001);
+        assert!(!frames.is_in_kernel());
+
+        let socket = CanSocket::open("vcan0")?;
+        for id in [0x050, 0x101, 0x102, 0x200, 0x10F] {
+            socket
+                .write_frame(CanFrame::new(std(id), &[]).unwrap())
+                .await?;
+        }
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let frame = ::tokio::time::timeout(TIMEOUT, frames.next())
+                .await
+                .unwrap()
+                .unwrap()?;
+            ids.push(frame.raw_id());
+        }
+        assert_eq!(ids, [0x101, 0x10F]);
+        assert!(::tokio::time::timeout(TIMEOUT, frames.next())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_forward() -> Result<()> {
+        let std = |id| StandardId::new(id).unwrap();
+
+        // On the one interface, so the rewritten frames mustn't pass
+        let rules = [ForwardRule::new(CanFilter::exact(std(0x100))).rewrite_id(std(0x200))];
+        let task = ::tokio::spawn(forward(
+            CanSocket::open("vcan0")?,
+            CanSocket::open("vcan0")?,
+            rules,
+        ));
+
+        let socket_rx = CanSocket::open("vcan0")?;
+        socket_rx.set_filters(&[CanFilter::exact(std(0x200))])?;
+
+        let socket = CanSocket::open("vcan0")?;
+        socket
+            .write_frame(CanFrame::new(std(0x300), &[3]).unwrap())
+            .await?;
+        socket
+            .write_frame(CanFrame::new(std(0x100), &[1]).unwrap())
+            .await?;
+
+        let frame = ::tokio::time::timeout(TIMEOUT, socket_rx.read_frame())
+            .await
+            .unwrap()?;
+        assert_eq!(frame.raw_id(), 0x200);
+        assert_eq!(frame.data(), &[1]);
+        assert!(::tokio::time::timeout(TIMEOUT, socket_rx.read_frame())
+            .await
+            .is_err());
+
+        task.abort();
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_transact() -> Result<()> {
+        let req_id = StandardId::new(0x7E0).unwrap();
+        let reply_id = StandardId::new(0x7E8).unwrap();
+        let other_id = StandardId::new(0x123).unwrap();
+
+        let client = CanSocket::open("vcan0")?;
+        let server = CanSocket::open("vcan0")?;
+
+        let respond = async {
+            let req = server.read_frame().await?;
+            assert_eq!(req.raw_id(), 0x7E0);
+            server
+                .write_frame(CanFrame::new(other_id, &[0xFF]).unwrap())
+                .await?;
+            server
+                .write_frame(CanFrame::new(reply_id, &[req.data()[0] + 1]).unwrap())
+                .await?;
+            Ok::<_, IoError>(())
+        };
+        let request = client.transact(
+            CanFrame::new(req_id, &[1]).unwrap(),
+            CanFilter::exact(reply_id),
+            TIMEOUT,
+        );
+        let (reply, ()) = try_join!(request, respond)?;
+        assert_eq!(reply.raw_id(), 0x7E8);
+        assert_eq!(reply.data(), &[2]);
+
+        // Nobody answers this time
+        let err = client
+            .transact(
+                CanFrame::new(req_id, &[1]).unwrap(),
+                CanFilter::exact(reply_id),
+                TIMEOUT,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), IoErrorKind::TimedOut);
+        Ok(())
+    }
+
+    #[serial]
+    #[tokio::test]
+    async fn test_tx_queue() -> Result<()> {
+        let id = StandardId::new(0x110).unwrap();
+        let socket_rx = CanSocket::open("vcan0")?;
+        let queue = CanTxQueue::new(CanSocket::open("vcan0")?, 4);
+        assert_eq!(queue.capacity(), 4);
+
+        for i in 0..8 {
+            queue.send(CanFrame::new(id, &[i]).unwrap()).await?;
+        }
+        for i in 0..8 {
+            let frame = ::tokio::time::timeout(TIMEOUT, socket_rx.read_frame())
+                .await
+                .unwrap()?;
+            assert_eq!(frame.data(), &[i]);
+        }
+
+        let metrics = queue.metrics();
+        assert!(metrics.peak_depth >= 1 && metrics.peak_depth <= 4);
+        queue.close().await?;
+        Ok(())
+    }
 }
@@ -0,0 +1,668 @@
+// socketcan/src/tp20.rs
+//
+// A userspace implementation of the VW TP 2.0 transport protocol.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! VW TP 2.0 channels.
+//!
+//! TP 2.0 is the transport protocol used by VW group (VAG) ECUs of the
+//! 2000s for KWP2000 diagnostics over CAN. The kernel reserves a protocol
+//! number for it, `CAN_TP20`, but has no implementation, so this runs in
+//! userspace over a raw [`CanSocket`].
+//!
+//! A tester sets up a channel to an ECU by its logical address, with a
+//! request broadcast on ID 0x200. The ECU answers with the pair of CAN
+//! IDs to use for the channel, after which the two sides agree on the
+//! timing parameters. Messages are then sent in numbered packets of up to
+//! 7 bytes, with the first one giving the length of the message, and the
+//! receiver acknowledges them in blocks. An idle channel has to be kept
+//! alive with a channel test about once a second, or the ECU closes it.
+//!
+//! ```no_run
+//! use socketcan::tp20::{Tp20Channel, APP_KWP, ECU_ENGINE};
+//!
+//! let mut chan = Tp20Channel::open("can0", ECU_ENGINE, APP_KWP).unwrap();
+//!
+//! // KWP2000 StartDiagnosticSession
+//! let resp = chan.request(&[0x10, 0x89]).unwrap();
+//! println!("{:02X?}", resp);
+//!
+//! chan.disconnect().unwrap();
+//! ```
+
+use crate::{CanFrame, CanSocket, EmbeddedFrame, Id, Socket, StandardId};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+pub use libc::CAN_TP20;
+
+/// The CAN ID of the channel setup requests
+pub const SETUP_REQUEST_ID: u16 = 0x200;
+
+/// The logical address of the engine ECU
+pub const ECU_ENGINE: u8 = 0x01;
+
+/// The application type for KWP2000 diagnostics
+pub const APP_KWP: u8 = 0x01;
+
+/// How long to wait for the ECU to respond, by default
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// How often an idle channel is tested by default, to keep it open
+pub const DEFAULT_KEEP_ALIVE: Duration = Duration::from_millis(1000);
+
+/// The longest message that fits in a TP 2.0 packet sequence
+pub const MAX_MSG_LEN: usize = 0xFFFF;
+
+// The setup opcodes
+const OP_SETUP_REQUEST: u8 = 0xC0;
+const OP_SETUP_POSITIVE: u8 = 0xD0;
+
+// The channel opcodes
+const OP_PARAMS_REQUEST: u8 = 0xA0;
+const OP_PARAMS_RESPONSE: u8 = 0xA1;
+const OP_CHANNEL_TEST: u8 = 0xA3;
+const OP_BREAK: u8 = 0xA4;
+const OP_DISCONNECT: u8 = 0xA8;
+
+// The data packet opcodes, in the high nibble
+const DATA_ACK_MORE: u8 = 0x00;
+const DATA_ACK_LAST: u8 = 0x10;
+const DATA_MORE: u8 = 0x20;
+const DATA_LAST: u8 = 0x30;
+const ACK_READY: u8 = 0xB0;
+const ACK_NOT_READY: u8 = 0x90;
+
+/// The bit in the high byte of an ID, in a setup message, that marks it
+/// as not given
+const ID_INVALID: u8 = 0x10;
+
+// ===== Errors =====
+
+/// An error on a TP 2.0 channel
+#[derive(Error, Debug)]
+pub enum Tp20Error {
+    /// I/O Error
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The ECU refused to set up the channel, with the opcode of its
+    /// response
+    #[error("Channel setup rejected: 0x{0:02X}")]
+    Rejected(u8),
+    /// The ECU didn't respond within the timeout
+    #[error("No response from the ECU")]
+    Timeout,
+    /// The ECU closed the channel
+    #[error("Channel closed by the ECU")]
+    Disconnected,
+    /// The ECU sent something that doesn't fit the protocol
+    #[error("Unexpected response")]
+    UnexpectedResponse,
+}
+
+/// The result of an operation on a TP 2.0 channel
+pub type Tp20Result<T> = Result<T, Tp20Error>;
+
+// ===== Tp20Params =====
+
+/// Decodes a TP 2.0 timing byte.
+///
+/// The top two bits are the unit, from 0.1ms up to 100ms, and the rest
+/// are the count of them.
+fn decode_timing(b: u8) -> Duration {
+    let n = u64::from(b & 0x3F);
+    match b >> 6 {
+        0 => Duration::from_micros(100 * n),
+        1 => Duration::from_millis(n),
+        2 => Duration::from_millis(10 * n),
+        _ => Duration::from_millis(100 * n),
+    }
+}
+
+/// Encodes a time as a TP 2.0 timing byte, in the finest unit that holds
+/// it. Times that are too long are saturated.
+fn encode_timing(time: Duration) -> u8 {
+    let us = time.as_micros();
+    match us {
+        us if us / 100 < 64 => (us / 100) as u8,
+        us if us / 1000 < 64 => 0x40 | (us / 1000) as u8,
+        us if us / 10_000 < 64 => 0x80 | (us / 10_000) as u8,
+        us => 0xC0 | (us / 100_000).min(0x3F) as u8,
+    }
+}
+
+/// The timing parameters of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tp20Params {
+    /// The number of packets that can be sent before waiting for an
+    /// acknowledgment
+    pub block_size: u8,
+    /// How long to wait for an acknowledgment (T1)
+    pub ack_timeout: Duration,
+    /// The minimum time between packets that are sent (T3)
+    pub packet_interval: Duration,
+}
+
+impl Default for Tp20Params {
+    /// The parameters commonly requested by testers: blocks of 15 packets,
+    /// 100ms for acknowledgments, and 5ms between packets.
+    fn default() -> Self {
+        Self {
+            block_size: 0x0F,
+            ack_timeout: Duration::from_millis(100),
+            packet_interval: Duration::from_millis(5),
+        }
+    }
+}
+
+impl Tp20Params {
+    /// Creates a parameters message, with the opcode given.
+    fn to_msg(self, op: u8) -> [u8; 6] {
+        [
+            op,
+            self.block_size,
+            encode_timing(self.ack_timeout),
+            0xFF,
+            encode_timing(self.packet_interval),
+            0xFF,
+        ]
+    }
+
+    /// Parses a parameters message.
+    fn from_msg(msg: &[u8]) -> Option<Self> {
+        match *msg {
+            [OP_PARAMS_RESPONSE, block_size, t1, _, t3, _] => Some(Self {
+                block_size,
+                ack_timeout: decode_timing(t1),
+                packet_interval: decode_timing(t3),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// ===== Channel setup =====
+
+/// Creates the channel setup request for an ECU, proposing the ID that
+/// the tester receives on.
+fn setup_request(ecu: u8, rx_id: StandardId, app: u8) -> [u8; 7] {
+    let [lo, hi] = rx_id.as_raw().to_le_bytes();
+    [ecu, OP_SETUP_REQUEST, 0x00, ID_INVALID, lo, hi, app]
+}
+
+/// Parses the ECU's response to a setup request, giving the IDs that the
+/// tester transmits and receives on.
+fn parse_setup_response(msg: &[u8]) -> Tp20Result<(StandardId, StandardId)> {
+    let id = |lo: u8, hi: u8| StandardId::new(u16::from_le_bytes([lo, hi & 0x07]));
+    match *msg {
+        [_, OP_SETUP_POSITIVE, rx_lo, rx_hi, tx_lo, tx_hi, _] => {
+            match (id(tx_lo, tx_hi), id(rx_lo, rx_hi)) {
+                (Some(tx), Some(rx)) => Ok((tx, rx)),
+                _ => Err(Tp20Error::UnexpectedResponse),
+            }
+        }
+        [_, op, ..] => Err(Tp20Error::Rejected(op)),
+        _ => Err(Tp20Error::UnexpectedResponse),
+    }
+}
+
+// ===== Packets =====
+
+/// Splits a message into its data packets, starting at the sequence
+/// number given.
+///
+/// The first packet starts with the length of the message. The last
+/// packet, and the last of each block, ask for an acknowledgment.
+fn segment(data: &[u8], seq: u8, block_size: u8) -> Vec<Vec<u8>> {
+    let mut payload = Vec::with_capacity(2 + data.len());
+    payload.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    payload.extend_from_slice(data);
+
+    let n = (payload.len() + 6) / 7;
+    let block_size = usize::from(block_size.max(1));
+    payload
+        .chunks(7)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let last = i + 1 == n;
+            let op = match (last, (i + 1) % block_size == 0) {
+                (true, _) => DATA_ACK_LAST,
+                (false, true) => DATA_ACK_MORE,
+                (false, false) => DATA_MORE,
+            };
+            let mut pkt = Vec::with_capacity(8);
+            pkt.push(op | (seq.wrapping_add(i as u8) & 0x0F));
+            pkt.extend_from_slice(chunk);
+            pkt
+        })
+        .collect()
+}
+
+/// What to do with a data packet received
+#[derive(Debug, PartialEq, Eq)]
+enum Received {
+    // Keep going, acknowledging with the sequence number, if asked
+    More(Option<u8>),
+    // The message is complete, acknowledging with the sequence number, if
+    // asked
+    Done(Vec<u8>, Option<u8>),
+}
+
+/// Puts a message back together from its data packets.
+#[derive(Debug, Default)]
+struct Reassembler {
+    data: Vec<u8>,
+    next_seq: u8,
+    // Dropping the rest of a broken message, up to its last packet
+    skipping: bool,
+}
+
+impl Reassembler {
+    // Adds a data packet. After an error, the partial message is dropped,
+    // along with the rest of its packets. The ECU's sequence counter keeps
+    // running across messages, so ours picks up from the packet received.
+    fn push(&mut self, pkt: &[u8]) -> Tp20Result<Received> {
+        let res = self.push_packet(pkt);
+        if res.is_err() {
+            self.data.clear();
+            if let Some(&op) = pkt.first() {
+                self.next_seq = (op + 1) & 0x0F;
+                self.skipping = !matches!(op & 0xF0, DATA_ACK_LAST | DATA_LAST);
+            }
+        }
+        res
+    }
+
+    fn push_packet(&mut self, pkt: &[u8]) -> Tp20Result<Received> {
+        let (&op, chunk) = pkt.split_first().ok_or(Tp20Error::UnexpectedResponse)?;
+        let seq = op & 0x0F;
+        if seq != self.next_seq {
+            return Err(Tp20Error::UnexpectedResponse);
+        }
+        self.next_seq = (seq + 1) & 0x0F;
+
+        let ack = match op & 0xF0 {
+            DATA_ACK_MORE | DATA_ACK_LAST => Some(ACK_READY | self.next_seq),
+            DATA_MORE | DATA_LAST => None,
+            _ => return Err(Tp20Error::UnexpectedResponse),
+        };
+
+        if self.skipping {
+            self.skipping = !matches!(op & 0xF0, DATA_ACK_LAST | DATA_LAST);
+            return Ok(Received::More(ack));
+        }
+        self.data.extend_from_slice(chunk);
+
+        match op & 0xF0 {
+            DATA_ACK_LAST | DATA_LAST => {
+                let len = match *self.data.as_slice() {
+                    [hi, lo, ..] => usize::from(u16::from_be_bytes([hi, lo])),
+                    _ => return Err(Tp20Error::UnexpectedResponse),
+                };
+                let mut data = std::mem::take(&mut self.data);
+                if data.len() < 2 + len {
+                    return Err(Tp20Error::UnexpectedResponse);
+                }
+                data.truncate(2 + len);
+                data.drain(..2);
+                Ok(Received::Done(data, ack))
+            }
+            _ => Ok(Received::More(ack)),
+        }
+    }
+}
+
+// ===== Tp20Channel =====
+
+/// A TP 2.0 channel to an ECU, over a raw CAN socket.
+///
+/// The socket should be left to the channel while it's open, since frames
+/// for other IDs are read and dropped.
+#[derive(Debug)]
+pub struct Tp20Channel {
+    sock: CanSocket,
+    tx_id: StandardId,
+    rx_id: StandardId,
+    tx_seq: u8,
+    rx: Reassembler,
+    params: Tp20Params,
+    timeout: Duration,
+    keep_alive: Duration,
+    last_tx: Instant,
+}
+
+impl Tp20Channel {
+    /// Opens a socket on the named interface, and sets up a channel to the
+    /// ECU with the logical address given, for the application type.
+    pub fn open(ifname: &str, ecu: u8, app: u8) -> Tp20Result<Self> {
+        let sock = CanSocket::open(ifname)?;
+        Self::connect(sock, ecu, app)
+    }
+
+    /// Sets up a channel to the ECU on the socket, with the default
+    /// parameters.
+    ///
+    /// The tester proposes to receive on ID 0x300, which the ECU can
+    /// override in its response.
+    pub fn connect(sock: CanSocket, ecu: u8, app: u8) -> Tp20Result<Self> {
+        let proposed = StandardId::new(0x300).unwrap();
+        let request = setup_request(ecu, proposed, app);
+        let resp_id = StandardId::new(SETUP_REQUEST_ID + u16::from(ecu))
+            .ok_or(Tp20Error::UnexpectedResponse)?;
+
+        let mut chan = Self {
+            sock,
+            tx_id: StandardId::new(SETUP_REQUEST_ID).unwrap(),
+            rx_id: resp_id,
+            tx_seq: 0,
+            rx: Reassembler::default(),
+            params: Tp20Params::default(),
+            timeout: DEFAULT_TIMEOUT,
+            keep_alive: DEFAULT_KEEP_ALIVE,
+            last_tx: Instant::now(),
+        };
+
+        chan.send_frame(&request)?;
+        let resp = chan.recv_frame()?;
+        let (tx_id, rx_id) = parse_setup_response(&resp)?;
+        chan.tx_id = tx_id;
+        chan.rx_id = rx_id;
+
+        chan.send_frame(&Tp20Params::default().to_msg(OP_PARAMS_REQUEST))?;
+        chan.read_params()?;
+        Ok(chan)
+    }
+
+    /// Gets a shared reference to the underlying CAN socket
+    pub fn as_socket(&self) -> &CanSocket {
+        &self.sock
+    }
+
+    /// Gets the timing parameters that the ECU asked for.
+    pub fn params(&self) -> Tp20Params {
+        self.params
+    }
+
+    /// Sets how long to wait for the ECU to respond.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Sets how long the channel can be idle before a request tests it,
+    /// to keep it open.
+    pub fn set_keep_alive(&mut self, interval: Duration) {
+        self.keep_alive = interval;
+    }
+
+    /// Sends a message to the ECU.
+    ///
+    /// If the channel has been idle for longer than the keep-alive
+    /// interval, it's tested first.
+    pub fn send(&mut self, data: &[u8]) -> Tp20Result<()> {
+        if data.len() > MAX_MSG_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "message too long").into());
+        }
+        if self.last_tx.elapsed() >= self.keep_alive {
+            self.test_channel()?;
+        }
+
+        let packets = segment(data, self.tx_seq, self.params.block_size);
+        self.tx_seq = self.tx_seq.wrapping_add(packets.len() as u8) & 0x0F;
+
+        for (i, pkt) in packets.iter().enumerate() {
+            if i > 0 {
+                std::thread::sleep(self.params.packet_interval);
+            }
+            self.send_frame(pkt)?;
+            if pkt[0] & 0xF0 == DATA_ACK_MORE || pkt[0] & 0xF0 == DATA_ACK_LAST {
+                self.wait_ack((pkt[0] + 1) & 0x0F)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Receives a message from the ECU, acknowledging its packets.
+    ///
+    /// If a packet is lost or bad, the message is dropped with an error,
+    /// and the rest of its packets are skipped by the next call.
+    pub fn recv(&mut self) -> Tp20Result<Vec<u8>> {
+        loop {
+            let pkt = self.recv_frame()?;
+            match pkt.first() {
+                Some(&OP_DISCONNECT) => return Err(Tp20Error::Disconnected),
+                Some(&op) if op & 0xF0 == ACK_READY || op & 0xF0 == ACK_NOT_READY => continue,
+                Some(&OP_PARAMS_RESPONSE) => continue,
+                Some(&OP_CHANNEL_TEST) => {
+                    self.send_frame(&Tp20Params::default().to_msg(OP_PARAMS_RESPONSE))?;
+                    continue;
+                }
+                _ => (),
+            }
+            match self.rx.push(&pkt) {
+                Ok(Received::More(ack)) => self.send_ack(ack)?,
+                Ok(Received::Done(data, ack)) => {
+                    self.send_ack(ack)?;
+                    return Ok(data);
+                }
+                Err(err) => {
+                    // Acknowledge a bad packet anyway, if asked, so the
+                    // ECU doesn't stall on the rest of the message
+                    if let Some(&op) = pkt.first() {
+                        if matches!(op & 0xF0, DATA_ACK_MORE | DATA_ACK_LAST) {
+                            self.send_ack(Some(ACK_READY | self.rx.next_seq))?;
+                        }
+                    }
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Sends a message, and waits for the response.
+    ///
+    /// A KWP2000 "response pending" (0x7F, 0x78) is skipped, and the wait
+    /// continues for the real response.
+    pub fn request(&mut self, data: &[u8]) -> Tp20Result<Vec<u8>> {
+        self.send(data)?;
+        loop {
+            let resp = self.recv()?;
+            if !matches!(*resp, [0x7F, _, 0x78]) {
+                return Ok(resp);
+            }
+        }
+    }
+
+    /// Tests the channel, which keeps it open.
+    pub fn test_channel(&mut self) -> Tp20Result<()> {
+        self.send_frame(&[OP_CHANNEL_TEST])?;
+        self.read_params()
+    }
+
+    /// Sends a break, telling the ECU to drop the message it's receiving.
+    pub fn send_break(&mut self) -> Tp20Result<()> {
+        self.send_frame(&[OP_BREAK])
+    }
+
+    /// Closes the channel.
+    pub fn disconnect(mut self) -> Tp20Result<()> {
+        self.send_frame(&[OP_DISCONNECT])?;
+        match self.recv_frame() {
+            Ok(_) | Err(Tp20Error::Timeout) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Waits for a parameters response, and takes the ECU's parameters
+    fn read_params(&mut self) -> Tp20Result<()> {
+        let resp = self.recv_frame()?;
+        match Tp20Params::from_msg(&resp) {
+            Some(params) => {
+                self.params = params;
+                Ok(())
+            }
+            None if resp.first() == Some(&OP_DISCONNECT) => Err(Tp20Error::Disconnected),
+            None => Err(Tp20Error::UnexpectedResponse),
+        }
+    }
+
+    // Waits for an acknowledgment, with the next sequence number
+    fn wait_ack(&mut self, seq: u8) -> Tp20Result<()> {
+        loop {
+            let resp = self.recv_frame()?;
+            match resp.first() {
+                Some(&op) if op == ACK_READY | seq => return Ok(()),
+                // Not ready yet: keep waiting
+                Some(&op) if op & 0xF0 == ACK_NOT_READY => continue,
+                Some(&OP_DISCONNECT) => return Err(Tp20Error::Disconnected),
+                _ => return Err(Tp20Error::UnexpectedResponse),
+            }
+        }
+    }
+
+    fn send_ack(&mut self, ack: Option<u8>) -> Tp20Result<()> {
+        match ack {
+            Some(ack) => self.send_frame(&[ack]),
+            None => Ok(()),
+        }
+    }
+
+    fn send_frame(&mut self, data: &[u8]) -> Tp20Result<()> {
+        let frame = CanFrame::new(self.tx_id, data).ok_or(Tp20Error::UnexpectedResponse)?;
+        self.sock.write_frame_insist(&frame)?;
+        self.last_tx = Instant::now();
+        Ok(())
+    }
+
+    // Reads the next data frame on the channel's receive ID
+    fn recv_frame(&mut self) -> Tp20Result<Vec<u8>> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Tp20Error::Timeout);
+            }
+            let frame = match self.sock.read_frame_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    return Err(Tp20Error::Timeout)
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if frame.id() == Id::Standard(self.rx_id) && matches!(frame, CanFrame::Data(_)) {
+                return Ok(frame.data().to_vec());
+            }
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timing() {
+        assert_eq!(decode_timing(0x8A), Duration::from_millis(100));
+        assert_eq!(decode_timing(0x32), Duration::from_millis(5));
+        assert_eq!(decode_timing(0x4A), Duration::from_millis(10));
+        assert_eq!(encode_timing(Duration::from_millis(100)), 0x8A);
+        assert_eq!(encode_timing(Duration::from_millis(5)), 0x32);
+        assert_eq!(encode_timing(Duration::from_secs(60)), 0xFF);
+
+        let msg = Tp20Params::default().to_msg(OP_PARAMS_REQUEST);
+        assert_eq!(msg[..2], [OP_PARAMS_REQUEST, 0x0F]);
+        assert_eq!(
+            Tp20Params::from_msg(&[0xA1, 0x0F, 0x8A, 0xFF, 0x4A, 0xFF]),
+            Some(Tp20Params {
+                block_size: 0x0F,
+                ack_timeout: Duration::from_millis(100),
+                packet_interval: Duration::from_millis(10),
+            })
+        );
+    }
+
+    #[test]
+    fn test_setup() {
+        let rx_id = StandardId::new(0x300).unwrap();
+        assert_eq!(
+            setup_request(0x01, rx_id, APP_KWP),
+            [0x01, 0xC0, 0x00, 0x10, 0x00, 0x03, 0x01]
+        );
+
+        let (tx, rx) = parse_setup_response(&[0x00, 0xD0, 0x00, 0x03, 0x40, 0x07, 0x01]).unwrap();
+        assert_eq!((tx.as_raw(), rx.as_raw()), (0x740, 0x300));
+
+        let res = parse_setup_response(&[0x00, 0xD8, 0x00, 0x03, 0x40, 0x07, 0x01]);
+        assert!(matches!(res, Err(Tp20Error::Rejected(0xD8))));
+    }
+
+    #[test]
+    fn test_segment() {
+        let pkts = segment(&[0x10, 0x89], 0, 15);
+        assert_eq!(pkts, &[vec![0x10, 0x00, 0x02, 0x10, 0x89]]);
+
+        let data: Vec<u8> = (0..20).collect();
+        let pkts = segment(&data, 0x0E, 2);
+        assert_eq!(pkts.len(), 4);
+        assert_eq!(pkts[0][..3], [0x2E, 0x00, 20]);
+        assert_eq!(pkts[1][0], 0x0F);
+        assert_eq!(pkts[2][0], 0x20);
+        assert_eq!(pkts[3][0], 0x11);
+    }
+
+    #[test]
+    fn test_reassemble() {
+        let data: Vec<u8> = (0..20).collect();
+        let pkts = segment(&data, 0, 2);
+
+        let mut rx = Reassembler::default();
+        assert_eq!(rx.push(&pkts[0]).unwrap(), Received::More(None));
+        assert_eq!(rx.push(&pkts[1]).unwrap(), Received::More(Some(0xB2)));
+        assert_eq!(rx.push(&pkts[2]).unwrap(), Received::More(None));
+        assert_eq!(
+            rx.push(&pkts[3]).unwrap(),
+            Received::Done(data.clone(), Some(0xB4))
+        );
+
+        // Out of sequence
+        assert!(rx.push(&pkts[0]).is_err());
+
+        // A lost packet drops the message, and the sequence picks up from
+        // the packet received, with the rest of the message skipped
+        let mut rx = Reassembler::default();
+        assert!(rx.push(&pkts[0]).is_ok());
+        assert!(rx.push(&pkts[2]).is_err());
+        assert!(rx.data.is_empty());
+        assert_eq!(rx.next_seq, 3);
+        assert_eq!(rx.push(&pkts[3]).unwrap(), Received::More(Some(0xB4)));
+
+        // ...and the next message, which carries on the ECU's count
+        let next = segment(&data, 4, 2);
+        assert!(rx.push(&next[0]).is_ok());
+        assert!(rx.push(&next[1]).is_ok());
+        assert!(rx.push(&next[2]).is_ok());
+        assert_eq!(
+            rx.push(&next[3]).unwrap(),
+            Received::Done(data.clone(), Some(0xB8))
+        );
+
+        // A bad last packet resyncs without skipping anything
+        assert!(rx.push(&[0x18]).is_err());
+        assert_eq!(rx.next_seq, 9);
+        let next = segment(&[0x10, 0x89], 9, 15);
+        assert_eq!(
+            rx.push(&next[0]).unwrap(),
+            Received::Done(vec![0x10, 0x89], Some(0xBA))
+        );
+    }
+}
@@ -0,0 +1,598 @@
+// socketcan/src/uds.rs
+//
+// A UDS (ISO 14229) diagnostic client over ISO-TP.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A UDS (ISO 14229) diagnostic client.
+//!
+//! Unified Diagnostic Services is the request/response protocol used to
+//! diagnose and configure automotive ECUs. A [`UdsClient`] sends requests
+//! to a single ECU over an ISO-TP socket, and waits for the responses.
+//!
+//! Each request is a service ID (SID) followed by its parameters. The ECU
+//! answers with a positive response, which has the SID plus 0x40, or a
+//! negative response, `[0x7F, SID, NRC]`, which is returned as a
+//! [`UdsError::Negative`] with the [`NegativeResponseCode`].
+//!
+//! The responses are timed under the limits of the protocol. The ECU has
+//! to answer within P2 (50ms by default), unless it needs longer, in which
+//! case it replies with a "response pending" code (0x78), and then has up
+//! to P2* (5s by default) for each further response. The client handles
+//! the pending replies itself.
+//!
+//! ```no_run
+//! use socketcan::{uds::{SessionType, UdsClient}, StandardId};
+//!
+//! let rx_id = StandardId::new(0x7E8).unwrap();
+//! let tx_id = StandardId::new(0x7E0).unwrap();
+//! let mut client = UdsClient::open("vcan0", rx_id, tx_id).unwrap();
+//!
+//! client.diagnostic_session_control(SessionType::Extended).unwrap();
+//!
+//! // Read the VIN
+//! let vin = client.read_data_by_identifier(0xF190).unwrap();
+//! println!("{}", String::from_utf8_lossy(&vin));
+//! ```
+
+use crate::isotp::CanIsoTpSocket;
+use embedded_can::Id;
+use std::{fmt, io, time::Duration};
+use thiserror::Error;
+
+/// The DiagnosticSessionControl service ID
+pub const DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+/// The ECUReset service ID
+pub const ECU_RESET: u8 = 0x11;
+/// The ReadDataByIdentifier service ID
+pub const READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+/// The WriteDataByIdentifier service ID
+pub const WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+/// The RoutineControl service ID
+pub const ROUTINE_CONTROL: u8 = 0x31;
+/// The TesterPresent service ID
+pub const TESTER_PRESENT: u8 = 0x3E;
+/// The first byte of a negative response
+pub const NEGATIVE_RESPONSE: u8 = 0x7F;
+
+/// The offset added to the service ID in a positive response
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+
+/// The default time an ECU has to respond
+pub const DEFAULT_P2: Duration = Duration::from_millis(50);
+
+/// The default time an ECU has to respond, after a "response pending"
+pub const DEFAULT_P2_STAR: Duration = Duration::from_millis(5000);
+
+/// The largest ISO-TP message
+const MAX_MSG_LEN: usize = 4095;
+
+// ===== Errors =====
+
+/// An error from a UDS request
+#[derive(Error, Debug)]
+pub enum UdsError {
+    /// I/O Error
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The ECU rejected the request
+    #[error("Negative response to service 0x{service:02X}: {code}")]
+    Negative {
+        /// The service ID of the request
+        service: u8,
+        /// The reason the request was rejected
+        code: NegativeResponseCode,
+    },
+    /// The ECU didn't respond within the timeout
+    #[error("No response from the ECU")]
+    Timeout,
+    /// The response didn't match the request
+    #[error("Unexpected response")]
+    UnexpectedResponse,
+}
+
+/// The result of a UDS request
+pub type UdsResult<T> = Result<T, UdsError>;
+
+// ===== NegativeResponseCode =====
+
+/// The reason an ECU gives for rejecting a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegativeResponseCode {
+    /// General reject (0x10)
+    GeneralReject,
+    /// Service not supported (0x11)
+    ServiceNotSupported,
+    /// Sub-function not supported (0x12)
+    SubFunctionNotSupported,
+    /// Incorrect message length or invalid format (0x13)
+    IncorrectMessageLength,
+    /// Response too long (0x14)
+    ResponseTooLong,
+    /// Busy, repeat request (0x21)
+    BusyRepeatRequest,
+    /// Conditions not correct (0x22)
+    ConditionsNotCorrect,
+    /// Request sequence error (0x24)
+    RequestSequenceError,
+    /// Request out of range (0x31)
+    RequestOutOfRange,
+    /// Security access denied (0x33)
+    SecurityAccessDenied,
+    /// Invalid key (0x35)
+    InvalidKey,
+    /// Exceeded number of attempts (0x36)
+    ExceededNumberOfAttempts,
+    /// Required time delay not expired (0x37)
+    RequiredTimeDelayNotExpired,
+    /// General programming failure (0x72)
+    GeneralProgrammingFailure,
+    /// Request correctly received, response pending (0x78)
+    ResponsePending,
+    /// Sub-function not supported in the active session (0x7E)
+    SubFunctionNotSupportedInActiveSession,
+    /// Service not supported in the active session (0x7F)
+    ServiceNotSupportedInActiveSession,
+    /// Any other code
+    Other(u8),
+}
+
+impl From<u8> for NegativeResponseCode {
+    fn from(code: u8) -> Self {
+        use NegativeResponseCode::*;
+        match code {
+            0x10 => GeneralReject,
+            0x11 => ServiceNotSupported,
+            0x12 => SubFunctionNotSupported,
+            0x13 => IncorrectMessageLength,
+            0x14 => ResponseTooLong,
+            0x21 => BusyRepeatRequest,
+            0x22 => ConditionsNotCorrect,
+            0x24 => RequestSequenceError,
+            0x31 => RequestOutOfRange,
+            0x33 => SecurityAccessDenied,
+            0x35 => InvalidKey,
+            0x36 => ExceededNumberOfAttempts,
+            0x37 => RequiredTimeDelayNotExpired,
+            0x72 => GeneralProgrammingFailure,
+            0x78 => ResponsePending,
+            0x7E => SubFunctionNotSupportedInActiveSession,
+            0x7F => ServiceNotSupportedInActiveSession,
+            code => Other(code),
+        }
+    }
+}
+
+impl From<NegativeResponseCode> for u8 {
+    fn from(code: NegativeResponseCode) -> Self {
+        use NegativeResponseCode::*;
+        match code {
+            GeneralReject => 0x10,
+            ServiceNotSupported => 0x11,
+            SubFunctionNotSupported => 0x12,
+            IncorrectMessageLength => 0x13,
+            ResponseTooLong => 0x14,
+            BusyRepeatRequest => 0x21,
+            ConditionsNotCorrect => 0x22,
+            RequestSequenceError => 0x24,
+            RequestOutOfRange => 0x31,
+            SecurityAccessDenied => 0x33,
+            InvalidKey => 0x35,
+            ExceededNumberOfAttempts => 0x36,
+            RequiredTimeDelayNotExpired => 0x37,
+            GeneralProgrammingFailure => 0x72,
+            ResponsePending => 0x78,
+            SubFunctionNotSupportedInActiveSession => 0x7E,
+            ServiceNotSupportedInActiveSession => 0x7F,
+            Other(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for NegativeResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use NegativeResponseCode::*;
+        let msg = match *self {
+            GeneralReject => "general reject",
+            ServiceNotSupported => "service not supported",
+            SubFunctionNotSupported => "sub-function not supported",
+            IncorrectMessageLength => "incorrect message length or invalid format",
+            ResponseTooLong => "response too long",
+            BusyRepeatRequest => "busy, repeat request",
+            ConditionsNotCorrect => "conditions not correct",
+            RequestSequenceError => "request sequence error",
+            RequestOutOfRange => "request out of range",
+            SecurityAccessDenied => "security access denied",
+            InvalidKey => "invalid key",
+            ExceededNumberOfAttempts => "exceeded number of attempts",
+            RequiredTimeDelayNotExpired => "required time delay not expired",
+            GeneralProgrammingFailure => "general programming failure",
+            ResponsePending => "response pending",
+            SubFunctionNotSupportedInActiveSession => {
+                "sub-function not supported in active session"
+            }
+            ServiceNotSupportedInActiveSession => "service not supported in active session",
+            Other(code) => return write!(f, "code 0x{:02X}", code),
+        };
+        f.write_str(msg)
+    }
+}
+
+// ===== Service parameters =====
+
+/// The diagnostic sessions that an ECU can be switched into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionType {
+    /// The default session (0x01)
+    Default,
+    /// The programming session (0x02)
+    Programming,
+    /// The extended diagnostic session (0x03)
+    Extended,
+    /// The safety system diagnostic session (0x04)
+    SafetySystem,
+    /// A vehicle manufacturer or supplier specific session
+    Other(u8),
+}
+
+impl From<SessionType> for u8 {
+    fn from(session: SessionType) -> Self {
+        use SessionType::*;
+        match session {
+            Default => 0x01,
+            Programming => 0x02,
+            Extended => 0x03,
+            SafetySystem => 0x04,
+            Other(session) => session,
+        }
+    }
+}
+
+/// The kinds of reset for the ECUReset service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetType {
+    /// A hard reset, like a power cycle (0x01)
+    Hard,
+    /// A key off/on cycle (0x02)
+    KeyOffOn,
+    /// A soft reset, restarting the application (0x03)
+    Soft,
+    /// Any other reset type
+    Other(u8),
+}
+
+impl From<ResetType> for u8 {
+    fn from(reset: ResetType) -> Self {
+        use ResetType::*;
+        match reset {
+            Hard => 0x01,
+            KeyOffOn => 0x02,
+            Soft => 0x03,
+            Other(reset) => reset,
+        }
+    }
+}
+
+/// The operations of the RoutineControl service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutineControlType {
+    /// Starts the routine (0x01)
+    Start,
+    /// Stops the routine (0x02)
+    Stop,
+    /// Requests the results of the routine (0x03)
+    RequestResults,
+}
+
+impl From<RoutineControlType> for u8 {
+    fn from(control: RoutineControlType) -> Self {
+        use RoutineControlType::*;
+        match control {
+            Start => 0x01,
+            Stop => 0x02,
+            RequestResults => 0x03,
+        }
+    }
+}
+
+/// The response timing an ECU reports when it enters a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionTiming {
+    /// The time the ECU has to start a response
+    pub p2: Duration,
+    /// The time the ECU has to respond, after a "response pending"
+    pub p2_star: Duration,
+}
+
+impl SessionTiming {
+    /// Parses the timing from the parameters of a session control response.
+    ///
+    /// P2 is in milliseconds, and P2* in units of 10ms, both big-endian.
+    fn from_bytes(params: &[u8]) -> Option<Self> {
+        match *params {
+            [p2_hi, p2_lo, p2_star_hi, p2_star_lo, ..] => Some(Self {
+                p2: Duration::from_millis(u16::from_be_bytes([p2_hi, p2_lo]).into()),
+                p2_star: Duration::from_millis(
+                    10 * u64::from(u16::from_be_bytes([p2_star_hi, p2_star_lo])),
+                ),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// ===== Responses =====
+
+/// A response to a request, as sorted out by `parse_response()`.
+#[derive(Debug, PartialEq, Eq)]
+enum Response<'a> {
+    /// A positive response, with the parameters after the SID
+    Positive(&'a [u8]),
+    /// The ECU needs more time, and will respond later
+    Pending,
+}
+
+/// Sorts out a response to a request with the service ID.
+fn parse_response(service: u8, resp: &[u8]) -> UdsResult<Response<'_>> {
+    match *resp {
+        [NEGATIVE_RESPONSE, sid, code] if sid == service => {
+            match NegativeResponseCode::from(code) {
+                NegativeResponseCode::ResponsePending => Ok(Response::Pending),
+                code => Err(UdsError::Negative { service, code }),
+            }
+        }
+        [sid, ref params @ ..] if sid == service.wrapping_add(POSITIVE_RESPONSE_OFFSET) => {
+            Ok(Response::Positive(params))
+        }
+        _ => Err(UdsError::UnexpectedResponse),
+    }
+}
+
+/// Splits the echo of the request parameters from the start of the
+/// response parameters, checking that they match.
+pub(crate) fn strip_echo<'a>(params: &'a [u8], echo: &[u8]) -> UdsResult<&'a [u8]> {
+    params
+        .strip_prefix(echo)
+        .ok_or(UdsError::UnexpectedResponse)
+}
+
+// ===== UdsClient =====
+
+/// A client for the diagnostic services of an ECU.
+///
+/// This sends requests over a [`CanIsoTpSocket`], connected to the ECU's
+/// pair of CAN IDs, and waits for each response in turn.
+#[derive(Debug)]
+pub struct UdsClient {
+    sock: CanIsoTpSocket,
+    p2: Duration,
+    p2_star: Duration,
+}
+
+impl UdsClient {
+    /// Creates a client that uses the ISO-TP socket given.
+    ///
+    /// The socket's read timeout is managed by the client from then on.
+    pub fn new(sock: CanIsoTpSocket) -> Self {
+        Self {
+            sock,
+            p2: DEFAULT_P2,
+            p2_star: DEFAULT_P2_STAR,
+        }
+    }
+
+    /// Opens a client for the ECU on the named interface, which responds
+    /// on `rx_id` to requests sent on `tx_id`.
+    pub fn open<R, T>(ifname: &str, rx_id: R, tx_id: T) -> io::Result<Self>
+    where
+        R: Into<Id>,
+        T: Into<Id>,
+    {
+        CanIsoTpSocket::open(ifname, rx_id, tx_id).map(Self::new)
+    }
+
+    /// Gets a shared reference to the underlying ISO-TP socket
+    pub fn as_socket(&self) -> &CanIsoTpSocket {
+        &self.sock
+    }
+
+    /// Gets the P2 and P2* response timeouts.
+    pub fn timeouts(&self) -> (Duration, Duration) {
+        (self.p2, self.p2_star)
+    }
+
+    /// Sets the P2 and P2* response timeouts.
+    ///
+    /// These are updated automatically by
+    /// [`UdsClient::diagnostic_session_control()`], from the ECU's response.
+    pub fn set_timeouts(&mut self, p2: Duration, p2_star: Duration) {
+        self.p2 = p2;
+        self.p2_star = p2_star;
+    }
+
+    /// Sends a raw request to the ECU, and waits for the response.
+    ///
+    /// The request starts with the service ID. This returns the parameters
+    /// of the positive response, after its SID. "Response pending" replies
+    /// are waited out, up to P2* for each, and any other negative response
+    /// is returned as a [`UdsError::Negative`].
+    pub fn request(&self, req: &[u8]) -> UdsResult<Vec<u8>> {
+        let service = *req.first().ok_or(UdsError::UnexpectedResponse)?;
+        self.sock.write(req)?;
+
+        let mut buf = vec![0u8; MAX_MSG_LEN];
+        let mut timeout = self.p2;
+        loop {
+            self.sock.set_read_timeout(timeout)?;
+            let n = match self.sock.read(&mut buf) {
+                Ok(n) => n,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    return Err(UdsError::Timeout)
+                }
+                Err(err) => return Err(err.into()),
+            };
+            match parse_response(service, &buf[..n])? {
+                Response::Positive(params) => return Ok(params.to_vec()),
+                Response::Pending => timeout = self.p2_star,
+            }
+        }
+    }
+
+    /// Switches the ECU into a diagnostic session.
+    ///
+    /// The ECU reports its response timing for the session, which the
+    /// client adopts, and which is returned.
+    pub fn diagnostic_session_control(&mut self, session: SessionType) -> UdsResult<SessionTiming> {
+        let session = u8::from(session);
+        let resp = self.request(&[DIAGNOSTIC_SESSION_CONTROL, session])?;
+        let timing = SessionTiming::from_bytes(strip_echo(&resp, &[session])?)
+            .ok_or(UdsError::UnexpectedResponse)?;
+        self.set_timeouts(timing.p2, timing.p2_star);
+        Ok(timing)
+    }
+
+    /// Resets the ECU.
+    pub fn ecu_reset(&self, reset: ResetType) -> UdsResult<()> {
+        let reset = u8::from(reset);
+        let resp = self.request(&[ECU_RESET, reset])?;
+        strip_echo(&resp, &[reset])?;
+        Ok(())
+    }
+
+    /// Tells the ECU that a tester is still connected, to keep it in a
+    /// non-default session.
+    pub fn tester_present(&self) -> UdsResult<()> {
+        let resp = self.request(&[TESTER_PRESENT, 0x00])?;
+        strip_echo(&resp, &[0x00])?;
+        Ok(())
+    }
+
+    /// Reads the value of a data identifier (DID).
+    pub fn read_data_by_identifier(&self, did: u16) -> UdsResult<Vec<u8>> {
+        let [hi, lo] = did.to_be_bytes();
+        let resp = self.request(&[READ_DATA_BY_IDENTIFIER, hi, lo])?;
+        Ok(strip_echo(&resp, &[hi, lo])?.to_vec())
+    }
+
+    /// Writes the value of a data identifier (DID).
+    pub fn write_data_by_identifier(&self, did: u16, data: &[u8]) -> UdsResult<()> {
+        let [hi, lo] = did.to_be_bytes();
+        let mut req = vec![WRITE_DATA_BY_IDENTIFIER, hi, lo];
+        req.extend_from_slice(data);
+        let resp = self.request(&req)?;
+        strip_echo(&resp, &[hi, lo])?;
+        Ok(())
+    }
+
+    /// Starts or stops a routine in the ECU, or requests its results.
+    ///
+    /// This returns the routine status record from the response, which
+    /// may be empty.
+    pub fn routine_control(
+        &self,
+        control: RoutineControlType,
+        routine: u16,
+        data: &[u8],
+    ) -> UdsResult<Vec<u8>> {
+        let control = u8::from(control);
+        let [hi, lo] = routine.to_be_bytes();
+        let mut req = vec![ROUTINE_CONTROL, control, hi, lo];
+        req.extend_from_slice(data);
+        let resp = self.request(&req)?;
+        Ok(strip_echo(&resp, &[control, hi, lo])?.to_vec())
+    }
+}
+
+impl From<CanIsoTpSocket> for UdsClient {
+    fn from(sock: CanIsoTpSocket) -> Self {
+        Self::new(sock)
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nrc() {
+        for code in 0..=u8::MAX {
+            assert_eq!(u8::from(NegativeResponseCode::from(code)), code);
+        }
+        assert_eq!(
+            NegativeResponseCode::from(0x31),
+            NegativeResponseCode::RequestOutOfRange
+        );
+        assert_eq!(
+            NegativeResponseCode::from(0x99),
+            NegativeResponseCode::Other(0x99)
+        );
+        assert_eq!(
+            NegativeResponseCode::RequestOutOfRange.to_string(),
+            "request out of range"
+        );
+        assert_eq!(NegativeResponseCode::Other(0x99).to_string(), "code 0x99");
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let resp = [0x62, 0xF1, 0x90, b'V'];
+        assert_eq!(
+            parse_response(READ_DATA_BY_IDENTIFIER, &resp).unwrap(),
+            Response::Positive(&[0xF1, 0x90, b'V'])
+        );
+
+        let resp = [NEGATIVE_RESPONSE, READ_DATA_BY_IDENTIFIER, 0x78];
+        assert_eq!(
+            parse_response(READ_DATA_BY_IDENTIFIER, &resp).unwrap(),
+            Response::Pending
+        );
+
+        let resp = [NEGATIVE_RESPONSE, READ_DATA_BY_IDENTIFIER, 0x31];
+        match parse_response(READ_DATA_BY_IDENTIFIER, &resp) {
+            Err(UdsError::Negative { service, code }) => {
+                assert_eq!(service, READ_DATA_BY_IDENTIFIER);
+                assert_eq!(code, NegativeResponseCode::RequestOutOfRange);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        // A response to a different service
+        assert!(matches!(
+            parse_response(READ_DATA_BY_IDENTIFIER, &[0x50, 0x01]),
+            Err(UdsError::UnexpectedResponse)
+        ));
+        assert!(matches!(
+            parse_response(READ_DATA_BY_IDENTIFIER, &[]),
+            Err(UdsError::UnexpectedResponse)
+        ));
+    }
+
+    #[test]
+    fn test_strip_echo() {
+        assert_eq!(
+            strip_echo(&[0xF1, 0x90, 1, 2], &[0xF1, 0x90]).unwrap(),
+            &[1, 2]
+        );
+        assert!(strip_echo(&[0xF1, 0x91, 1, 2], &[0xF1, 0x90]).is_err());
+    }
+
+    #[test]
+    fn test_session_timing() {
+        let timing = SessionTiming::from_bytes(&[0x00, 0x32, 0x01, 0xF4]).unwrap();
+        assert_eq!(timing.p2, Duration::from_millis(50));
+        assert_eq!(timing.p2_star, Duration::from_millis(5000));
+        assert!(SessionTiming::from_bytes(&[0x00, 0x32]).is_none());
+    }
+}
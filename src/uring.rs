@@ -0,0 +1,492 @@
+// socketcan/src/uring.rs
+//
+// An io_uring backend for receiving bursts of CAN frames.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! An io_uring backend for receiving CAN frames.
+//!
+//! On a bus running near full load, especially at 1 Mbit/s or with CAN FD,
+//! making a `read()` system call for every frame becomes the bottleneck.
+//! [`CanSocketUring`] keeps a single multishot receive operation armed in
+//! an `io_uring`, with a ring of kernel-selected buffers, so the kernel
+//! fills frames into memory shared with the application as they arrive.
+//! Each read then only has to enter the kernel when no frames are already
+//! waiting, and collects all the ones that are.
+//!
+//! ```no_run
+//! use socketcan::{uring::CanSocketUring, CanSocket, Socket};
+//!
+//! let sock = CanSocket::open("vcan0").unwrap();
+//! let mut sock = CanSocketUring::new(sock, 256).unwrap();
+//!
+//! loop {
+//!     for frame in sock.read().unwrap() {
+//!         println!("{:X}", frame);
+//!     }
+//! }
+//! ```
+//!
+//! This requires Linux 6.0 or later, for multishot receives with a
+//! registered buffer ring.
+
+use crate::{frame::can_frame_default, CanFrame, CanSocket};
+use libc::{can_frame, CAN_MTU};
+use rustix::{
+    io_uring::{
+        io_uring_buf, io_uring_buf_reg, io_uring_cqe, io_uring_enter, io_uring_params,
+        io_uring_ptr, io_uring_register, io_uring_setup, io_uring_sqe, io_uring_user_data,
+        IoringCqeFlags, IoringEnterFlags, IoringOp, IoringRecvFlags, IoringRegisterOp,
+        IoringSetupFlags, IoringSqeFlags, IORING_CQE_BUFFER_SHIFT, IORING_OFF_CQ_RING,
+        IORING_OFF_SQES, IORING_OFF_SQ_RING,
+    },
+    mm::{mmap, mmap_anonymous, munmap, MapFlags, ProtFlags},
+};
+use std::{
+    ffi::c_void,
+    fmt,
+    io::{Error as IoError, Result as IoResult},
+    mem::size_of,
+    os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    ptr,
+    sync::atomic::{AtomicU16, AtomicU32, Ordering},
+};
+
+/// The ID of the group of buffers that the receives select from.
+const BUF_GROUP: u16 = 0;
+
+/// The user data that tags the completions of the receive operation.
+const RECV_USER_DATA: u64 = 1;
+
+/// The maximum number of buffers that the kernel allows in a buffer ring.
+const MAX_BUFFERS: usize = 32768;
+
+/// The number of submission queue entries. Only the receive is submitted.
+const SQ_ENTRIES: u32 = 2;
+
+// ===== Mmap =====
+
+/// A memory mapping, which is unmapped when dropped.
+struct Mmap {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl Mmap {
+    /// Maps a region of the io_uring with the specified fd.
+    fn ring<Fd: AsFd>(fd: Fd, len: usize, offset: u64) -> IoResult<Self> {
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED | MapFlags::POPULATE,
+                fd,
+                offset,
+            )?
+        };
+        Ok(Self { ptr, len })
+    }
+
+    /// Maps a page-aligned region of zeroed memory.
+    fn anonymous(len: usize) -> IoResult<Self> {
+        let ptr = unsafe {
+            mmap_anonymous(
+                ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::PRIVATE | MapFlags::POPULATE,
+            )?
+        };
+        Ok(Self { ptr, len })
+    }
+
+    /// Gets a pointer to a value at the specified byte offset.
+    fn at<T>(&self, offset: u32) -> *mut T {
+        debug_assert!(offset as usize + size_of::<T>() <= self.len);
+        unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        let _ = unsafe { munmap(self.ptr, self.len) };
+    }
+}
+
+// ===== CanSocketUring =====
+
+/// A CAN socket that receives frames through an `io_uring`.
+///
+/// This takes over a [`CanSocket`], and arms a multishot receive on it
+/// that keeps running in the kernel, filling up to `nbufs` frames into a
+/// ring of buffers before the application has to take them. Frames are
+/// received in the order that they arrived, and any that arrive while all
+/// the buffers are full are left queued in the socket, to be taken once
+/// the receive is re-armed.
+///
+/// The socket can still be used for writing, and for its other options,
+/// through [`as_socket()`](Self::as_socket).
+pub struct CanSocketUring {
+    /// The io_uring itself. This is closed before the memory is unmapped.
+    ring: OwnedFd,
+    /// The CAN socket that frames are received from
+    sock: CanSocket,
+    /// The submission ring
+    sq: Mmap,
+    /// The completion ring
+    cq: Mmap,
+    /// The submission queue entries
+    sqes: Mmap,
+    /// The ring of buffer descriptors, followed by the buffers themselves
+    bufs: Mmap,
+    /// The offsets into the submission and completion rings
+    params: io_uring_params,
+    /// The number of buffers, a power of two
+    nbufs: u16,
+    /// Our copy of the tail of the buffer ring
+    buf_tail: u16,
+    /// Whether the multishot receive is still armed
+    armed: bool,
+    /// The frames from the last read
+    frames: Vec<CanFrame>,
+}
+
+// The raw pointers all refer to memory owned by the struct.
+unsafe impl Send for CanSocketUring {}
+
+impl CanSocketUring {
+    /// Creates an io_uring receiver for the socket, with `nbufs` buffers.
+    ///
+    /// The number of buffers is rounded up to a power of two, and is the
+    /// most frames that can be received between reads without leaving
+    /// them queued in the socket.
+    pub fn new(sock: CanSocket, nbufs: usize) -> IoResult<Self> {
+        let nbufs = nbufs.clamp(1, MAX_BUFFERS).next_power_of_two();
+
+        let mut params = io_uring_params::default();
+        params.cq_entries = nbufs as u32;
+        params.flags = IoringSetupFlags::CQSIZE;
+        let ring = unsafe { io_uring_setup(SQ_ENTRIES, &mut params)? };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<io_uring_cqe>();
+        let sqes_len = params.sq_entries as usize * size_of::<io_uring_sqe>();
+
+        let sq = Mmap::ring(&ring, sq_len, IORING_OFF_SQ_RING)?;
+        let cq = Mmap::ring(&ring, cq_len, IORING_OFF_CQ_RING)?;
+        let sqes = Mmap::ring(&ring, sqes_len, IORING_OFF_SQES)?;
+
+        let ring_len = nbufs * size_of::<io_uring_buf>();
+        let bufs = Mmap::anonymous(ring_len + nbufs * CAN_MTU)?;
+
+        let mut reg = io_uring_buf_reg::default();
+        reg.ring_addr = io_uring_ptr::new(bufs.ptr);
+        reg.ring_entries = nbufs as u32;
+        reg.bgid = BUF_GROUP;
+        unsafe {
+            io_uring_register(
+                &ring,
+                IoringRegisterOp::RegisterPbufRing,
+                (&reg as *const io_uring_buf_reg).cast(),
+                1,
+            )?;
+        }
+
+        let mut this = Self {
+            ring,
+            sock,
+            sq,
+            cq,
+            sqes,
+            bufs,
+            params,
+            nbufs: nbufs as u16,
+            buf_tail: 0,
+            armed: false,
+            frames: Vec::with_capacity(nbufs),
+        };
+
+        for bid in 0..this.nbufs {
+            this.recycle(bid);
+        }
+        this.publish_bufs();
+        Ok(this)
+    }
+
+    /// Gets a reference to the underlying CAN socket.
+    pub fn as_socket(&self) -> &CanSocket {
+        &self.sock
+    }
+
+    /// Stops receiving through the io_uring, and gives back the socket.
+    pub fn into_socket(self) -> CanSocket {
+        let Self { sock, .. } = self;
+        sock
+    }
+
+    /// The maximum number of frames that can be received in a single read.
+    pub fn capacity(&self) -> usize {
+        self.nbufs as usize
+    }
+
+    /// Reads a burst of frames from the socket.
+    ///
+    /// This returns all the frames that the kernel has received since the
+    /// last read, and blocks until at least one arrives if there are none.
+    /// The socket's read timeout does not apply to this. Any message that
+    /// isn't the size of a classic CAN frame is skipped.
+    ///
+    /// The frames are valid until the next read.
+    pub fn read(&mut self) -> IoResult<&[CanFrame]> {
+        self.frames.clear();
+        while self.frames.is_empty() {
+            let to_submit = if self.armed {
+                0
+            } else {
+                self.arm();
+                1
+            };
+            if to_submit > 0 || self.cq_ready() == 0 {
+                self.enter(to_submit, 1)?;
+            }
+            self.reap()?;
+        }
+        Ok(&self.frames)
+    }
+
+    /// Reads the frames that have already been received, without blocking.
+    ///
+    /// This returns an empty slice if there are none.
+    pub fn try_read(&mut self) -> IoResult<&[CanFrame]> {
+        self.frames.clear();
+        if !self.armed {
+            self.arm();
+            self.enter(1, 0)?;
+        }
+        self.reap()?;
+        Ok(&self.frames)
+    }
+
+    /// Gets the frames from the last read.
+    pub fn frames(&self) -> &[CanFrame] {
+        &self.frames
+    }
+
+    /// Submits and/or waits for operations, retrying if interrupted.
+    fn enter(&self, to_submit: u32, min_complete: u32) -> IoResult<()> {
+        let flags = if min_complete > 0 {
+            IoringEnterFlags::GETEVENTS
+        } else {
+            IoringEnterFlags::empty()
+        };
+        loop {
+            match unsafe { io_uring_enter(&self.ring, to_submit, min_complete, flags) } {
+                Err(rustix::io::Errno::INTR) => continue,
+                res => return res.map(|_| ()).map_err(IoError::from),
+            }
+        }
+    }
+
+    /// Queues the multishot receive in the submission ring.
+    fn arm(&mut self) {
+        let off = &self.params.sq_off;
+        let tail = unsafe { &*self.sq.at::<AtomicU32>(off.tail) };
+        let mask = unsafe { *self.sq.at::<u32>(off.ring_mask) };
+
+        let mut sqe = io_uring_sqe {
+            opcode: IoringOp::Recv,
+            flags: IoringSqeFlags::BUFFER_SELECT,
+            fd: self.sock.as_raw_fd(),
+            ..Default::default()
+        };
+        sqe.ioprio.recv_flags = IoringRecvFlags::MULTISHOT;
+        sqe.buf.buf_group = BUF_GROUP;
+        sqe.user_data = io_uring_user_data::from_u64(RECV_USER_DATA);
+
+        let n = tail.load(Ordering::Relaxed);
+        let idx = n & mask;
+        unsafe {
+            ptr::write(self.sqes.at::<io_uring_sqe>(0).add(idx as usize), sqe);
+            *self.sq.at::<u32>(off.array).add(idx as usize) = idx;
+        }
+        tail.store(n.wrapping_add(1), Ordering::Release);
+        self.armed = true;
+    }
+
+    /// The number of completions waiting to be reaped.
+    fn cq_ready(&self) -> u32 {
+        let off = &self.params.cq_off;
+        let head = unsafe { &*self.cq.at::<AtomicU32>(off.head) };
+        let tail = unsafe { &*self.cq.at::<AtomicU32>(off.tail) };
+        tail.load(Ordering::Acquire)
+            .wrapping_sub(head.load(Ordering::Relaxed))
+    }
+
+    /// Takes the frames out of the waiting completions.
+    fn reap(&mut self) -> IoResult<()> {
+        let off = self.params.cq_off;
+        let head = unsafe { &*self.cq.at::<AtomicU32>(off.head) };
+        let tail = unsafe { &*self.cq.at::<AtomicU32>(off.tail) };
+        let mask = unsafe { *self.cq.at::<u32>(off.ring_mask) };
+        let cqes = self.cq.at::<io_uring_cqe>(off.cqes);
+
+        let mut res = Ok(());
+        let mut n = head.load(Ordering::Relaxed);
+        let end = tail.load(Ordering::Acquire);
+
+        while n != end {
+            let cqe = unsafe { &*cqes.add((n & mask) as usize) };
+            let (ret, flags) = (cqe.res, cqe.flags);
+            n = n.wrapping_add(1);
+
+            if unsafe { cqe.user_data.u64_ } != RECV_USER_DATA {
+                continue;
+            }
+            if !flags.contains(IoringCqeFlags::MORE) {
+                self.armed = false;
+            }
+            if flags.contains(IoringCqeFlags::BUFFER) {
+                let bid = (flags.bits() >> IORING_CQE_BUFFER_SHIFT) as u16;
+                if ret as usize == CAN_MTU {
+                    let frame = unsafe { ptr::read(self.buf(bid)) };
+                    self.sock.on_read(CAN_MTU);
+                    self.frames.push(CanFrame::from(frame));
+                }
+                self.recycle(bid);
+            }
+            // Running out of buffers just ends the receive until it's
+            // re-armed, which the next read does.
+            if ret < 0 && ret != -libc::ENOBUFS && res.is_ok() {
+                res = Err(IoError::from_raw_os_error(-ret));
+            }
+        }
+
+        head.store(n, Ordering::Release);
+        self.publish_bufs();
+        res
+    }
+
+    /// Gets a pointer to the buffer with the specified ID.
+    fn buf(&self, bid: u16) -> *mut can_frame {
+        let ring_len = self.nbufs as usize * size_of::<io_uring_buf>();
+        let offset = ring_len + bid as usize * CAN_MTU;
+        self.bufs.at::<can_frame>(offset as u32)
+    }
+
+    /// Adds a buffer back to the ring, to be published with the tail.
+    fn recycle(&mut self, bid: u16) {
+        let buf = self.buf(bid);
+        unsafe { ptr::write(buf, can_frame_default()) };
+
+        let idx = self.buf_tail & (self.nbufs - 1);
+        let entry = unsafe { self.bufs.at::<io_uring_buf>(0).add(idx as usize) };
+        // Only the fields that are set here belong to the entry. For the
+        // first one, the reserved field at the end is the ring's tail.
+        unsafe {
+            ptr::addr_of_mut!((*entry).addr).write(io_uring_ptr::new(buf.cast()));
+            ptr::addr_of_mut!((*entry).len).write(CAN_MTU as u32);
+            ptr::addr_of_mut!((*entry).bid).write(bid);
+        }
+        self.buf_tail = self.buf_tail.wrapping_add(1);
+    }
+
+    /// Hands the recycled buffers back to the kernel.
+    fn publish_bufs(&self) {
+        // The tail is the last field of the first entry in the ring.
+        let tail = self.bufs.at::<AtomicU16>(14);
+        unsafe { &*tail }.store(self.buf_tail, Ordering::Release);
+    }
+}
+
+impl fmt::Debug for CanSocketUring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CanSocketUring")
+            .field("sock", &self.sock)
+            .field("capacity", &self.capacity())
+            .field("armed", &self.armed)
+            .finish()
+    }
+}
+
+impl AsRawFd for CanSocketUring {
+    /// Gets the fd of the CAN socket.
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.as_raw_fd()
+    }
+}
+
+impl AsFd for CanSocketUring {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.sock.as_fd()
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{frame::AsPtr, EmbeddedFrame, Frame, StandardId};
+    use std::os::unix::net::UnixDatagram;
+
+    // A datagram socket pair stands in for a CAN socket, since the uring
+    // only needs each message to arrive whole.
+    fn pair(nbufs: usize) -> Option<(UnixDatagram, CanSocketUring)> {
+        let (tx, rx) = UnixDatagram::pair().unwrap();
+        let sock = CanSocket::from(OwnedFd::from(rx));
+        match CanSocketUring::new(sock, nbufs) {
+            Ok(sock) => Some((tx, sock)),
+            // io_uring may be disabled or too old here.
+            Err(_) => None,
+        }
+    }
+
+    fn send(tx: &UnixDatagram, id: u16) {
+        let id = StandardId::new(id).unwrap();
+        let frame = CanFrame::new(id, &[id.as_raw() as u8]).unwrap();
+        tx.send(frame.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_read_burst() {
+        let Some((tx, mut sock)) = pair(8) else {
+            return;
+        };
+        assert_eq!(sock.capacity(), 8);
+        assert!(sock.try_read().unwrap().is_empty());
+
+        for id in 1..=5 {
+            send(&tx, id);
+        }
+
+        let mut ids = Vec::new();
+        while ids.len() < 5 {
+            ids.extend(sock.read().unwrap().iter().map(|f| f.raw_id()));
+        }
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_buffers_recycled() {
+        let Some((tx, mut sock)) = pair(2) else {
+            return;
+        };
+
+        // More frames than buffers, so the receive has to be re-armed.
+        let mut ids = Vec::new();
+        for id in 1..=6 {
+            send(&tx, id);
+        }
+        while ids.len() < 6 {
+            ids.extend(sock.read().unwrap().iter().map(|f| f.raw_id()));
+        }
+        assert_eq!(ids, [1, 2, 3, 4, 5, 6]);
+    }
+}
@@ -0,0 +1,606 @@
+// socketcan/src/xcp.rs
+//
+// A basic XCP-on-CAN master: connection, memory access, and DAQ setup.
+//
+// This file is part of the Rust 'socketcan-rs' library.
+//
+// Licensed under the MIT license:
+//   <LICENSE or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according
+// to those terms.
+
+//! A basic XCP-on-CAN master.
+//!
+//! XCP (ASAM MCD-1 XCP) is the protocol used by calibration and
+//! measurement tools to read and write the memory of an ECU while it runs,
+//! and to have it send the values of variables periodically. On CAN, the
+//! master sends its commands (CTOs) with one CAN ID, and the slave sends
+//! its responses, and its measurement data (DTOs), with another.
+//!
+//! This covers the basic command set that such a tool needs:
+//!
+//! - `CONNECT`, `DISCONNECT`, and `GET_STATUS`.
+//! - Memory access, with `SHORT_UPLOAD` for small reads, `SET_MTA` and
+//!   `UPLOAD` for longer ones, and `DOWNLOAD` for writes.
+//! - Dynamic DAQ setup: allocating the DAQ lists, ODTs, and ODT entries,
+//!   pointing the entries at variables in memory, and starting and
+//!   stopping the lists. The packets that the slave sends are then read
+//!   with [`XcpMaster::read_daq()`].
+//!
+//! ```no_run
+//! use socketcan::{xcp::XcpMaster, StandardId};
+//!
+//! let cmd_id = StandardId::new(0x7E0).unwrap();
+//! let res_id = StandardId::new(0x7E1).unwrap();
+//! let mut xcp = XcpMaster::open("can0", cmd_id, res_id).unwrap();
+//!
+//! let info = xcp.connect().unwrap();
+//! println!("{:?}", info);
+//!
+//! // Read a 32-bit variable
+//! let data = xcp.short_upload(0x2000_1000, 0, 4).unwrap();
+//! println!("{:02X?}", data);
+//!
+//! xcp.disconnect().unwrap();
+//! ```
+//!
+//! Multi-byte values in the commands are sent in the byte order that the
+//! slave gives in its `CONNECT` response. Only the slave's default address
+//! granularity, of one byte, is supported.
+
+use crate::{CanFrame, CanSocket, EmbeddedFrame, Id, Socket};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The default time a slave has to respond to a command (T1)
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(25);
+
+// The command codes
+const CMD_CONNECT: u8 = 0xFF;
+const CMD_DISCONNECT: u8 = 0xFE;
+const CMD_GET_STATUS: u8 = 0xFD;
+const CMD_SET_MTA: u8 = 0xF6;
+const CMD_UPLOAD: u8 = 0xF5;
+const CMD_SHORT_UPLOAD: u8 = 0xF4;
+const CMD_DOWNLOAD: u8 = 0xF0;
+const CMD_SET_DAQ_PTR: u8 = 0xE2;
+const CMD_WRITE_DAQ: u8 = 0xE1;
+const CMD_SET_DAQ_LIST_MODE: u8 = 0xE0;
+const CMD_START_STOP_DAQ_LIST: u8 = 0xDE;
+const CMD_START_STOP_SYNCH: u8 = 0xDD;
+const CMD_FREE_DAQ: u8 = 0xD6;
+const CMD_ALLOC_DAQ: u8 = 0xD5;
+const CMD_ALLOC_ODT: u8 = 0xD4;
+const CMD_ALLOC_ODT_ENTRY: u8 = 0xD3;
+
+// The packet identifiers of the packets from the slave. Those below the
+// service request are DAQ packets.
+const PID_RES: u8 = 0xFF;
+const PID_ERR: u8 = 0xFE;
+const PID_SERV: u8 = 0xFC;
+
+/// The CTO size of a classic CAN transport
+const CAN_MAX_CTO: u8 = 8;
+
+// ===== Errors =====
+
+/// An error from an XCP command
+#[derive(Error, Debug)]
+pub enum XcpError {
+    /// I/O Error
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The slave rejected the command, with its error code
+    #[error("XCP command failed with error 0x{0:02X}")]
+    Command(u8),
+    /// The slave didn't respond within the timeout
+    #[error("No XCP response from the slave")]
+    Timeout,
+    /// The response didn't follow the protocol
+    #[error("Unexpected XCP response")]
+    UnexpectedResponse,
+}
+
+/// The result of an XCP command
+pub type XcpResult<T> = Result<T, XcpError>;
+
+// ===== Responses =====
+
+/// What the slave reports about itself when the master connects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectInfo {
+    /// The resources available: calibration and paging, DAQ, STIM, and
+    /// programming
+    pub resource: u8,
+    /// The basic communication mode, with the byte order in bit 0
+    pub comm_mode_basic: u8,
+    /// The largest command or response packet
+    pub max_cto: u8,
+    /// The largest data packet
+    pub max_dto: u16,
+    /// The major version of the protocol layer
+    pub protocol_version: u8,
+    /// The major version of the transport layer
+    pub transport_version: u8,
+}
+
+impl ConnectInfo {
+    /// Parses a positive `CONNECT` response.
+    fn from_response(resp: &[u8]) -> XcpResult<Self> {
+        match *resp {
+            [PID_RES, resource, comm_mode_basic, max_cto, d0, d1, protocol_version, transport_version] =>
+            {
+                let big_endian = comm_mode_basic & 0x01 != 0;
+                Ok(Self {
+                    resource,
+                    comm_mode_basic,
+                    max_cto,
+                    max_dto: match big_endian {
+                        true => u16::from_be_bytes([d0, d1]),
+                        false => u16::from_le_bytes([d0, d1]),
+                    },
+                    protocol_version,
+                    transport_version,
+                })
+            }
+            _ => Err(XcpError::UnexpectedResponse),
+        }
+    }
+
+    /// Determines if the slave uses big-endian (Motorola) byte order.
+    pub fn is_big_endian(&self) -> bool {
+        self.comm_mode_basic & 0x01 != 0
+    }
+}
+
+/// The current status of the slave, from `GET_STATUS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionStatus {
+    /// The session state bits, such as whether DAQ is running
+    pub status: u8,
+    /// The resources that are protected, and need to be unlocked
+    pub protection: u8,
+    /// The session configuration ID
+    pub config_id: u16,
+}
+
+/// The mode for [`XcpMaster::start_stop_daq_list()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DaqListMode {
+    /// Stops the list
+    Stop = 0,
+    /// Starts the list
+    Start = 1,
+    /// Selects the list, to be started or stopped with the others by
+    /// [`XcpMaster::start_stop_synch()`]
+    Select = 2,
+}
+
+/// The mode for [`XcpMaster::start_stop_synch()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SynchMode {
+    /// Stops all the lists
+    StopAll = 0,
+    /// Starts the selected lists
+    StartSelected = 1,
+    /// Stops the selected lists
+    StopSelected = 2,
+}
+
+/// A packet of measurement data sent by the slave.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaqPacket {
+    /// The packet identifier, which is the absolute number of the ODT
+    pub pid: u8,
+    /// The data of the ODT entries, after the identifier
+    pub data: Vec<u8>,
+}
+
+impl DaqPacket {
+    /// Parses a DAQ packet, if the frame data is one.
+    pub fn from_data(data: &[u8]) -> Option<Self> {
+        match data.split_first() {
+            Some((&pid, data)) if pid < PID_SERV => Some(Self {
+                pid,
+                data: data.to_vec(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Checks a response packet, returning its parameters after the PID.
+///
+/// An error packet gives the slave's error code. Returns `None` for
+/// packets that aren't a response to a command, like events and DAQ data.
+fn check_response(resp: &[u8]) -> Option<XcpResult<&[u8]>> {
+    match resp.split_first() {
+        Some((&PID_RES, params)) => Some(Ok(params)),
+        Some((&PID_ERR, &[code, ..])) => Some(Err(XcpError::Command(code))),
+        Some((&PID_ERR, _)) => Some(Err(XcpError::UnexpectedResponse)),
+        _ => None,
+    }
+}
+
+// ===== XcpMaster =====
+
+/// An XCP master, talking to one slave over a pair of CAN IDs.
+///
+/// The master sends its commands one at a time, and waits for each
+/// response. DAQ packets that arrive while it's waiting are dropped, so
+/// commands are best sent while the DAQ lists are stopped.
+#[derive(Debug)]
+pub struct XcpMaster {
+    sock: CanSocket,
+    cmd_id: Id,
+    res_id: Id,
+    timeout: Duration,
+    pad: Option<u8>,
+    big_endian: bool,
+    max_cto: u8,
+}
+
+impl XcpMaster {
+    /// Creates a master that sends commands with `cmd_id` and receives
+    /// responses on `res_id`, over the CAN socket given.
+    pub fn new<C, R>(sock: CanSocket, cmd_id: C, res_id: R) -> Self
+    where
+        C: Into<Id>,
+        R: Into<Id>,
+    {
+        Self {
+            sock,
+            cmd_id: cmd_id.into(),
+            res_id: res_id.into(),
+            timeout: DEFAULT_TIMEOUT,
+            pad: None,
+            big_endian: false,
+            max_cto: CAN_MAX_CTO,
+        }
+    }
+
+    /// Opens a master on the named interface.
+    pub fn open<C, R>(ifname: &str, cmd_id: C, res_id: R) -> io::Result<Self>
+    where
+        C: Into<Id>,
+        R: Into<Id>,
+    {
+        CanSocket::open(ifname).map(|sock| Self::new(sock, cmd_id, res_id))
+    }
+
+    /// Gets a shared reference to the underlying CAN socket
+    pub fn as_socket(&self) -> &CanSocket {
+        &self.sock
+    }
+
+    /// Sets the time the slave has to respond to each command.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Pads each command frame to 8 bytes with the value given, for
+    /// slaves that need a fixed DLC, or sends them at their own length,
+    /// with `None`.
+    pub fn set_padding(&mut self, pad: Option<u8>) {
+        self.pad = pad;
+    }
+
+    /// Connects to the slave, in normal mode, and takes its byte order
+    /// and packet sizes for the commands that follow.
+    ///
+    /// XCP on CAN needs a MAX_CTO of at least 8, so a slave that reports
+    /// less fails with [`XcpError::UnexpectedResponse`].
+    pub fn connect(&mut self) -> XcpResult<ConnectInfo> {
+        let resp = self.exchange_raw(&[CMD_CONNECT, 0x00])?;
+        let info = ConnectInfo::from_response(&resp)?;
+        if info.max_cto < CAN_MAX_CTO {
+            return Err(XcpError::UnexpectedResponse);
+        }
+        self.big_endian = info.is_big_endian();
+        self.max_cto = CAN_MAX_CTO;
+        Ok(info)
+    }
+
+    /// Disconnects from the slave.
+    pub fn disconnect(&mut self) -> XcpResult<()> {
+        self.command(&[CMD_DISCONNECT]).map(|_| ())
+    }
+
+    /// Gets the current status of the slave.
+    pub fn get_status(&mut self) -> XcpResult<SessionStatus> {
+        let params = self.command(&[CMD_GET_STATUS])?;
+        match *params {
+            [status, protection, _, c0, c1, ..] => Ok(SessionStatus {
+                status,
+                protection,
+                config_id: self.get_u16([c0, c1]),
+            }),
+            _ => Err(XcpError::UnexpectedResponse),
+        }
+    }
+
+    /// Sets the memory transfer address, for the uploads and downloads
+    /// that follow.
+    pub fn set_mta(&mut self, addr: u32, ext: u8) -> XcpResult<()> {
+        let mut cmd = vec![CMD_SET_MTA, 0, 0, ext];
+        self.put_u32(&mut cmd, addr);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Reads `len` bytes from the memory transfer address, which moves
+    /// on past them.
+    ///
+    /// This is split into as many `UPLOAD` commands as needed.
+    pub fn upload(&mut self, len: usize) -> XcpResult<Vec<u8>> {
+        let max = usize::from(self.max_cto) - 1;
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let n = (len - data.len()).min(max);
+            let params = self.command(&[CMD_UPLOAD, n as u8])?;
+            let chunk = params.get(..n).ok_or(XcpError::UnexpectedResponse)?;
+            data.extend_from_slice(chunk);
+        }
+        Ok(data)
+    }
+
+    /// Reads a few bytes of memory at the address given, with a single
+    /// `SHORT_UPLOAD` command.
+    ///
+    /// On CAN, this is up to 7 bytes.
+    pub fn short_upload(&mut self, addr: u32, ext: u8, len: u8) -> XcpResult<Vec<u8>> {
+        if len == 0 || len >= self.max_cto {
+            return Err(io::Error::from(io::ErrorKind::InvalidInput).into());
+        }
+        let mut cmd = vec![CMD_SHORT_UPLOAD, len, 0, ext];
+        self.put_u32(&mut cmd, addr);
+        let params = self.command(&cmd)?;
+        params
+            .get(..usize::from(len))
+            .map(<[u8]>::to_vec)
+            .ok_or(XcpError::UnexpectedResponse)
+    }
+
+    /// Writes data to the memory transfer address, which moves on past
+    /// it.
+    ///
+    /// This is split into as many `DOWNLOAD` commands as needed.
+    pub fn download(&mut self, data: &[u8]) -> XcpResult<()> {
+        let max = usize::from(self.max_cto) - 2;
+        for chunk in data.chunks(max) {
+            let mut cmd = vec![CMD_DOWNLOAD, chunk.len() as u8];
+            cmd.extend_from_slice(chunk);
+            self.command(&cmd)?;
+        }
+        Ok(())
+    }
+
+    /// Frees all the DAQ lists, to start a new dynamic configuration.
+    pub fn free_daq(&mut self) -> XcpResult<()> {
+        self.command(&[CMD_FREE_DAQ]).map(|_| ())
+    }
+
+    /// Allocates `count` DAQ lists.
+    pub fn alloc_daq(&mut self, count: u16) -> XcpResult<()> {
+        let mut cmd = vec![CMD_ALLOC_DAQ, 0];
+        self.put_u16(&mut cmd, count);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Allocates `count` ODTs in a DAQ list.
+    pub fn alloc_odt(&mut self, daq: u16, count: u8) -> XcpResult<()> {
+        let mut cmd = vec![CMD_ALLOC_ODT, 0];
+        self.put_u16(&mut cmd, daq);
+        cmd.push(count);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Allocates `count` entries in an ODT of a DAQ list.
+    pub fn alloc_odt_entry(&mut self, daq: u16, odt: u8, count: u8) -> XcpResult<()> {
+        let mut cmd = vec![CMD_ALLOC_ODT_ENTRY, 0];
+        self.put_u16(&mut cmd, daq);
+        cmd.extend_from_slice(&[odt, count]);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Points at an ODT entry, to be set by the [`write_daq()`](Self::write_daq)
+    /// calls that follow.
+    pub fn set_daq_ptr(&mut self, daq: u16, odt: u8, entry: u8) -> XcpResult<()> {
+        let mut cmd = vec![CMD_SET_DAQ_PTR, 0];
+        self.put_u16(&mut cmd, daq);
+        cmd.extend_from_slice(&[odt, entry]);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Sets the current ODT entry to `size` bytes of memory at the
+    /// address, and moves on to the next entry.
+    pub fn write_daq(&mut self, addr: u32, ext: u8, size: u8) -> XcpResult<()> {
+        // No bit offset: the whole element is sampled
+        let mut cmd = vec![CMD_WRITE_DAQ, 0xFF, size, ext];
+        self.put_u32(&mut cmd, addr);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Sets the mode of a DAQ list, and the event channel that triggers
+    /// it.
+    ///
+    /// The mode bits pick, among others, timestamps and direction. Zero
+    /// is plain DAQ, without timestamps.
+    pub fn set_daq_list_mode(
+        &mut self,
+        daq: u16,
+        mode: u8,
+        event: u16,
+        prescaler: u8,
+        priority: u8,
+    ) -> XcpResult<()> {
+        let mut cmd = vec![CMD_SET_DAQ_LIST_MODE, mode];
+        self.put_u16(&mut cmd, daq);
+        self.put_u16(&mut cmd, event);
+        cmd.extend_from_slice(&[prescaler, priority]);
+        self.command(&cmd).map(|_| ())
+    }
+
+    /// Starts, stops, or selects a DAQ list.
+    ///
+    /// This returns the PID of the first ODT of the list, which the DAQ
+    /// packets for it start from.
+    pub fn start_stop_daq_list(&mut self, daq: u16, mode: DaqListMode) -> XcpResult<u8> {
+        let mut cmd = vec![CMD_START_STOP_DAQ_LIST, mode as u8];
+        self.put_u16(&mut cmd, daq);
+        let params = self.command(&cmd)?;
+        params.first().copied().ok_or(XcpError::UnexpectedResponse)
+    }
+
+    /// Starts or stops the selected DAQ lists together, or stops all of
+    /// them.
+    pub fn start_stop_synch(&mut self, mode: SynchMode) -> XcpResult<()> {
+        self.command(&[CMD_START_STOP_SYNCH, mode as u8])
+            .map(|_| ())
+    }
+
+    /// Reads the next DAQ packet from the slave.
+    ///
+    /// This blocks, subject to the read timeout of the socket. Other
+    /// packets from the slave are skipped.
+    pub fn read_daq(&self) -> XcpResult<DaqPacket> {
+        loop {
+            let frame = self.sock.read_frame()?;
+            if frame.id() == self.res_id && matches!(frame, CanFrame::Data(_)) {
+                if let Some(pkt) = DaqPacket::from_data(frame.data()) {
+                    return Ok(pkt);
+                }
+            }
+        }
+    }
+
+    // Sends a command, and gets the parameters of its positive response
+    fn command(&mut self, cmd: &[u8]) -> XcpResult<Vec<u8>> {
+        let resp = self.exchange_raw(cmd)?;
+        match check_response(&resp) {
+            Some(Ok(params)) => Ok(params.to_vec()),
+            Some(Err(err)) => Err(err),
+            None => Err(XcpError::UnexpectedResponse),
+        }
+    }
+
+    // Sends a command, and waits for the response, or error, packet
+    fn exchange_raw(&mut self, cmd: &[u8]) -> XcpResult<Vec<u8>> {
+        let mut data = cmd.to_vec();
+        if let Some(pad) = self.pad {
+            data.resize(usize::from(CAN_MAX_CTO), pad);
+        }
+        let frame = CanFrame::new(self.cmd_id, &data)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        self.sock.write_frame(&frame)?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(XcpError::Timeout);
+            }
+            let frame = match self.sock.read_frame_timeout(remaining) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => return Err(XcpError::Timeout),
+                Err(err) => return Err(err.into()),
+            };
+            if frame.id() == self.res_id && !frame.is_remote_frame() {
+                let resp = frame.data();
+                match check_response(resp) {
+                    Some(Ok(_)) => return Ok(resp.to_vec()),
+                    Some(Err(err)) => return Err(err),
+                    None => (),
+                }
+            }
+        }
+    }
+
+    fn put_u16(&self, buf: &mut Vec<u8>, val: u16) {
+        match self.big_endian {
+            true => buf.extend_from_slice(&val.to_be_bytes()),
+            false => buf.extend_from_slice(&val.to_le_bytes()),
+        }
+    }
+
+    fn put_u32(&self, buf: &mut Vec<u8>, val: u32) {
+        match self.big_endian {
+            true => buf.extend_from_slice(&val.to_be_bytes()),
+            false => buf.extend_from_slice(&val.to_le_bytes()),
+        }
+    }
+
+    fn get_u16(&self, b: [u8; 2]) -> u16 {
+        match self.big_endian {
+            true => u16::from_be_bytes(b),
+            false => u16::from_le_bytes(b),
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StandardId;
+    use std::os::{fd::OwnedFd, unix::net::UnixDatagram};
+
+    #[test]
+    fn test_connect_info() {
+        let info =
+            ConnectInfo::from_response(&[0xFF, 0x15, 0xC0, 0x08, 0x08, 0x00, 0x01, 0x01]).unwrap();
+        assert!(!info.is_big_endian());
+        assert_eq!(info.resource, 0x15);
+        assert_eq!(info.max_cto, 8);
+        assert_eq!(info.max_dto, 8);
+
+        let info =
+            ConnectInfo::from_response(&[0xFF, 0x15, 0xC1, 0x08, 0x00, 0x08, 0x01, 0x01]).unwrap();
+        assert!(info.is_big_endian());
+        assert_eq!(info.max_dto, 8);
+
+        assert!(ConnectInfo::from_response(&[0xFF, 0x15]).is_err());
+    }
+
+    #[test]
+    fn test_connect_small_cto() {
+        let (a, b) = UnixDatagram::pair().unwrap();
+        let peer = CanSocket::from(OwnedFd::from(b));
+        let cmd_id = StandardId::new(0x7F0).unwrap();
+        let res_id = StandardId::new(0x7F1).unwrap();
+        let mut xcp = XcpMaster::new(CanSocket::from(OwnedFd::from(a)), cmd_id, res_id);
+
+        // A slave that reports a MAX_CTO of 2
+        let resp = [0xFF, 0x15, 0xC0, 0x02, 0x08, 0x00, 0x01, 0x01];
+        peer.write_frame(&CanFrame::new(res_id, &resp).unwrap())
+            .unwrap();
+        assert!(matches!(xcp.connect(), Err(XcpError::UnexpectedResponse)));
+    }
+
+    #[test]
+    fn test_response() {
+        assert_eq!(check_response(&[0xFF, 1, 2]).unwrap().unwrap(), &[1, 2]);
+        assert!(matches!(
+            check_response(&[0xFE, 0x20]),
+            Some(Err(XcpError::Command(0x20)))
+        ));
+        // An event, and a DAQ packet
+        assert!(check_response(&[0xFD, 0x00]).is_none());
+        assert!(check_response(&[0x03, 0x11]).is_none());
+
+        assert_eq!(
+            DaqPacket::from_data(&[0x03, 0x11, 0x22]),
+            Some(DaqPacket {
+                pid: 3,
+                data: vec![0x11, 0x22]
+            })
+        );
+        assert!(DaqPacket::from_data(&[0xFF]).is_none());
+        assert!(DaqPacket::from_data(&[]).is_none());
+    }
+}
@@ -12,7 +12,8 @@
 #[cfg(feature = "vcan_tests")]
 use socketcan::{
     id::{ERR_MASK_ALL, ERR_MASK_NONE},
-    CanFrame, CanSocket, EmbeddedFrame, ShouldRetry, Socket, SocketOptions, StandardId,
+    CanErrorFrame, CanFilter, CanFrame, CanSocket, EmbeddedFrame, Frame, OpenOptions, ShouldRetry,
+    Socket, SocketOptions, StandardId, TimestampMode,
 };
 
 #[cfg(feature = "vcan_tests")]
@@ -28,6 +29,19 @@ fn test_nonexistent_device() {
     assert!(CanSocket::open("invalid").is_err());
 }
 
+#[cfg(feature = "vcan_tests")]
+#[test]
+fn test_open_retry() {
+    let timeout = time::Duration::from_millis(50);
+    let interval = time::Duration::from_millis(10);
+
+    let start = time::Instant::now();
+    assert!(CanSocket::open_retry("invalid", timeout, interval).is_err());
+    assert!(start.elapsed() >= timeout);
+
+    assert!(CanSocket::open_retry(VCAN, timeout, interval).is_ok());
+}
+
 #[test]
 #[cfg(feature = "vcan_tests")]
 fn vcan_timeout() {
@@ -40,6 +54,815 @@ fn vcan_timeout() {
     assert!(sock.read_frame().should_retry());
 }
 
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_wait_writable() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_send_buffer_size(4096).unwrap();
+    assert!(sock.send_buffer_size().unwrap() >= 4096);
+
+    let timeout = time::Duration::from_millis(100);
+    assert!(sock.wait_writable(Some(timeout)).unwrap());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_enable_error_frames() {
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock.set_filter_drop_all().unwrap();
+    rx_sock.enable_error_frames().unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    // Error frames written to vcan are looped back to the other sockets
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    let err_frame = CanErrorFrame::new_error(0x04, &[0, 0x04]).unwrap();
+    tx_sock.write_frame(&err_frame).unwrap();
+
+    match rx_sock.read_frame().unwrap() {
+        CanFrame::Error(frame) => assert_eq!(frame.error_bits(), 0x04),
+        frame => panic!("Expected an error frame, got {:?}", frame),
+    }
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_open_with_filters() {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    const ID: u16 = 0x100;
+    const OTHER_ID: u16 = 0x200;
+
+    // Keep the bus busy with frames that should be filtered out
+    let done = Arc::new(AtomicBool::new(false));
+    let tx_done = Arc::clone(&done);
+    let tx = thread::spawn(move || {
+        let tx_sock = CanSocket::open(VCAN).unwrap();
+        let frames = [
+            CanFrame::new(StandardId::new(OTHER_ID).unwrap(), &[0]).unwrap(),
+            CanFrame::new(StandardId::new(ID).unwrap(), &[1]).unwrap(),
+        ];
+        while !tx_done.load(Ordering::Relaxed) {
+            for frame in &frames {
+                let _ = tx_sock.write_frame(frame);
+            }
+        }
+    });
+
+    let opts = OpenOptions::new().filters(&[CanFilter::new(ID as u32, 0x7FF)]);
+
+    for _ in 0..50 {
+        let sock = CanSocket::open_with(VCAN, &opts).unwrap();
+        sock.set_read_timeout(time::Duration::from_millis(100))
+            .unwrap();
+        for _ in 0..4 {
+            let frame = sock.read_frame().unwrap();
+            assert_eq!(frame.raw_id(), ID as u32);
+        }
+    }
+
+    done.store(true, Ordering::Relaxed);
+    tx.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_frame_named() {
+    use socketcan::CanAddr;
+
+    let rx_sock = CanSocket::open_addr(&CanAddr::any()).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    tx_sock.write_frame(&frame).unwrap();
+
+    // Skip any traffic from other interfaces
+    loop {
+        let (rx_frame, name) = rx_sock.read_frame_named().unwrap();
+        if name == VCAN {
+            assert_eq!(rx_frame.data(), frame.data());
+            break;
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_recvmsg() {
+    use std::mem::{size_of, size_of_val};
+
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+    rx_sock.enable_timestamps().unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    tx_sock.write_frame(&frame).unwrap();
+
+    let mut buf = [0u8; 16];
+    let mut cmsg_buf = [0u64; 16];
+    let cmsg_bytes = unsafe {
+        std::slice::from_raw_parts_mut(cmsg_buf.as_mut_ptr().cast::<u8>(), size_of_val(&cmsg_buf))
+    };
+    let res = rx_sock.recvmsg(&mut buf, cmsg_bytes).unwrap();
+
+    assert_eq!(res.len, 16);
+    assert_eq!(res.flags & libc::MSG_CTRUNC, 0);
+    assert!(res.cmsg_len >= size_of::<libc::cmsghdr>());
+
+    // The timestamp comes in the first control message
+    let hdr = unsafe { &*cmsg_buf.as_ptr().cast::<libc::cmsghdr>() };
+    assert_eq!(hdr.cmsg_level, libc::SOL_SOCKET);
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_send_confirmed() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    sock.send_confirmed(&frame, time::Duration::from_millis(100))
+        .unwrap();
+
+    let rx_frame = rx_sock.read_frame().unwrap();
+    assert_eq!(rx_frame.raw_id(), 0x123);
+    assert_eq!(rx_frame.data(), frame.data());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_write_frame_to() {
+    use socketcan::CanAddr;
+
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let tx_sock = CanSocket::open_addr(&CanAddr::any()).unwrap();
+    let ifindex = CanAddr::from_iface(VCAN).unwrap().ifindex() as u32;
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    tx_sock.write_frame_to(&frame, ifindex).unwrap();
+
+    let rx_frame = rx_sock.read_frame().unwrap();
+    assert_eq!(rx_frame.raw_id(), 0x123);
+    assert_eq!(rx_frame.data(), frame.data());
+
+    // There's no interface to send to with an index of zero
+    let err = tx_sock.write_frame_to(&frame, 0).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::ENXIO));
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_write_frame_at() {
+    use socketcan::SocketError;
+    use std::io;
+
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+
+    // Fails, rather than sending right away, until TXTIME is enabled
+    let err = tx_sock
+        .write_frame_at(&frame, time::SystemTime::now())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    // The vcan interface has no ETF qdisc, so TXTIME can't be enabled,
+    // and the frame still isn't sent
+    let err = tx_sock.enable_txtime(libc::CLOCK_TAI).unwrap_err();
+    let inner = err.get_ref().unwrap().downcast_ref::<SocketError>();
+    assert_eq!(inner, Some(&SocketError::NoEtfQdisc));
+
+    let err = tx_sock
+        .write_frame_at(&frame, time::SystemTime::now())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(rx_sock.read_frame().is_err());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_shutdown_write() {
+    use std::{io, net::Shutdown};
+
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1]).unwrap();
+    sock.write_frame(&frame).unwrap();
+
+    sock.shutdown(Shutdown::Write).unwrap();
+    let err = sock.write_frame(&frame).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+    // The read side still drains
+    assert_eq!(sock.read_frame().unwrap().data(), frame.data());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_too_many_filters() {
+    use socketcan::SocketError;
+    use std::io;
+
+    let sock = CanSocket::open(VCAN).unwrap();
+    let filters = vec![CanFilter::new(0x123, 0x7FF); 513];
+
+    let err = sock.set_filters(&filters).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<SocketError>(),
+        Some(&SocketError::TooManyFilters { got: 513, max: 512 })
+    );
+
+    sock.set_filters(&filters[..512]).unwrap();
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_set_filters_from() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    // The duplicates don't count against the kernel limit
+    let filters = (0..1000).map(|i| CanFilter::new(0x100 + (i % 2), 0x7FF));
+    assert_eq!(sock.set_filters_from(filters).unwrap(), 2);
+
+    let filters = [(0x123, 0x7FF), (0x123, 0x7FF), (0x456, 0x7FF)];
+    assert_eq!(sock.set_filters_from(filters).unwrap(), 2);
+
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    for id in [0x100, 0x123, 0x456] {
+        let frame = CanFrame::new(StandardId::new(id).unwrap(), &[]).unwrap();
+        tx_sock.write_frame(&frame).unwrap();
+    }
+
+    assert_eq!(sock.read_frame().unwrap().raw_id(), 0x123);
+    assert_eq!(sock.read_frame().unwrap().raw_id(), 0x456);
+    assert!(sock.read_frame().is_err());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_replace_filters() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+    sock.set_filters(&[(0x100, 0x7FF)]).unwrap();
+
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    let send = |id| {
+        let frame = CanFrame::new(StandardId::new(id).unwrap(), &[]).unwrap();
+        tx_sock.write_frame(&frame).unwrap();
+    };
+
+    send(0x100);
+    send(0x200);
+    sock.replace_filters(&[(0x200, 0x7FF)]).unwrap();
+    send(0x100);
+    send(0x200);
+
+    // Frames queued before the swap were filtered by the old set
+    assert_eq!(sock.read_frame().unwrap().raw_id(), 0x100);
+    assert_eq!(sock.read_frame().unwrap().raw_id(), 0x200);
+    assert!(sock.read_frame().is_err());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_recv_frames_timeout() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+
+    for id in 0x100..0x103 {
+        let frame = CanFrame::new(StandardId::new(id).unwrap(), &[]).unwrap();
+        sock.write_frame(&frame).unwrap();
+    }
+
+    // The deadline passes with the buffer only partly filled
+    let mut buf = [CanFrame::default(); 8];
+    let timeout = time::Duration::from_millis(50);
+    let start = time::Instant::now();
+    let n = sock.recv_frames_timeout(&mut buf, start + timeout).unwrap();
+    assert!(start.elapsed() >= timeout);
+
+    assert_eq!(n, 3);
+    let ids: Vec<_> = buf[..n].iter().map(|frame| frame.raw_id()).collect();
+    assert_eq!(ids, [0x100, 0x101, 0x102]);
+
+    // Nothing left, and the deadline already passed
+    let n = sock
+        .recv_frames_timeout(&mut buf, time::Instant::now())
+        .unwrap();
+    assert_eq!(n, 0);
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_raw_frame_uninit() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2, 3]).unwrap();
+    sock.write_frame(&frame).unwrap();
+
+    let raw = sock.read_raw_frame_uninit().unwrap();
+    assert_eq!(raw.can_id, 0x123);
+    assert_eq!(raw.can_dlc, 3);
+    assert_eq!(&raw.data[..3], &[1, 2, 3]);
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_ring_reader() {
+    use socketcan::CanRingReader;
+
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    for i in 0..6u8 {
+        let frame = CanFrame::new(StandardId::new(0x100).unwrap(), &[i]).unwrap();
+        sock.write_frame(&frame).unwrap();
+    }
+
+    // The first read fills the buffer, the second gets what's left
+    let mut reader = CanRingReader::<4>::new();
+    let frames = reader.read(&sock).unwrap();
+    assert_eq!(frames.len(), 4);
+    assert_eq!(frames[0].data(), &[0]);
+
+    let frames = reader.read(&sock).unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[1].data(), &[5]);
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_write_any_frame() {
+    use socketcan::{CanAnyFrame, CanFdFrame, CanFdSocket, CanXlFrame, SocketError};
+    use std::io;
+
+    let id = StandardId::new(0x123).unwrap();
+    let frame = CanAnyFrame::from(CanFrame::new(id, &[1, 2]).unwrap());
+    let fdframe = CanAnyFrame::from(CanFdFrame::new(id, &[0; 12]).unwrap());
+
+    let sock = CanSocket::open(VCAN).unwrap();
+    assert!(!sock.fd_frames_enabled().unwrap());
+    sock.write_any_frame(&frame).unwrap();
+
+    let err = sock.write_any_frame(&fdframe).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<SocketError>(),
+        Some(&SocketError::FdFramesDisabled)
+    );
+
+    let sock = CanFdSocket::open(VCAN).unwrap();
+    assert!(sock.fd_frames_enabled().unwrap());
+    sock.write_any_frame(frame).unwrap();
+    sock.write_any_frame(fdframe).unwrap();
+
+    // XL frames weren't enabled
+    let prio = StandardId::new(0x123).unwrap();
+    let xlframe = CanXlFrame::new(prio, 0x01, 0, &[5; 100]).unwrap();
+    let err = sock.write_any_frame(xlframe).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<SocketError>(),
+        Some(&SocketError::XlFramesDisabled)
+    );
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_write_padded() {
+    use socketcan::{CanAnyFrame, CanFdSocket};
+
+    let sock = CanFdSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    sock.set_tx_pad_byte(0xAA);
+    assert_eq!(sock.tx_pad_byte(), 0xAA);
+
+    let id = StandardId::new(0x123).unwrap();
+    sock.write_padded(id, &[1; 10]).unwrap();
+
+    match sock.read_frame().unwrap() {
+        CanAnyFrame::Fd(frame) => {
+            assert_eq!(frame.len(), 12);
+            assert_eq!(&frame.data()[10..], &[0xAA, 0xAA]);
+        }
+        frame => panic!("expected an FD frame, got {:?}", frame),
+    }
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_fd_frames_supported() {
+    use socketcan::CanFdSocket;
+
+    // Any kernel able to run the vcan tests has FD support
+    assert!(CanFdSocket::fd_frames_supported().unwrap());
+    assert!(CanFdSocket::open(VCAN).is_ok());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_frame_as_fd() {
+    use socketcan::{id::FdFlags, CanFdFrame, CanFdSocket};
+
+    let sock = CanFdSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    sock.write_frame(&frame).unwrap();
+
+    let fdframe = sock.read_frame_as_fd().unwrap();
+    assert_eq!(fdframe.raw_id(), 0x123);
+    assert_eq!(fdframe.data(), &[1, 2]);
+    assert!(!fdframe.flags().contains(FdFlags::FDF));
+
+    let frame = CanFdFrame::new(StandardId::new(0x456).unwrap(), &[3; 12]).unwrap();
+    sock.write_frame(&frame).unwrap();
+
+    let fdframe = sock.read_frame_as_fd().unwrap();
+    assert_eq!(fdframe.raw_id(), 0x456);
+    assert!(fdframe.flags().contains(FdFlags::FDF));
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_xl_frames() {
+    use socketcan::{CanFdSocket, CanMixedFrame, SocketError};
+    use std::io;
+
+    // XL frames need FD frames to be enabled
+    let opts = OpenOptions::new().xl_frames(true);
+    let err = CanSocket::open_with(VCAN, &opts).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<SocketError>(),
+        Some(&SocketError::XlRequiresFd)
+    );
+
+    let sock = CanFdSocket::open_with(VCAN, &opts.recv_own_msgs(true)).unwrap();
+    assert!(sock.xl_frames_enabled().unwrap());
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    sock.write_frame(&frame).unwrap();
+
+    match sock.read_any_frame().unwrap() {
+        CanMixedFrame::Classic(rx_frame) => assert_eq!(rx_frame.data(), frame.data()),
+        frame => panic!("expected a classic frame, got {:?}", frame),
+    }
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_xl_frame() {
+    use socketcan::{CanFdSocket, CanXlFrame};
+    use std::{fs, io};
+
+    // The kernel only sends XL frames on an interface with an XL MTU
+    let mtu: usize = fs::read_to_string(format!("/sys/class/net/{}/mtu", VCAN))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    if mtu < libc::CANXL_MIN_MTU {
+        eprintln!(
+            "Skipping: {} has an MTU of {}, too small for XL frames",
+            VCAN, mtu
+        );
+        return;
+    }
+
+    let sock = CanFdSocket::open_with(VCAN, &OpenOptions::new().recv_own_msgs(true)).unwrap();
+    sock.enable_xl_frames().unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let prio = StandardId::new(0x123).unwrap();
+    let frame = CanXlFrame::new(prio, 0x01, 0x1234_5678, &[5; 100]).unwrap();
+    sock.write_xl_frame(&frame).unwrap();
+
+    let rx_frame = sock.read_xl_frame().unwrap();
+    assert_eq!(rx_frame.prio(), 0x123);
+    assert_eq!(rx_frame.af(), 0x1234_5678);
+    assert_eq!(rx_frame.data(), frame.data());
+
+    let frame = CanFrame::new(prio, &[1, 2]).unwrap();
+    sock.write_frame(&frame).unwrap();
+    assert_eq!(
+        sock.read_xl_frame().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_checked() {
+    use socketcan::{CanFdSocket, SocketError};
+    use std::io;
+
+    let sock = CanFdSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+    sock.set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    sock.write_frame(&frame).unwrap();
+    sock.write_frame(&frame).unwrap();
+
+    let mut buf = [0u8; 72];
+    assert_eq!(sock.read_checked(&mut buf).unwrap(), libc::CAN_MTU);
+
+    // The frame doesn't fit, but the kernel doesn't say how big it was
+    let mut buf = [0u8; 8];
+    let err = sock.read_checked(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<SocketError>(),
+        Some(&SocketError::Truncated {
+            received: 8,
+            actual: None
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_isotp_socket() {
+    use socketcan::{CanIsoTpSocket, SocketError};
+
+    let tester = StandardId::new(0x7E0).unwrap();
+    let ecu = StandardId::new(0x7E8).unwrap();
+
+    let tx_sock = CanIsoTpSocket::open(VCAN, ecu, tester).unwrap();
+    let rx_sock = CanIsoTpSocket::open(VCAN, tester, ecu).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_secs(1))
+        .unwrap();
+
+    // A multi-frame message, with flow control from the receiving socket
+    let msg: Vec<u8> = (0..100).collect();
+    tx_sock.write(&msg).unwrap();
+
+    let mut buf = [0u8; 4095];
+    let n = rx_sock.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], &msg[..]);
+
+    tx_sock.write(&msg).unwrap();
+    let err = rx_sock.read(&mut buf[..10]).unwrap_err();
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<SocketError>(),
+        Some(&SocketError::Truncated {
+            received: 10,
+            actual: Some(100)
+        })
+    );
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_uds_client() {
+    use socketcan::{
+        uds::{NegativeResponseCode, UdsError},
+        CanIsoTpSocket, UdsClient,
+    };
+    use std::thread;
+
+    let tester = StandardId::new(0x7E1).unwrap();
+    let ecu = StandardId::new(0x7E9).unwrap();
+
+    let client = UdsClient::open(VCAN, ecu, tester).unwrap();
+    let ecu_sock = CanIsoTpSocket::open(VCAN, tester, ecu).unwrap();
+
+    // A fake ECU that answers one read after a "response pending", and
+    // rejects the next
+    let server = thread::spawn(move || {
+        let mut buf = [0u8; 4095];
+        let n = ecu_sock.read(&mut buf).unwrap();
+        assert_eq!(&buf[..n], &[0x22, 0xF1, 0x90]);
+        ecu_sock.write(&[0x7F, 0x22, 0x78]).unwrap();
+        ecu_sock.write(b"\x62\xF1\x90WVW123").unwrap();
+
+        ecu_sock.read(&mut buf).unwrap();
+        ecu_sock.write(&[0x7F, 0x22, 0x31]).unwrap();
+    });
+
+    assert_eq!(client.read_data_by_identifier(0xF190).unwrap(), b"WVW123");
+    match client.read_data_by_identifier(0xF191) {
+        Err(UdsError::Negative { code, .. }) => {
+            assert_eq!(code, NegativeResponseCode::RequestOutOfRange)
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_j1939_socket() {
+    use socketcan::{
+        j1939::{J1939_NO_NAME, J1939_NO_PGN},
+        CanAddr, CanJ1939Socket, J1939Filter,
+    };
+
+    const PGN: u32 = 0xFEF1;
+
+    let rx_sock = CanJ1939Socket::open(VCAN, J1939_NO_NAME, J1939_NO_PGN, 0x20).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_secs(1))
+        .unwrap();
+    rx_sock.set_filters(&[J1939Filter::new().pgn(PGN)]).unwrap();
+
+    let tx_sock = CanJ1939Socket::open(VCAN, J1939_NO_NAME, J1939_NO_PGN, 0x80).unwrap();
+    tx_sock.set_send_priority(3).unwrap();
+
+    // Longer than a single frame, so it goes through the transport protocol
+    let msg: Vec<u8> = (0..20).collect();
+    let peer = CanAddr::from_iface_j1939(VCAN, J1939_NO_NAME, PGN, 0x20).unwrap();
+    tx_sock.send_to(&msg, &peer).unwrap();
+
+    let mut buf = [0u8; 1785];
+    let (n, from) = rx_sock.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], &msg[..]);
+    assert_eq!(from.j1939_pgn(), PGN);
+    assert_eq!(from.j1939_addr(), 0x80);
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_bcm_tx_setup() {
+    use socketcan::{bcm::TxJob, CanBcmSocket};
+
+    let id = StandardId::new(0x123).unwrap();
+
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock
+        .set_filters(&[CanFilter::new(0x123, 0x7FF)])
+        .unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_secs(1))
+        .unwrap();
+
+    let bcm_sock = CanBcmSocket::open(VCAN).unwrap();
+    let frame = CanFrame::new(id, &[1, 2, 3, 4]).unwrap();
+    let job = TxJob::new(frame, time::Duration::from_millis(10)).announce(true);
+    bcm_sock.tx_setup(&job).unwrap();
+
+    for _ in 0..3 {
+        let rx_frame = rx_sock.read_frame().unwrap();
+        assert_eq!(rx_frame.id(), frame.id());
+        assert_eq!(rx_frame.data(), frame.data());
+    }
+
+    bcm_sock.tx_delete(id).unwrap();
+    assert!(bcm_sock.tx_delete(id).is_err());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_bcm_tx_multiplex() {
+    use socketcan::{bcm::TxJob, CanBcmSocket};
+
+    let id = StandardId::new(0x125).unwrap();
+
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock
+        .set_filters(&[CanFilter::new(0x125, 0x7FF)])
+        .unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_secs(1))
+        .unwrap();
+
+    let bcm_sock = CanBcmSocket::open(VCAN).unwrap();
+    let frames: Vec<_> = (0..3u8)
+        .map(|i| CanFrame::new(id, &[i, 0xAA]).unwrap())
+        .collect();
+    let job = TxJob::multiplex(frames, time::Duration::from_millis(10)).unwrap();
+    bcm_sock.tx_setup(&job).unwrap();
+
+    // The frames come around in rotation
+    let first = rx_sock.read_frame().unwrap().data()[0];
+    for i in 1..6u8 {
+        let rx_frame = rx_sock.read_frame().unwrap();
+        assert_eq!(rx_frame.data(), &[(first + i) % 3, 0xAA]);
+    }
+
+    bcm_sock.tx_delete(id).unwrap();
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_bcm_rx_setup() {
+    use socketcan::{
+        bcm::{BcmMsg, RxJob},
+        CanBcmSocket,
+    };
+
+    let id = StandardId::new(0x124).unwrap();
+
+    let bcm_sock = CanBcmSocket::open(VCAN).unwrap();
+    bcm_sock
+        .set_read_timeout(time::Duration::from_secs(1))
+        .unwrap();
+    let job = RxJob::new(id)
+        .data_mask([0xFF, 0, 0, 0, 0, 0, 0, 0])
+        .timeout(time::Duration::from_millis(50));
+    bcm_sock.rx_setup(&job).unwrap();
+
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    for data in [[1, 2], [1, 3], [2, 3]] {
+        tx_sock
+            .write_frame(&CanFrame::new(id, &data).unwrap())
+            .unwrap();
+    }
+
+    // Only the first byte is watched, so the second frame is filtered out
+    for data in [[1, 2], [2, 3]] {
+        match bcm_sock.read_msg().unwrap() {
+            BcmMsg::RxChanged(frame) => assert_eq!(frame.data(), &data),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    assert!(matches!(
+        bcm_sock.read_msg().unwrap(),
+        BcmMsg::RxTimeout(rx_id) if rx_id == id.into()
+    ));
+
+    bcm_sock.rx_delete(id).unwrap();
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_j1939_address_claim() {
+    use socketcan::j1939::{AddressClaimer, ClaimState};
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    // The lower NAME has the higher priority
+    let mut high = AddressClaimer::new(VCAN, 0x10, [0x90]).unwrap();
+    assert_eq!(high.claim().unwrap(), 0x90);
+    assert_eq!(high.state(), ClaimState::Claimed(0x90));
+
+    let lost = Arc::new(Mutex::new(None));
+    let server = thread::spawn(move || {
+        // Defends against the claim for the same address
+        high.process(time::Duration::from_secs(1)).unwrap()
+    });
+
+    let mut low = AddressClaimer::new(VCAN, 0x20, [0x90, 0x91]).unwrap();
+    let lost_addr = lost.clone();
+    low.on_address_lost(move |addr| *lost_addr.lock().unwrap() = Some(addr));
+    assert_eq!(low.claim().unwrap(), 0x91);
+    assert_eq!(low.address(), Some(0x91));
+
+    // The address was never held, so it wasn't "lost"
+    assert_eq!(*lost.lock().unwrap(), None);
+    assert_eq!(server.join().unwrap(), ClaimState::Claimed(0x90));
+}
+
 #[test]
 #[cfg(feature = "vcan_tests")]
 fn vcan_set_error_mask() {
@@ -48,6 +871,25 @@ fn vcan_set_error_mask() {
     sock.set_error_mask(ERR_MASK_NONE).unwrap();
 }
 
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_error_filter_mask() {
+    use socketcan::CanErrorMask;
+
+    let sock = CanSocket::open(VCAN).unwrap();
+    assert_eq!(sock.error_filter_mask().unwrap(), CanErrorMask::empty());
+
+    let mask = CanErrorMask::BUS_OFF | CanErrorMask::CONTROLLER;
+    sock.set_error_filter_mask(mask).unwrap();
+    assert_eq!(sock.error_filter_mask().unwrap(), mask);
+
+    sock.set_error_filter_accept_all().unwrap();
+    assert!(sock
+        .error_filter_mask()
+        .unwrap()
+        .contains(CanErrorMask::all()));
+}
+
 #[test]
 #[cfg(feature = "vcan_tests")]
 fn vcan_enable_own_loopback() {
@@ -62,6 +904,104 @@ fn vcan_enable_own_loopback() {
     sock.read_frame().unwrap();
 }
 
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_local_addr() {
+    use socketcan::CanAddr;
+
+    let sock = CanSocket::open(VCAN).unwrap();
+    let addr = sock.local_addr().unwrap();
+    assert_eq!(addr.ifindex(), CanAddr::from_iface(VCAN).unwrap().ifindex());
+    assert_eq!(addr.iface_name().unwrap(), VCAN);
+
+    let sock = CanSocket::open_addr(&CanAddr::any()).unwrap();
+    assert!(sock.local_addr().unwrap().is_any());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_frame_meta() {
+    let tx_sock = CanSocket::open(VCAN).unwrap();
+    tx_sock.set_recv_own_msgs(true).unwrap();
+    tx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+
+    let rx_sock = CanSocket::open(VCAN).unwrap();
+    rx_sock
+        .set_read_timeout(time::Duration::from_millis(100))
+        .unwrap();
+    rx_sock.enable_timestamps().unwrap();
+    rx_sock.set_rxq_overflow(true).unwrap();
+
+    let frame = CanFrame::new(StandardId::new(0x123).unwrap(), &[1, 2]).unwrap();
+    tx_sock.write_frame(&frame).unwrap();
+
+    // The sender gets its own frame back, without the optional fields
+    let (_, meta) = tx_sock.read_frame_meta().unwrap();
+    assert!(meta.loopback);
+    assert!(meta.own);
+    assert!(meta.timestamp.is_none());
+    assert!(meta.drops.is_none());
+    assert_eq!(
+        meta.ifindex,
+        socketcan::CanAddr::from_iface(VCAN).unwrap().ifindex()
+    );
+
+    // Another local socket sees it looped back, but not as its own
+    let (_, meta) = rx_sock.read_frame_meta().unwrap();
+    assert!(meta.loopback);
+    assert!(!meta.own);
+    assert!(!meta.is_from_bus());
+    assert!(meta.timestamp.is_some());
+    // Nothing was dropped, so the kernel doesn't report a count
+    assert!(meta.drops.is_none());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_frame_with_timestamp() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+
+    let id = StandardId::new(0x123).unwrap();
+    let frame = CanFrame::new(id, &[1, 2, 3]).unwrap();
+
+    let mode = sock.enable_timestamps().unwrap();
+    assert_ne!(mode, TimestampMode::Disabled);
+
+    sock.write_frame(&frame).unwrap();
+    let (rframe, ts) = sock.read_frame_with_timestamp().unwrap();
+    assert_eq!(rframe.data(), frame.data());
+    assert!(ts.is_some());
+
+    // The legacy, microsecond, timestamps
+    sock.set_timestamp_mode(TimestampMode::Legacy).unwrap();
+    sock.write_frame(&frame).unwrap();
+    let (_, ts) = sock.read_frame_with_timestamp().unwrap();
+    assert!(ts.is_some());
+
+    sock.set_timestamp_mode(TimestampMode::Disabled).unwrap();
+    sock.write_frame(&frame).unwrap();
+    let (_, ts) = sock.read_frame_with_timestamp().unwrap();
+    assert!(ts.is_none());
+}
+
+#[test]
+#[cfg(feature = "vcan_tests")]
+fn vcan_read_frame_raw_len() {
+    let sock = CanSocket::open(VCAN).unwrap();
+    sock.set_recv_own_msgs(true).unwrap();
+
+    let id = StandardId::new(0x123).unwrap();
+    let frame = CanFrame::new(id, &[1, 2, 3, 4]).unwrap();
+
+    sock.write_frame(&frame).unwrap();
+    let (rframe, n) = sock.read_frame_raw_len().unwrap();
+    assert_eq!(n, socketcan::socket::CAN_MTU);
+    assert_eq!(rframe.data(), frame.data());
+}
+
 // #[test]
 // fn vcan_set_down() {
 //     let can_if = CanInterface::open(VCAN).unwrap();